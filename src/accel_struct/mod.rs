@@ -130,6 +130,11 @@ impl AccelerationStructure {
                 )
                 .unwrap()
         };
+        loader.device().resource_registry().register(
+            vk::ObjectType::ACCELERATION_STRUCTURE_KHR,
+            acceleration_structure,
+            Some(size),
+        );
         let device_address = unsafe {
             loader.get_acceleration_structure_device_address(
                 &vk::AccelerationStructureDeviceAddressInfoKHR {
@@ -268,6 +273,10 @@ impl AccelerationStructure {
 
 impl Drop for AccelerationStructure {
     fn drop(&mut self) {
+        self.loader
+            .device()
+            .resource_registry()
+            .unregister(vk::ObjectType::ACCELERATION_STRUCTURE_KHR, self.raw);
         unsafe {
             self.loader.destroy_acceleration_structure(self.raw, None);
             ManuallyDrop::drop(&mut self.backing_buffer);
@@ -10,6 +10,7 @@ use crate::HasDevice;
 pub struct CommandBufferBuilder {
     command_buffer: CommandBuffer,
     resource_guards: Vec<ReferencedResource>,
+    label: Option<Box<str>>,
 }
 
 // A command buffer in Executable state.
@@ -18,6 +19,7 @@ pub struct CommandBufferBuilder {
 pub struct CommandExecutable {
     pub(crate) command_buffer: CommandBuffer,
     pub(crate) _resource_guards: Vec<ReferencedResource>,
+    pub(crate) label: Option<Box<str>>,
 }
 impl Debug for CommandExecutable {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -29,6 +31,10 @@ impl Debug for CommandExecutable {
 }
 
 impl CommandExecutable {
+    /// The label assigned with [`CommandBufferBuilder::set_label`], if any.
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
     pub fn reset(self, release_resources: bool) -> CommandBuffer {
         let mut flags = vk::CommandBufferResetFlags::empty();
         if release_resources {
@@ -66,6 +72,9 @@ impl<T: Send + Sync + 'static> CommandBufferResource for Box<T> {
         ReferencedResource::Boxed(self)
     }
 }
+// Raw handles are not tracked. The caller is responsible for keeping them alive.
+impl CommandBufferResource for vk::Buffer {}
+impl CommandBufferResource for vk::Image {}
 impl CommandBufferResource for Box<dyn Send + Sync> {
     fn command_buffer_resource(self) -> ReferencedResource {
         ReferencedResource::Boxed(self)
@@ -110,11 +119,18 @@ impl CommandBuffer {
         Ok(CommandBufferBuilder {
             command_buffer: self,
             resource_guards: Vec::new(),
+            label: None,
         })
     }
 }
 
 impl CommandBufferBuilder {
+    /// Assign a human-readable label to the command buffer. The label is carried over to the
+    /// [`CommandExecutable`] and shows up in submission traces.
+    pub fn set_label(&mut self, label: &str) -> &mut Self {
+        self.label = Some(label.into());
+        self
+    }
     pub fn record<R>(&mut self, f: impl FnOnce(CommandRecorder) -> R) -> R {
         let recorder = CommandRecorder {
             device: self.command_buffer.pool.device().as_ref(),
@@ -136,6 +152,7 @@ impl CommandBufferBuilder {
             let exec = CommandExecutable {
                 command_buffer: self.command_buffer,
                 _resource_guards: self.resource_guards,
+                label: self.label,
             };
             Ok(exec)
         }
@@ -205,6 +222,66 @@ impl<'a> CommandRecorder<'a> {
         self
     }
 
+    pub fn copy_image_to_buffer<
+        SRC: HasImage + CommandBufferResource,
+        DST: HasBuffer + CommandBufferResource,
+    >(
+        &mut self,
+        src_image: SRC,
+        src_image_layout: vk::ImageLayout,
+        dst_buffer: DST,
+        regions: &[vk::BufferImageCopy],
+    ) -> &mut Self {
+        // Safety: Host Syncronization rule for vkCmdCopyImageToBuffer:
+        // - Host access to commandBuffer must be externally synchronized.
+        // - Host access to the VkCommandPool that commandBuffer was allocated from must be externally synchronized.
+        // We have &mut self and self.command_buffer is &mut, so we have exclusive control on self.command_buffer.buffer.
+        // self.command_buffer.pool is a &mut, so we have exclusive control on self.command_buffer.pool.pool.
+        unsafe {
+            self.device.cmd_copy_image_to_buffer(
+                self.command_buffer,
+                src_image.raw_image(),
+                src_image_layout,
+                dst_buffer.raw_buffer(),
+                regions,
+            );
+        }
+        self.track_resource(src_image.command_buffer_resource());
+        self.track_resource(dst_buffer.command_buffer_resource());
+        self
+    }
+
+    pub fn copy_image<
+        SRC: HasImage + CommandBufferResource,
+        DST: HasImage + CommandBufferResource,
+    >(
+        &mut self,
+        src_image: SRC,
+        src_image_layout: vk::ImageLayout,
+        dst_image: DST,
+        dst_image_layout: vk::ImageLayout,
+        regions: &[vk::ImageCopy],
+    ) -> &mut Self {
+        // Safety: Host Syncronization rule for vkCmdCopyImage:
+        // - Host access to commandBuffer must be externally synchronized.
+        // - Host access to the VkCommandPool that commandBuffer was allocated from must be externally synchronized.
+        // We have &mut self and self.command_buffer is &mut, so we have exclusive control on self.command_buffer.buffer.
+        // self.command_buffer.pool is a &mut, so we have exclusive control on self.command_buffer.pool.pool.
+        unsafe {
+            self.device.cmd_copy_image(
+                self.command_buffer,
+                src_image.raw_image(),
+                src_image_layout,
+                dst_image.raw_image(),
+                dst_image_layout,
+                regions,
+            );
+        }
+        self.track_resource(src_image.command_buffer_resource());
+        self.track_resource(dst_image.command_buffer_resource());
+        self
+    }
+
     pub fn clear_color_image<T: HasImage + CommandBufferResource>(
         &mut self,
         image: T,
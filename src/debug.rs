@@ -116,6 +116,11 @@ pub trait DebugObject: crate::HasDevice {
                         ..Default::default()
                     },
                 )?;
+            self.device().resource_registry().set_name(
+                Self::OBJECT_TYPE,
+                object_handle,
+                Some(cstr.to_string_lossy().into_owned()),
+            );
         }
         Ok(())
     }
@@ -142,6 +147,9 @@ pub trait DebugObject: crate::HasDevice {
                     },
                 )
                 .unwrap();
+            self.device()
+                .resource_registry()
+                .set_name(Self::OBJECT_TYPE, object_handle, None);
         }
     }
 }
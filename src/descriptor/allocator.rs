@@ -0,0 +1,215 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use ash::{prelude::VkResult, vk};
+
+use super::{DescriptorPool, DescriptorSet, DescriptorSetLayout};
+use crate::Device;
+
+const INITIAL_SETS_PER_POOL: u32 = 16;
+const MAX_SETS_PER_POOL: u32 = 4096;
+
+/// Allocates descriptor sets from a chain of [`DescriptorPool`]s, moving on to another pool whenever the
+/// current one runs out of memory or becomes fragmented.
+///
+/// New pools are sized from the descriptor counts of the layouts allocated so far, and each new pool holds
+/// twice as many sets as the previous one. Sets can't be freed individually. Instead,
+/// [`DescriptorAllocator::reset`] recycles whole pools once all sets allocated from them were dropped.
+/// Keep one allocator per frame in flight to reset them in a round-robin fashion.
+pub struct DescriptorAllocator {
+    device: Arc<Device>,
+    flags: vk::DescriptorPoolCreateFlags,
+    sets_per_pool: u32,
+    /// Total number of descriptors of each type in the sets allocated so far.
+    descriptor_totals: BTreeMap<i32, u64>,
+    set_total: u64,
+
+    current: Option<Arc<DescriptorPool>>,
+    /// Exhausted pools, and pools that still had live sets on the last reset.
+    used: Vec<Arc<DescriptorPool>>,
+    /// Pools that were reset and may be allocated from again.
+    ready: Vec<Arc<DescriptorPool>>,
+}
+
+impl DescriptorAllocator {
+    /// `flags` must not contain `FREE_DESCRIPTOR_SET`. Pass `UPDATE_AFTER_BIND` to allocate sets with
+    /// `UPDATE_AFTER_BIND_POOL` layouts.
+    pub fn new(device: Arc<Device>, flags: vk::DescriptorPoolCreateFlags) -> Self {
+        assert!(
+            !flags.contains(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET),
+            "Descriptor sets are freed along with their pool"
+        );
+        Self {
+            device,
+            flags,
+            sets_per_pool: INITIAL_SETS_PER_POOL,
+            descriptor_totals: BTreeMap::new(),
+            set_total: 0,
+            current: None,
+            used: Vec::new(),
+            ready: Vec::new(),
+        }
+    }
+
+    pub fn allocate(&mut self, layout: &DescriptorSetLayout) -> VkResult<DescriptorSet> {
+        for size in layout.descriptor_counts() {
+            *self.descriptor_totals.entry(size.ty.as_raw()).or_default() +=
+                size.descriptor_count as u64;
+        }
+        self.set_total += 1;
+
+        match self.current.as_ref().map(|pool| pool.allocate_one(layout)) {
+            Some(Ok(set)) => return Ok(set),
+            Some(Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL)) => {
+                self.used.push(self.current.take().unwrap());
+            }
+            Some(Err(err)) => return Err(err),
+            None => (),
+        }
+
+        while let Some(pool) = self.ready.pop() {
+            match pool.allocate_one(layout) {
+                Ok(set) => {
+                    self.current = Some(pool);
+                    return Ok(set);
+                }
+                // The pool is empty, so it was sized for other layouts. Destroy it.
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {
+                    drop(pool)
+                }
+                Err(err) => {
+                    self.ready.push(pool);
+                    return Err(err);
+                }
+            }
+        }
+
+        let pool = Arc::new(self.create_pool(layout)?);
+        let set = pool.allocate_one(layout)?;
+        self.current = Some(pool);
+        Ok(set)
+    }
+
+    fn create_pool(&mut self, layout: &DescriptorSetLayout) -> VkResult<DescriptorPool> {
+        let pool_sizes = pool_sizes(
+            &self.descriptor_totals,
+            self.set_total,
+            self.sets_per_pool,
+            layout.descriptor_counts(),
+        );
+        let pool = DescriptorPool::new(
+            self.device.clone(),
+            &vk::DescriptorPoolCreateInfo {
+                flags: self.flags,
+                max_sets: self.sets_per_pool,
+                pool_size_count: pool_sizes.len() as u32,
+                p_pool_sizes: pool_sizes.as_ptr(),
+                ..Default::default()
+            },
+        )?;
+        self.sets_per_pool = (self.sets_per_pool * 2).min(MAX_SETS_PER_POOL);
+        Ok(pool)
+    }
+
+    /// Reset the pools whose descriptor sets were all dropped, so that they can be allocated from again.
+    /// Pools with live descriptor sets are kept aside until a later reset.
+    pub fn reset(&mut self) -> VkResult<()> {
+        self.used.extend(self.current.take());
+        let used = std::mem::take(&mut self.used);
+        for mut pool in used {
+            if let Some(raw_pool) = Arc::get_mut(&mut pool) {
+                raw_pool.reset()?;
+                self.ready.push(pool);
+            } else {
+                self.used.push(pool);
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of pools owned by the allocator.
+    pub fn num_pools(&self) -> usize {
+        self.current.iter().count() + self.used.len() + self.ready.len()
+    }
+}
+
+/// Pool sizes for `max_sets` sets, assuming that future sets look like the `set_total` sets observed so
+/// far. The pool can always hold at least one set with the descriptor counts of `layout`.
+fn pool_sizes(
+    descriptor_totals: &BTreeMap<i32, u64>,
+    set_total: u64,
+    max_sets: u32,
+    layout: &[vk::DescriptorPoolSize],
+) -> Vec<vk::DescriptorPoolSize> {
+    let mut sizes: BTreeMap<i32, u32> = descriptor_totals
+        .iter()
+        .map(|(&ty, &total)| {
+            let count = (total * max_sets as u64).div_ceil(set_total.max(1));
+            (ty, count.min(u32::MAX as u64) as u32)
+        })
+        .collect();
+    for size in layout {
+        let count = sizes.entry(size.ty.as_raw()).or_default();
+        *count = (*count).max(size.descriptor_count);
+    }
+    sizes
+        .into_iter()
+        .filter(|&(_, count)| count > 0)
+        .map(|(ty, descriptor_count)| vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::from_raw(ty),
+            descriptor_count,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pool_sizes;
+    use ash::vk;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn pool_sizes_follow_observed_usage() {
+        let mut totals = BTreeMap::new();
+        totals.insert(vk::DescriptorType::UNIFORM_BUFFER.as_raw(), 3);
+        totals.insert(vk::DescriptorType::COMBINED_IMAGE_SAMPLER.as_raw(), 8);
+
+        // 4 sets with 3 uniform buffers and 8 samplers in total, scaled to 16 sets.
+        let sizes = pool_sizes(&totals, 4, 16, &[]);
+        assert_eq!(sizes.len(), 2);
+        assert_eq!(sizes[0].ty, vk::DescriptorType::COMBINED_IMAGE_SAMPLER);
+        assert_eq!(sizes[0].descriptor_count, 32);
+        assert_eq!(sizes[1].ty, vk::DescriptorType::UNIFORM_BUFFER);
+        assert_eq!(sizes[1].descriptor_count, 12);
+
+        // The layout being allocated always fits.
+        let sizes = pool_sizes(
+            &totals,
+            4,
+            1,
+            &[
+                vk::DescriptorPoolSize {
+                    ty: vk::DescriptorType::UNIFORM_BUFFER,
+                    descriptor_count: 5,
+                },
+                vk::DescriptorPoolSize {
+                    ty: vk::DescriptorType::STORAGE_IMAGE,
+                    descriptor_count: 2,
+                },
+            ],
+        );
+        let count = |ty: vk::DescriptorType| {
+            sizes
+                .iter()
+                .find(|size| size.ty == ty)
+                .map(|size| size.descriptor_count)
+        };
+        assert_eq!(count(vk::DescriptorType::UNIFORM_BUFFER), Some(5));
+        assert_eq!(count(vk::DescriptorType::STORAGE_IMAGE), Some(2));
+        assert_eq!(count(vk::DescriptorType::COMBINED_IMAGE_SAMPLER), Some(2));
+    }
+
+    #[test]
+    fn pool_sizes_without_observations() {
+        assert!(pool_sizes(&BTreeMap::new(), 0, 16, &[]).is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+
+use ash::{prelude::VkResult, vk};
+
+use super::{DescriptorSet, DescriptorVec, DescriptorVecBinding, DescriptorVecBindingType};
+use crate::{
+    accel_struct::AccelerationStructure,
+    resources::{buffer::HasBuffer, image::HasImageView, sampler::Sampler},
+    Device,
+};
+
+/// The kind of descriptor a [`BindlessHandle`] refers to.
+pub trait BindlessSlot {
+    const TYPE: DescriptorVecBindingType;
+}
+
+macro_rules! bindless_slot {
+    ($name:ident, $ty:ident) => {
+        pub struct $name;
+        impl BindlessSlot for $name {
+            const TYPE: DescriptorVecBindingType = DescriptorVecBindingType::$ty;
+        }
+    };
+}
+bindless_slot!(SampledImageSlot, SampledImage);
+bindless_slot!(StorageImageSlot, StorageImage);
+bindless_slot!(UniformBufferSlot, UniformBuffer);
+bindless_slot!(StorageBufferSlot, StorageBuffer);
+bindless_slot!(CombinedImageSamplerSlot, CombinedImageSampler);
+bindless_slot!(AccelerationStructureSlot, AccelerationStructure);
+
+type Resource = Box<dyn Any + Send + Sync>;
+
+struct BindlessState {
+    vec: DescriptorVec,
+    frames_in_flight: u64,
+    /// Number of calls to [`BindlessTable::flush`] so far.
+    frame: u64,
+    pending_writes: Vec<(u32, DescriptorVecBinding)>,
+    /// Slots released by dropped handles, along with the frame they were released on. The resources are
+    /// kept alive until the slots are recycled.
+    released: VecDeque<(u64, DescriptorVecBindingType, u32, Resource)>,
+}
+
+impl BindlessState {
+    /// Release the slot of a dropped handle. A descriptor that wasn't written yet is never written, as
+    /// the slot may be recycled before the next flush.
+    fn release(&mut self, ty: DescriptorVecBindingType, index: u32, resource: Resource) {
+        self.pending_writes
+            .retain(|(pending_index, binding)| *pending_index != index || binding.ty() != ty);
+        self.released.push_back((self.frame, ty, index, resource));
+    }
+}
+
+/// A [`DescriptorVec`] handing out slots as [`BindlessHandle`]s.
+///
+/// Registering a resource reserves a slot immediately, but the descriptor is only written on the next call
+/// to [`BindlessTable::flush`]. Slots of dropped handles are recycled `frames_in_flight` flushes later, so
+/// that frames still in flight never see a slot change under them. All bindings are `UPDATE_AFTER_BIND`,
+/// so the descriptor set may stay bound while it is flushed.
+///
+/// The table doesn't grow: the bindings are created as large as the device allows for `UPDATE_AFTER_BIND`
+/// descriptors, so the layout never changes, and registering fails with `ERROR_OUT_OF_POOL_MEMORY` once a
+/// binding is full. Types whose `descriptorBinding*UpdateAfterBind` feature isn't enabled have no slots.
+pub struct BindlessTable {
+    state: Arc<Mutex<BindlessState>>,
+}
+
+impl BindlessTable {
+    /// `frames_in_flight` is the number of frames that may still be executing on the GPU when
+    /// [`BindlessTable::flush`] is called.
+    pub fn new(
+        device: Arc<Device>,
+        shader_stage_flags: vk::ShaderStageFlags,
+        frames_in_flight: u32,
+    ) -> VkResult<Self> {
+        let vec = DescriptorVec::new(device, shader_stage_flags)?;
+        Ok(Self {
+            state: Arc::new(Mutex::new(BindlessState {
+                vec,
+                frames_in_flight: frames_in_flight as u64,
+                frame: 0,
+                pending_writes: Vec::new(),
+                released: VecDeque::new(),
+            })),
+        })
+    }
+
+    /// Fails with `ERROR_OUT_OF_POOL_MEMORY` if all slots of the binding are taken.
+    fn register<S: BindlessSlot>(
+        &self,
+        binding: DescriptorVecBinding,
+        resource: Resource,
+    ) -> VkResult<BindlessHandle<S>> {
+        assert_eq!(binding.ty(), S::TYPE);
+        let mut state = self.state.lock().unwrap();
+        let index = state.vec.reserve(S::TYPE)?;
+        state.pending_writes.push((index, binding));
+        Ok(BindlessHandle {
+            state: self.state.clone(),
+            index,
+            resource: Some(resource),
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn register_sampled_image<V: HasImageView + Send + Sync + 'static>(
+        &self,
+        view: V,
+        image_layout: vk::ImageLayout,
+    ) -> VkResult<BindlessHandle<SampledImageSlot>> {
+        let binding = DescriptorVecBinding::SampledImage(vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: view.raw_image_view(),
+            image_layout,
+        });
+        self.register(binding, Box::new(view))
+    }
+
+    /// The image must be in the `GENERAL` layout when accessed.
+    pub fn register_storage_image<V: HasImageView + Send + Sync + 'static>(
+        &self,
+        view: V,
+    ) -> VkResult<BindlessHandle<StorageImageSlot>> {
+        let binding = DescriptorVecBinding::StorageImage(vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: view.raw_image_view(),
+            image_layout: vk::ImageLayout::GENERAL,
+        });
+        self.register(binding, Box::new(view))
+    }
+
+    pub fn register_combined_image_sampler<V: HasImageView + Send + Sync + 'static>(
+        &self,
+        view: V,
+        sampler: Arc<Sampler>,
+        image_layout: vk::ImageLayout,
+    ) -> VkResult<BindlessHandle<CombinedImageSamplerSlot>> {
+        let binding = DescriptorVecBinding::CombinedImageSampler(vk::DescriptorImageInfo {
+            sampler: sampler.raw(),
+            image_view: view.raw_image_view(),
+            image_layout,
+        });
+        self.register(binding, Box::new((view, sampler)))
+    }
+
+    /// `range` may be `vk::WHOLE_SIZE`.
+    pub fn register_uniform_buffer<B: HasBuffer>(
+        &self,
+        buffer: B,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> VkResult<BindlessHandle<UniformBufferSlot>> {
+        let binding = DescriptorVecBinding::UniformBuffer(vk::DescriptorBufferInfo {
+            buffer: buffer.raw_buffer(),
+            offset,
+            range,
+        });
+        self.register(binding, Box::new(buffer))
+    }
+
+    /// `range` may be `vk::WHOLE_SIZE`.
+    pub fn register_storage_buffer<B: HasBuffer>(
+        &self,
+        buffer: B,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> VkResult<BindlessHandle<StorageBufferSlot>> {
+        let binding = DescriptorVecBinding::StorageBuffer(vk::DescriptorBufferInfo {
+            buffer: buffer.raw_buffer(),
+            offset,
+            range,
+        });
+        self.register(binding, Box::new(buffer))
+    }
+
+    /// Requires the `VK_KHR_acceleration_structure` extension.
+    pub fn register_acceleration_structure(
+        &self,
+        acceleration_structure: Arc<AccelerationStructure>,
+    ) -> VkResult<BindlessHandle<AccelerationStructureSlot>> {
+        let binding = DescriptorVecBinding::AccelerationStructure(acceleration_structure.raw());
+        self.register(binding, Box::new(acceleration_structure))
+    }
+
+    /// Apply the deferred updates. Call this once per frame, after waiting for the oldest frame in flight
+    /// and before recording commands that use the table.
+    pub fn flush(&self) {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        state.frame += 1;
+
+        while let Some((frame, ..)) = state.released.front()
+            && frame + state.frames_in_flight <= state.frame
+        {
+            let (_, ty, index, resource) = state.released.pop_front().unwrap();
+            state.vec.free(ty, index);
+            drop(resource);
+        }
+        state.vec.write(state.pending_writes.drain(..));
+    }
+
+    pub fn raw(&self) -> vk::DescriptorSet {
+        self.state.lock().unwrap().vec.raw()
+    }
+    pub fn raw_layout(&self) -> vk::DescriptorSetLayout {
+        self.state.lock().unwrap().vec.raw_layout()
+    }
+    pub fn descriptor_set(&self) -> Arc<DescriptorSet> {
+        self.state.lock().unwrap().vec.descriptor_set().clone()
+    }
+}
+
+/// A slot in a [`BindlessTable`]. The slot is released when the handle is dropped, and the resource is
+/// kept alive until the slot is recycled.
+pub struct BindlessHandle<S: BindlessSlot> {
+    state: Arc<Mutex<BindlessState>>,
+    index: u32,
+    resource: Option<Resource>,
+    _marker: PhantomData<S>,
+}
+
+impl<S: BindlessSlot> BindlessHandle<S> {
+    /// The array element of the descriptor in its binding.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+    /// The binding of the descriptor in the descriptor set.
+    pub fn binding(&self) -> u32 {
+        S::TYPE as u32
+    }
+}
+
+impl<S: BindlessSlot> Drop for BindlessHandle<S> {
+    fn drop(&mut self) {
+        let resource = self.resource.take().unwrap();
+        self.state
+            .lock()
+            .unwrap()
+            .release(S::TYPE, self.index, resource);
+    }
+}
@@ -2,11 +2,14 @@ use std::sync::Arc;
 
 use ash::{prelude::VkResult, vk};
 
-use crate::Device;
+use crate::{resources::sampler::Sampler, Device, HasDevice};
 
 pub struct DescriptorSetLayout {
     device: Arc<Device>,
     pub(super) raw: vk::DescriptorSetLayout,
+    immutable_samplers: Vec<Arc<Sampler>>,
+    descriptor_counts: Vec<vk::DescriptorPoolSize>,
+    flags: vk::DescriptorSetLayoutCreateFlags,
 }
 impl DescriptorSetLayout {
     pub unsafe fn new(
@@ -14,12 +17,60 @@ impl DescriptorSetLayout {
         info: &vk::DescriptorSetLayoutCreateInfo,
     ) -> VkResult<Self> {
         let raw = device.create_descriptor_set_layout(info, None)?;
-        Ok(Self { device, raw })
+        let bindings: &[vk::DescriptorSetLayoutBinding] = if info.binding_count == 0 {
+            &[]
+        } else {
+            std::slice::from_raw_parts(info.p_bindings, info.binding_count as usize)
+        };
+        let mut descriptor_counts: Vec<vk::DescriptorPoolSize> = Vec::new();
+        for binding in bindings
+            .iter()
+            .filter(|binding| binding.descriptor_count > 0)
+        {
+            if let Some(size) = descriptor_counts
+                .iter_mut()
+                .find(|size| size.ty == binding.descriptor_type)
+            {
+                size.descriptor_count += binding.descriptor_count;
+            } else {
+                descriptor_counts.push(vk::DescriptorPoolSize {
+                    ty: binding.descriptor_type,
+                    descriptor_count: binding.descriptor_count,
+                });
+            }
+        }
+        Ok(Self {
+            device,
+            raw,
+            immutable_samplers: Vec::new(),
+            descriptor_counts,
+            flags: info.flags,
+        })
+    }
+    /// Keep the immutable samplers referenced by the layout alive as long as the layout.
+    pub fn with_immutable_samplers(mut self, samplers: Vec<Arc<Sampler>>) -> Self {
+        self.immutable_samplers = samplers;
+        self
+    }
+    pub fn immutable_samplers(&self) -> &[Arc<Sampler>] {
+        &self.immutable_samplers
+    }
+    /// Number of descriptors of each type needed to allocate one set with this layout.
+    pub fn descriptor_counts(&self) -> &[vk::DescriptorPoolSize] {
+        &self.descriptor_counts
+    }
+    pub fn flags(&self) -> vk::DescriptorSetLayoutCreateFlags {
+        self.flags
     }
     pub fn raw(&self) -> vk::DescriptorSetLayout {
         self.raw
     }
 }
+impl HasDevice for DescriptorSetLayout {
+    fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+}
 impl Drop for DescriptorSetLayout {
     fn drop(&mut self) {
         tracing::info!(device = ?self.raw, "destroy descriptor layout");
@@ -1,5 +1,9 @@
+mod allocator;
+mod bindless;
 mod layout;
 mod pool;
+mod push;
+mod template;
 mod vec;
 
 use std::sync::Arc;
@@ -8,9 +12,17 @@ use crate::HasDevice;
 
 use ash::vk;
 
+pub use allocator::DescriptorAllocator;
+pub use bindless::{
+    AccelerationStructureSlot, BindlessHandle, BindlessSlot, BindlessTable,
+    CombinedImageSamplerSlot, SampledImageSlot, StorageBufferSlot, StorageImageSlot,
+    UniformBufferSlot,
+};
 pub use layout::DescriptorSetLayout;
 pub use pool::DescriptorPool;
-pub use vec::{DescriptorVec, DescriptorVecBinding};
+pub use push::{PushDescriptorLoader, PushDescriptorWrites};
+pub use template::{DescriptorData, DescriptorUpdateTemplate};
+pub use vec::{DescriptorVec, DescriptorVecBinding, DescriptorVecBindingType};
 
 pub struct DescriptorSet {
     raw: vk::DescriptorSet,
@@ -20,6 +32,10 @@ impl DescriptorSet {
     pub fn raw(&self) -> vk::DescriptorSet {
         self.raw
     }
+    /// The pool the set was allocated from.
+    pub fn pool(&self) -> &Arc<DescriptorPool> {
+        &self.pool
+    }
 }
 impl Drop for DescriptorSet {
     fn drop(&mut self) {
@@ -30,6 +30,9 @@ impl DescriptorPool {
             .flags
             .contains(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET);
         let raw = unsafe { device.create_descriptor_pool(info, None)? };
+        device
+            .resource_registry()
+            .register(vk::ObjectType::DESCRIPTOR_POOL, raw, None);
         Ok(DescriptorPool {
             device,
             raw: Mutex::new(DescriptorPoolInner(raw)),
@@ -108,11 +111,23 @@ impl DescriptorPool {
             self.device.free_descriptor_sets(raw.0, &sets)
         }
     }
+    /// Return all descriptor sets to the pool. Taking `&mut self` ensures that no [`DescriptorSet`]
+    /// allocated from the pool is still alive.
+    pub fn reset(&mut self) -> VkResult<()> {
+        let raw = self.raw.get_mut().unwrap();
+        unsafe {
+            self.device
+                .reset_descriptor_pool(raw.0, vk::DescriptorPoolResetFlags::empty())
+        }
+    }
 }
 impl Drop for DescriptorPool {
     fn drop(&mut self) {
         let raw = self.raw.get_mut().unwrap();
         tracing::info!(device = ?raw.0, "drop descriptor pool");
+        self.device
+            .resource_registry()
+            .unregister(vk::ObjectType::DESCRIPTOR_POOL, raw.0);
         unsafe {
             self.device.destroy_descriptor_pool(raw.0, None);
         }
@@ -0,0 +1,472 @@
+use std::{ffi::c_void, mem::size_of, ops::Deref, sync::Arc};
+
+use ash::{extensions::khr, vk};
+
+use super::{template::template_entries, DescriptorData, DescriptorUpdateTemplate};
+use crate::{
+    accel_struct::AccelerationStructure,
+    command::recorder::{CommandBufferResource, CommandRecorder, ReferencedResource},
+    pipeline::PipelineLayout,
+    resources::{
+        buffer::{HasBuffer, HasBufferView},
+        image::HasImageView,
+        sampler::Sampler,
+    },
+    Device, HasDevice,
+};
+
+/// Function pointers of VK_KHR_push_descriptor. The extension must be enabled on the device.
+pub struct PushDescriptorLoader {
+    device: Arc<Device>,
+    loader: khr::PushDescriptor,
+}
+
+impl HasDevice for PushDescriptorLoader {
+    fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+}
+
+impl PushDescriptorLoader {
+    pub fn new(device: Arc<Device>) -> Self {
+        let loader = khr::PushDescriptor::new(device.instance(), &device);
+        Self { device, loader }
+    }
+}
+impl Deref for PushDescriptorLoader {
+    type Target = khr::PushDescriptor;
+
+    fn deref(&self) -> &Self::Target {
+        &self.loader
+    }
+}
+
+enum DescriptorInfo {
+    Image(vk::DescriptorImageInfo),
+    Buffer(vk::DescriptorBufferInfo),
+    TexelBufferView(vk::BufferView),
+    AccelerationStructure(vk::AccelerationStructureKHR),
+}
+
+struct PushDescriptorWrite {
+    binding: u32,
+    array_element: u32,
+    ty: vk::DescriptorType,
+    info: DescriptorInfo,
+}
+
+/// Descriptors for [`CommandRecorder::push_descriptor_set`], along with the resources they reference.
+#[derive(Default)]
+pub struct PushDescriptorWrites {
+    writes: Vec<PushDescriptorWrite>,
+    resources: Vec<ReferencedResource>,
+}
+
+impl PushDescriptorWrites {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn push(
+        &mut self,
+        binding: u32,
+        array_element: u32,
+        ty: vk::DescriptorType,
+        info: DescriptorInfo,
+        resource: ReferencedResource,
+    ) -> &mut Self {
+        self.writes.push(PushDescriptorWrite {
+            binding,
+            array_element,
+            ty,
+            info,
+        });
+        if !matches!(resource, ReferencedResource::Untracked) {
+            self.resources.push(resource);
+        }
+        self
+    }
+
+    /// `ty` is `UNIFORM_BUFFER` or `STORAGE_BUFFER`. `range` may be `vk::WHOLE_SIZE`.
+    pub fn buffer<B: HasBuffer + CommandBufferResource>(
+        &mut self,
+        binding: u32,
+        array_element: u32,
+        ty: vk::DescriptorType,
+        buffer: B,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> &mut Self {
+        assert!(
+            ty == vk::DescriptorType::UNIFORM_BUFFER || ty == vk::DescriptorType::STORAGE_BUFFER,
+            "{:?} is not a buffer descriptor type",
+            ty
+        );
+        let info = DescriptorInfo::Buffer(vk::DescriptorBufferInfo {
+            buffer: buffer.raw_buffer(),
+            offset,
+            range,
+        });
+        self.push(
+            binding,
+            array_element,
+            ty,
+            info,
+            buffer.command_buffer_resource(),
+        )
+    }
+
+    /// `ty` is `UNIFORM_TEXEL_BUFFER` or `STORAGE_TEXEL_BUFFER`.
+    pub fn texel_buffer<V: HasBufferView + CommandBufferResource>(
+        &mut self,
+        binding: u32,
+        array_element: u32,
+        ty: vk::DescriptorType,
+        view: V,
+    ) -> &mut Self {
+        assert!(
+            ty == vk::DescriptorType::UNIFORM_TEXEL_BUFFER
+                || ty == vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+            "{:?} is not a texel buffer descriptor type",
+            ty
+        );
+        let info = DescriptorInfo::TexelBufferView(view.raw_buffer_view());
+        self.push(
+            binding,
+            array_element,
+            ty,
+            info,
+            view.command_buffer_resource(),
+        )
+    }
+
+    /// `ty` is `SAMPLED_IMAGE`, `STORAGE_IMAGE` or `INPUT_ATTACHMENT`.
+    pub fn image<V: HasImageView + CommandBufferResource>(
+        &mut self,
+        binding: u32,
+        array_element: u32,
+        ty: vk::DescriptorType,
+        view: V,
+        image_layout: vk::ImageLayout,
+    ) -> &mut Self {
+        assert!(
+            ty == vk::DescriptorType::SAMPLED_IMAGE
+                || ty == vk::DescriptorType::STORAGE_IMAGE
+                || ty == vk::DescriptorType::INPUT_ATTACHMENT,
+            "{:?} is not an image descriptor type",
+            ty
+        );
+        let info = DescriptorInfo::Image(vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: view.raw_image_view(),
+            image_layout,
+        });
+        self.push(
+            binding,
+            array_element,
+            ty,
+            info,
+            view.command_buffer_resource(),
+        )
+    }
+
+    pub fn combined_image_sampler<V: HasImageView + CommandBufferResource>(
+        &mut self,
+        binding: u32,
+        array_element: u32,
+        view: V,
+        sampler: Arc<Sampler>,
+        image_layout: vk::ImageLayout,
+    ) -> &mut Self {
+        let info = DescriptorInfo::Image(vk::DescriptorImageInfo {
+            sampler: sampler.raw(),
+            image_view: view.raw_image_view(),
+            image_layout,
+        });
+        self.resources.push(sampler.command_buffer_resource());
+        self.push(
+            binding,
+            array_element,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            info,
+            view.command_buffer_resource(),
+        )
+    }
+
+    pub fn sampler(
+        &mut self,
+        binding: u32,
+        array_element: u32,
+        sampler: Arc<Sampler>,
+    ) -> &mut Self {
+        let info = DescriptorInfo::Image(vk::DescriptorImageInfo {
+            sampler: sampler.raw(),
+            image_view: vk::ImageView::null(),
+            image_layout: vk::ImageLayout::UNDEFINED,
+        });
+        self.push(
+            binding,
+            array_element,
+            vk::DescriptorType::SAMPLER,
+            info,
+            sampler.command_buffer_resource(),
+        )
+    }
+
+    pub fn acceleration_structure(
+        &mut self,
+        binding: u32,
+        array_element: u32,
+        acceleration_structure: Arc<AccelerationStructure>,
+    ) -> &mut Self {
+        let info = DescriptorInfo::AccelerationStructure(acceleration_structure.raw());
+        self.push(
+            binding,
+            array_element,
+            vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            info,
+            acceleration_structure.command_buffer_resource(),
+        )
+    }
+}
+
+/// Template data laid out like `T`, with the descriptors of `writes` at the offsets of their binding and
+/// array element.
+///
+/// Panics if `writes` doesn't cover every descriptor of `T`: null descriptors are only valid with the
+/// `nullDescriptor` feature.
+fn template_data<T: DescriptorData>(writes: &[PushDescriptorWrite]) -> Vec<u64> {
+    let (entries, size) = template_entries(T::BINDINGS);
+    let mut data = vec![0_u64; size.div_ceil(size_of::<u64>())];
+    let mut written: Vec<Vec<bool>> = entries
+        .iter()
+        .map(|entry| vec![false; entry.descriptor_count as usize])
+        .collect();
+    for write in writes {
+        let (index, entry) = entries
+            .iter()
+            .enumerate()
+            .find(|(_, entry)| entry.dst_binding == write.binding)
+            .unwrap_or_else(|| panic!("Binding {} isn't part of the template", write.binding));
+        assert_eq!(
+            entry.descriptor_type, write.ty,
+            "Binding {} has a different type",
+            write.binding
+        );
+        assert!(
+            write.array_element < entry.descriptor_count,
+            "Array element {} is out of bounds for binding {}",
+            write.array_element,
+            write.binding
+        );
+        written[index][write.array_element as usize] = true;
+        let offset = entry.offset + write.array_element as usize * entry.stride;
+        unsafe {
+            let ptr = (data.as_mut_ptr() as *mut u8).add(offset);
+            match write.info {
+                DescriptorInfo::Image(info) => {
+                    ptr.cast::<vk::DescriptorImageInfo>().write_unaligned(info)
+                }
+                DescriptorInfo::Buffer(info) => {
+                    ptr.cast::<vk::DescriptorBufferInfo>().write_unaligned(info)
+                }
+                DescriptorInfo::TexelBufferView(view) => {
+                    ptr.cast::<vk::BufferView>().write_unaligned(view)
+                }
+                DescriptorInfo::AccelerationStructure(acceleration_structure) => ptr
+                    .cast::<vk::AccelerationStructureKHR>()
+                    .write_unaligned(acceleration_structure),
+            }
+        }
+    }
+    for (entry, written) in entries.iter().zip(written) {
+        if let Some(array_element) = written.iter().position(|&done| !done) {
+            panic!(
+                "Array element {} of binding {} is missing from the writes",
+                array_element, entry.dst_binding
+            );
+        }
+    }
+    data
+}
+
+fn assert_push_descriptor_set(pipeline_layout: &PipelineLayout, set: u32) {
+    let (_, layout) = pipeline_layout
+        .descriptor_sets
+        .get(set as usize)
+        .expect("Descriptor set index out of bounds");
+    assert!(
+        layout
+            .flags()
+            .contains(vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR),
+        "Descriptor set {} wasn't created for push descriptors",
+        set
+    );
+}
+
+impl<'a> CommandRecorder<'a> {
+    /// Push descriptors to set `set` of `pipeline_layout`, which must have been created with the
+    /// `PUSH_DESCRIPTOR_KHR` flag. The resources referenced by `writes` are kept alive along with the
+    /// command buffer.
+    pub fn push_descriptor_set(
+        &mut self,
+        loader: &PushDescriptorLoader,
+        bind_point: vk::PipelineBindPoint,
+        pipeline_layout: &PipelineLayout,
+        set: u32,
+        writes: PushDescriptorWrites,
+    ) -> &mut Self {
+        assert_push_descriptor_set(pipeline_layout, set);
+        let acceleration_structure_writes: Vec<vk::WriteDescriptorSetAccelerationStructureKHR> =
+            writes
+                .writes
+                .iter()
+                .filter_map(|write| match &write.info {
+                    DescriptorInfo::AccelerationStructure(acceleration_structure) => {
+                        Some(vk::WriteDescriptorSetAccelerationStructureKHR {
+                            acceleration_structure_count: 1,
+                            p_acceleration_structures: acceleration_structure,
+                            ..Default::default()
+                        })
+                    }
+                    _ => None,
+                })
+                .collect();
+        let mut acceleration_structure_indice: usize = 0;
+        let raw_writes: Vec<vk::WriteDescriptorSet> = writes
+            .writes
+            .iter()
+            .map(|write| {
+                let mut raw_write = vk::WriteDescriptorSet {
+                    dst_binding: write.binding,
+                    dst_array_element: write.array_element,
+                    descriptor_count: 1,
+                    descriptor_type: write.ty,
+                    ..Default::default()
+                };
+                match &write.info {
+                    DescriptorInfo::Image(info) => raw_write.p_image_info = info,
+                    DescriptorInfo::Buffer(info) => raw_write.p_buffer_info = info,
+                    DescriptorInfo::TexelBufferView(view) => raw_write.p_texel_buffer_view = view,
+                    DescriptorInfo::AccelerationStructure(_) => {
+                        raw_write.p_next = &acceleration_structure_writes
+                            [acceleration_structure_indice]
+                            as *const _ as *const c_void;
+                        acceleration_structure_indice += 1;
+                    }
+                }
+                raw_write
+            })
+            .collect();
+        unsafe {
+            loader.cmd_push_descriptor_set(
+                self.command_buffer,
+                bind_point,
+                pipeline_layout.raw(),
+                set,
+                &raw_writes,
+            );
+        }
+        for resource in writes.resources {
+            self.track_resource(resource);
+        }
+        self
+    }
+
+    /// Push `writes` with a template created by [`DescriptorUpdateTemplate::for_push_descriptor`]. The
+    /// writes are laid out as the `T` expected by the template, and must target its bindings. Every array
+    /// element of every binding of `T` must be written, as templates can't skip descriptors. The resources
+    /// referenced by `writes` are kept alive along with the command buffer.
+    pub fn push_descriptor_set_with_template<T: DescriptorData>(
+        &mut self,
+        loader: &PushDescriptorLoader,
+        template: &DescriptorUpdateTemplate<T>,
+        writes: PushDescriptorWrites,
+    ) -> &mut Self {
+        let (_, pipeline_layout, set) = template
+            .push_descriptor()
+            .expect("The template wasn't created for push descriptors");
+        let data = template_data::<T>(&writes.writes);
+        unsafe {
+            loader.cmd_push_descriptor_set_with_template(
+                self.command_buffer,
+                template.raw(),
+                pipeline_layout.raw(),
+                set,
+                data.as_ptr() as *const c_void,
+            );
+        }
+        for resource in writes.resources {
+            self.track_resource(resource);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{template_data, DescriptorInfo, PushDescriptorWrite};
+    use crate::descriptor::DescriptorData;
+    use ash::vk::{self, Handle};
+
+    #[repr(C)]
+    struct Lighting {
+        shadow_map: vk::DescriptorImageInfo,
+        lights: [vk::DescriptorBufferInfo; 2],
+    }
+    unsafe impl DescriptorData for Lighting {
+        const BINDINGS: &'static [(u32, vk::DescriptorType, u32)] = &[
+            (0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1),
+            (2, vk::DescriptorType::STORAGE_BUFFER, 2),
+        ];
+    }
+
+    fn buffer_write(array_element: u32, buffer: u64) -> PushDescriptorWrite {
+        PushDescriptorWrite {
+            binding: 2,
+            array_element,
+            ty: vk::DescriptorType::STORAGE_BUFFER,
+            info: DescriptorInfo::Buffer(vk::DescriptorBufferInfo {
+                buffer: vk::Buffer::from_raw(buffer),
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }),
+        }
+    }
+
+    fn image_write(image_view: u64) -> PushDescriptorWrite {
+        PushDescriptorWrite {
+            binding: 0,
+            array_element: 0,
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            info: DescriptorInfo::Image(vk::DescriptorImageInfo {
+                sampler: vk::Sampler::from_raw(1),
+                image_view: vk::ImageView::from_raw(image_view),
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }),
+        }
+    }
+
+    #[test]
+    fn template_data_matches_descriptor_data() {
+        let data =
+            template_data::<Lighting>(&[buffer_write(1, 7), image_write(5), buffer_write(0, 6)]);
+        assert!(data.len() * 8 >= std::mem::size_of::<Lighting>());
+        let lighting = unsafe { &*(data.as_ptr() as *const Lighting) };
+        assert_eq!(lighting.shadow_map.image_view, vk::ImageView::from_raw(5));
+        assert_eq!(lighting.lights[0].buffer, vk::Buffer::from_raw(6));
+        assert_eq!(lighting.lights[1].buffer, vk::Buffer::from_raw(7));
+        assert_eq!(lighting.lights[1].range, vk::WHOLE_SIZE);
+    }
+
+    #[test]
+    #[should_panic(expected = "Array element 0 of binding 2 is missing")]
+    fn template_data_requires_every_descriptor() {
+        template_data::<Lighting>(&[buffer_write(1, 7), image_write(5)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn template_data_checks_array_elements() {
+        template_data::<Lighting>(&[buffer_write(2, 7)]);
+    }
+}
@@ -0,0 +1,330 @@
+use std::{
+    ffi::c_void,
+    marker::PhantomData,
+    mem::{align_of, size_of},
+    sync::Arc,
+};
+
+use ash::{prelude::VkResult, vk};
+
+use super::{DescriptorSet, DescriptorSetLayout};
+use crate::{
+    pipeline::{
+        layout_cache::{DescriptorSetLayoutCreateInfo, PipelineLayoutCache},
+        Binding, PipelineLayout,
+    },
+    Device, HasDevice,
+};
+
+/// A plain struct holding the descriptors written by a [`DescriptorUpdateTemplate`].
+///
+/// ```ignore
+/// #[repr(C)]
+/// struct MaterialDescriptors {
+///     albedo: vk::DescriptorImageInfo,
+///     lights: [vk::DescriptorBufferInfo; 4],
+/// }
+/// unsafe impl DescriptorData for MaterialDescriptors {
+///     const BINDINGS: &'static [(u32, vk::DescriptorType, u32)] = &[
+///         (0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1),
+///         (1, vk::DescriptorType::UNIFORM_BUFFER, 4),
+///     ];
+/// }
+/// ```
+///
+/// # Safety
+/// `Self` must be `#[repr(C)]` with one field per entry of `BINDINGS`, in the same order. The field for
+/// `count` descriptors of a given type is a `[I; count]` or, when `count` is 1, a plain `I`, where `I` is:
+/// - `vk::DescriptorImageInfo` for samplers, images and input attachments
+/// - `vk::DescriptorBufferInfo` for uniform and storage buffers
+/// - `vk::BufferView` for texel buffers
+/// - `vk::AccelerationStructureKHR` for acceleration structures
+/// - `u8` for inline uniform blocks, where `count` is the size in bytes
+pub unsafe trait DescriptorData: 'static {
+    /// Binding index, descriptor type and descriptor count of each field.
+    const BINDINGS: &'static [(u32, vk::DescriptorType, u32)];
+}
+
+/// Size and alignment of the data for one descriptor of type `ty`.
+fn descriptor_info_layout(ty: vk::DescriptorType) -> (usize, usize) {
+    match ty {
+        vk::DescriptorType::SAMPLER
+        | vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+        | vk::DescriptorType::SAMPLED_IMAGE
+        | vk::DescriptorType::STORAGE_IMAGE
+        | vk::DescriptorType::INPUT_ATTACHMENT => (
+            size_of::<vk::DescriptorImageInfo>(),
+            align_of::<vk::DescriptorImageInfo>(),
+        ),
+        vk::DescriptorType::UNIFORM_BUFFER
+        | vk::DescriptorType::STORAGE_BUFFER
+        | vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
+        | vk::DescriptorType::STORAGE_BUFFER_DYNAMIC => (
+            size_of::<vk::DescriptorBufferInfo>(),
+            align_of::<vk::DescriptorBufferInfo>(),
+        ),
+        vk::DescriptorType::UNIFORM_TEXEL_BUFFER | vk::DescriptorType::STORAGE_TEXEL_BUFFER => {
+            (size_of::<vk::BufferView>(), align_of::<vk::BufferView>())
+        }
+        vk::DescriptorType::ACCELERATION_STRUCTURE_KHR => (
+            size_of::<vk::AccelerationStructureKHR>(),
+            align_of::<vk::AccelerationStructureKHR>(),
+        ),
+        vk::DescriptorType::INLINE_UNIFORM_BLOCK => (1, 1),
+        _ => panic!("Descriptor type {:?} is not supported in templates", ty),
+    }
+}
+
+/// Template entries for a `#[repr(C)]` struct laid out as described by [`DescriptorData`], and the size of
+/// that struct.
+pub(super) fn template_entries(
+    bindings: &[(u32, vk::DescriptorType, u32)],
+) -> (Vec<vk::DescriptorUpdateTemplateEntry>, usize) {
+    let mut offset: usize = 0;
+    let mut struct_align: usize = 1;
+    let entries = bindings
+        .iter()
+        .map(|&(binding, ty, count)| {
+            let (size, align) = descriptor_info_layout(ty);
+            offset = offset.next_multiple_of(align);
+            struct_align = struct_align.max(align);
+            let entry = vk::DescriptorUpdateTemplateEntry {
+                dst_binding: binding,
+                dst_array_element: 0,
+                descriptor_count: count,
+                descriptor_type: ty,
+                offset,
+                stride: size,
+            };
+            offset += size * count as usize;
+            entry
+        })
+        .collect();
+    (entries, offset.next_multiple_of(struct_align))
+}
+
+/// Update a descriptor set from a [`DescriptorData`] struct in a single call.
+pub struct DescriptorUpdateTemplate<T: DescriptorData> {
+    device: Arc<Device>,
+    raw: vk::DescriptorUpdateTemplate,
+    layout: Arc<DescriptorSetLayout>,
+    /// Bind point, pipeline layout and set index of templates created for push descriptors.
+    push_descriptor: Option<(vk::PipelineBindPoint, Arc<PipelineLayout>, u32)>,
+    _marker: PhantomData<fn(&T)>,
+}
+
+impl<T: DescriptorData> DescriptorUpdateTemplate<T> {
+    /// The bindings of `T` must exist in `layout`. Use [`DescriptorUpdateTemplate::from_layout_info`] or
+    /// [`DescriptorUpdateTemplate::from_pipeline_layout`] to have them checked.
+    pub fn new(layout: Arc<DescriptorSetLayout>) -> VkResult<Self> {
+        Self::create(layout, None)
+    }
+
+    /// Create a template for [`crate::command::recorder::CommandRecorder::push_descriptor_set_with_template`].
+    /// Set `set` of `pipeline_layout` must have been created with the `PUSH_DESCRIPTOR_KHR` flag.
+    /// Panics if the bindings of `T` don't match the ones of the set.
+    pub fn for_push_descriptor(
+        pipeline_layout: &Arc<PipelineLayout>,
+        bind_point: vk::PipelineBindPoint,
+        set: u32,
+    ) -> VkResult<Self> {
+        let (bindings, layout) = &pipeline_layout.descriptor_sets[set as usize];
+        assert!(
+            layout
+                .flags()
+                .contains(vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR),
+            "Descriptor set {} wasn't created for push descriptors",
+            set
+        );
+        check_bindings::<T>(bindings.iter().map(|(index, binding)| (*index, binding)));
+        Self::create(
+            layout.clone(),
+            Some((bind_point, pipeline_layout.clone(), set)),
+        )
+    }
+
+    fn create(
+        layout: Arc<DescriptorSetLayout>,
+        push_descriptor: Option<(vk::PipelineBindPoint, Arc<PipelineLayout>, u32)>,
+    ) -> VkResult<Self> {
+        let (entries, size) = template_entries(T::BINDINGS);
+        assert_eq!(
+            size,
+            size_of::<T>(),
+            "The size of the descriptor data doesn't match its bindings"
+        );
+        let device = layout.device().clone();
+        let raw = unsafe {
+            device.create_descriptor_update_template(
+                &vk::DescriptorUpdateTemplateCreateInfo {
+                    descriptor_update_entry_count: entries.len() as u32,
+                    p_descriptor_update_entries: entries.as_ptr(),
+                    template_type: if push_descriptor.is_some() {
+                        vk::DescriptorUpdateTemplateType::PUSH_DESCRIPTORS_KHR
+                    } else {
+                        vk::DescriptorUpdateTemplateType::DESCRIPTOR_SET
+                    },
+                    descriptor_set_layout: layout.raw(),
+                    pipeline_bind_point: push_descriptor
+                        .as_ref()
+                        .map_or(vk::PipelineBindPoint::default(), |(bind_point, ..)| {
+                            *bind_point
+                        }),
+                    pipeline_layout: push_descriptor
+                        .as_ref()
+                        .map_or(vk::PipelineLayout::null(), |(_, pipeline_layout, _)| {
+                            pipeline_layout.raw()
+                        }),
+                    set: push_descriptor.as_ref().map_or(0, |(.., set)| *set),
+                    ..Default::default()
+                },
+                None,
+            )?
+        };
+        Ok(Self {
+            device,
+            raw,
+            layout,
+            push_descriptor,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Create the template along with its descriptor set layout. Panics if the bindings of `T` don't match `info`.
+    pub fn from_layout_info(
+        cache: &mut PipelineLayoutCache,
+        info: DescriptorSetLayoutCreateInfo,
+    ) -> VkResult<Self> {
+        check_bindings::<T>(
+            info.bindings
+                .iter()
+                .map(|(index, binding)| (*index, binding)),
+        );
+        let layout = cache.create_descriptor_set_layout(info).clone();
+        Self::new(layout)
+    }
+
+    /// Create the template for descriptor set `set` of a pipeline layout, whose bindings usually come from
+    /// shader reflection. Panics if the bindings of `T` don't match the reflected ones.
+    pub fn from_pipeline_layout(pipeline_layout: &PipelineLayout, set: u32) -> VkResult<Self> {
+        let (bindings, layout) = &pipeline_layout.descriptor_sets[set as usize];
+        check_bindings::<T>(bindings.iter().map(|(index, binding)| (*index, binding)));
+        Self::new(layout.clone())
+    }
+
+    /// Write `data` into `set`, which must have been allocated with the layout of this template.
+    pub fn update(&self, set: &mut DescriptorSet, data: &T) {
+        assert!(
+            self.push_descriptor.is_none(),
+            "Templates for push descriptors can't update descriptor sets"
+        );
+        unsafe {
+            self.device.update_descriptor_set_with_template(
+                set.raw(),
+                self.raw,
+                data as *const T as *const c_void,
+            );
+        }
+    }
+
+    /// Bind point, pipeline layout and set index, if the template was created for push descriptors.
+    pub fn push_descriptor(&self) -> Option<(vk::PipelineBindPoint, &Arc<PipelineLayout>, u32)> {
+        self.push_descriptor
+            .as_ref()
+            .map(|(bind_point, pipeline_layout, set)| (*bind_point, pipeline_layout, *set))
+    }
+    pub fn layout(&self) -> &Arc<DescriptorSetLayout> {
+        &self.layout
+    }
+    pub fn raw(&self) -> vk::DescriptorUpdateTemplate {
+        self.raw
+    }
+}
+
+fn check_bindings<'a, T: DescriptorData>(bindings: impl Iterator<Item = (u32, &'a Binding)>) {
+    let bindings: Vec<(u32, &Binding)> = bindings.collect();
+    for &(index, ty, count) in T::BINDINGS {
+        let (_, binding) = bindings
+            .iter()
+            .find(|(binding_index, _)| *binding_index == index)
+            .unwrap_or_else(|| panic!("Binding {} is missing from the layout", index));
+        assert_eq!(binding.ty, ty, "Binding {} has a different type", index);
+        assert!(
+            count <= binding.count,
+            "Binding {} has fewer than {} descriptors",
+            index,
+            count
+        );
+    }
+}
+
+impl<T: DescriptorData> HasDevice for DescriptorUpdateTemplate<T> {
+    fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+}
+
+impl<T: DescriptorData> crate::debug::DebugObject for DescriptorUpdateTemplate<T> {
+    const OBJECT_TYPE: vk::ObjectType = vk::ObjectType::DESCRIPTOR_UPDATE_TEMPLATE;
+    fn object_handle(&mut self) -> u64 {
+        unsafe { std::mem::transmute(self.raw) }
+    }
+}
+
+impl<T: DescriptorData> Drop for DescriptorUpdateTemplate<T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .destroy_descriptor_update_template(self.raw, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{template_entries, DescriptorData};
+    use ash::vk;
+    use std::mem::size_of;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Material {
+        albedo: vk::DescriptorImageInfo,
+        params: [u8; 4],
+        lights: [vk::DescriptorBufferInfo; 2],
+        texels: vk::BufferView,
+        scene: vk::AccelerationStructureKHR,
+    }
+    unsafe impl DescriptorData for Material {
+        const BINDINGS: &'static [(u32, vk::DescriptorType, u32)] = &[
+            (0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1),
+            (1, vk::DescriptorType::INLINE_UNIFORM_BLOCK, 4),
+            (2, vk::DescriptorType::STORAGE_BUFFER, 2),
+            (3, vk::DescriptorType::UNIFORM_TEXEL_BUFFER, 1),
+            (5, vk::DescriptorType::ACCELERATION_STRUCTURE_KHR, 1),
+        ];
+    }
+
+    #[test]
+    fn entries_match_repr_c_layout() {
+        let material = Material::default();
+        let base = &material as *const Material as usize;
+        let offsets = [
+            &material.albedo as *const _ as usize - base,
+            &material.params as *const _ as usize - base,
+            &material.lights as *const _ as usize - base,
+            &material.texels as *const _ as usize - base,
+            &material.scene as *const _ as usize - base,
+        ];
+
+        let (entries, size) = template_entries(Material::BINDINGS);
+        assert_eq!(size, size_of::<Material>());
+        assert_eq!(entries.len(), offsets.len());
+        for (entry, offset) in entries.iter().zip(offsets) {
+            assert_eq!(entry.offset, offset);
+        }
+        assert_eq!(entries[2].dst_binding, 2);
+        assert_eq!(entries[2].descriptor_count, 2);
+        assert_eq!(entries[2].stride, size_of::<vk::DescriptorBufferInfo>());
+    }
+}
@@ -1,20 +1,32 @@
-use std::{ffi::c_void, mem::MaybeUninit, sync::Arc};
+use std::{ffi::c_void, sync::Arc};
 
-use crate::{Device, HasDevice};
+use crate::{Device, HasDevice, PhysicalDeviceFeatures, PhysicalDeviceProperties};
 
 use super::{DescriptorPool, DescriptorSet, DescriptorSetLayout};
 use ash::{prelude::VkResult, vk};
-/// Dynamically resized descriptor pool, useful for bindless
+/// Number of bindings in a DescriptorVec, one per [`DescriptorVecBinding`] type.
+const NUM_BINDING_TYPES: usize = 6;
+/// Upper bound on the number of descriptors of each binding, whatever the device limits.
+const MAX_DESCRIPTORS_PER_BINDING: u32 = 1 << 16;
+
+/// Descriptor set with one partially bound binding per descriptor type, useful for bindless.
+///
+/// [`DescriptorVec::new`] sizes the bindings from the device limits, so the layout never has to change and
+/// pipeline layouts created with [`DescriptorVec::raw_layout`] stay valid. Slots are handed out until a
+/// binding is full, after which [`DescriptorVec::reserve`] and [`DescriptorVec::extend`] fail with
+/// `ERROR_OUT_OF_POOL_MEMORY` instead of growing the set. Sets created with smaller capacities by
+/// [`DescriptorVec::with_capacity`] can be grown explicitly with [`DescriptorVec::realloc`], which replaces
+/// the layout.
 pub struct DescriptorVec {
-    /// self.capacity[binding_type] = number of descriptors currently allocated for that type
-    capacity: [u32; 4],
-    /// Total number of descriptors currently allocated.
-    sizes: [u32; 4],
+    /// self.capacity[binding_type] = number of descriptors in the binding of that type
+    capacity: [u32; NUM_BINDING_TYPES],
+    /// Number of descriptors currently in use.
+    sizes: [u32; NUM_BINDING_TYPES],
 
     /// descriptors at the following locations are considered free
-    freelists: [Vec<u32>; 4],
+    freelists: [Vec<u32>; NUM_BINDING_TYPES],
     /// All descriptors after these indices are considered free
-    tail: [u32; 4],
+    tail: [u32; NUM_BINDING_TYPES],
 
     pool: Arc<DescriptorPool>,
     layout: DescriptorSetLayout,
@@ -22,12 +34,14 @@ pub struct DescriptorVec {
     shader_stage_flags: vk::ShaderStageFlags,
 }
 
-#[derive(Clone, Copy)]
-enum DescriptorVecBindingType {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DescriptorVecBindingType {
     SampledImage = 0,
     StorageImage,
     UniformBuffer,
     StorageBuffer,
+    CombinedImageSampler,
+    AccelerationStructure,
 }
 
 pub enum DescriptorVecBinding {
@@ -35,14 +49,20 @@ pub enum DescriptorVecBinding {
     StorageImage(vk::DescriptorImageInfo),
     UniformBuffer(vk::DescriptorBufferInfo),
     StorageBuffer(vk::DescriptorBufferInfo),
+    /// The sampler in the image info must be kept alive by the application while the descriptor is in use.
+    CombinedImageSampler(vk::DescriptorImageInfo),
+    /// Requires the `VK_KHR_acceleration_structure` extension.
+    AccelerationStructure(vk::AccelerationStructureKHR),
 }
 impl DescriptorVecBinding {
-    fn ty(&self) -> DescriptorVecBindingType {
+    pub fn ty(&self) -> DescriptorVecBindingType {
         match self {
             Self::SampledImage(_) => DescriptorVecBindingType::SampledImage,
             Self::StorageImage(_) => DescriptorVecBindingType::StorageImage,
             Self::UniformBuffer(_) => DescriptorVecBindingType::UniformBuffer,
             Self::StorageBuffer(_) => DescriptorVecBindingType::StorageBuffer,
+            Self::CombinedImageSampler(_) => DescriptorVecBindingType::CombinedImageSampler,
+            Self::AccelerationStructure(_) => DescriptorVecBindingType::AccelerationStructure,
         }
     }
 }
@@ -56,6 +76,8 @@ impl TryFrom<u32> for DescriptorVecBindingType {
             1 => Ok(Self::StorageImage),
             2 => Ok(Self::UniformBuffer),
             3 => Ok(Self::StorageBuffer),
+            4 => Ok(Self::CombinedImageSampler),
+            5 => Ok(Self::AccelerationStructure),
             _ => Err(()),
         }
     }
@@ -67,34 +89,44 @@ impl From<DescriptorVecBindingType> for vk::DescriptorType {
             DescriptorVecBindingType::StorageImage => vk::DescriptorType::STORAGE_IMAGE,
             DescriptorVecBindingType::UniformBuffer => vk::DescriptorType::UNIFORM_BUFFER,
             DescriptorVecBindingType::StorageBuffer => vk::DescriptorType::STORAGE_BUFFER,
+            DescriptorVecBindingType::CombinedImageSampler => {
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+            }
+            DescriptorVecBindingType::AccelerationStructure => {
+                vk::DescriptorType::ACCELERATION_STRUCTURE_KHR
+            }
         }
     }
 }
 
 impl DescriptorVec {
+    /// Bindings with a count of zero are left out of the layout. All other bindings are `UPDATE_AFTER_BIND`,
+    /// which requires the matching `descriptorBinding*UpdateAfterBind` feature.
     pub fn create_layout(
         device: Arc<Device>,
-        binding_counts: &[u32; 4],
+        binding_counts: &[u32; NUM_BINDING_TYPES],
         shader_stage_flags: vk::ShaderStageFlags,
     ) -> VkResult<DescriptorSetLayout> {
-        let mut bindings: [MaybeUninit<vk::DescriptorSetLayoutBinding>; 4] =
-            MaybeUninit::uninit_array();
-        for (i, item) in bindings.iter_mut().enumerate() {
-            item.write(vk::DescriptorSetLayoutBinding {
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = binding_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(i, &count)| vk::DescriptorSetLayoutBinding {
                 binding: i as u32,
                 descriptor_type: DescriptorVecBindingType::try_from(i as u32).unwrap().into(),
-                descriptor_count: binding_counts[i],
+                descriptor_count: count,
                 stage_flags: shader_stage_flags,
                 p_immutable_samplers: std::ptr::null(),
-            });
-        }
-        let bindings: [vk::DescriptorSetLayoutBinding; 4] =
-            unsafe { std::mem::transmute(bindings) };
+            })
+            .collect();
 
-        let flags = [vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
-            | vk::DescriptorBindingFlags::PARTIALLY_BOUND; 4];
+        let flags = vec![
+            vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+                | vk::DescriptorBindingFlags::PARTIALLY_BOUND;
+            bindings.len()
+        ];
         let flags = vk::DescriptorSetLayoutBindingFlagsCreateInfo {
-            binding_count: binding_counts.len() as u32,
+            binding_count: flags.len() as u32,
             p_binding_flags: flags.as_ptr(),
             ..Default::default()
         };
@@ -104,22 +136,26 @@ impl DescriptorVec {
                 &vk::DescriptorSetLayoutCreateInfo {
                     p_next: &flags as *const _ as *const c_void,
                     flags: vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
-                    binding_count: binding_counts.len() as u32,
+                    binding_count: bindings.len() as u32,
                     p_bindings: bindings.as_ptr(),
                     ..Default::default()
                 },
             )
         }
     }
-    pub fn create_pool(device: Arc<Device>, binding_counts: &[u32; 4]) -> VkResult<DescriptorPool> {
-        let mut pool_sizes: [MaybeUninit<vk::DescriptorPoolSize>; 4] = MaybeUninit::uninit_array();
-        for (i, item) in pool_sizes.iter_mut().enumerate() {
-            item.write(vk::DescriptorPoolSize {
+    pub fn create_pool(
+        device: Arc<Device>,
+        binding_counts: &[u32; NUM_BINDING_TYPES],
+    ) -> VkResult<DescriptorPool> {
+        let pool_sizes: Vec<vk::DescriptorPoolSize> = binding_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(i, &count)| vk::DescriptorPoolSize {
                 ty: DescriptorVecBindingType::try_from(i as u32).unwrap().into(),
-                descriptor_count: binding_counts[i],
-            });
-        }
-        let pool_sizes: [vk::DescriptorPoolSize; 4] = unsafe { std::mem::transmute(pool_sizes) };
+                descriptor_count: count,
+            })
+            .collect();
         let info = vk::DescriptorPoolCreateInfo {
             flags: vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND,
             max_sets: binding_counts.len() as u32,
@@ -129,152 +165,232 @@ impl DescriptorVec {
         };
         DescriptorPool::new(device, &info)
     }
+    /// Create a descriptor set with as many descriptors of each type as the device allows for
+    /// `UPDATE_AFTER_BIND` bindings. Types whose `descriptorBinding*UpdateAfterBind` feature isn't enabled
+    /// get no binding, and the acceleration structure binding also requires the
+    /// `VK_KHR_acceleration_structure` extension.
     pub fn new(device: Arc<Device>, shader_stage_flags: vk::ShaderStageFlags) -> VkResult<Self> {
-        let default_binding_counts: [u32; 4] = [4, 4, 4, 4];
-        let layout =
-            Self::create_layout(device.clone(), &default_binding_counts, shader_stage_flags)?;
-        let pool = Self::create_pool(device, &default_binding_counts)?;
+        let acceleration_structures =
+            device.is_extension_enabled(ash::extensions::khr::AccelerationStructure::name());
+        let binding_counts = binding_counts(
+            device.physical_device().properties(),
+            device.enabled_features(),
+            acceleration_structures,
+        );
+        Self::with_capacity(device, shader_stage_flags, binding_counts)
+    }
+
+    /// Create a descriptor set with `capacity[ty]` descriptors in the binding of each type.
+    /// See [`DescriptorVec::create_layout`] for the features required by non-empty bindings.
+    pub fn with_capacity(
+        device: Arc<Device>,
+        shader_stage_flags: vk::ShaderStageFlags,
+        capacity: [u32; NUM_BINDING_TYPES],
+    ) -> VkResult<Self> {
+        let layout = Self::create_layout(device.clone(), &capacity, shader_stage_flags)?;
+        let pool = Self::create_pool(device, &capacity)?;
         let pool = Arc::new(pool);
         let mut desc = pool.allocate(std::iter::once(&layout))?;
         assert_eq!(desc.len(), 1);
         let desc = desc.drain(..).next().unwrap();
         Ok(Self {
-            capacity: default_binding_counts,
-            sizes: [0; 4],
-            freelists: [Vec::new(), Vec::new(), Vec::new(), Vec::new()],
-            tail: [0; 4],
+            capacity,
+            sizes: [0; NUM_BINDING_TYPES],
+            freelists: Default::default(),
+            tail: [0; NUM_BINDING_TYPES],
             pool,
             desc: Arc::new(desc),
-            shader_stage_flags,
             layout,
+            shader_stage_flags,
         })
     }
 
-    pub fn realloc(&mut self, new_capacity: [u32; 4]) -> VkResult<()> {
+    /// Replace the descriptor set with one of `new_capacity`, copying the descriptors of the slots handed out
+    /// so far. The layout changes, so pipeline layouts created with [`DescriptorVec::raw_layout`] need to be
+    /// recreated. The old set stays alive as long as [`DescriptorVec::descriptor_set`] handles to it.
+    ///
+    /// Panics if a binding would shrink below the slots handed out so far.
+    pub fn realloc(&mut self, new_capacity: [u32; NUM_BINDING_TYPES]) -> VkResult<()> {
+        for (ty, (&capacity, &tail)) in new_capacity.iter().zip(self.tail.iter()).enumerate() {
+            assert!(
+                capacity >= tail,
+                "Reallocating binding {} below its {} slots in use",
+                ty,
+                tail
+            );
+        }
         let device = self.pool.device().clone();
         let layout = Self::create_layout(device.clone(), &new_capacity, self.shader_stage_flags)?;
-        let new_pool = Self::create_pool(device, &new_capacity)?;
-        let new_pool = Arc::new(new_pool);
-        let mut new_desc = new_pool.allocate(std::iter::once(&layout))?;
-        assert_eq!(new_desc.len(), 1);
-        let new_desc = new_desc.drain(..).next().unwrap();
-
-        {
-            let mut copy_desc_sets: [MaybeUninit<vk::CopyDescriptorSet>; 4] =
-                MaybeUninit::uninit_array();
-            for (i, item) in copy_desc_sets.iter_mut().enumerate() {
-                item.write(vk::CopyDescriptorSet {
-                    src_set: self.desc.raw,
-                    src_binding: 0,
-                    src_array_element: 0,
-                    dst_set: new_desc.raw,
-                    dst_binding: 0,
-                    dst_array_element: 0,
-                    descriptor_count: self.capacity[i],
-                    ..Default::default()
-                });
-            }
+        let pool = Arc::new(Self::create_pool(device, &new_capacity)?);
+        let mut desc = pool.allocate(std::iter::once(&layout))?;
+        assert_eq!(desc.len(), 1);
+        let desc = desc.drain(..).next().unwrap();
 
+        let copies: Vec<vk::CopyDescriptorSet> = self
+            .tail
+            .iter()
+            .enumerate()
+            .filter(|(_, &tail)| tail > 0)
+            .map(|(binding, &tail)| vk::CopyDescriptorSet {
+                src_set: self.desc.raw,
+                src_binding: binding as u32,
+                dst_set: desc.raw,
+                dst_binding: binding as u32,
+                descriptor_count: tail,
+                ..Default::default()
+            })
+            .collect();
+        if !copies.is_empty() {
             unsafe {
-                let copy_desc_sets: [vk::CopyDescriptorSet; 4] =
-                    std::mem::transmute(copy_desc_sets);
-                new_pool
-                    .device()
-                    .update_descriptor_sets(&[], &copy_desc_sets);
+                pool.device().update_descriptor_sets(&[], &copies);
             }
         }
 
         self.capacity = new_capacity;
-        self.desc = Arc::new(new_desc);
-        self.pool = new_pool;
+        self.pool = pool;
+        self.desc = Arc::new(desc);
         self.layout = layout;
         Ok(())
     }
 
-    pub fn extend(
-        &mut self,
-        items: impl IntoIterator<Item = DescriptorVecBinding>,
-    ) -> VkResult<Vec<u32>> {
-        let mut num_desc_to_allocate: [u32; 4] = [0; 4];
+    /// Take a slot for a descriptor of type `ty` without writing to it.
+    /// Fails with `ERROR_OUT_OF_POOL_MEMORY` if all slots of the binding are taken.
+    pub fn reserve(&mut self, ty: DescriptorVecBindingType) -> VkResult<u32> {
+        let ty = ty as usize;
+        let element = if let Some(element) = self.freelists[ty].pop() {
+            element
+        } else if self.tail[ty] < self.capacity[ty] {
+            let element = self.tail[ty];
+            self.tail[ty] += 1;
+            element
+        } else {
+            return Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY);
+        };
+        self.sizes[ty] += 1;
+        Ok(element)
+    }
+
+    /// Return a slot to the freelist. The caller must ensure that the descriptor is no longer
+    /// accessed by any pending command buffer.
+    pub fn free(&mut self, ty: DescriptorVecBindingType, index: u32) {
+        let ty = ty as usize;
+        assert!(
+            index < self.tail[ty],
+            "Freeing a slot that was never reserved"
+        );
+        self.sizes[ty] -= 1;
+        self.freelists[ty].push(index);
+    }
+
+    /// Write descriptors to slots previously returned by [`DescriptorVec::reserve`].
+    pub fn write(&self, items: impl IntoIterator<Item = (u32, DescriptorVecBinding)>) {
+        let items: Vec<(u32, DescriptorVecBinding)> = items.into_iter().collect();
 
         let mut descriptor_image_info: Vec<vk::DescriptorImageInfo> = Vec::new();
         let mut descriptor_buffer_info: Vec<vk::DescriptorBufferInfo> = Vec::new();
-
-        let mut writes: Vec<_> = items
-            .into_iter()
-            .map(|binding| {
-                let descriptor_type = binding.ty();
-                num_desc_to_allocate[descriptor_type as usize] += 1;
-                match binding {
-                    DescriptorVecBinding::SampledImage(i)
-                    | DescriptorVecBinding::StorageImage(i) => descriptor_image_info.push(i),
-                    DescriptorVecBinding::UniformBuffer(i)
-                    | DescriptorVecBinding::StorageBuffer(i) => descriptor_buffer_info.push(i),
+        let mut acceleration_structures: Vec<vk::AccelerationStructureKHR> = Vec::new();
+        for (_, binding) in items.iter() {
+            match binding {
+                DescriptorVecBinding::SampledImage(i)
+                | DescriptorVecBinding::StorageImage(i)
+                | DescriptorVecBinding::CombinedImageSampler(i) => descriptor_image_info.push(*i),
+                DescriptorVecBinding::UniformBuffer(i) | DescriptorVecBinding::StorageBuffer(i) => {
+                    descriptor_buffer_info.push(*i)
                 }
-                vk::WriteDescriptorSet {
-                    dst_set: vk::DescriptorSet::null(),
-                    dst_binding: descriptor_type as u32,
-                    dst_array_element: 0,
+                DescriptorVecBinding::AccelerationStructure(i) => acceleration_structures.push(*i),
+            }
+        }
+        let acceleration_structure_writes: Vec<vk::WriteDescriptorSetAccelerationStructureKHR> =
+            acceleration_structures
+                .iter()
+                .map(
+                    |acceleration_structure| vk::WriteDescriptorSetAccelerationStructureKHR {
+                        acceleration_structure_count: 1,
+                        p_acceleration_structures: acceleration_structure,
+                        ..Default::default()
+                    },
+                )
+                .collect();
+
+        let mut image_info_indice: usize = 0;
+        let mut buffer_info_indice: usize = 0;
+        let mut acceleration_structure_indice: usize = 0;
+        let writes: Vec<vk::WriteDescriptorSet> = items
+            .iter()
+            .map(|(index, binding)| {
+                let ty = binding.ty();
+                assert!(
+                    *index < self.tail[ty as usize],
+                    "Writing to a slot that was never reserved"
+                );
+                let mut write = vk::WriteDescriptorSet {
+                    dst_set: self.desc.raw,
+                    dst_binding: ty as u32,
+                    dst_array_element: *index,
                     descriptor_count: 1,
-                    descriptor_type: descriptor_type.into(),
+                    descriptor_type: ty.into(),
                     ..Default::default()
+                };
+                match binding {
+                    DescriptorVecBinding::SampledImage(_)
+                    | DescriptorVecBinding::StorageImage(_)
+                    | DescriptorVecBinding::CombinedImageSampler(_) => {
+                        write.p_image_info = &descriptor_image_info[image_info_indice];
+                        image_info_indice += 1;
+                    }
+                    DescriptorVecBinding::UniformBuffer(_)
+                    | DescriptorVecBinding::StorageBuffer(_) => {
+                        write.p_buffer_info = &descriptor_buffer_info[buffer_info_indice];
+                        buffer_info_indice += 1;
+                    }
+                    DescriptorVecBinding::AccelerationStructure(_) => {
+                        write.p_next = &acceleration_structure_writes[acceleration_structure_indice]
+                            as *const _ as *const c_void;
+                        acceleration_structure_indice += 1;
+                    }
                 }
+                write
             })
             .collect();
-
-        let size_after_alloc = self.sizes.zip(num_desc_to_allocate).map(|(a, b)| a + b);
-        let needs_realloc = size_after_alloc
-            .iter()
-            .enumerate()
-            .any(|(i, &size)| size > self.capacity[i]);
-        if needs_realloc {
-            let needed_size =
-                size_after_alloc
-                    .zip(self.capacity)
-                    .map(|(size_after_alloc, capacity)| {
-                        if size_after_alloc > capacity {
-                            size_after_alloc * 2
-                        } else {
-                            capacity
-                        }
-                    });
-            self.realloc(needed_size)?;
-        }
-
-        let mut image_info_indice: usize = 0;
-        let mut buffer_info_indice: usize = 0;
-        let mut array_elemnets: Vec<u32> = Vec::with_capacity(writes.len());
-        for write in writes.iter_mut() {
-            write.dst_set = self.desc.raw;
-            let ty = write.dst_binding;
-            write.dst_array_element = if let Some(element) = self.freelists[ty as usize].pop() {
-                element
-            } else {
-                let element = self.tail[ty as usize];
-                self.tail[ty as usize] += 1;
-                element
-            };
-            array_elemnets.push(write.dst_array_element);
-            if write.descriptor_type == vk::DescriptorType::STORAGE_IMAGE
-                || write.descriptor_type == vk::DescriptorType::SAMPLED_IMAGE
-            {
-                write.p_image_info = &descriptor_image_info[image_info_indice];
-                image_info_indice += 1;
-            } else if write.descriptor_type == vk::DescriptorType::STORAGE_BUFFER
-                || write.descriptor_type == vk::DescriptorType::UNIFORM_BUFFER
-            {
-                write.p_buffer_info = &descriptor_buffer_info[buffer_info_indice];
-                buffer_info_indice += 1;
-            } else {
-                panic!()
-            }
+        if writes.is_empty() {
+            return;
         }
         unsafe {
             self.pool.device().update_descriptor_sets(&writes, &[]);
         }
-        Ok(array_elemnets)
     }
 
+    pub fn extend(
+        &mut self,
+        items: impl IntoIterator<Item = DescriptorVecBinding>,
+    ) -> VkResult<Vec<u32>> {
+        let items: Vec<DescriptorVecBinding> = items.into_iter().collect();
+        let mut array_elements: Vec<u32> = Vec::with_capacity(items.len());
+        for binding in items.iter() {
+            match self.reserve(binding.ty()) {
+                Ok(element) => array_elements.push(element),
+                Err(err) => {
+                    for (&element, binding) in array_elements.iter().zip(items.iter()) {
+                        self.free(binding.ty(), element);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        self.write(array_elements.iter().copied().zip(items));
+        Ok(array_elements)
+    }
+
+    /// Number of descriptors of type `ty` currently in use.
+    pub fn len(&self, ty: DescriptorVecBindingType) -> u32 {
+        self.sizes[ty as usize]
+    }
+    pub fn capacity(&self, ty: DescriptorVecBindingType) -> u32 {
+        self.capacity[ty as usize]
+    }
+    pub fn descriptor_set(&self) -> &Arc<DescriptorSet> {
+        &self.desc
+    }
     pub fn raw(&self) -> vk::DescriptorSet {
         self.desc.raw
     }
@@ -282,3 +398,133 @@ impl DescriptorVec {
         self.layout.raw
     }
 }
+
+/// Number of descriptors of each type in the bindings of [`DescriptorVec::new`].
+fn binding_counts(
+    properties: &PhysicalDeviceProperties,
+    features: &PhysicalDeviceFeatures,
+    acceleration_structures: bool,
+) -> [u32; NUM_BINDING_TYPES] {
+    let v12 = &properties.v12;
+    let acceleration_structure = &properties.acceleration_structure;
+    let update_after_bind = update_after_bind_support(
+        &features.v12,
+        &features.acceleration_structure,
+        acceleration_structures,
+    );
+    let mut limits = [
+        v12.max_descriptor_set_update_after_bind_sampled_images
+            .min(v12.max_per_stage_descriptor_update_after_bind_sampled_images),
+        v12.max_descriptor_set_update_after_bind_storage_images
+            .min(v12.max_per_stage_descriptor_update_after_bind_storage_images),
+        v12.max_descriptor_set_update_after_bind_uniform_buffers
+            .min(v12.max_per_stage_descriptor_update_after_bind_uniform_buffers),
+        v12.max_descriptor_set_update_after_bind_storage_buffers
+            .min(v12.max_per_stage_descriptor_update_after_bind_storage_buffers),
+        v12.max_descriptor_set_update_after_bind_samplers
+            .min(v12.max_per_stage_descriptor_update_after_bind_samplers),
+        acceleration_structure
+            .max_descriptor_set_update_after_bind_acceleration_structures
+            .min(
+                acceleration_structure
+                    .max_per_stage_descriptor_update_after_bind_acceleration_structures,
+            ),
+    ];
+    for (limit, supported) in limits.iter_mut().zip(update_after_bind) {
+        if !supported {
+            *limit = 0;
+        }
+    }
+    fit_binding_counts(limits, v12.max_per_stage_update_after_bind_resources)
+}
+
+/// Whether the descriptors of each type may be `UPDATE_AFTER_BIND` with the enabled features.
+fn update_after_bind_support(
+    v12: &vk::PhysicalDeviceVulkan12Features,
+    acceleration_structure: &vk::PhysicalDeviceAccelerationStructureFeaturesKHR,
+    acceleration_structures: bool,
+) -> [bool; NUM_BINDING_TYPES] {
+    [
+        v12.descriptor_binding_sampled_image_update_after_bind,
+        v12.descriptor_binding_storage_image_update_after_bind,
+        v12.descriptor_binding_uniform_buffer_update_after_bind,
+        v12.descriptor_binding_storage_buffer_update_after_bind,
+        // Combined image samplers fall under the sampled image feature.
+        v12.descriptor_binding_sampled_image_update_after_bind,
+        if acceleration_structures {
+            acceleration_structure.descriptor_binding_acceleration_structure_update_after_bind
+        } else {
+            vk::FALSE
+        },
+    ]
+    .map(|enabled| enabled == vk::TRUE)
+}
+
+/// Split the descriptor limits of each type between the bindings, so that the descriptor set stays within
+/// `max_resources` descriptors.
+fn fit_binding_counts(
+    mut limits: [u32; NUM_BINDING_TYPES],
+    max_resources: u32,
+) -> [u32; NUM_BINDING_TYPES] {
+    // Combined image samplers count as both sampled images and samplers.
+    let sampled_images = &mut limits[DescriptorVecBindingType::SampledImage as usize];
+    let sampled_image_limit = *sampled_images;
+    *sampled_images /= 2;
+    let combined = &mut limits[DescriptorVecBindingType::CombinedImageSampler as usize];
+    *combined = (*combined).min(sampled_image_limit - sampled_image_limit / 2);
+
+    let limits = limits.map(|limit| limit.min(MAX_DESCRIPTORS_PER_BINDING));
+    let total: u64 = limits.iter().map(|&limit| limit as u64).sum();
+    if total <= max_resources as u64 {
+        return limits;
+    }
+    limits.map(|limit| (limit as u64 * max_resources as u64 / total) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fit_binding_counts, update_after_bind_support, DescriptorVecBindingType,
+        MAX_DESCRIPTORS_PER_BINDING,
+    };
+    use ash::vk;
+
+    #[test]
+    fn binding_counts_fit_device_limits() {
+        let sampled = DescriptorVecBindingType::SampledImage as usize;
+        let combined = DescriptorVecBindingType::CombinedImageSampler as usize;
+
+        let counts = fit_binding_counts([1000, 500, 12, 500, 4000, 0], u32::MAX);
+        assert_eq!(counts, [500, 500, 12, 500, 500, 0]);
+
+        let counts = fit_binding_counts([u32::MAX; 6], u32::MAX);
+        assert!(counts
+            .iter()
+            .all(|&count| count == MAX_DESCRIPTORS_PER_BINDING));
+
+        let counts = fit_binding_counts([1000, 1000, 1000, 1000, 1000, 0], 2000);
+        assert!(counts.iter().map(|&count| count as u64).sum::<u64>() <= 2000);
+        assert_eq!(counts[sampled], counts[combined]);
+    }
+
+    #[test]
+    fn update_after_bind_requires_features() {
+        let v12 = vk::PhysicalDeviceVulkan12Features {
+            descriptor_binding_sampled_image_update_after_bind: vk::TRUE,
+            descriptor_binding_storage_buffer_update_after_bind: vk::TRUE,
+            ..Default::default()
+        };
+        let acceleration_structure = vk::PhysicalDeviceAccelerationStructureFeaturesKHR {
+            descriptor_binding_acceleration_structure_update_after_bind: vk::TRUE,
+            ..Default::default()
+        };
+        assert_eq!(
+            update_after_bind_support(&v12, &acceleration_structure, true),
+            [true, false, false, true, true, true]
+        );
+        assert_eq!(
+            update_after_bind_support(&v12, &acceleration_structure, false),
+            [true, false, false, true, true, false]
+        );
+    }
+}
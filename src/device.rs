@@ -1,7 +1,10 @@
+use crate::queue::DeferredDestructionQueue;
+use crate::registry::{ResourceRegistry, ResourceReport};
 use crate::Deref;
 use crate::Instance;
-use crate::PhysicalDevice;
-use std::sync::Arc;
+use crate::{PhysicalDevice, PhysicalDeviceFeatures};
+use std::ffi::{CStr, CString};
+use std::sync::{Arc, Mutex, Weak};
 
 pub trait HasDevice {
     fn device(&self) -> &Arc<Device>;
@@ -16,13 +19,27 @@ pub trait HasDevice {
 pub struct Device {
     physical_device: PhysicalDevice,
     device: ash::Device,
+    enabled_extensions: Vec<CString>,
+    enabled_features: Box<PhysicalDeviceFeatures>,
+    registry: ResourceRegistry,
+    /// Weak, because the retired resources hold on to the device.
+    deferred_destruction: Mutex<Weak<DeferredDestructionQueue>>,
 }
 
 impl Device {
-    pub(crate) fn new(physical_device: PhysicalDevice, device: ash::Device) -> Self {
+    pub(crate) fn new(
+        physical_device: PhysicalDevice,
+        device: ash::Device,
+        enabled_extensions: Vec<CString>,
+        enabled_features: Box<PhysicalDeviceFeatures>,
+    ) -> Self {
         Self {
             physical_device,
             device,
+            enabled_extensions,
+            enabled_features,
+            registry: ResourceRegistry::default(),
+            deferred_destruction: Mutex::new(Weak::new()),
         }
     }
     pub fn instance(&self) -> &Arc<Instance> {
@@ -31,6 +48,38 @@ impl Device {
     pub fn physical_device(&self) -> &PhysicalDevice {
         &self.physical_device
     }
+    pub fn is_extension_enabled(&self, name: &CStr) -> bool {
+        self.enabled_extensions
+            .iter()
+            .any(|enabled| enabled.as_c_str() == name)
+    }
+    /// The features enabled on device creation. Unlike [`PhysicalDevice::features`], these are the features
+    /// that may be used.
+    pub fn enabled_features(&self) -> &PhysicalDeviceFeatures {
+        &self.enabled_features
+    }
+    /// The registry of live objects. Disabled unless [`ResourceRegistry::enable`] was called.
+    pub fn resource_registry(&self) -> &ResourceRegistry {
+        &self.registry
+    }
+    /// Log and return the objects of this device that are still alive, as recorded by the
+    /// [`ResourceRegistry`]. Objects hold on to their device, so call this before dropping the last
+    /// handles to the device: anything reported at that point was leaked.
+    pub fn report_live_objects(&self) -> ResourceReport {
+        let report = self.registry.report();
+        if !report.is_empty() {
+            tracing::warn!(device = ?self.device.handle(), "live objects: {}", report);
+        }
+        report
+    }
+    /// The deferred destruction queue of the [`crate::queue::Queues`] created with this device, if they are
+    /// still alive.
+    pub fn deferred_destruction_queue(&self) -> Option<Arc<DeferredDestructionQueue>> {
+        self.deferred_destruction.lock().unwrap().upgrade()
+    }
+    pub(crate) fn set_deferred_destruction_queue(&self, queue: &Arc<DeferredDestructionQueue>) {
+        *self.deferred_destruction.lock().unwrap() = Arc::downgrade(queue);
+    }
 }
 
 impl Deref for Device {
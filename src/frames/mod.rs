@@ -1,5 +1,5 @@
 use crate::queue::semaphore::{Semaphore, TimelineSemaphoreOp};
-use crate::queue::{QueueType, QueuesCreateInfo};
+use crate::queue::{DeferredDestructionQueue, QueueType, QueuesCreateInfo};
 use crate::Device;
 use crate::{resources::HasImage, swapchain::Swapchain};
 use ash::{prelude::VkResult, vk};
@@ -39,6 +39,8 @@ pub struct FrameManager {
 
     generation: u64,
     old_swapchains: VecDeque<(vk::SwapchainKHR, u64)>,
+
+    deferred_destruction: Option<Arc<DeferredDestructionQueue>>,
 }
 
 impl crate::HasDevice for FrameManager {
@@ -98,7 +100,7 @@ impl FrameManager {
                 .chain(std::iter::once(
                     queue_info.queue_family_index_for_type(QueueType::Compute),
                 ))
-                .chain(0_u32..queue_info.create_infos.len() as u32)
+                .chain(queue_info.queue_family_indices())
                 .find(|&queue_family_index| {
                     surface
                         .supports_queue_family(
@@ -150,6 +152,7 @@ impl FrameManager {
             .unwrap()
             .ok_or(vk::Result::ERROR_OUT_OF_DATE_KHR)?;
 
+        let deferred_destruction = swapchain_loader.device().deferred_destruction_queue();
         let result = Self {
             swapchain_loader,
             swapchain: None,
@@ -170,10 +173,18 @@ impl FrameManager {
             present_mode,
             pre_transform,
             image_count,
+            deferred_destruction,
         };
         Ok(result)
     }
 
+    /// Poll `queue` every time a frame was acquired, releasing resources retired on
+    /// timeline values that were reached by the previous frames. Defaults to the deferred destruction
+    /// queue of the device's [`crate::queue::Queues`].
+    pub fn set_deferred_destruction_queue(&mut self, queue: Arc<DeferredDestructionQueue>) {
+        self.deferred_destruction = Some(queue);
+    }
+
     pub fn update(&mut self, extent: vk::Extent2D) {
         self.extent = extent;
         self.needs_rebuild = true;
@@ -192,6 +203,9 @@ impl FrameManager {
                 .collect();
             TimelineSemaphoreOp::block_many(semaphore_refs.as_slice()).unwrap();
             // self.current_frame() has finished rendering
+            if let Some(deferred_destruction) = self.deferred_destruction.as_ref() {
+                deferred_destruction.poll()?;
+            }
             while let Some(&(swapchain, generation)) = self.old_swapchains.front() {
                 if self.frames[next_frame_index].generation == generation {
                     // next frame is still being rendered, and it's using the same swapchain as this one.
@@ -10,6 +10,7 @@ use crate::descriptor::{DescriptorPool, DescriptorSet};
 use crate::pipeline::{Binding, Pipeline, PipelineCache};
 use crate::resources::buffer::HasBufferView;
 use crate::resources::image::HasImageView;
+use crate::resources::sampler::Sampler;
 use crate::resources::{HasBuffer, HasImage};
 
 pub struct RenderGraph {
@@ -112,6 +113,29 @@ impl RenderGraph {
             _marker: PhantomData,
         }
     }
+    pub fn import_image_view<T: HasImageView + Send + Sync + 'static>(
+        &mut self,
+        resource: T,
+    ) -> ResourceHandle<T> {
+        let idx = self.resources.len();
+        self.resources.push(Resource::ImageView(Box::new(resource)));
+        ResourceHandle {
+            idx,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn import_buffer_view<T: HasBufferView + Send + Sync + 'static>(
+        &mut self,
+        resource: T,
+    ) -> ResourceHandle<T> {
+        let idx = self.resources.len();
+        self.resources.push(Resource::BufferView(Box::new(resource)));
+        ResourceHandle {
+            idx,
+            _marker: PhantomData,
+        }
+    }
     pub fn run(
         mut self,
         mut command_recorder: CommandRecorder,
@@ -328,13 +352,14 @@ impl RenderGraph {
                                     dst_binding: *binding_id,
                                     dst_array_element: 0,
                                     descriptor_count: 1,
+                                    descriptor_type: pipeline_layout.descriptor_sets[set_id as usize].0[binding_id].ty,
                                     ..Default::default()
                                 }; // TODO: test that this works, and put to use. After that, separate this into multiple files.
                                 match binding {
-                                    RenderGraphContextBinding::Image { layout } => {
+                                    RenderGraphContextBinding::Image { layout, sampler } => {
                                         assert_eq!(resource.layout, *layout);
                                         base.p_image_info = Box::leak(Box::new(vk::DescriptorImageInfo {
-                                            sampler: vk::Sampler::null(),
+                                            sampler: sampler.as_ref().map_or(vk::Sampler::null(), |sampler| sampler.raw()),
                                             image_layout: *layout,
                                             image_view: match &resource.resource {
                                                 Resource::ImageView(image_view) => image_view.raw_image_view(),
@@ -420,6 +445,20 @@ impl RenderGraph {
                 .drain_filter(|next| Rc::strong_count(&mut next.1) == 1);
             self.heads.extend(new_heads);
         }
+        // Samplers written into the descriptor sets need to outlive the command buffer.
+        let samplers: Vec<Arc<Sampler>> = descriptor_cache
+            .keys()
+            .flat_map(|set| set.values())
+            .filter_map(|(_, binding)| match binding {
+                RenderGraphContextBinding::Image { sampler, .. } => sampler.clone(),
+                _ => None,
+            })
+            .collect();
+        command_recorder.referenced_resources.extend(
+            samplers
+                .into_iter()
+                .map(|sampler| sampler.command_buffer_resource()),
+        );
         command_recorder
             .referenced_resources
             .extend(resources.into_iter().map(|a| match a.resource {
@@ -536,6 +575,8 @@ impl<'a, 'b> RenderGraphRecordingContext<'a, 'b> {
 enum RenderGraphContextBinding {
     Image {
         layout: vk::ImageLayout,
+        /// Set for combined image samplers.
+        sampler: Option<Arc<Sampler>>,
     },
     Buffer {
         offset: vk::DeviceSize,
@@ -852,7 +893,10 @@ impl<'a, P: Pipeline> RenderGraphPipelineContext<'a, P> {
             set_id,
             binding_id,
             resource,
-            RenderGraphContextBinding::Image { layout },
+            RenderGraphContextBinding::Image {
+                layout,
+                sampler: None,
+            },
         );
         let binding: &Binding = self
             .pipeline
@@ -874,6 +918,38 @@ impl<'a, P: Pipeline> RenderGraphPipelineContext<'a, P> {
         );
         self
     }
+    /// Bind a combined image sampler. Bindings with immutable samplers should use [`Self::bind_image`].
+    pub fn bind_image_sampler<T: HasImageView>(
+        &mut self,
+        set_id: u32,
+        binding_id: u32,
+        resource: ResourceHandle<T>,
+        layout: vk::ImageLayout,
+        sampler: Arc<Sampler>,
+    ) -> &mut Self {
+        self.bind(
+            set_id,
+            binding_id,
+            resource,
+            RenderGraphContextBinding::Image {
+                layout,
+                sampler: Some(sampler),
+            },
+        );
+        let binding: &Binding = self
+            .pipeline
+            .binding(set_id, binding_id)
+            .expect("Unknown binding");
+        assert_eq!(binding.ty, vk::DescriptorType::COMBINED_IMAGE_SAMPLER);
+        self.inner.image_view_access(
+            resource,
+            crate::util::shader_stage_to_pipeline_stage(binding.shader_read_stage_flags),
+            crate::util::descriptor_type_to_access_flags_read(binding.ty),
+            layout,
+            layout,
+        );
+        self
+    }
     pub fn bind_texel_buffer<T: HasBufferView>(
         &mut self,
         set_id: u32,
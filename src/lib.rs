@@ -24,6 +24,7 @@ pub mod fence;
 pub mod frames;
 mod physical_device;
 pub mod queue;
+pub mod registry;
 pub mod resources;
 pub mod surface;
 pub mod swapchain;
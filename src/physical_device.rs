@@ -2,7 +2,7 @@ use crate::queue;
 
 use super::{Device, Instance};
 use ash::{prelude::VkResult, vk};
-use core::ffi::{c_char, c_void};
+use core::ffi::{c_char, c_void, CStr};
 use std::{
     ops::{Deref, DerefMut},
     sync::Arc,
@@ -65,6 +65,48 @@ impl PhysicalDevice {
             }
         }
     }
+    pub fn format_properties(&self, format: vk::Format) -> vk::FormatProperties {
+        // Safety: No Host Syncronization rules for vkGetPhysicalDeviceFormatProperties.
+        unsafe {
+            self.instance
+                .get_physical_device_format_properties(self.physical_device, format)
+        }
+    }
+    /// Empty if the format doesn't support sparse residency with these parameters.
+    pub fn sparse_image_format_properties(
+        &self,
+        format: vk::Format,
+        ty: vk::ImageType,
+        samples: vk::SampleCountFlags,
+        usage: vk::ImageUsageFlags,
+        tiling: vk::ImageTiling,
+    ) -> Vec<vk::SparseImageFormatProperties> {
+        // Safety: No Host Syncronization rules for vkGetPhysicalDeviceSparseImageFormatProperties.
+        unsafe {
+            self.instance
+                .get_physical_device_sparse_image_format_properties(
+                    self.physical_device,
+                    format,
+                    ty,
+                    samples,
+                    usage,
+                    tiling,
+                )
+        }
+    }
+    pub fn supports_extension(&self, name: &CStr) -> bool {
+        // Safety: No Host Syncronization rules for vkEnumerateDeviceExtensionProperties.
+        let extensions = unsafe {
+            self.instance
+                .enumerate_device_extension_properties(self.physical_device)
+        };
+        extensions.map_or(false, |extensions| {
+            extensions.iter().any(|ext| {
+                // Safety: extension_name is a null-terminated string.
+                unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) == name }
+            })
+        })
+    }
     pub(crate) fn get_queue_family_properties(&self) -> Vec<vk::QueueFamilyProperties> {
         unsafe {
             self.instance
@@ -78,6 +120,32 @@ impl PhysicalDevice {
         enabled_features: &vk::PhysicalDeviceFeatures2,
     ) -> VkResult<(Arc<Device>, crate::queue::Queues)> {
         let queue_create_info = queue::QueuesCreateInfo::find(&self);
+        self.create_device_with_queues(
+            enabled_layers,
+            enabled_extensions,
+            enabled_features,
+            &queue_create_info,
+        )
+    }
+    /// Like [`PhysicalDevice::create_device`], creating the queues selected by `queue_create_info`.
+    /// See [`queue::QueuesCreateInfoBuilder`].
+    pub fn create_device_with_queues(
+        self,
+        enabled_layers: &[*const c_char],
+        enabled_extensions: &[*const c_char],
+        enabled_features: &vk::PhysicalDeviceFeatures2,
+        queue_create_info: &queue::QueuesCreateInfo,
+    ) -> VkResult<(Arc<Device>, crate::queue::Queues)> {
+        let mut enabled_extensions = enabled_extensions.to_vec();
+        // Enable VK_EXT_memory_budget when available so that the allocator can report heap budgets.
+        let memory_budget = vk::ExtMemoryBudgetFn::name();
+        if self.supports_extension(memory_budget)
+            && !enabled_extensions
+                .iter()
+                .any(|&ext| unsafe { CStr::from_ptr(ext) } == memory_budget)
+        {
+            enabled_extensions.push(memory_budget.as_ptr());
+        }
         let create_info = vk::DeviceCreateInfo {
             p_next: enabled_features as *const vk::PhysicalDeviceFeatures2 as *const _,
             queue_create_info_count: queue_create_info.create_infos.len() as u32,
@@ -99,11 +167,21 @@ impl PhysicalDevice {
             self.instance
                 .create_device(self.physical_device, &create_info, None)?
         };
-        let device = Arc::new(Device::new(self, device));
+        let enabled_extensions = enabled_extensions
+            .iter()
+            .map(|&ext| unsafe { CStr::from_ptr(ext) }.to_owned())
+            .collect();
+        let enabled_features = PhysicalDeviceFeatures::enabled(enabled_features);
+        let device = Arc::new(Device::new(
+            self,
+            device,
+            enabled_extensions,
+            enabled_features,
+        ));
 
         let queues = unsafe {
             // Safe because this is only called once per device.
-            crate::queue::Queues::from_device(&device, &queue_create_info)
+            crate::queue::Queues::from_device(&device, queue_create_info)
         };
         Ok((device, queues))
     }
@@ -172,6 +250,62 @@ impl PhysicalDeviceFeatures {
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
     ) -> Box<PhysicalDeviceFeatures> {
+        let mut this = Self::empty();
+        unsafe {
+            instance.get_physical_device_features2(physical_device, &mut this.inner);
+        }
+        this
+    }
+    /// The features enabled by `enabled` and its `p_next` chain, as passed to vkCreateDevice.
+    /// Features enabled through structures other than the fields of [`PhysicalDeviceFeatures`] aren't recorded.
+    fn enabled(enabled: &vk::PhysicalDeviceFeatures2) -> Box<PhysicalDeviceFeatures> {
+        let mut this = Self::empty();
+        this.inner.features = enabled.features;
+        let mut next = enabled.p_next as *const vk::BaseInStructure;
+        // Safety: the chain was valid for vkCreateDevice. Each structure is copied according to its
+        // s_type, keeping the p_next of the copy.
+        while let Some(structure) = unsafe { next.as_ref() } {
+            unsafe {
+                match structure.s_type {
+                    vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_1_FEATURES => {
+                        this.v11 = vk::PhysicalDeviceVulkan11Features {
+                            p_next: this.v11.p_next,
+                            ..*next.cast::<vk::PhysicalDeviceVulkan11Features>()
+                        }
+                    }
+                    vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_2_FEATURES => {
+                        this.v12 = vk::PhysicalDeviceVulkan12Features {
+                            p_next: this.v12.p_next,
+                            ..*next.cast::<vk::PhysicalDeviceVulkan12Features>()
+                        }
+                    }
+                    vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_3_FEATURES => {
+                        this.v13 = vk::PhysicalDeviceVulkan13Features {
+                            p_next: this.v13.p_next,
+                            ..*next.cast::<vk::PhysicalDeviceVulkan13Features>()
+                        }
+                    }
+                    vk::StructureType::PHYSICAL_DEVICE_ACCELERATION_STRUCTURE_FEATURES_KHR => {
+                        this.acceleration_structure =
+                            vk::PhysicalDeviceAccelerationStructureFeaturesKHR {
+                                p_next: this.acceleration_structure.p_next,
+                                ..*next.cast::<vk::PhysicalDeviceAccelerationStructureFeaturesKHR>()
+                            }
+                    }
+                    vk::StructureType::PHYSICAL_DEVICE_RAY_TRACING_PIPELINE_FEATURES_KHR => {
+                        this.ray_tracing = vk::PhysicalDeviceRayTracingPipelineFeaturesKHR {
+                            p_next: this.ray_tracing.p_next,
+                            ..*next.cast::<vk::PhysicalDeviceRayTracingPipelineFeaturesKHR>()
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            next = structure.p_next;
+        }
+        this
+    }
+    fn empty() -> Box<PhysicalDeviceFeatures> {
         let mut this = Box::new(Self {
             inner: vk::PhysicalDeviceFeatures2::default(),
             v11: vk::PhysicalDeviceVulkan11Features::default(),
@@ -185,9 +319,6 @@ impl PhysicalDeviceFeatures {
         this.v12.p_next = &mut this.v13 as *mut _ as *mut c_void;
         this.v13.p_next = &mut this.acceleration_structure as *mut _ as *mut c_void;
         this.acceleration_structure.p_next = &mut this.ray_tracing as *mut _ as *mut c_void;
-        unsafe {
-            instance.get_physical_device_features2(physical_device, &mut this.inner);
-        }
         this
     }
 }
@@ -18,6 +18,37 @@ pub struct DescriptorSetLayoutCreateInfo {
     pub bindings: Vec<(u32, Binding)>,
 }
 
+impl DescriptorSetLayoutCreateInfo {
+    /// A layout for descriptors pushed with [`crate::command::recorder::CommandRecorder::push_descriptor_set`].
+    /// Requires the `VK_KHR_push_descriptor` extension.
+    /// Panics if a binding is a dynamic buffer or an inline uniform block.
+    pub fn push_descriptor(mut bindings: Vec<(u32, Binding)>) -> Self {
+        check_push_descriptor_bindings(&bindings);
+        bindings.sort_by_key(|(index, _)| *index);
+        Self {
+            flags: vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR,
+            bindings,
+        }
+    }
+}
+
+/// Push descriptor layouts can't contain dynamic buffers or inline uniform blocks.
+fn check_push_descriptor_bindings(bindings: &[(u32, Binding)]) {
+    for (index, binding) in bindings.iter() {
+        assert!(
+            !matches!(
+                binding.ty,
+                vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
+                    | vk::DescriptorType::STORAGE_BUFFER_DYNAMIC
+                    | vk::DescriptorType::INLINE_UNIFORM_BLOCK
+            ),
+            "Binding {} of a push descriptor layout can't be of type {:?}",
+            index,
+            binding.ty
+        );
+    }
+}
+
 #[repr(C)]
 #[derive(Hash, PartialEq, Eq, Clone, PartialOrd, Ord)]
 pub struct PushConstantRange {
@@ -40,16 +71,36 @@ impl PipelineLayoutCache {
         info: DescriptorSetLayoutCreateInfo,
     ) -> &'a Arc<DescriptorSetLayout> {
         map.entry(info).or_insert_with_key(|info| {
+            if info
+                .flags
+                .contains(vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR)
+            {
+                check_push_descriptor_bindings(&info.bindings);
+            }
+            let immutable_samplers: Vec<Vec<vk::Sampler>> = info
+                .bindings
+                .iter()
+                .map(|(_, binding)| binding.immutable_samplers.iter().map(|s| s.raw()).collect())
+                .collect();
             let bindings: Vec<_> = info
                 .bindings
                 .iter()
-                .map(|(binding_index, binding)| vk::DescriptorSetLayoutBinding {
-                    binding: *binding_index,
-                    descriptor_type: binding.ty,
-                    descriptor_count: binding.count,
-                    stage_flags: binding.shader_read_stage_flags | binding.shader_read_stage_flags,
-                    p_immutable_samplers: std::ptr::null(),
-                })
+                .zip(immutable_samplers.iter())
+                .map(
+                    |((binding_index, binding), samplers)| vk::DescriptorSetLayoutBinding {
+                        binding: *binding_index,
+                        descriptor_type: binding.ty,
+                        descriptor_count: binding.count,
+                        stage_flags: binding.shader_read_stage_flags
+                            | binding.shader_read_stage_flags,
+                        p_immutable_samplers: if samplers.is_empty() {
+                            std::ptr::null()
+                        } else {
+                            assert_eq!(samplers.len() as u32, binding.count);
+                            samplers.as_ptr()
+                        },
+                    },
+                )
                 .collect();
             let layout = unsafe {
                 DescriptorSetLayout::new(
@@ -61,7 +112,12 @@ impl PipelineLayoutCache {
                 )
                 .unwrap()
             };
-            Arc::new(layout)
+            let samplers = info
+                .bindings
+                .iter()
+                .flat_map(|(_, binding)| binding.immutable_samplers.iter().cloned())
+                .collect();
+            Arc::new(layout.with_immutable_samplers(samplers))
         })
     }
     pub fn create_descriptor_set_layout(
@@ -123,3 +179,51 @@ impl PipelineLayoutCache {
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::DescriptorSetLayoutCreateInfo;
+    use crate::pipeline::Binding;
+    use ash::vk;
+
+    fn binding(ty: vk::DescriptorType) -> Binding {
+        Binding {
+            ty,
+            count: 1,
+            shader_read_stage_flags: vk::ShaderStageFlags::COMPUTE,
+            shader_write_stage_flags: vk::ShaderStageFlags::empty(),
+            immutable_samplers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn push_descriptor_layout_sorts_bindings() {
+        let info = DescriptorSetLayoutCreateInfo::push_descriptor(vec![
+            (1, binding(vk::DescriptorType::STORAGE_BUFFER)),
+            (0, binding(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)),
+        ]);
+        assert!(info
+            .flags
+            .contains(vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR));
+        assert_eq!(info.bindings[0].0, 0);
+        assert_eq!(info.bindings[1].0, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be of type")]
+    fn push_descriptor_layout_rejects_dynamic_buffers() {
+        DescriptorSetLayoutCreateInfo::push_descriptor(vec![(
+            0,
+            binding(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC),
+        )]);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be of type")]
+    fn push_descriptor_layout_rejects_inline_uniform_blocks() {
+        DescriptorSetLayoutCreateInfo::push_descriptor(vec![(
+            0,
+            binding(vk::DescriptorType::INLINE_UNIFORM_BLOCK),
+        )]);
+    }
+}
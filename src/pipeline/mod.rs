@@ -1,7 +1,8 @@
 mod cache;
 pub mod layout_cache;
 use crate::{
-    command::recorder::CommandRecorder, descriptor::DescriptorSetLayout, Device, HasDevice,
+    command::recorder::CommandRecorder, descriptor::DescriptorSetLayout,
+    resources::sampler::Sampler, Device, HasDevice,
 };
 use ash::{prelude::VkResult, vk};
 pub use cache::*;
@@ -13,6 +14,8 @@ pub struct Binding {
     pub count: u32,
     pub shader_read_stage_flags: vk::ShaderStageFlags,
     pub shader_write_stage_flags: vk::ShaderStageFlags,
+    /// For SAMPLER and COMBINED_IMAGE_SAMPLER bindings. Either empty or `count` samplers.
+    pub immutable_samplers: Vec<Arc<Sampler>>,
 }
 
 pub struct PipelineLayout {
@@ -56,6 +59,9 @@ impl PipelineLayout {
         descriptor_sets: Vec<(BTreeMap<u32, Binding>, Arc<DescriptorSetLayout>)>,
     ) -> VkResult<Self> {
         let layout = device.create_pipeline_layout(info, None)?;
+        device
+            .resource_registry()
+            .register(vk::ObjectType::PIPELINE_LAYOUT, layout, None);
         Ok(Self {
             device,
             layout,
@@ -70,6 +76,9 @@ impl PipelineLayout {
 }
 impl Drop for PipelineLayout {
     fn drop(&mut self) {
+        self.device
+            .resource_registry()
+            .unregister(vk::ObjectType::PIPELINE_LAYOUT, self.layout);
         unsafe {
             self.device.destroy_pipeline_layout(self.layout, None);
         }
@@ -123,6 +132,7 @@ pub mod utils {
                                     count: other_binding_count,
                                     shader_read_stage_flags: vk::ShaderStageFlags::empty(),
                                     shader_write_stage_flags: vk::ShaderStageFlags::empty(),
+                                    immutable_samplers: Vec::new(),
                                 });
                         }
                     })
@@ -137,6 +147,7 @@ pub mod utils {
                                     count: other_binding_count,
                                     shader_read_stage_flags: stage,
                                     shader_write_stage_flags: stage,
+                                    immutable_samplers: Vec::new(),
                                 };
                                 (*index, binding)
                             })
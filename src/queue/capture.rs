@@ -0,0 +1,490 @@
+//! Structured traces of queue submissions.
+//!
+//! When capturing is enabled with [`crate::queue::Queues::start_capture`], every call to
+//! [`crate::queue::Queues::flush`] appends a [`FlushRecord`] to the trace. The trace only contains
+//! plain data, so it can be written to disk with its [`std::fmt::Display`] implementation, parsed
+//! back with [`SubmissionTrace::parse`] and inspected without a device.
+use std::{collections::HashMap, fmt::Display};
+
+use ash::vk::{self, Handle};
+
+use super::QueueIndex;
+
+/// A semaphore wait or signal operation.
+/// When `value == 0`, the operation is on a binary semaphore.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SemaphoreRecord {
+    pub semaphore: vk::Semaphore,
+    pub value: u64,
+    pub stage_mask: vk::PipelineStageFlags2,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandBufferRecord {
+    pub command_buffer: vk::CommandBuffer,
+    pub label: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct SubmitRecord {
+    pub wait_semaphores: Vec<SemaphoreRecord>,
+    pub command_buffers: Vec<CommandBufferRecord>,
+    pub signal_semaphores: Vec<SemaphoreRecord>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SparseBindTarget {
+    Buffer(vk::Buffer),
+    ImageOpaque(vk::Image),
+    Image(vk::Image),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SparseBindRecord {
+    pub target: SparseBindTarget,
+    pub bind_count: u32,
+}
+
+/// Stage masks are always empty on sparse binding operations.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct BindSparseRecord {
+    pub wait_semaphores: Vec<SemaphoreRecord>,
+    pub binds: Vec<SparseBindRecord>,
+    pub signal_semaphores: Vec<SemaphoreRecord>,
+}
+
+/// All queue operations submitted to one queue in one flush.
+/// Sparse binding operations are always submitted before command buffers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlushRecord {
+    pub queue: QueueIndex,
+    pub family_index: u32,
+    pub bind_sparse: Vec<BindSparseRecord>,
+    pub submits: Vec<SubmitRecord>,
+    pub fence: vk::Fence,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct SubmissionTrace {
+    pub flushes: Vec<FlushRecord>,
+}
+
+/// A violation of the dependency rules found in a [`SubmissionTrace`].
+/// `flush` is the index of the offending [`FlushRecord`] in [`SubmissionTrace::flushes`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TraceIssue {
+    /// A timeline semaphore was signaled with a value not greater than a value it was signaled with before.
+    NonIncreasingSignal {
+        flush: usize,
+        semaphore: vk::Semaphore,
+        previous: u64,
+        value: u64,
+    },
+    /// A timeline semaphore value was waited on, but no operation in the trace signals it.
+    /// This is not necessarily an error if the semaphore is signaled on the host.
+    UnsignaledWait {
+        flush: usize,
+        semaphore: vk::Semaphore,
+        value: u64,
+    },
+    /// A timeline semaphore value was waited on, and it's only signaled by an operation submitted
+    /// later on the same queue.
+    WaitBeforeSignalOnSameQueue {
+        flush: usize,
+        queue: QueueIndex,
+        semaphore: vk::Semaphore,
+        value: u64,
+    },
+}
+
+impl SubmissionTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All batches in submission order, as (flush index, queue, waits, signals).
+    fn batches(
+        &self,
+    ) -> impl Iterator<Item = (usize, QueueIndex, &[SemaphoreRecord], &[SemaphoreRecord])> {
+        self.flushes
+            .iter()
+            .enumerate()
+            .flat_map(|(flush_index, flush)| {
+                let binds = flush.bind_sparse.iter().map(move |bind| {
+                    (
+                        flush_index,
+                        flush.queue,
+                        bind.wait_semaphores.as_slice(),
+                        bind.signal_semaphores.as_slice(),
+                    )
+                });
+                let submits = flush.submits.iter().map(move |submit| {
+                    (
+                        flush_index,
+                        flush.queue,
+                        submit.wait_semaphores.as_slice(),
+                        submit.signal_semaphores.as_slice(),
+                    )
+                });
+                binds.chain(submits)
+            })
+    }
+
+    /// Check the timeline semaphore operations in the trace against the dependency rules.
+    pub fn validate(&self) -> Vec<TraceIssue> {
+        let mut issues = Vec::new();
+
+        // For each semaphore, the signaled values in submission order with the index and queue of the batch.
+        let mut signals: HashMap<vk::Semaphore, Vec<(usize, QueueIndex, u64)>> = HashMap::new();
+        for (batch_index, (flush, queue, _, signal_semaphores)) in self.batches().enumerate() {
+            for signal in signal_semaphores.iter().filter(|s| s.value != 0) {
+                let history = signals.entry(signal.semaphore).or_default();
+                if let Some(&(_, _, previous)) = history.last() && previous >= signal.value {
+                    issues.push(TraceIssue::NonIncreasingSignal {
+                        flush,
+                        semaphore: signal.semaphore,
+                        previous,
+                        value: signal.value,
+                    });
+                }
+                history.push((batch_index, queue, signal.value));
+            }
+        }
+
+        for (batch_index, (flush, queue, wait_semaphores, _)) in self.batches().enumerate() {
+            for wait in wait_semaphores.iter().filter(|s| s.value != 0) {
+                let first_signal = signals
+                    .get(&wait.semaphore)
+                    .and_then(|history| history.iter().find(|&&(_, _, value)| value >= wait.value));
+                match first_signal {
+                    None => issues.push(TraceIssue::UnsignaledWait {
+                        flush,
+                        semaphore: wait.semaphore,
+                        value: wait.value,
+                    }),
+                    Some(&(signal_batch_index, signal_queue, _))
+                        if signal_batch_index > batch_index && signal_queue == queue =>
+                    {
+                        issues.push(TraceIssue::WaitBeforeSignalOnSameQueue {
+                            flush,
+                            queue,
+                            semaphore: wait.semaphore,
+                            value: wait.value,
+                        })
+                    }
+                    Some(_) => (),
+                }
+            }
+        }
+        issues
+    }
+}
+
+fn write_semaphore(
+    f: &mut std::fmt::Formatter<'_>,
+    op: &str,
+    semaphore: &SemaphoreRecord,
+) -> std::fmt::Result {
+    writeln!(
+        f,
+        "    {} {:#x} {} {:#x}",
+        op,
+        semaphore.semaphore.as_raw(),
+        semaphore.value,
+        semaphore.stage_mask.as_raw()
+    )
+}
+
+impl Display for SubmissionTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for flush in self.flushes.iter() {
+            writeln!(
+                f,
+                "flush {} {} {:#x}",
+                flush.queue.0,
+                flush.family_index,
+                flush.fence.as_raw()
+            )?;
+            for bind in flush.bind_sparse.iter() {
+                writeln!(f, "  bind_sparse")?;
+                for wait in bind.wait_semaphores.iter() {
+                    write_semaphore(f, "wait", wait)?;
+                }
+                for record in bind.binds.iter() {
+                    let (ty, handle) = match record.target {
+                        SparseBindTarget::Buffer(buffer) => ("buffer", buffer.as_raw()),
+                        SparseBindTarget::ImageOpaque(image) => ("image_opaque", image.as_raw()),
+                        SparseBindTarget::Image(image) => ("image", image.as_raw()),
+                    };
+                    writeln!(f, "    {} {:#x} {}", ty, handle, record.bind_count)?;
+                }
+                for signal in bind.signal_semaphores.iter() {
+                    write_semaphore(f, "signal", signal)?;
+                }
+            }
+            for submit in flush.submits.iter() {
+                writeln!(f, "  submit")?;
+                for wait in submit.wait_semaphores.iter() {
+                    write_semaphore(f, "wait", wait)?;
+                }
+                for command_buffer in submit.command_buffers.iter() {
+                    write!(
+                        f,
+                        "    command_buffer {:#x}",
+                        command_buffer.command_buffer.as_raw()
+                    )?;
+                    if let Some(label) = command_buffer.label.as_ref() {
+                        write!(f, " {}", label)?;
+                    }
+                    writeln!(f)?;
+                }
+                for signal in submit.signal_semaphores.iter() {
+                    write_semaphore(f, "signal", signal)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceParseError {
+    /// 1-based line number
+    pub line: usize,
+    pub message: &'static str,
+}
+
+impl Display for TraceParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for TraceParseError {}
+
+impl SubmissionTrace {
+    /// Parse a trace written with the [`Display`] implementation of [`SubmissionTrace`].
+    pub fn parse(text: &str) -> Result<Self, TraceParseError> {
+        /// The kind of batch that the following lines belong to. It's always the last batch of the last flush.
+        #[derive(PartialEq, Eq)]
+        enum Batch {
+            None,
+            BindSparse,
+            Submit,
+        }
+        fn number(token: Option<&str>) -> Option<u64> {
+            let token = token?;
+            match token.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16).ok(),
+                None => token.parse().ok(),
+            }
+        }
+        fn semaphore<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Option<SemaphoreRecord> {
+            Some(SemaphoreRecord {
+                semaphore: vk::Semaphore::from_raw(number(tokens.next())?),
+                value: number(tokens.next())?,
+                stage_mask: vk::PipelineStageFlags2::from_raw(number(tokens.next())?),
+            })
+        }
+
+        let mut trace = SubmissionTrace::new();
+        let mut batch = Batch::None;
+        for (line_index, line) in text.lines().enumerate() {
+            let error = |message| TraceParseError {
+                line: line_index + 1,
+                message,
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let mut tokens = rest.split_whitespace();
+            if keyword == "flush" {
+                batch = Batch::None;
+                let queue = number(tokens.next()).ok_or(error("invalid queue index"))?;
+                let family_index = number(tokens.next()).ok_or(error("invalid queue family"))?;
+                let fence = number(tokens.next()).ok_or(error("invalid fence"))?;
+                trace.flushes.push(FlushRecord {
+                    queue: QueueIndex(queue as usize),
+                    family_index: family_index as u32,
+                    bind_sparse: Vec::new(),
+                    submits: Vec::new(),
+                    fence: vk::Fence::from_raw(fence),
+                });
+                continue;
+            }
+            let flush = trace
+                .flushes
+                .last_mut()
+                .ok_or(error("expected flush"))?;
+            match keyword {
+                "bind_sparse" => {
+                    flush.bind_sparse.push(BindSparseRecord::default());
+                    batch = Batch::BindSparse;
+                }
+                "submit" => {
+                    flush.submits.push(SubmitRecord::default());
+                    batch = Batch::Submit;
+                }
+                "wait" | "signal" => {
+                    let record = semaphore(tokens).ok_or(error("invalid semaphore operation"))?;
+                    let (waits, signals) = match batch {
+                        Batch::BindSparse => {
+                            let bind = flush.bind_sparse.last_mut().unwrap();
+                            (&mut bind.wait_semaphores, &mut bind.signal_semaphores)
+                        }
+                        Batch::Submit => {
+                            let submit = flush.submits.last_mut().unwrap();
+                            (&mut submit.wait_semaphores, &mut submit.signal_semaphores)
+                        }
+                        Batch::None => return Err(error("expected bind_sparse or submit")),
+                    };
+                    if keyword == "wait" {
+                        waits.push(record);
+                    } else {
+                        signals.push(record);
+                    }
+                }
+                "buffer" | "image_opaque" | "image" => {
+                    let handle = number(tokens.next()).ok_or(error("invalid handle"))?;
+                    let bind_count = number(tokens.next()).ok_or(error("invalid bind count"))?;
+                    let target = match keyword {
+                        "buffer" => SparseBindTarget::Buffer(vk::Buffer::from_raw(handle)),
+                        "image_opaque" => {
+                            SparseBindTarget::ImageOpaque(vk::Image::from_raw(handle))
+                        }
+                        _ => SparseBindTarget::Image(vk::Image::from_raw(handle)),
+                    };
+                    if batch != Batch::BindSparse {
+                        return Err(error("expected bind_sparse"));
+                    }
+                    flush
+                        .bind_sparse
+                        .last_mut()
+                        .unwrap()
+                        .binds
+                        .push(SparseBindRecord {
+                            target,
+                            bind_count: bind_count as u32,
+                        });
+                }
+                "command_buffer" => {
+                    let (handle, label) = rest.split_once(' ').unwrap_or((rest, ""));
+                    let handle = number(Some(handle)).ok_or(error("invalid handle"))?;
+                    if batch != Batch::Submit {
+                        return Err(error("expected submit"));
+                    }
+                    flush
+                        .submits
+                        .last_mut()
+                        .unwrap()
+                        .command_buffers
+                        .push(CommandBufferRecord {
+                            command_buffer: vk::CommandBuffer::from_raw(handle),
+                            label: if label.is_empty() {
+                                None
+                            } else {
+                                Some(label.to_string())
+                            },
+                        });
+                }
+                _ => return Err(error("unknown keyword")),
+            }
+        }
+        Ok(trace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op(semaphore: u64, value: u64) -> SemaphoreRecord {
+        SemaphoreRecord {
+            semaphore: vk::Semaphore::from_raw(semaphore),
+            value,
+            stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+        }
+    }
+    fn flush(queue: usize, submits: Vec<SubmitRecord>) -> FlushRecord {
+        FlushRecord {
+            queue: QueueIndex(queue),
+            family_index: queue as u32,
+            bind_sparse: Vec::new(),
+            submits,
+            fence: vk::Fence::from_raw(0x100 + queue as u64),
+        }
+    }
+    fn submit(waits: Vec<SemaphoreRecord>, signals: Vec<SemaphoreRecord>) -> SubmitRecord {
+        SubmitRecord {
+            wait_semaphores: waits,
+            command_buffers: vec![CommandBufferRecord {
+                command_buffer: vk::CommandBuffer::from_raw(0x10),
+                label: Some("shadow pass".to_string()),
+            }],
+            signal_semaphores: signals,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut trace = SubmissionTrace::new();
+        let mut first = flush(0, vec![submit(vec![op(1, 1)], vec![op(2, 1)])]);
+        first.bind_sparse.push(BindSparseRecord {
+            wait_semaphores: vec![],
+            binds: vec![SparseBindRecord {
+                target: SparseBindTarget::ImageOpaque(vk::Image::from_raw(0x20)),
+                bind_count: 3,
+            }],
+            signal_semaphores: vec![SemaphoreRecord {
+                semaphore: vk::Semaphore::from_raw(1),
+                value: 1,
+                stage_mask: vk::PipelineStageFlags2::empty(),
+            }],
+        });
+        trace.flushes.push(first);
+        trace
+            .flushes
+            .push(flush(1, vec![submit(vec![op(2, 1)], vec![])]));
+        let text = trace.to_string();
+        assert_eq!(SubmissionTrace::parse(&text).unwrap(), trace);
+        assert!(trace.validate().is_empty());
+    }
+
+    #[test]
+    fn detects_issues() {
+        let mut trace = SubmissionTrace::new();
+        trace.flushes.push(flush(
+            0,
+            vec![
+                submit(vec![op(1, 2)], vec![op(2, 2)]),
+                submit(vec![], vec![op(1, 2), op(2, 1)]),
+                submit(vec![op(3, 1)], vec![]),
+            ],
+        ));
+        let issues = trace.validate();
+        assert_eq!(
+            issues,
+            vec![
+                TraceIssue::NonIncreasingSignal {
+                    flush: 0,
+                    semaphore: vk::Semaphore::from_raw(2),
+                    previous: 2,
+                    value: 1,
+                },
+                TraceIssue::WaitBeforeSignalOnSameQueue {
+                    flush: 0,
+                    queue: QueueIndex(0),
+                    semaphore: vk::Semaphore::from_raw(1),
+                    value: 2,
+                },
+                TraceIssue::UnsignaledWait {
+                    flush: 0,
+                    semaphore: vk::Semaphore::from_raw(3),
+                    value: 1,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use std::sync::{Arc, Mutex};
+
+use ash::prelude::VkResult;
+
+use crate::command::recorder::{CommandBufferResource, ReferencedResource};
+
+use super::semaphore::TimelineSemaphoreOp;
+
+/// Resources retired while the GPU may still be using them.
+///
+/// A retired resource is kept alive until the timeline semaphore it was retired with reaches the
+/// specified value. The queue is polled by [`crate::queue::Queues::flush`] and by
+/// [`crate::frames::FrameManager::acquire`] of every frame manager created on the same device, so resources dropped by hand are released on the same
+/// schedule as the resources referenced by a [`crate::command::recorder::CommandExecutable`].
+pub struct DeferredDestructionQueue {
+    pending: Mutex<Vec<(TimelineSemaphoreOp, ReferencedResource)>>,
+}
+
+impl DeferredDestructionQueue {
+    pub fn new() -> Self {
+        Self {
+            pending: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Keep `resource` alive until `semaphore` was signaled.
+    pub fn retire<T: CommandBufferResource>(&self, resource: T, semaphore: TimelineSemaphoreOp) {
+        let resource = resource.command_buffer_resource();
+        if let ReferencedResource::Untracked = resource {
+            return;
+        }
+        self.pending.lock().unwrap().push((semaphore, resource));
+    }
+
+    /// Number of resources still waiting for the GPU.
+    pub fn len(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Release all resources whose timeline semaphore was signaled, in the order they were retired.
+    /// Returns the number of resources released.
+    pub fn poll(&self) -> VkResult<usize> {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.is_empty() {
+            return Ok(0);
+        }
+        let completed = take_completed(
+            &mut pending,
+            |(op, _)| (Arc::as_ptr(&op.semaphore), op.value),
+            |(op, _)| op.semaphore.value(),
+        )?;
+        // Drop the resources after releasing the lock, so that a destructor retiring more resources wouldn't deadlock.
+        drop(pending);
+        let num_completed = completed.len();
+        drop(completed);
+        Ok(num_completed)
+    }
+
+    /// Blocks until all retired resources can be released, and release them.
+    pub fn block(&self) -> VkResult<()> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        let ops: Vec<&TimelineSemaphoreOp> = pending.iter().map(|(op, _)| op).collect();
+        TimelineSemaphoreOp::block_many(&ops)?;
+        drop(ops);
+        drop(pending);
+        Ok(())
+    }
+}
+
+/// Remove the entries whose semaphore reached the target value, keeping both the removed and the remaining
+/// entries in retirement order. `target` returns the semaphore and the value an entry waits for, and
+/// `current_value` is only queried once for each semaphore. Nothing is removed if a query fails.
+fn take_completed<T, K: PartialEq>(
+    pending: &mut Vec<T>,
+    target: impl Fn(&T) -> (K, u64),
+    mut current_value: impl FnMut(&T) -> VkResult<u64>,
+) -> VkResult<Vec<T>> {
+    // Many resources are usually retired on the same semaphore, so we only query each semaphore once.
+    let mut values: Vec<(K, u64)> = Vec::new();
+    let mut reached: Vec<bool> = Vec::with_capacity(pending.len());
+    for entry in pending.iter() {
+        let (key, target_value) = target(entry);
+        let value = match values.iter().find(|(semaphore, _)| *semaphore == key) {
+            Some((_, value)) => *value,
+            None => {
+                let value = current_value(entry)?;
+                values.push((key, value));
+                value
+            }
+        };
+        reached.push(value >= target_value);
+    }
+    let mut completed = Vec::new();
+    let mut remaining = Vec::with_capacity(pending.len());
+    for (entry, reached) in pending.drain(..).zip(reached) {
+        if reached {
+            completed.push(entry);
+        } else {
+            remaining.push(entry);
+        }
+    }
+    *pending = remaining;
+    Ok(completed)
+}
+
+impl Default for DeferredDestructionQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::take_completed;
+    use ash::vk;
+
+    // (semaphore, target value, name)
+    type Entry = (u32, u64, &'static str);
+
+    #[test]
+    fn releases_reached_entries_in_retirement_order() {
+        let mut pending: Vec<Entry> = vec![
+            (0, 1, "a"),
+            (1, 5, "b"),
+            (0, 3, "c"),
+            (1, 2, "d"),
+            (0, 2, "e"),
+        ];
+        let mut queries = Vec::new();
+        let completed = take_completed(
+            &mut pending,
+            |&(semaphore, value, _)| (semaphore, value),
+            |&(semaphore, ..)| {
+                queries.push(semaphore);
+                Ok([2, 4][semaphore as usize])
+            },
+        )
+        .unwrap();
+        assert_eq!(queries, vec![0, 1], "Each semaphore is queried once");
+        let names = |entries: &[Entry]| entries.iter().map(|e| e.2).collect::<Vec<_>>();
+        assert_eq!(names(&completed), vec!["a", "d", "e"]);
+        assert_eq!(names(&pending), vec!["b", "c"]);
+
+        // The semaphores advance.
+        let completed = take_completed(
+            &mut pending,
+            |&(semaphore, value, _)| (semaphore, value),
+            |_| Ok(5),
+        )
+        .unwrap();
+        assert_eq!(names(&completed), vec!["b", "c"]);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn failed_query_keeps_everything() {
+        let mut pending: Vec<Entry> = vec![(0, 1, "a"), (1, 1, "b")];
+        let result = take_completed(
+            &mut pending,
+            |&(semaphore, value, _)| (semaphore, value),
+            |&(semaphore, ..)| {
+                if semaphore == 0 {
+                    Ok(1)
+                } else {
+                    Err(vk::Result::ERROR_DEVICE_LOST)
+                }
+            },
+        );
+        assert_eq!(result.err(), Some(vk::Result::ERROR_DEVICE_LOST));
+        assert_eq!(pending.len(), 2);
+    }
+}
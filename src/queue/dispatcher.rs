@@ -2,7 +2,7 @@ use std::{
     fmt::Debug,
     sync::{
         atomic::{AtomicUsize, Ordering},
-        Arc,
+        Arc, Mutex,
     },
 };
 
@@ -14,8 +14,13 @@ use crate::{command::recorder::CommandExecutable, fence::Fence, Device};
 use crate::command::shared_pool::SharedCommandPool;
 
 use super::{
+    capture::{
+        BindSparseRecord, CommandBufferRecord, FlushRecord, SemaphoreRecord, SparseBindRecord,
+        SparseBindTarget, SubmissionTrace, SubmitRecord,
+    },
     router::QueueIndex,
     semaphore::{Semaphore, TimelineSemaphoreOp},
+    validation::PendingBatch,
     Queue, QueueType,
 };
 
@@ -29,6 +34,9 @@ pub struct QueueDispatcher {
     assigned_queue_type: Option<QueueType>,
     commands: crossbeam_queue::SegQueue<QueueCommand>,
     command_count: AtomicUsize,
+    /// Serializes the submissions made through [`QueueDispatcher::submit_immediately`]. Flushes take
+    /// `&mut self`, so they can't overlap with those.
+    immediate_submission: Mutex<()>,
 
     #[cfg(feature = "shared_command_pool")]
     shared_command_pool: SharedCommandPool,
@@ -46,6 +54,7 @@ impl QueueDispatcher {
             assigned_queue_type: assigned_type,
             commands: crossbeam_queue::SegQueue::new(),
             command_count: AtomicUsize::new(0),
+            immediate_submission: Mutex::new(()),
             #[cfg(feature = "shared_command_pool")]
             shared_command_pool: SharedCommandPool::new(&queue),
             queue,
@@ -75,9 +84,35 @@ impl QueueDispatcher {
         }));
         self
     }
+    /// Submit `executables` right away instead of batching them until the next flush, and return a
+    /// fence signaled on their completion. The submission isn't ordered with the queue operations waiting
+    /// for the next flush. Meant for work that blocks on its results, like defragmentation.
+    pub(crate) fn submit_immediately(
+        &self,
+        executables: &[Arc<CommandExecutable>],
+    ) -> VkResult<Fence> {
+        let _guard = self.immediate_submission.lock().unwrap();
+        let fence = Fence::new(self.device().clone(), false)?;
+        let buffers: Vec<vk::CommandBuffer> = executables
+            .iter()
+            .map(|executable| executable.command_buffer.buffer)
+            .collect();
+        unsafe {
+            self.device().queue_submit(
+                self.queue.queue,
+                &[vk::SubmitInfo::builder().command_buffers(&buffers).build()],
+                fence.fence,
+            )?;
+        }
+        Ok(fence)
+    }
     pub fn is_empty(&self) -> bool {
         self.command_count.load(Ordering::Relaxed) == 0
     }
+    /// Number of queue operations waiting for the next flush.
+    pub fn pending_count(&self) -> usize {
+        self.command_count.load(Ordering::Relaxed)
+    }
 
     pub fn sparse_bind(
         &self,
@@ -100,41 +135,93 @@ impl QueueDispatcher {
 
     #[must_use = "Call block(), wait() or wait_detached() to ensure that the render resources are released after submission completion."]
     pub fn flush(&mut self) -> VkResult<Option<QueueSubmissionFence>> {
+        self.flush_captured(None)
+    }
+
+    /// Flush the queue, appending a record of the submitted operations to `trace` if provided.
+    #[must_use = "Call block(), wait() or wait_detached() to ensure that the render resources are released after submission completion."]
+    pub(crate) fn flush_captured(
+        &mut self,
+        trace: Option<&mut SubmissionTrace>,
+    ) -> VkResult<Option<QueueSubmissionFence>> {
+        match self.take_pending() {
+            Some(pending) => self.submit_pending(pending, trace).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Take all queue operations out of the dispatcher without submitting them.
+    pub(crate) fn take_pending(&mut self) -> Option<PendingCommands> {
         let num_submissions = *self.command_count.get_mut();
         if num_submissions == 0 {
-            return Ok(None);
+            return None;
         }
         *self.command_count.get_mut() = 0;
-        let mut submissions: Vec<Submission> = Vec::new();
-        let mut wait_semaphore_count: usize = 0;
-        let mut signal_semaphore_count: usize = 0;
-        let mut executables_count: usize = 0;
-
-        let mut bind_semaphore_count: usize = 0;
-        let mut buffer_bind_count: usize = 0;
-        let mut image_opaque_bind_count: usize = 0;
-        let mut image_bind_count: usize = 0;
-        let mut binds: Vec<BindSparse> = Vec::new();
-        {
-            while let Some(op) = self.commands.pop() {
-                match op {
-                    QueueCommand::Submit(op) => {
-                        wait_semaphore_count += op.wait_semaphores.len();
-                        signal_semaphore_count += op.signal_semaphores.len();
-                        executables_count += op.executables.len();
-                        submissions.push(op);
-                    }
-                    QueueCommand::BindSparse(bind) => {
-                        bind_semaphore_count +=
-                            bind.signal_semaphores.len() + bind.wait_semaphores.len();
-                        image_bind_count += bind.image_binds.len();
-                        image_opaque_bind_count += bind.image_opaque_binds.len();
-                        buffer_bind_count += bind.buffer_binds.len();
-                        binds.push(bind);
-                    }
+        let mut pending = PendingCommands::default();
+        while let Some(op) = self.commands.pop() {
+            match op {
+                QueueCommand::Submit(op) => {
+                    pending.wait_semaphore_count += op.wait_semaphores.len();
+                    pending.signal_semaphore_count += op.signal_semaphores.len();
+                    pending.executables_count += op.executables.len();
+                    pending.submissions.push(op);
+                }
+                QueueCommand::BindSparse(bind) => {
+                    pending.bind_semaphore_count +=
+                        bind.signal_semaphores.len() + bind.wait_semaphores.len();
+                    pending.image_bind_count += bind.image_binds.len();
+                    pending.image_opaque_bind_count += bind.image_opaque_binds.len();
+                    pending.buffer_bind_count += bind.buffer_binds.len();
+                    pending.binds.push(bind);
                 }
             }
         }
+        Some(pending)
+    }
+
+    /// Put queue operations taken out with [`QueueDispatcher::take_pending`] back, ahead of the operations
+    /// queued since.
+    pub(crate) fn restore_pending(&mut self, pending: PendingCommands) {
+        let newer: Vec<QueueCommand> = std::iter::from_fn(|| self.commands.pop()).collect();
+        *self.command_count.get_mut() += pending.binds.len() + pending.submissions.len();
+        for bind in pending.binds {
+            self.commands.push(QueueCommand::BindSparse(bind));
+        }
+        for submission in pending.submissions {
+            self.commands.push(QueueCommand::Submit(submission));
+        }
+        for command in newer {
+            self.commands.push(command);
+        }
+    }
+
+    /// Submit queue operations previously taken out with [`QueueDispatcher::take_pending`].
+    pub(crate) fn submit_pending(
+        &mut self,
+        pending: PendingCommands,
+        trace: Option<&mut SubmissionTrace>,
+    ) -> VkResult<QueueSubmissionFence> {
+        let PendingCommands {
+            submissions,
+            binds,
+            wait_semaphore_count,
+            signal_semaphore_count,
+            executables_count,
+            bind_semaphore_count,
+            buffer_bind_count,
+            image_opaque_bind_count,
+            image_bind_count,
+        } = pending;
+        let mut record = trace.map(|trace| {
+            trace.flushes.push(FlushRecord {
+                queue: self.index,
+                family_index: self.family_index(),
+                bind_sparse: binds.iter().map(BindSparse::record).collect(),
+                submits: submissions.iter().map(Submission::record).collect(),
+                fence: vk::Fence::null(),
+            });
+            trace.flushes.last_mut().unwrap()
+        });
         if !binds.is_empty() {
             unsafe {
                 self.queue_bind_sparse(
@@ -149,16 +236,18 @@ impl QueueDispatcher {
         // If there are still some unfenced submissions
         assert!(!submissions.is_empty());
         let fence = Fence::new(self.queue.device.clone(), false)?;
-        let task = unsafe {
+        if let Some(record) = record.as_mut() {
+            record.fence = fence.fence;
+        }
+        unsafe {
             self.queue_submit(
                 submissions,
                 fence,
                 wait_semaphore_count,
                 signal_semaphore_count,
                 executables_count,
-            )?
-        };
-        Ok(Some(task))
+            )
+        }
     }
 
     unsafe fn queue_bind_sparse(
@@ -456,6 +545,140 @@ struct BindSparse {
     image_binds: Box<[(vk::Image, Box<[vk::SparseImageMemoryBind]>)]>,
     signal_semaphores: Box<[SemaphoreOp]>,
 }
+
+impl StagedSemaphoreOp {
+    fn record(&self) -> SemaphoreRecord {
+        SemaphoreRecord {
+            semaphore: self.semaphore.semaphore,
+            value: self.value,
+            stage_mask: self.stage_mask,
+        }
+    }
+}
+impl SemaphoreOp {
+    fn record(&self) -> SemaphoreRecord {
+        SemaphoreRecord {
+            semaphore: self.semaphore.semaphore,
+            value: self.value,
+            stage_mask: vk::PipelineStageFlags2::empty(),
+        }
+    }
+}
+impl Submission {
+    fn record(&self) -> SubmitRecord {
+        SubmitRecord {
+            wait_semaphores: self.wait_semaphores.iter().map(|s| s.record()).collect(),
+            command_buffers: self
+                .executables
+                .iter()
+                .map(|exec| CommandBufferRecord {
+                    command_buffer: exec.command_buffer.buffer,
+                    label: exec.label().map(str::to_string),
+                })
+                .collect(),
+            signal_semaphores: self.signal_semaphores.iter().map(|s| s.record()).collect(),
+        }
+    }
+}
+impl BindSparse {
+    fn record(&self) -> BindSparseRecord {
+        let buffer_binds = self
+            .buffer_binds
+            .iter()
+            .map(|(buffer, binds)| SparseBindRecord {
+                target: SparseBindTarget::Buffer(*buffer),
+                bind_count: binds.len() as u32,
+            });
+        let image_opaque_binds =
+            self.image_opaque_binds
+                .iter()
+                .map(|(image, binds)| SparseBindRecord {
+                    target: SparseBindTarget::ImageOpaque(*image),
+                    bind_count: binds.len() as u32,
+                });
+        let image_binds = self
+            .image_binds
+            .iter()
+            .map(|(image, binds)| SparseBindRecord {
+                target: SparseBindTarget::Image(*image),
+                bind_count: binds.len() as u32,
+            });
+        BindSparseRecord {
+            wait_semaphores: self.wait_semaphores.iter().map(|s| s.record()).collect(),
+            binds: buffer_binds
+                .chain(image_opaque_binds)
+                .chain(image_binds)
+                .collect(),
+            signal_semaphores: self.signal_semaphores.iter().map(|s| s.record()).collect(),
+        }
+    }
+}
+/// Queue operations taken out of a [`QueueDispatcher`] but not yet submitted.
+#[derive(Default)]
+pub(crate) struct PendingCommands {
+    submissions: Vec<Submission>,
+    binds: Vec<BindSparse>,
+    wait_semaphore_count: usize,
+    signal_semaphore_count: usize,
+    executables_count: usize,
+    bind_semaphore_count: usize,
+    buffer_bind_count: usize,
+    image_opaque_bind_count: usize,
+    image_bind_count: usize,
+}
+
+impl PendingCommands {
+    /// The batches in the order they will be submitted. Sparse binding operations go first.
+    pub(crate) fn batches(&self, queue: QueueIndex) -> impl Iterator<Item = PendingBatch> + '_ {
+        let binds = self.binds.iter().map(move |bind| PendingBatch {
+            queue,
+            wait_semaphores: bind.wait_semaphores.iter().map(|s| s.record()).collect(),
+            signal_semaphores: bind.signal_semaphores.iter().map(|s| s.record()).collect(),
+        });
+        let submissions = self.submissions.iter().map(move |submission| PendingBatch {
+            queue,
+            wait_semaphores: submission
+                .wait_semaphores
+                .iter()
+                .map(|s| s.record())
+                .collect(),
+            signal_semaphores: submission
+                .signal_semaphores
+                .iter()
+                .map(|s| s.record())
+                .collect(),
+        });
+        binds.chain(submissions)
+    }
+
+    /// All timeline semaphore operations, as (semaphore, value, is_signal).
+    pub(crate) fn timeline_ops(&self) -> impl Iterator<Item = (&Arc<Semaphore>, u64, bool)> {
+        let binds = self.binds.iter().flat_map(|bind| {
+            let waits = bind
+                .wait_semaphores
+                .iter()
+                .map(|s| (&s.semaphore, s.value, false));
+            let signals = bind
+                .signal_semaphores
+                .iter()
+                .map(|s| (&s.semaphore, s.value, true));
+            waits.chain(signals)
+        });
+        let submissions = self.submissions.iter().flat_map(|submission| {
+            let waits = submission
+                .wait_semaphores
+                .iter()
+                .map(|s| (&s.semaphore, s.value, false));
+            let signals = submission
+                .signal_semaphores
+                .iter()
+                .map(|s| (&s.semaphore, s.value, true));
+            waits.chain(signals)
+        });
+        binds.chain(submissions).filter(|&(_, value, _)| value != 0)
+    }
+}
+
 enum QueueCommand {
     Submit(Submission),
     BindSparse(BindSparse),
@@ -1,11 +1,20 @@
+pub mod capture;
+mod deferred;
 mod dispatcher;
+pub use deferred::DeferredDestructionQueue;
 pub use dispatcher::{SemaphoreOp, StagedSemaphoreOp};
 mod router;
+mod selection;
 pub mod semaphore;
 use crate::{command::recorder::CommandExecutable, fence::Fence, Device};
 use ash::{prelude::VkResult, vk};
 pub use dispatcher::QueueDispatcher;
-pub use router::{QueueIndex, QueueType, Queues, QueuesCreateInfo};
+pub use router::{QueueIndex, QueueRouting, QueueType, Queues, QueuesCreateInfo};
+pub use selection::{
+    ExtraQueueSelection, FamilySharing, QueueSelectionError, QueueSelectionReport,
+    QueueTypeSelection, QueuesCreateInfoBuilder,
+};
+pub mod validation;
 use std::{
     future::{Future, IntoFuture},
     sync::Arc,
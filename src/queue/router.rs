@@ -1,9 +1,20 @@
-use super::{dispatcher::QueueDispatcher, Queue};
+use super::{
+    capture::SubmissionTrace,
+    deferred::DeferredDestructionQueue,
+    dispatcher::{PendingCommands, QueueDispatcher, QueueSubmissionFence},
+    selection::{QueueCreateRecord, QueuesCreateInfoBuilder},
+    semaphore::Semaphore,
+    validation::{FlushError, SubmissionHistory},
+    Queue,
+};
 use crate::{frames::AcquiredFrame, Device, PhysicalDevice};
 use ash::{prelude::VkResult, vk};
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum QueueType {
     Graphics = 0,
     Compute = 1,
@@ -22,29 +33,56 @@ impl QueueType {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct QueueIndex(pub(crate) usize);
 
 /// A collection of QueueDispatcher. It creates manages a number of QueueDispatcher based on the device-specific queue flags.
 /// On submission, it routes the submission to the queue with the minimal number of declared capabilities.
 ///
-/// The current implementation creates at most one queue for each queue family.
+/// One QueueDispatcher is created for each queue selected by [`QueuesCreateInfo`]. A queue type may have
+/// several queues, see [`QueuesCreateInfoBuilder::queues`]. [`Queues::route`] spreads work among them,
+/// and [`Queues::flush`] submits to each of them from a separate thread.
 pub struct Queues {
     queues: Vec<QueueDispatcher>,
-    queue_type_to_dispatcher: [u32; 4],
+    queue_type_to_dispatchers: [Vec<QueueIndex>; 4],
+    deferred_destruction: Arc<DeferredDestructionQueue>,
+    capture: Option<SubmissionTrace>,
+    submission_history: Option<SubmissionHistory>,
+    routing: QueueRouting,
+    next_queue: [AtomicUsize; 4],
+}
+
+/// How [`Queues::route`] distributes work among the queues of the same queue type.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum QueueRouting {
+    /// Always use the first queue of the queue type.
+    #[default]
+    First,
+    /// Cycle through the queues of the queue type.
+    RoundRobin,
+    /// Use the queue with the fewest operations waiting for the next flush.
+    LeastLoaded,
 }
 
 impl Queues {
+    /// The first queue of the queue type.
     pub fn of_type(&self, ty: QueueType) -> &QueueDispatcher {
-        let i = self.queue_type_to_dispatcher[ty as usize];
-        &self.queues[i as usize]
+        &self.queues[self.index_of_type(ty).0]
     }
     pub fn of_index(&self, index: QueueIndex) -> &QueueDispatcher {
         &self.queues[index.0]
     }
     pub fn index_of_type(&self, ty: QueueType) -> QueueIndex {
-        let i = self.queue_type_to_dispatcher[ty as usize];
-        QueueIndex(i as usize)
+        self.queue_type_to_dispatchers[ty as usize][0]
+    }
+    /// All queues of the queue type, in the order of the priorities given to [`QueuesCreateInfoBuilder::queues`].
+    pub fn indices_of_type(&self, ty: QueueType) -> &[QueueIndex] {
+        &self.queue_type_to_dispatchers[ty as usize]
+    }
+    /// The deferred destruction queue shared by all queues on this device.
+    /// It is also polled on frame acquire by the [`crate::frames::FrameManager`]s created on this device.
+    pub fn deferred_destruction(&self) -> &Arc<DeferredDestructionQueue> {
+        &self.deferred_destruction
     }
 }
 
@@ -52,23 +90,29 @@ impl Queues {
     // Safety: Can only be called once for each device.
     pub(crate) unsafe fn from_device(device: &Arc<Device>, create_info: &QueuesCreateInfo) -> Self {
         let queue_dispatchers: Vec<QueueDispatcher> = create_info
-            .create_infos
+            .queues
             .iter()
-            .zip(create_info.queue_family_to_types.iter())
             .enumerate()
-            .map(|(index, (queue_create_info, ty))| {
-                let queue = device.get_device_queue(queue_create_info.queue_family_index, 0); // We always just create at most one queue for each queue family
+            .map(|(index, record)| {
+                let queue = device.get_device_queue(record.family_index, record.queue_index);
                 let queue = Queue {
                     device: device.clone(),
                     queue,
-                    family_index: queue_create_info.queue_family_index,
+                    family_index: record.family_index,
                 };
-                QueueDispatcher::new(queue, *ty, QueueIndex(index))
+                QueueDispatcher::new(queue, record.ty, QueueIndex(index))
             })
             .collect();
+        let deferred_destruction = Arc::new(DeferredDestructionQueue::new());
+        device.set_deferred_destruction_queue(&deferred_destruction);
         Queues {
             queues: queue_dispatchers,
-            queue_type_to_dispatcher: create_info.queue_type_to_family,
+            queue_type_to_dispatchers: create_info.queue_type_to_queues.clone(),
+            deferred_destruction,
+            capture: None,
+            submission_history: None,
+            routing: QueueRouting::default(),
+            next_queue: Default::default(),
         }
     }
 
@@ -76,155 +120,267 @@ impl Queues {
     pub fn flush(&mut self) -> VkResult<blocking::Task<()>> {
         // We take ownership of AcquiredFrame here, ensuring that Swapchain Acquire occured before submitting command buffers.
         // Note that acquire and present calls should be interleaved. Always present your existing AcquiredFrame before acquiring the next one.
-        let mut submission = super::dispatcher::QueueSubmissionFence::new();
-        for dispatcher in self.queues.iter_mut() {
-            let new_submission = dispatcher.flush()?;
-            if let Some(new_submission) = new_submission {
-                submission.merge(new_submission);
+        let pending = self.take_pending();
+        if let Some(history) = self.submission_history.as_mut() {
+            for (_, pending) in pending.iter() {
+                history.record(pending);
             }
         }
+        let submission = self.submit_pending(pending)?;
+        self.poll_deferred_destruction();
         Ok(submission.wait())
     }
 
+    /// Like [`Queues::flush`], but checks the pending operations on all queues for waits that can
+    /// never be satisfied before submitting anything. Submitting those would hang the device.
+    ///
+    /// `external_binary_signals` are binary semaphores signaled outside of queue submissions,
+    /// such as [`AcquiredFrame::acquire_ready_semaphore`].
+    ///
+    /// The first call enables tracking of the timeline semaphore signals submitted by all later
+    /// flushes, including flushes through [`Queues::flush`]. Signal operations submitted before that
+    /// are only known once the GPU has reached them.
+    ///
+    /// When the check fails, nothing is submitted and the pending operations are put back in their queues,
+    /// so that they can be flushed once the missing signal operations were queued.
+    #[must_use]
+    pub fn flush_checked(
+        &mut self,
+        external_binary_signals: &[&Semaphore],
+    ) -> Result<blocking::Task<()>, FlushError> {
+        self.submission_history
+            .get_or_insert_with(SubmissionHistory::default)
+            .prune()?;
+        let pending = self.take_pending();
+        let history = self.submission_history.as_mut().unwrap();
+
+        let check = history
+            .checker(pending.iter().map(|(_, pending)| pending))
+            .map_err(FlushError::Vk)
+            .and_then(|mut checker| {
+                checker
+                    .external_binary_signals
+                    .extend(external_binary_signals.iter().map(|s| s.semaphore));
+                let batches: Vec<_> = pending
+                    .iter()
+                    .flat_map(|(index, pending)| pending.batches(*index))
+                    .collect();
+                checker.check(&batches).map_err(FlushError::Submission)
+            });
+        if let Err(err) = check {
+            tracing::error!(%err, "pending submissions were not flushed");
+            self.restore_pending(pending);
+            return Err(err);
+        }
+        for (_, pending) in pending.iter() {
+            history.record(pending);
+        }
+
+        let submission = self.submit_pending(pending)?;
+        self.poll_deferred_destruction();
+        Ok(submission.wait())
+    }
+
+    /// Release resources retired on timeline values that were reached since the last flush. The work was
+    /// already submitted at this point, so a failure is only logged and the resources are released later.
+    fn poll_deferred_destruction(&self) {
+        if let Err(err) = self.deferred_destruction.poll() {
+            tracing::error!(%err, "failed to poll the deferred destruction queue");
+        }
+    }
+
+    fn restore_pending(&mut self, pending: Vec<(QueueIndex, PendingCommands)>) {
+        for (index, pending) in pending {
+            self.queues[index.0].restore_pending(pending);
+        }
+    }
+
+    fn take_pending(&mut self) -> Vec<(QueueIndex, PendingCommands)> {
+        self.queues
+            .iter_mut()
+            .filter_map(|dispatcher| {
+                let index = dispatcher.index();
+                dispatcher.take_pending().map(|pending| (index, pending))
+            })
+            .collect()
+    }
+
+    /// Submit to each queue from its own thread. Submitting to different queues requires no
+    /// host synchronization, and it avoids serializing the driver overhead of all queues on one thread.
+    fn submit_pending(
+        &mut self,
+        pending: Vec<(QueueIndex, PendingCommands)>,
+    ) -> VkResult<QueueSubmissionFence> {
+        let capturing = self.capture.is_some();
+        // `pending` is sorted by queue index, so the dispatchers can be borrowed mutably one by one.
+        let mut dispatchers = self.queues.iter_mut();
+        let mut jobs: Vec<(&mut QueueDispatcher, PendingCommands)> = pending
+            .into_iter()
+            .map(|(index, pending)| {
+                let dispatcher = dispatchers
+                    .find(|dispatcher| dispatcher.index() == index)
+                    .unwrap();
+                (dispatcher, pending)
+            })
+            .collect();
+        let submit = |(dispatcher, pending): (&mut QueueDispatcher, PendingCommands)| {
+            let mut trace = capturing.then(SubmissionTrace::new);
+            dispatcher
+                .submit_pending(pending, trace.as_mut())
+                .map(|fence| (fence, trace))
+        };
+        let results: Vec<VkResult<(QueueSubmissionFence, Option<SubmissionTrace>)>> =
+            if jobs.len() <= 1 {
+                jobs.into_iter().map(submit).collect()
+            } else {
+                let first = jobs.remove(0);
+                std::thread::scope(|scope| {
+                    let handles: Vec<_> = jobs
+                        .into_iter()
+                        .map(|job| scope.spawn(move || submit(job)))
+                        .collect();
+                    std::iter::once(submit(first))
+                        .chain(handles.into_iter().map(|handle| handle.join().unwrap()))
+                        .collect()
+                })
+            };
+
+        let mut submission = QueueSubmissionFence::new();
+        for result in results {
+            let (new_submission, trace) = result?;
+            submission.merge(new_submission);
+            if let Some(capture) = self.capture.as_mut()
+                && let Some(mut trace) = trace
+            {
+                capture.flushes.append(&mut trace.flushes);
+            }
+        }
+        Ok(submission)
+    }
+
+    /// Set how [`Queues::route`] picks a queue among the queues of a queue type.
+    pub fn set_routing(&mut self, routing: QueueRouting) {
+        self.routing = routing;
+    }
+
+    /// Pick one of the queues of the queue type according to the current [`QueueRouting`].
+    pub fn route(&self, ty: QueueType) -> QueueIndex {
+        let queues = &self.queue_type_to_dispatchers[ty as usize];
+        if queues.len() == 1 {
+            return queues[0];
+        }
+        match self.routing {
+            QueueRouting::First => queues[0],
+            QueueRouting::RoundRobin => {
+                let i = self.next_queue[ty as usize].fetch_add(1, Ordering::Relaxed);
+                queues[i % queues.len()]
+            }
+            // Ties go to the queue listed first, which has the highest priority requested.
+            QueueRouting::LeastLoaded => *queues
+                .iter()
+                .min_by_key(|&&index| self.queues[index.0].pending_count())
+                .unwrap(),
+        }
+    }
+
+    /// Start recording every subsequent flush into a [`SubmissionTrace`].
+    /// Any trace currently being recorded is discarded.
+    pub fn start_capture(&mut self) {
+        self.capture = Some(SubmissionTrace::new());
+    }
+
+    /// Stop recording and return the trace recorded since [`Queues::start_capture`].
+    pub fn stop_capture(&mut self) -> Option<SubmissionTrace> {
+        self.capture.take()
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+
     pub fn present(
         &mut self,
         frames: &mut crate::frames::FrameManager,
         frame: AcquiredFrame,
     ) -> VkResult<()> {
         // We perform queue present after all dispatchers are flushed to ensure that queue present happens last.
-        let present_queue = self.queues[frame.present_queue_family as usize].queue.queue;
+        let present_queue = self
+            .queues
+            .iter()
+            .find(|dispatcher| dispatcher.family_index() == frame.present_queue_family)
+            .expect("No queue was created in the present queue family")
+            .queue
+            .queue;
         unsafe { frames.present(present_queue, frame) }
     }
 }
 
 pub struct QueuesCreateInfo {
     pub(crate) create_infos: Vec<vk::DeviceQueueCreateInfo>,
+    /// Queue priorities for each entry in `create_infos`, pointed to by `p_queue_priorities`.
+    #[allow(dead_code)]
+    priorities: Vec<Vec<f32>>,
+    pub(crate) queues: Vec<QueueCreateRecord>,
     pub(crate) queue_family_to_types: Vec<Option<QueueType>>,
     pub(crate) queue_type_to_family: [u32; 4],
+    pub(crate) queue_type_to_queues: [Vec<QueueIndex>; 4],
 }
 
 const QUEUE_PRIORITY_HIGH: f32 = 1.0;
 const QUEUE_PRIORITY_MID: f32 = 0.5;
-const QUEUE_PRIORITY_LOW: f32 = 0.1;
+pub(super) const QUEUE_PRIORITY_LOW: f32 = 0.1;
 
 impl QueuesCreateInfo {
+    /// Selects one queue for each queue type, and one low priority queue in every other queue family.
+    /// Use [`QueuesCreateInfoBuilder`] to customize the selection.
     pub fn find(physical_device: &PhysicalDevice) -> QueuesCreateInfo {
-        let available_queue_family = physical_device.get_queue_family_properties();
-        Self::find_with_queue_family_properties(&available_queue_family)
+        QueuesCreateInfoBuilder::new()
+            .build(physical_device)
+            .expect("No queue family supports graphics operations")
+            .0
     }
-    fn find_with_queue_family_properties(
-        available_queue_family: &[vk::QueueFamilyProperties],
-    ) -> QueuesCreateInfo {
-        let graphics_queue_family = available_queue_family
-            .iter()
-            .enumerate()
-            .filter(|&(_i, family)| family.queue_flags.contains(vk::QueueFlags::GRAPHICS))
-            .max_by_key(|&(_i, family)| {
-                let mut priority: i32 = 0;
-                if family.queue_flags.contains(vk::QueueFlags::COMPUTE) {
-                    priority -= 1;
-                }
-                if family.queue_flags.contains(vk::QueueFlags::SPARSE_BINDING) {
-                    priority -= 1;
-                }
-                priority
-            })
-            .unwrap()
-            .0 as u32;
-        let compute_queue_family = available_queue_family
-            .iter()
-            .enumerate()
-            .filter(|&(_id, family)| family.queue_flags.contains(vk::QueueFlags::COMPUTE))
-            .max_by_key(|&(_, family)| {
-                // Use first compute-capable queue family
-                let mut priority: i32 = 0;
-                if family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-                    priority -= 100;
-                }
-                if family.queue_flags.contains(vk::QueueFlags::SPARSE_BINDING) {
-                    priority -= 1;
-                }
-                priority
-            })
-            .unwrap()
-            .0 as u32;
-        let transfer_queue_family = available_queue_family
+    pub fn builder() -> QueuesCreateInfoBuilder {
+        QueuesCreateInfoBuilder::new()
+    }
+    pub(super) fn new(
+        queues: Vec<QueueCreateRecord>,
+        queue_family_to_types: Vec<Option<QueueType>>,
+        queue_type_to_family: [u32; 4],
+        queue_type_to_queues: [Vec<QueueIndex>; 4],
+    ) -> Self {
+        let mut families: Vec<u32> = queues.iter().map(|queue| queue.family_index).collect();
+        families.sort();
+        families.dedup();
+        // Queues are created in the order of their index within the family.
+        let priorities: Vec<Vec<f32>> = families
             .iter()
-            .enumerate()
-            .max_by_key(|&(_, family)| {
-                // Use first compute-capable queue family
-                let mut priority: i32 = 0;
-                if family.queue_flags.contains(vk::QueueFlags::TRANSFER) {
-                    priority += 100;
-                }
-                if family.queue_flags.contains(vk::QueueFlags::COMPUTE) {
-                    priority -= 10;
-                }
-                if family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-                    priority -= 20;
-                }
-                if family.queue_flags.contains(vk::QueueFlags::SPARSE_BINDING) {
-                    priority -= 1;
-                }
-                priority
+            .map(|&family_index| {
+                let mut family_queues: Vec<&QueueCreateRecord> = queues
+                    .iter()
+                    .filter(|queue| queue.family_index == family_index)
+                    .collect();
+                family_queues.sort_by_key(|queue| queue.queue_index);
+                family_queues.iter().map(|queue| queue.priority).collect()
             })
-            .unwrap()
-            .0 as u32;
-        let sparse_binding_queue_family = available_queue_family
+            .collect();
+        let create_infos = families
             .iter()
-            .enumerate()
-            .filter(|&(_id, family)| family.queue_flags.contains(vk::QueueFlags::SPARSE_BINDING))
-            .max_by_key(|&(_, family)| {
-                // Use first compute-capable queue family
-                let mut priority: i32 = 0;
-                if family.queue_flags.contains(vk::QueueFlags::TRANSFER) {
-                    priority -= 1;
-                }
-                if family.queue_flags.contains(vk::QueueFlags::COMPUTE) {
-                    priority -= 10;
-                }
-                if family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-                    priority -= 20;
-                }
-                priority
+            .zip(priorities.iter())
+            .map(|(&family_index, priorities)| vk::DeviceQueueCreateInfo {
+                flags: vk::DeviceQueueCreateFlags::empty(),
+                queue_family_index: family_index,
+                queue_count: priorities.len() as u32,
+                p_queue_priorities: priorities.as_ptr(),
+                ..Default::default()
             })
-            .unwrap()
-            .0 as u32;
-
-        let mut queue_family_to_types: Vec<Option<QueueType>> =
-            vec![None; available_queue_family.len()];
-        queue_family_to_types[graphics_queue_family as usize] = Some(QueueType::Graphics);
-        queue_family_to_types[compute_queue_family as usize] = Some(QueueType::Compute);
-        queue_family_to_types[transfer_queue_family as usize] = Some(QueueType::Transfer);
-        queue_family_to_types[sparse_binding_queue_family as usize] =
-            Some(QueueType::SparseBinding);
-
-        let queue_type_to_family: [u32; 4] = [
-            graphics_queue_family,
-            compute_queue_family,
-            transfer_queue_family,
-            sparse_binding_queue_family,
-        ];
-
-        let create_infos = queue_family_to_types
-            .iter()
-            .enumerate()
-            .map(
-                |(queue_family_index, queue_type)| vk::DeviceQueueCreateInfo {
-                    flags: vk::DeviceQueueCreateFlags::empty(),
-                    queue_family_index: queue_family_index as u32,
-                    queue_count: 1,
-                    p_queue_priorities: queue_type
-                        .map_or(&QUEUE_PRIORITY_LOW, |queue_type| queue_type.priority()),
-                    ..Default::default()
-                },
-            )
             .collect();
-
         QueuesCreateInfo {
             create_infos,
+            priorities,
+            queues,
             queue_family_to_types,
             queue_type_to_family,
+            queue_type_to_queues,
         }
     }
     pub fn queue_family_index_for_type(&self, ty: QueueType) -> u32 {
@@ -236,4 +392,8 @@ impl QueuesCreateInfo {
     ) -> Option<QueueType> {
         self.queue_family_to_types[queue_family_index as usize]
     }
+    /// The queue families with at least one queue to be created.
+    pub fn queue_family_indices(&self) -> impl Iterator<Item = u32> + '_ {
+        self.create_infos.iter().map(|info| info.queue_family_index)
+    }
 }
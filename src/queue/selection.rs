@@ -0,0 +1,734 @@
+//! Selection of the queues to create on a device.
+//!
+//! [`QueuesCreateInfo::find`] picks one queue per [`QueueType`] using a fixed set of heuristics.
+//! [`QueuesCreateInfoBuilder`] exposes the same heuristics while letting the application choose the number of
+//! queues and their priorities for each type, control how queue types share queue families, and request
+//! additional queues for work outside of the four queue types such as video decoding.
+use std::fmt::Display;
+
+use ash::vk;
+
+use super::router::{QueueIndex, QueueType, QUEUE_PRIORITY_LOW};
+use super::QueuesCreateInfo;
+use crate::PhysicalDevice;
+
+const QUEUE_TYPES: [QueueType; 4] = [
+    QueueType::Graphics,
+    QueueType::Compute,
+    QueueType::Transfer,
+    QueueType::SparseBinding,
+];
+
+/// How a queue type may share its queue family with the other queue types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FamilySharing {
+    /// Pick the queue family with the best score, even if another queue type already uses it.
+    Allowed,
+    /// Pick a queue family that no other queue type uses. Fails if there isn't one.
+    Forbidden,
+    /// Use the queues of another queue type.
+    SameAs(QueueType),
+}
+
+#[derive(Clone, Debug)]
+struct QueueTypeRequest {
+    priorities: Vec<f32>,
+    sharing: FamilySharing,
+}
+
+#[derive(Clone, Debug)]
+struct ExtraQueueRequest {
+    flags: vk::QueueFlags,
+    priorities: Vec<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QueueSelectionError {
+    /// A queue type was requested with no queues.
+    NoQueuesRequested(QueueType),
+    /// Queue priorities must be between 0.0 and 1.0.
+    InvalidPriority(f32),
+    /// No queue family supports the queue type.
+    Unsupported(QueueType),
+    /// The queue type requested [`FamilySharing::Forbidden`], but all suitable queue families were already taken.
+    NoDedicatedFamily(QueueType),
+    /// [`FamilySharing::SameAs`] refers to a queue type that itself uses [`FamilySharing::SameAs`].
+    SharingChain(QueueType),
+}
+
+impl Display for QueueSelectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueueSelectionError::NoQueuesRequested(ty) => {
+                write!(f, "no queues requested for {:?}", ty)
+            }
+            QueueSelectionError::InvalidPriority(priority) => {
+                write!(f, "queue priority {} is outside of [0.0, 1.0]", priority)
+            }
+            QueueSelectionError::Unsupported(ty) => {
+                write!(f, "no queue family supports {:?}", ty)
+            }
+            QueueSelectionError::NoDedicatedFamily(ty) => {
+                write!(f, "no dedicated queue family is available for {:?}", ty)
+            }
+            QueueSelectionError::SharingChain(ty) => {
+                write!(
+                    f,
+                    "{:?} shares queues with a type that shares queues itself",
+                    ty
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for QueueSelectionError {}
+
+/// The queues chosen for one [`QueueType`].
+#[derive(Clone, Debug)]
+pub struct QueueTypeSelection {
+    pub ty: QueueType,
+    pub family_index: u32,
+    pub family_flags: vk::QueueFlags,
+    /// Number of queues requested for this type.
+    pub requested: u32,
+    /// The queues assigned to this type. Might be fewer than requested, and might be shared with other types.
+    pub queues: Vec<QueueIndex>,
+    /// Other queue types using the same queue family.
+    pub shared_with: Vec<QueueType>,
+    /// Human readable explanations for the choices made.
+    pub notes: Vec<String>,
+}
+
+/// The queues chosen for a request made with [`QueuesCreateInfoBuilder::extra_queues`].
+#[derive(Clone, Debug)]
+pub struct ExtraQueueSelection {
+    pub flags: vk::QueueFlags,
+    /// None if no queue family supports `flags` or the queue families supporting it have no queues left.
+    pub family_index: Option<u32>,
+    pub requested: u32,
+    pub queues: Vec<QueueIndex>,
+    pub notes: Vec<String>,
+}
+
+/// Explains how the queues were selected.
+#[derive(Clone, Debug, Default)]
+pub struct QueueSelectionReport {
+    pub types: Vec<QueueTypeSelection>,
+    pub extra: Vec<ExtraQueueSelection>,
+}
+
+impl QueueSelectionReport {
+    pub fn of_type(&self, ty: QueueType) -> &QueueTypeSelection {
+        self.types
+            .iter()
+            .find(|selection| selection.ty == ty)
+            .unwrap()
+    }
+}
+
+impl Display for QueueSelectionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for selection in self.types.iter() {
+            writeln!(
+                f,
+                "{:?}: family {} ({:?}), {}/{} queues {:?}",
+                selection.ty,
+                selection.family_index,
+                selection.family_flags,
+                selection.queues.len(),
+                selection.requested,
+                selection.queues.iter().map(|q| q.0).collect::<Vec<_>>()
+            )?;
+            if !selection.shared_with.is_empty() {
+                writeln!(f, "  family shared with {:?}", selection.shared_with)?;
+            }
+            for note in selection.notes.iter() {
+                writeln!(f, "  {}", note)?;
+            }
+        }
+        for selection in self.extra.iter() {
+            match selection.family_index {
+                Some(family_index) => writeln!(
+                    f,
+                    "extra {:?}: family {}, {}/{} queues {:?}",
+                    selection.flags,
+                    family_index,
+                    selection.queues.len(),
+                    selection.requested,
+                    selection.queues.iter().map(|q| q.0).collect::<Vec<_>>()
+                )?,
+                None => writeln!(f, "extra {:?}: not available", selection.flags)?,
+            }
+            for note in selection.notes.iter() {
+                writeln!(f, "  {}", note)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds a [`QueuesCreateInfo`].
+///
+/// ```ignore
+/// let (queues_create_info, report) = QueuesCreateInfoBuilder::new()
+///     .queues(QueueType::Compute, &[1.0, 0.2])
+///     .sharing(QueueType::Transfer, FamilySharing::Forbidden)
+///     .extra_queues(vk::QueueFlags::VIDEO_DECODE_KHR, &[0.5])
+///     .build(&physical_device)?;
+/// println!("{}", report);
+/// ```
+#[derive(Clone, Debug)]
+pub struct QueuesCreateInfoBuilder {
+    types: [QueueTypeRequest; 4],
+    extra: Vec<ExtraQueueRequest>,
+    all_families: bool,
+}
+
+impl Default for QueuesCreateInfoBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QueuesCreateInfoBuilder {
+    /// One queue for each queue type at its default priority, and one low priority queue in every other queue family.
+    pub fn new() -> Self {
+        Self {
+            types: QUEUE_TYPES.map(|ty| QueueTypeRequest {
+                priorities: vec![*ty.priority()],
+                sharing: FamilySharing::Allowed,
+            }),
+            extra: Vec::new(),
+            all_families: true,
+        }
+    }
+    /// Request one queue for `ty` for each priority in `priorities`.
+    pub fn queues(&mut self, ty: QueueType, priorities: &[f32]) -> &mut Self {
+        self.types[ty as usize].priorities = priorities.to_vec();
+        self
+    }
+    pub fn sharing(&mut self, ty: QueueType, sharing: FamilySharing) -> &mut Self {
+        self.types[ty as usize].sharing = sharing;
+        self
+    }
+    /// Request additional queues from a queue family supporting `flags`, preferring families not used
+    /// by any queue type. The request is dropped if no queue family supports `flags`.
+    pub fn extra_queues(&mut self, flags: vk::QueueFlags, priorities: &[f32]) -> &mut Self {
+        self.extra.push(ExtraQueueRequest {
+            flags,
+            priorities: priorities.to_vec(),
+        });
+        self
+    }
+    /// Whether to create a low priority queue in the queue families not selected for anything else.
+    /// Defaults to true.
+    pub fn all_families(&mut self, all_families: bool) -> &mut Self {
+        self.all_families = all_families;
+        self
+    }
+    pub fn build(
+        &self,
+        physical_device: &PhysicalDevice,
+    ) -> Result<(QueuesCreateInfo, QueueSelectionReport), QueueSelectionError> {
+        self.build_with_queue_family_properties(&physical_device.get_queue_family_properties())
+    }
+
+    pub(crate) fn build_with_queue_family_properties(
+        &self,
+        families: &[vk::QueueFamilyProperties],
+    ) -> Result<(QueuesCreateInfo, QueueSelectionReport), QueueSelectionError> {
+        for (ty, request) in QUEUE_TYPES.iter().zip(self.types.iter()) {
+            if request.priorities.is_empty() && !matches!(request.sharing, FamilySharing::SameAs(_))
+            {
+                return Err(QueueSelectionError::NoQueuesRequested(*ty));
+            }
+            if let FamilySharing::SameAs(other) = request.sharing
+                && matches!(self.types[other as usize].sharing, FamilySharing::SameAs(_))
+            {
+                return Err(QueueSelectionError::SharingChain(*ty));
+            }
+        }
+        for priority in self
+            .types
+            .iter()
+            .flat_map(|request| request.priorities.iter())
+            .chain(
+                self.extra
+                    .iter()
+                    .flat_map(|request| request.priorities.iter()),
+            )
+        {
+            if !(0.0..=1.0).contains(priority) {
+                return Err(QueueSelectionError::InvalidPriority(*priority));
+            }
+        }
+
+        let mut state = SelectionState {
+            families,
+            queues: Vec::new(),
+            family_queues: vec![Vec::new(); families.len()],
+            family_types: vec![Vec::new(); families.len()],
+        };
+        let mut selections: Vec<Option<QueueTypeSelection>> = vec![None; 4];
+
+        // Types with their own queues first, then the types sharing queues with them.
+        for &ty in QUEUE_TYPES.iter() {
+            let request = &self.types[ty as usize];
+            let mut notes = Vec::new();
+            // Families taken by types requiring a dedicated family can't be shared.
+            let taken: Vec<u32> = (0..families.len() as u32)
+                .filter(|&i| {
+                    state.family_types[i as usize].iter().any(|&other| {
+                        self.types[other as usize].sharing == FamilySharing::Forbidden
+                    })
+                })
+                .collect();
+            let reserved: Vec<u32> = QUEUE_TYPES
+                .iter()
+                .filter(|&&other| {
+                    other as usize > ty as usize
+                        && self.types[other as usize].sharing == FamilySharing::Forbidden
+                })
+                .filter_map(|&other| best_family(families, other, &taken))
+                .chain(taken.iter().copied())
+                .collect();
+            let family_index = match request.sharing {
+                FamilySharing::SameAs(_) => continue,
+                FamilySharing::Forbidden => {
+                    let used: Vec<u32> = (0..families.len() as u32)
+                        .filter(|&i| !state.family_types[i as usize].is_empty())
+                        .collect();
+                    best_family(families, ty, &used).ok_or(
+                        if best_family(families, ty, &[]).is_some() {
+                            QueueSelectionError::NoDedicatedFamily(ty)
+                        } else {
+                            QueueSelectionError::Unsupported(ty)
+                        },
+                    )?
+                }
+                FamilySharing::Allowed => {
+                    // Leave the families wanted by types requiring a dedicated family to them, if possible.
+                    match best_family(families, ty, &reserved) {
+                        Some(family_index) => family_index,
+                        None => match best_family(families, ty, &taken) {
+                            Some(family_index) => family_index,
+                            None if ty == QueueType::Graphics => {
+                                return Err(QueueSelectionError::Unsupported(ty))
+                            }
+                            None if self.types[QueueType::Graphics as usize].sharing
+                                == FamilySharing::Forbidden =>
+                            {
+                                return Err(QueueSelectionError::NoDedicatedFamily(
+                                    QueueType::Graphics,
+                                ))
+                            }
+                            None => {
+                                // Fall back to the graphics queues, so that every queue type is routed somewhere.
+                                notes.push(
+                                    if best_family(families, ty, &[]).is_some() {
+                                        format!(
+                                            "the queue families supporting {:?} are dedicated to other queue types, using the graphics queue family",
+                                            ty
+                                        )
+                                    } else {
+                                        format!(
+                                            "no queue family supports {:?}, using the graphics queue family",
+                                            ty
+                                        )
+                                    },
+                                );
+                                selections[QueueType::Graphics as usize]
+                                    .as_ref()
+                                    .unwrap()
+                                    .family_index
+                            }
+                        },
+                    }
+                }
+            };
+            let queues = state.allocate(family_index, Some(ty), &request.priorities, &mut notes);
+            state.family_types[family_index as usize].push(ty);
+            selections[ty as usize] = Some(QueueTypeSelection {
+                ty,
+                family_index,
+                family_flags: families[family_index as usize].queue_flags,
+                requested: request.priorities.len() as u32,
+                queues,
+                shared_with: Vec::new(),
+                notes,
+            });
+        }
+        for &ty in QUEUE_TYPES.iter() {
+            let request = &self.types[ty as usize];
+            if let FamilySharing::SameAs(other) = request.sharing {
+                let other_selection = selections[other as usize].as_ref().unwrap();
+                let selection = QueueTypeSelection {
+                    ty,
+                    family_index: other_selection.family_index,
+                    family_flags: other_selection.family_flags,
+                    requested: request.priorities.len() as u32,
+                    queues: other_selection.queues.clone(),
+                    shared_with: Vec::new(),
+                    notes: vec![format!("sharing the queues of {:?}", other)],
+                };
+                state.family_types[selection.family_index as usize].push(ty);
+                selections[ty as usize] = Some(selection);
+            }
+        }
+        let mut types: Vec<QueueTypeSelection> =
+            selections.into_iter().map(Option::unwrap).collect();
+        for selection in types.iter_mut() {
+            selection.shared_with = state.family_types[selection.family_index as usize]
+                .iter()
+                .copied()
+                .filter(|&other| other != selection.ty)
+                .collect();
+        }
+
+        let mut extra = Vec::new();
+        for request in self.extra.iter() {
+            let mut notes = Vec::new();
+            let candidates = || {
+                (0..families.len() as u32).filter(|&i| {
+                    families[i as usize].queue_flags.contains(request.flags)
+                        && state.remaining(i) > 0
+                })
+            };
+            // Prefer families unused by the queue types, then families with the fewest other capabilities.
+            let family_index = candidates().min_by_key(|&i| {
+                (
+                    !state.family_types[i as usize].is_empty(),
+                    (families[i as usize].queue_flags & !request.flags)
+                        .as_raw()
+                        .count_ones(),
+                )
+            });
+            let Some(family_index) = family_index else {
+                notes.push(
+                    if families
+                        .iter()
+                        .any(|f| f.queue_flags.contains(request.flags))
+                    {
+                        "all queue families supporting the flags are out of queues".to_string()
+                    } else {
+                        "no queue family supports the flags".to_string()
+                    },
+                );
+                extra.push(ExtraQueueSelection {
+                    flags: request.flags,
+                    family_index: None,
+                    requested: request.priorities.len() as u32,
+                    queues: Vec::new(),
+                    notes,
+                });
+                continue;
+            };
+            if !state.family_types[family_index as usize].is_empty() {
+                notes.push(format!(
+                    "no dedicated queue family available, sharing the family of {:?}",
+                    state.family_types[family_index as usize]
+                ));
+            }
+            let queues = state.allocate(family_index, None, &request.priorities, &mut notes);
+            extra.push(ExtraQueueSelection {
+                flags: request.flags,
+                family_index: Some(family_index),
+                requested: request.priorities.len() as u32,
+                queues,
+                notes,
+            });
+        }
+
+        if self.all_families {
+            for family_index in 0..families.len() as u32 {
+                if state.family_queues[family_index as usize].is_empty() {
+                    state.allocate(family_index, None, &[QUEUE_PRIORITY_LOW], &mut Vec::new());
+                }
+            }
+        }
+
+        let mut queue_type_to_family = [0; 4];
+        let mut queue_type_to_queues: [Vec<QueueIndex>; 4] = Default::default();
+        let mut queue_family_to_types: Vec<Option<QueueType>> = vec![None; families.len()];
+        for selection in types.iter() {
+            queue_type_to_family[selection.ty as usize] = selection.family_index;
+            queue_type_to_queues[selection.ty as usize] = selection.queues.clone();
+            queue_family_to_types[selection.family_index as usize] = Some(selection.ty);
+        }
+        let create_info = QueuesCreateInfo::new(
+            state.queues,
+            queue_family_to_types,
+            queue_type_to_family,
+            queue_type_to_queues,
+        );
+        Ok((create_info, QueueSelectionReport { types, extra }))
+    }
+}
+
+/// A queue to be created on the device.
+#[derive(Clone, Debug)]
+pub(crate) struct QueueCreateRecord {
+    pub(crate) family_index: u32,
+    /// Index of the queue within its queue family.
+    pub(crate) queue_index: u32,
+    pub(crate) priority: f32,
+    pub(crate) ty: Option<QueueType>,
+}
+
+struct SelectionState<'a> {
+    families: &'a [vk::QueueFamilyProperties],
+    queues: Vec<QueueCreateRecord>,
+    family_queues: Vec<Vec<QueueIndex>>,
+    family_types: Vec<Vec<QueueType>>,
+}
+
+impl<'a> SelectionState<'a> {
+    fn remaining(&self, family_index: u32) -> u32 {
+        self.families[family_index as usize].queue_count
+            - self.family_queues[family_index as usize].len() as u32
+    }
+    /// Create queues in `family_index` for each priority. When the family runs out of queues,
+    /// the queues created before in the same family are shared instead.
+    fn allocate(
+        &mut self,
+        family_index: u32,
+        ty: Option<QueueType>,
+        priorities: &[f32],
+        notes: &mut Vec<String>,
+    ) -> Vec<QueueIndex> {
+        let granted = (priorities.len() as u32).min(self.remaining(family_index));
+        let mut queues = Vec::with_capacity(priorities.len());
+        for &priority in priorities[..granted as usize].iter() {
+            let index = QueueIndex(self.queues.len());
+            self.queues.push(QueueCreateRecord {
+                family_index,
+                queue_index: self.family_queues[family_index as usize].len() as u32,
+                priority,
+                ty,
+            });
+            self.family_queues[family_index as usize].push(index);
+            queues.push(index);
+        }
+        if granted < priorities.len() as u32 {
+            notes.push(format!(
+                "family {} has {} queues, {} requested queue(s) not created",
+                family_index,
+                self.families[family_index as usize].queue_count,
+                priorities.len() as u32 - granted
+            ));
+            if queues.is_empty() {
+                notes.push(format!(
+                    "sharing the existing queues of family {}",
+                    family_index
+                ));
+                queues.extend(self.family_queues[family_index as usize].iter().copied());
+            }
+        }
+        queues
+    }
+}
+
+/// The heuristics used to rank queue families for each queue type. Families with fewer unrelated
+/// capabilities are preferred, as they are more likely to run asynchronously to the other queues.
+fn family_score(ty: QueueType, flags: vk::QueueFlags) -> Option<i32> {
+    let mut priority: i32 = 0;
+    match ty {
+        QueueType::Graphics => {
+            if !flags.contains(vk::QueueFlags::GRAPHICS) {
+                return None;
+            }
+            if flags.contains(vk::QueueFlags::COMPUTE) {
+                priority -= 1;
+            }
+            if flags.contains(vk::QueueFlags::SPARSE_BINDING) {
+                priority -= 1;
+            }
+        }
+        QueueType::Compute => {
+            if !flags.contains(vk::QueueFlags::COMPUTE) {
+                return None;
+            }
+            if flags.contains(vk::QueueFlags::GRAPHICS) {
+                priority -= 100;
+            }
+            if flags.contains(vk::QueueFlags::SPARSE_BINDING) {
+                priority -= 1;
+            }
+        }
+        QueueType::Transfer => {
+            // Graphics and compute queues support transfer operations implicitly.
+            if flags.contains(vk::QueueFlags::TRANSFER) {
+                priority += 100;
+            }
+            if flags.contains(vk::QueueFlags::COMPUTE) {
+                priority -= 10;
+            }
+            if flags.contains(vk::QueueFlags::GRAPHICS) {
+                priority -= 20;
+            }
+            if flags.contains(vk::QueueFlags::SPARSE_BINDING) {
+                priority -= 1;
+            }
+        }
+        QueueType::SparseBinding => {
+            if !flags.contains(vk::QueueFlags::SPARSE_BINDING) {
+                return None;
+            }
+            if flags.contains(vk::QueueFlags::TRANSFER) {
+                priority -= 1;
+            }
+            if flags.contains(vk::QueueFlags::COMPUTE) {
+                priority -= 10;
+            }
+            if flags.contains(vk::QueueFlags::GRAPHICS) {
+                priority -= 20;
+            }
+        }
+    }
+    Some(priority)
+}
+
+/// The family with the highest score for `ty`, excluding the families in `excluded`.
+/// Ties are resolved in favor of the last family, matching the behavior of `Iterator::max_by_key`.
+fn best_family(
+    families: &[vk::QueueFamilyProperties],
+    ty: QueueType,
+    excluded: &[u32],
+) -> Option<u32> {
+    families
+        .iter()
+        .enumerate()
+        .filter(|&(i, family)| family.queue_count > 0 && !excluded.contains(&(i as u32)))
+        .filter_map(|(i, family)| family_score(ty, family.queue_flags).map(|score| (i, score)))
+        .max_by_key(|&(_, score)| score)
+        .map(|(i, _)| i as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn family(queue_flags: vk::QueueFlags, queue_count: u32) -> vk::QueueFamilyProperties {
+        vk::QueueFamilyProperties {
+            queue_flags,
+            queue_count,
+            ..Default::default()
+        }
+    }
+
+    fn families() -> Vec<vk::QueueFamilyProperties> {
+        vec![
+            family(
+                vk::QueueFlags::GRAPHICS
+                    | vk::QueueFlags::COMPUTE
+                    | vk::QueueFlags::TRANSFER
+                    | vk::QueueFlags::SPARSE_BINDING,
+                16,
+            ),
+            family(vk::QueueFlags::TRANSFER | vk::QueueFlags::SPARSE_BINDING, 2),
+            family(
+                vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER | vk::QueueFlags::SPARSE_BINDING,
+                8,
+            ),
+        ]
+    }
+
+    #[test]
+    fn default_selection() {
+        let (info, report) = QueuesCreateInfoBuilder::new()
+            .build_with_queue_family_properties(&families())
+            .unwrap();
+        assert_eq!(info.queue_family_index_for_type(QueueType::Graphics), 0);
+        assert_eq!(info.queue_family_index_for_type(QueueType::Compute), 2);
+        assert_eq!(info.queue_family_index_for_type(QueueType::Transfer), 1);
+        assert_eq!(
+            info.queue_family_index_for_type(QueueType::SparseBinding),
+            1
+        );
+        assert_eq!(
+            report.of_type(QueueType::SparseBinding).shared_with,
+            vec![QueueType::Transfer]
+        );
+        assert_eq!(info.create_infos.len(), 3);
+    }
+
+    #[test]
+    fn two_compute_queues() {
+        let (info, report) = QueuesCreateInfoBuilder::new()
+            .queues(QueueType::Compute, &[1.0, 0.2])
+            .build_with_queue_family_properties(&families())
+            .unwrap();
+        let compute = report.of_type(QueueType::Compute);
+        assert_eq!(compute.queues.len(), 2);
+        let create_info = info
+            .create_infos
+            .iter()
+            .find(|info| info.queue_family_index == 2)
+            .unwrap();
+        assert_eq!(create_info.queue_count, 2);
+        let priorities = unsafe { std::slice::from_raw_parts(create_info.p_queue_priorities, 2) };
+        assert_eq!(priorities, &[1.0, 0.2]);
+    }
+
+    #[test]
+    fn sharing_and_fallbacks() {
+        let (_, report) = QueuesCreateInfoBuilder::new()
+            .queues(QueueType::Transfer, &[1.0, 0.5, 0.1])
+            .sharing(QueueType::SparseBinding, FamilySharing::Forbidden)
+            .extra_queues(vk::QueueFlags::VIDEO_DECODE_KHR, &[0.5])
+            .build_with_queue_family_properties(&families())
+            .unwrap();
+        let transfer = report.of_type(QueueType::Transfer);
+        let sparse = report.of_type(QueueType::SparseBinding);
+        // The sparse binding queue requires a dedicated family, so the transfer queue doesn't take family 1.
+        assert_ne!(transfer.family_index, sparse.family_index);
+        assert!(sparse.shared_with.is_empty());
+        assert_eq!(report.extra[0].family_index, None);
+
+        let err = QueuesCreateInfoBuilder::new()
+            .sharing(QueueType::Compute, FamilySharing::Forbidden)
+            .sharing(QueueType::Transfer, FamilySharing::Forbidden)
+            .sharing(QueueType::SparseBinding, FamilySharing::Forbidden)
+            .queues(QueueType::Graphics, &[1.0])
+            .build_with_queue_family_properties(&families()[..2])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            QueueSelectionError::NoDedicatedFamily(QueueType::Compute)
+        );
+    }
+
+    #[test]
+    fn dedicated_family_is_not_shared_with_later_types() {
+        let families = [
+            family(
+                vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::SPARSE_BINDING,
+                16,
+            ),
+            family(vk::QueueFlags::TRANSFER | vk::QueueFlags::SPARSE_BINDING, 2),
+        ];
+        let (_, report) = QueuesCreateInfoBuilder::new()
+            .sharing(QueueType::Transfer, FamilySharing::Forbidden)
+            .build_with_queue_family_properties(&families)
+            .unwrap();
+        let transfer = report.of_type(QueueType::Transfer);
+        let sparse = report.of_type(QueueType::SparseBinding);
+        assert_eq!(transfer.family_index, 1);
+        assert!(transfer.shared_with.is_empty());
+        assert_eq!(sparse.family_index, 0);
+
+        // Without another family supporting sparse binding, fall back to the graphics family.
+        let (_, report) = QueuesCreateInfoBuilder::new()
+            .sharing(QueueType::Transfer, FamilySharing::Forbidden)
+            .build_with_queue_family_properties(&[
+                family(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE, 16),
+                families[1],
+            ])
+            .unwrap();
+        assert!(report.of_type(QueueType::Transfer).shared_with.is_empty());
+        let sparse = report.of_type(QueueType::SparseBinding);
+        assert_eq!(sparse.family_index, 0);
+        assert_eq!(sparse.notes.len(), 1);
+    }
+}
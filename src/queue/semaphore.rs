@@ -1,7 +1,11 @@
+#[cfg(unix)]
+use std::os::fd::{FromRawFd, IntoRawFd, OwnedFd};
 use std::{future::Future, sync::Arc};
 
 use ash::{prelude::VkResult, vk};
 
+#[cfg(unix)]
+use crate::resources::external::ExternalFdLoader;
 use crate::Device;
 
 use super::SemaphoreOp;
@@ -52,6 +56,88 @@ impl Drop for Semaphore {
     }
 }
 
+#[cfg(unix)]
+impl Semaphore {
+    /// Create a binary semaphore whose payload can be exported as `handle_types`.
+    pub fn new_exportable(
+        device: Arc<Device>,
+        handle_types: vk::ExternalSemaphoreHandleTypeFlags,
+    ) -> VkResult<Self> {
+        let export_info = vk::ExportSemaphoreCreateInfo {
+            handle_types,
+            ..Default::default()
+        };
+        let create_info = vk::SemaphoreCreateInfo {
+            p_next: &export_info as *const _ as *const std::ffi::c_void,
+            ..Default::default()
+        };
+        let semaphore = unsafe { device.create_semaphore(&create_info, None)? };
+        Ok(Self { device, semaphore })
+    }
+    /// Create a binary semaphore with the payload imported from `fd`. See [`Semaphore::import_fd`].
+    pub fn from_fd(
+        device: Arc<Device>,
+        loader: &ExternalFdLoader,
+        handle_type: vk::ExternalSemaphoreHandleTypeFlags,
+        fd: OwnedFd,
+        temporary: bool,
+    ) -> VkResult<Self> {
+        let semaphore = Self::new(device)?;
+        semaphore.import_fd(loader, handle_type, fd, temporary)?;
+        Ok(semaphore)
+    }
+    /// Export the payload of the semaphore. Exporting a `SYNC_FD` requires a pending signal operation,
+    /// and resets the semaphore to the unsignaled state.
+    pub fn export_fd(
+        &self,
+        loader: &ExternalFdLoader,
+        handle_type: vk::ExternalSemaphoreHandleTypeFlags,
+    ) -> VkResult<OwnedFd> {
+        let fd = unsafe {
+            loader
+                .semaphore
+                .get_semaphore_fd(&vk::SemaphoreGetFdInfoKHR {
+                    semaphore: self.semaphore,
+                    handle_type,
+                    ..Default::default()
+                })?
+        };
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+    /// Replace the payload of the semaphore with `fd`. A temporary import is undone after the next wait.
+    /// The fd is consumed, even on failure.
+    pub fn import_fd(
+        &self,
+        loader: &ExternalFdLoader,
+        handle_type: vk::ExternalSemaphoreHandleTypeFlags,
+        fd: OwnedFd,
+        temporary: bool,
+    ) -> VkResult<()> {
+        let flags = if temporary {
+            vk::SemaphoreImportFlags::TEMPORARY
+        } else {
+            vk::SemaphoreImportFlags::empty()
+        };
+        let raw_fd = fd.into_raw_fd();
+        let result = unsafe {
+            loader
+                .semaphore
+                .import_semaphore_fd(&vk::ImportSemaphoreFdInfoKHR {
+                    semaphore: self.semaphore,
+                    flags,
+                    handle_type,
+                    fd: raw_fd,
+                    ..Default::default()
+                })
+        };
+        if result.is_err() {
+            // Ownership of the fd is only transferred on success.
+            drop(unsafe { OwnedFd::from_raw_fd(raw_fd) });
+        }
+        result
+    }
+}
+
 #[repr(transparent)]
 pub struct TimelineSemaphore(Semaphore);
 
@@ -124,6 +210,44 @@ impl TimelineSemaphore {
     }
 }
 
+#[cfg(unix)]
+impl TimelineSemaphore {
+    /// Create a timeline semaphore that can be exported as an opaque fd.
+    pub fn new_exportable(device: Arc<Device>, initial_value: u64) -> VkResult<Self> {
+        let export_info = vk::ExportSemaphoreCreateInfo {
+            handle_types: vk::ExternalSemaphoreHandleTypeFlags::OPAQUE_FD,
+            ..Default::default()
+        };
+        let type_info = vk::SemaphoreTypeCreateInfo {
+            p_next: &export_info as *const _ as *const std::ffi::c_void,
+            semaphore_type: vk::SemaphoreType::TIMELINE,
+            initial_value,
+            ..Default::default()
+        };
+        let create_info = vk::SemaphoreCreateInfo {
+            p_next: &type_info as *const _ as *const std::ffi::c_void,
+            ..Default::default()
+        };
+        let semaphore = unsafe { device.create_semaphore(&create_info, None)? };
+        Ok(TimelineSemaphore(Semaphore { device, semaphore }))
+    }
+    /// Create a timeline semaphore sharing the payload exported as an opaque fd by another instance or process.
+    pub fn from_fd(device: Arc<Device>, loader: &ExternalFdLoader, fd: OwnedFd) -> VkResult<Self> {
+        let semaphore = Self::new(device, 0)?;
+        semaphore.0.import_fd(
+            loader,
+            vk::ExternalSemaphoreHandleTypeFlags::OPAQUE_FD,
+            fd,
+            false,
+        )?;
+        Ok(semaphore)
+    }
+    pub fn export_fd(&self, loader: &ExternalFdLoader) -> VkResult<OwnedFd> {
+        self.0
+            .export_fd(loader, vk::ExternalSemaphoreHandleTypeFlags::OPAQUE_FD)
+    }
+}
+
 #[derive(Clone)]
 pub struct TimelineSemaphoreOp {
     pub semaphore: Arc<TimelineSemaphore>,
@@ -0,0 +1,406 @@
+//! Deadlock and ordering checks for pending queue operations.
+//!
+//! The checks only look at the semaphore operations of each batch, so they run on the CPU before
+//! anything was submitted to the driver. See [`crate::queue::Queues::flush_checked`].
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use ash::{prelude::VkResult, vk};
+
+use super::{
+    capture::SemaphoreRecord, dispatcher::PendingCommands, semaphore::Semaphore, QueueIndex,
+};
+
+/// A batch of queue operations about to be submitted.
+/// Batches on the same queue execute in the order they appear in the list.
+#[derive(Clone, Debug)]
+pub struct PendingBatch {
+    pub queue: QueueIndex,
+    pub wait_semaphores: Vec<SemaphoreRecord>,
+    pub signal_semaphores: Vec<SemaphoreRecord>,
+}
+
+/// A problem that will cause the pending batches to never complete.
+/// `batch` is the index of the offending batch in the list given to [`SubmissionChecker::check`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubmissionIssue {
+    /// A timeline semaphore value was waited on, but neither the pending batches nor the
+    /// operations submitted before will signal it.
+    UnsignaledWait {
+        batch: usize,
+        queue: QueueIndex,
+        semaphore: vk::Semaphore,
+        value: u64,
+    },
+    /// A binary semaphore was waited on without a pending signal operation.
+    BinaryWaitWithoutSignal {
+        batch: usize,
+        queue: QueueIndex,
+        semaphore: vk::Semaphore,
+    },
+    /// The batches wait on each other in a cycle. Each batch in the list waits on the one before it,
+    /// and the first batch waits on the last one.
+    Cycle { batches: Vec<usize> },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubmissionCheckError {
+    pub issues: Vec<SubmissionIssue>,
+}
+
+impl std::fmt::Display for SubmissionCheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} issue(s) found in pending submissions:",
+            self.issues.len()
+        )?;
+        for issue in self.issues.iter() {
+            writeln!(f, "  {:?}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SubmissionCheckError {}
+
+#[derive(Debug)]
+pub enum FlushError {
+    Vk(vk::Result),
+    Submission(SubmissionCheckError),
+}
+
+impl From<vk::Result> for FlushError {
+    fn from(result: vk::Result) -> Self {
+        FlushError::Vk(result)
+    }
+}
+
+impl std::fmt::Display for FlushError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlushError::Vk(result) => std::fmt::Display::fmt(result, f),
+            FlushError::Submission(err) => std::fmt::Display::fmt(err, f),
+        }
+    }
+}
+
+impl std::error::Error for FlushError {}
+
+#[derive(Default, Clone, Debug)]
+pub struct SubmissionChecker {
+    /// The value each timeline semaphore will reach regardless of the pending batches, either
+    /// because it was already signaled or because the signal operation was already submitted.
+    pub signaled: HashMap<vk::Semaphore, u64>,
+    /// Binary semaphores signaled outside of queue submissions, for example by `vkAcquireNextImageKHR`.
+    pub external_binary_signals: HashSet<vk::Semaphore>,
+}
+
+impl SubmissionChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn check(&self, batches: &[PendingBatch]) -> Result<(), SubmissionCheckError> {
+        let mut issues = Vec::new();
+        // dependencies[i] = batches that must complete before batch i can complete.
+        let mut dependencies: Vec<Vec<usize>> = vec![Vec::new(); batches.len()];
+
+        // Batches on the same queue complete in submission order.
+        let mut last_on_queue: HashMap<QueueIndex, usize> = HashMap::new();
+        for (i, batch) in batches.iter().enumerate() {
+            if let Some(prev) = last_on_queue.insert(batch.queue, i) {
+                dependencies[i].push(prev);
+            }
+        }
+
+        // Timeline semaphore signals: semaphore -> (value, batch). Binary semaphore signals in submission order.
+        let mut timeline_signals: HashMap<vk::Semaphore, Vec<(u64, usize)>> = HashMap::new();
+        let mut binary_signals: HashMap<vk::Semaphore, Vec<usize>> = HashMap::new();
+        for (i, batch) in batches.iter().enumerate() {
+            for signal in batch.signal_semaphores.iter() {
+                if signal.value == 0 {
+                    binary_signals.entry(signal.semaphore).or_default().push(i);
+                } else {
+                    timeline_signals
+                        .entry(signal.semaphore)
+                        .or_default()
+                        .push((signal.value, i));
+                }
+            }
+        }
+
+        let mut binary_waits_seen: HashMap<vk::Semaphore, usize> = HashMap::new();
+        for (i, batch) in batches.iter().enumerate() {
+            for wait in batch.wait_semaphores.iter() {
+                if wait.value == 0 {
+                    // The n-th wait on a binary semaphore consumes the n-th signal.
+                    let n = binary_waits_seen.entry(wait.semaphore).or_insert(0);
+                    let mut index = *n;
+                    *n += 1;
+                    if self.external_binary_signals.contains(&wait.semaphore) {
+                        if index == 0 {
+                            continue;
+                        }
+                        index -= 1;
+                    }
+                    match binary_signals
+                        .get(&wait.semaphore)
+                        .and_then(|signals| signals.get(index))
+                    {
+                        Some(&signaler) => dependencies[i].push(signaler),
+                        None => issues.push(SubmissionIssue::BinaryWaitWithoutSignal {
+                            batch: i,
+                            queue: batch.queue,
+                            semaphore: wait.semaphore,
+                        }),
+                    }
+                    continue;
+                }
+                if self
+                    .signaled
+                    .get(&wait.semaphore)
+                    .map_or(false, |&value| value >= wait.value)
+                {
+                    continue;
+                }
+                // Timeline values only go up, so the wait is satisfied by the smallest value reaching it.
+                let signaler = timeline_signals.get(&wait.semaphore).and_then(|signals| {
+                    signals
+                        .iter()
+                        .filter(|&&(value, _)| value >= wait.value)
+                        .min_by_key(|&&(value, _)| value)
+                });
+                match signaler {
+                    Some(&(_, signaler)) => dependencies[i].push(signaler),
+                    None => issues.push(SubmissionIssue::UnsignaledWait {
+                        batch: i,
+                        queue: batch.queue,
+                        semaphore: wait.semaphore,
+                        value: wait.value,
+                    }),
+                }
+            }
+        }
+
+        issues.extend(
+            find_cycles(&dependencies)
+                .into_iter()
+                .map(|batches| SubmissionIssue::Cycle { batches }),
+        );
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(SubmissionCheckError { issues })
+        }
+    }
+}
+
+/// Timeline semaphore signals submitted by previous flushes that the GPU may not have reached yet.
+/// The semaphores are kept alive so that their handles can't be reused while they're tracked.
+#[derive(Default)]
+pub(crate) struct SubmissionHistory {
+    signals: HashMap<vk::Semaphore, (Arc<Semaphore>, u64)>,
+}
+
+impl SubmissionHistory {
+    /// Remember the timeline signal operations in `pending`, which are about to be submitted.
+    pub(crate) fn record(&mut self, pending: &PendingCommands) {
+        for (semaphore, value, is_signal) in pending.timeline_ops() {
+            if !is_signal {
+                continue;
+            }
+            let entry = self
+                .signals
+                .entry(semaphore.semaphore)
+                .or_insert_with(|| (semaphore.clone(), value));
+            entry.1 = entry.1.max(value);
+        }
+    }
+
+    /// Forget the signal operations that the GPU has already reached.
+    pub(crate) fn prune(&mut self) -> VkResult<()> {
+        let mut reached = Vec::new();
+        for (handle, (semaphore, value)) in self.signals.iter() {
+            let current_value = unsafe { semaphore.clone().as_timeline_arc() }.value()?;
+            if current_value >= *value {
+                reached.push(*handle);
+            }
+        }
+        for handle in reached {
+            self.signals.remove(&handle);
+        }
+        Ok(())
+    }
+
+    /// Build a checker for `pending`, taking into account the signal operations submitted before
+    /// and the current values of the waited semaphores.
+    pub(crate) fn checker<'a>(
+        &self,
+        pending: impl IntoIterator<Item = &'a PendingCommands>,
+    ) -> VkResult<SubmissionChecker> {
+        let mut checker = SubmissionChecker::new();
+        checker.signaled.extend(
+            self.signals
+                .iter()
+                .map(|(handle, (_, value))| (*handle, *value)),
+        );
+        for pending in pending {
+            for (semaphore, value, is_signal) in pending.timeline_ops() {
+                if is_signal
+                    || checker
+                        .signaled
+                        .get(&semaphore.semaphore)
+                        .map_or(false, |v| *v >= value)
+                {
+                    continue;
+                }
+                // The semaphore may have been signaled on the host.
+                let current_value = unsafe { semaphore.clone().as_timeline_arc() }.value()?;
+                let known = checker.signaled.entry(semaphore.semaphore).or_insert(0);
+                *known = (*known).max(current_value);
+            }
+        }
+        Ok(checker)
+    }
+}
+
+/// Returns one cycle for each back edge found in a depth first search of the dependency graph.
+fn find_cycles(dependencies: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum State {
+        Unvisited,
+        OnStack,
+        Done,
+    }
+    let mut state = vec![State::Unvisited; dependencies.len()];
+    let mut cycles = Vec::new();
+    // (node, index of the next dependency to visit)
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    for root in 0..dependencies.len() {
+        if state[root] != State::Unvisited {
+            continue;
+        }
+        state[root] = State::OnStack;
+        stack.push((root, 0));
+        while let Some((node, next)) = stack.last_mut() {
+            let node = *node;
+            if let Some(&dependency) = dependencies[node].get(*next) {
+                *next += 1;
+                match state[dependency] {
+                    State::Unvisited => {
+                        state[dependency] = State::OnStack;
+                        stack.push((dependency, 0));
+                    }
+                    State::OnStack => {
+                        let start = stack.iter().position(|&(n, _)| n == dependency).unwrap();
+                        cycles.push(stack[start..].iter().map(|&(n, _)| n).collect());
+                    }
+                    State::Done => (),
+                }
+            } else {
+                state[node] = State::Done;
+                stack.pop();
+            }
+        }
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    fn op(semaphore: u64, value: u64) -> SemaphoreRecord {
+        SemaphoreRecord {
+            semaphore: vk::Semaphore::from_raw(semaphore),
+            value,
+            stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+        }
+    }
+    fn batch(
+        queue: usize,
+        waits: Vec<SemaphoreRecord>,
+        signals: Vec<SemaphoreRecord>,
+    ) -> PendingBatch {
+        PendingBatch {
+            queue: QueueIndex(queue),
+            wait_semaphores: waits,
+            signal_semaphores: signals,
+        }
+    }
+
+    #[test]
+    fn valid_cross_queue_chain() {
+        let batches = [
+            batch(1, vec![op(1, 1)], vec![op(2, 1)]),
+            batch(0, vec![], vec![op(1, 1)]),
+            batch(0, vec![op(2, 1), op(3, 5)], vec![]),
+        ];
+        let mut checker = SubmissionChecker::new();
+        checker.signaled.insert(vk::Semaphore::from_raw(3), 5);
+        assert_eq!(checker.check(&batches), Ok(()));
+    }
+
+    #[test]
+    fn unsignaled_waits() {
+        let batches = [batch(0, vec![op(1, 2), op(2, 0)], vec![op(1, 1)])];
+        let err = SubmissionChecker::new().check(&batches).unwrap_err();
+        assert_eq!(
+            err.issues,
+            vec![
+                SubmissionIssue::UnsignaledWait {
+                    batch: 0,
+                    queue: QueueIndex(0),
+                    semaphore: vk::Semaphore::from_raw(1),
+                    value: 2,
+                },
+                SubmissionIssue::BinaryWaitWithoutSignal {
+                    batch: 0,
+                    queue: QueueIndex(0),
+                    semaphore: vk::Semaphore::from_raw(2),
+                },
+            ]
+        );
+
+        let mut checker = SubmissionChecker::new();
+        checker
+            .external_binary_signals
+            .insert(vk::Semaphore::from_raw(2));
+        checker.signaled.insert(vk::Semaphore::from_raw(1), 2);
+        assert_eq!(checker.check(&batches), Ok(()));
+    }
+
+    #[test]
+    fn cycle_between_queues() {
+        let batches = [
+            batch(0, vec![op(1, 1)], vec![op(2, 1)]),
+            batch(1, vec![op(2, 1)], vec![op(1, 1)]),
+        ];
+        let err = SubmissionChecker::new().check(&batches).unwrap_err();
+        assert_eq!(
+            err.issues,
+            vec![SubmissionIssue::Cycle {
+                batches: vec![0, 1]
+            }]
+        );
+    }
+
+    #[test]
+    fn wait_before_signal_on_same_queue() {
+        let batches = [
+            batch(0, vec![op(1, 1)], vec![]),
+            batch(0, vec![], vec![op(1, 1)]),
+        ];
+        let err = SubmissionChecker::new().check(&batches).unwrap_err();
+        assert_eq!(
+            err.issues,
+            vec![SubmissionIssue::Cycle {
+                batches: vec![0, 1]
+            }]
+        );
+    }
+}
@@ -74,6 +74,10 @@ impl crate::debug::DebugObject for RayTracingPipeline {
 
 impl Drop for RayTracingPipeline {
     fn drop(&mut self) {
+        self.loader
+            .device
+            .resource_registry()
+            .unregister(vk::ObjectType::PIPELINE, self.pipeline);
         unsafe {
             self.loader.device.destroy_pipeline(self.pipeline, None);
         }
@@ -332,6 +336,10 @@ impl RayTracingPipeline {
             .into_iter()
             .zip(sbt_layouts.into_iter())
             .map(|(pipeline, layout)| {
+                loader
+                    .device
+                    .resource_registry()
+                    .register(vk::ObjectType::PIPELINE, pipeline, None);
                 Ok(RayTracingPipeline {
                     loader: loader.clone(),
                     pipeline,
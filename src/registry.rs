@@ -0,0 +1,223 @@
+use ash::vk;
+use std::{
+    backtrace::Backtrace,
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc, Mutex,
+    },
+};
+
+const DISABLED: u8 = 0;
+const ENABLED: u8 = 1;
+const ENABLED_WITH_BACKTRACES: u8 = 2;
+
+/// Records the live objects of a [`crate::Device`].
+///
+/// The registry is disabled by default. Once enabled, buffers, buffer views, images, external memory,
+/// acceleration structures, shaders, pipelines, pipeline layouts and descriptor pools register themselves on
+/// creation and unregister themselves on drop. Objects created before the registry was enabled are not
+/// tracked. Call [`crate::Device::report_live_objects`] to find the objects still alive.
+pub struct ResourceRegistry {
+    mode: AtomicU8,
+    objects: Mutex<HashMap<(vk::ObjectType, u64), LiveObject>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LiveObject {
+    pub object_type: vk::ObjectType,
+    pub handle: u64,
+    /// Size of the memory backing the object, if known.
+    pub size: Option<vk::DeviceSize>,
+    /// The name set with [`crate::DebugObject::set_name`].
+    pub name: Option<String>,
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+impl Default for ResourceRegistry {
+    fn default() -> Self {
+        Self {
+            mode: AtomicU8::new(DISABLED),
+            objects: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl ResourceRegistry {
+    /// Start tracking objects. Capturing backtraces is slow, and should only be enabled when hunting leaks.
+    pub fn enable(&self, capture_backtraces: bool) {
+        let mode = if capture_backtraces {
+            ENABLED_WITH_BACKTRACES
+        } else {
+            ENABLED
+        };
+        self.mode.store(mode, Ordering::Relaxed);
+    }
+    /// Stop tracking new objects and forget the ones already tracked.
+    pub fn disable(&self) {
+        self.mode.store(DISABLED, Ordering::Relaxed);
+        self.objects.lock().unwrap().clear();
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.mode.load(Ordering::Relaxed) != DISABLED
+    }
+
+    pub(crate) fn register<H: vk::Handle>(
+        &self,
+        object_type: vk::ObjectType,
+        handle: H,
+        size: Option<vk::DeviceSize>,
+    ) {
+        let mode = self.mode.load(Ordering::Relaxed);
+        if mode == DISABLED {
+            return;
+        }
+        let handle = handle.as_raw();
+        let backtrace =
+            (mode == ENABLED_WITH_BACKTRACES).then(|| Arc::new(Backtrace::force_capture()));
+        self.objects.lock().unwrap().insert(
+            (object_type, handle),
+            LiveObject {
+                object_type,
+                handle,
+                size,
+                name: None,
+                backtrace,
+            },
+        );
+    }
+    pub(crate) fn unregister<H: vk::Handle>(&self, object_type: vk::ObjectType, handle: H) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.objects
+            .lock()
+            .unwrap()
+            .remove(&(object_type, handle.as_raw()));
+    }
+    /// Track the object registered as `old` under the `new` handle, for objects recreated in place.
+    pub(crate) fn rekey<H: vk::Handle>(&self, object_type: vk::ObjectType, old: H, new: H) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut objects = self.objects.lock().unwrap();
+        if let Some(mut object) = objects.remove(&(object_type, old.as_raw())) {
+            object.handle = new.as_raw();
+            objects.insert((object_type, object.handle), object);
+        }
+    }
+    pub(crate) fn set_name(&self, object_type: vk::ObjectType, handle: u64, name: Option<String>) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let Some(object) = self.objects.lock().unwrap().get_mut(&(object_type, handle)) {
+            object.name = name;
+        }
+    }
+
+    /// A snapshot of the objects currently alive.
+    pub fn report(&self) -> ResourceReport {
+        let mut objects: Vec<LiveObject> = self.objects.lock().unwrap().values().cloned().collect();
+        objects.sort_by_key(|object| (object.object_type.as_raw(), object.handle));
+        ResourceReport { objects }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ResourceReport {
+    pub objects: Vec<LiveObject>,
+}
+
+impl ResourceReport {
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+    /// Number of objects and their total size for each object type.
+    pub fn summary(&self) -> Vec<(vk::ObjectType, usize, vk::DeviceSize)> {
+        let mut summary: BTreeMap<i32, (usize, vk::DeviceSize)> = BTreeMap::new();
+        for object in self.objects.iter() {
+            let entry = summary.entry(object.object_type.as_raw()).or_default();
+            entry.0 += 1;
+            entry.1 += object.size.unwrap_or(0);
+        }
+        summary
+            .into_iter()
+            .map(|(ty, (count, size))| (vk::ObjectType::from_raw(ty), count, size))
+            .collect()
+    }
+}
+
+impl Display for ResourceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} live objects", self.objects.len())?;
+        for (ty, count, size) in self.summary() {
+            writeln!(f, "  {:?}: {} objects, {} bytes", ty, count, size)?;
+        }
+        for object in self.objects.iter() {
+            write!(f, "{:?} {:#x}", object.object_type, object.handle)?;
+            if let Some(name) = &object.name {
+                write!(f, " \"{}\"", name)?;
+            }
+            if let Some(size) = object.size {
+                write!(f, " {} bytes", size)?;
+            }
+            writeln!(f)?;
+            if let Some(backtrace) = &object.backtrace {
+                writeln!(f, "{}", backtrace)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResourceRegistry;
+    use ash::vk::{self, Handle};
+
+    #[test]
+    fn register_and_report() {
+        let registry = ResourceRegistry::default();
+        registry.register(vk::ObjectType::BUFFER, vk::Buffer::from_raw(1), Some(256));
+        assert!(
+            registry.report().is_empty(),
+            "Disabled registries track nothing"
+        );
+
+        registry.enable(false);
+        registry.register(vk::ObjectType::BUFFER, vk::Buffer::from_raw(1), Some(256));
+        registry.register(vk::ObjectType::BUFFER, vk::Buffer::from_raw(2), Some(64));
+        registry.register(vk::ObjectType::IMAGE, vk::Image::from_raw(1), Some(1024));
+        registry.set_name(vk::ObjectType::BUFFER, 2, Some("vertices".into()));
+
+        let report = registry.report();
+        assert_eq!(report.objects.len(), 3);
+        assert_eq!(report.objects[1].name.as_deref(), Some("vertices"));
+        assert_eq!(
+            report.summary(),
+            vec![
+                (vk::ObjectType::BUFFER, 2, 320),
+                (vk::ObjectType::IMAGE, 1, 1024)
+            ]
+        );
+
+        registry.unregister(vk::ObjectType::BUFFER, vk::Buffer::from_raw(1));
+        registry.unregister(vk::ObjectType::IMAGE, vk::Image::from_raw(1));
+        let report = registry.report();
+        assert_eq!(report.objects.len(), 1);
+        assert!(report.to_string().contains("\"vertices\""));
+
+        registry.rekey(
+            vk::ObjectType::BUFFER,
+            vk::Buffer::from_raw(2),
+            vk::Buffer::from_raw(3),
+        );
+        let report = registry.report();
+        assert_eq!(report.objects.len(), 1);
+        assert_eq!(report.objects[0].handle, 3);
+        assert_eq!(report.objects[0].name.as_deref(), Some("vertices"));
+        registry.unregister(vk::ObjectType::BUFFER, vk::Buffer::from_raw(3));
+        assert!(registry.report().is_empty());
+    }
+}
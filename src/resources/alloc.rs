@@ -6,7 +6,10 @@ use crate::{
     MemoryType,
 };
 
-use super::buffer::HasBuffer;
+use super::{
+    budget::{AllocatorUsage, ScenarioKind},
+    buffer::HasBuffer,
+};
 
 pub use vk::BufferUsageFlags;
 pub use vk_mem::{Alloc, Allocation, AllocationCreateFlags, MemoryUsage};
@@ -17,6 +20,7 @@ pub struct Allocator {
     memory_model: DeviceMemoryModel,
     pub(crate) heaps: Box<[MemoryHeap]>,
     pub(crate) types: Box<[MemoryType]>,
+    pub(crate) usage: AllocatorUsage,
 }
 impl crate::HasDevice for Allocator {
     fn device(&self) -> &Arc<Device> {
@@ -24,6 +28,7 @@ impl crate::HasDevice for Allocator {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DeviceMemoryModel {
     Integrated,
     /// Discrete GPU without HOST_VISIBLE DEVICE_LOCAL memory
@@ -45,6 +50,9 @@ impl Allocator {
         {
             allocator_flags |= vk_mem::AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS;
         }
+        if device.is_extension_enabled(vk::ExtMemoryBudgetFn::name()) {
+            allocator_flags |= vk_mem::AllocatorCreateFlags::EXT_MEMORY_BUDGET;
+        }
 
         let allocator = vk_mem::Allocator::new(
             vk_mem::AllocatorCreateInfo::new(
@@ -89,9 +97,14 @@ impl Allocator {
             heaps,
             types,
             memory_model,
+            usage: AllocatorUsage::default(),
         }
     }
 
+    pub fn memory_model(&self) -> DeviceMemoryModel {
+        self.memory_model
+    }
+
     pub(crate) fn create_info_by_scenario(
         &self,
         flags: vk_mem::AllocationCreateFlags,
@@ -189,9 +202,15 @@ impl Allocator {
             self.allocator
                 .allocate_memory(memory_requirements, create_info)
         }?;
+        self.check_budget_after_allocation();
         Ok(allocation)
     }
 
+    /// Free memory allocated with [`Allocator::allocate_memory`].
+    pub unsafe fn free_memory(&self, allocation: Allocation) {
+        self.allocator.free_memory(allocation)
+    }
+
     pub fn allocate_buffer(self: &Arc<Self>, request: &BufferRequest) -> VkResult<MemBuffer> {
         let build_info = vk::BufferCreateInfo::builder()
             .size(request.size)
@@ -211,12 +230,18 @@ impl Allocator {
                 )
             }
         }?;
-        let (memory_flags, ptr) = unsafe {
+        let (memory_flags, ptr, allocation_size) = unsafe {
             let allocation_info = self.allocator.get_allocation_info(&allocation).unwrap();
             let memory_flags = self.types[allocation_info.memory_type as usize].property_flags;
             let ptr = allocation_info.mapped_data;
-            (memory_flags, ptr)
+            (memory_flags, ptr, allocation_info.size)
         };
+        let scenario = request.scenario.kind();
+        self.usage.record_allocation(scenario, allocation_size);
+        self.check_budget_after_allocation();
+        self.device
+            .resource_registry()
+            .register(vk::ObjectType::BUFFER, buffer, Some(allocation_size));
         let device_address = if request
             .usage
             .contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS)
@@ -240,6 +265,11 @@ impl Allocator {
             memory_flags,
             ptr,
             device_address,
+            scenario,
+            allocation_size,
+            usage: request.usage,
+            sharing_mode: request.sharing_mode,
+            queue_families: request.queue_families.into(),
         })
     }
 
@@ -359,8 +389,14 @@ pub struct MemBuffer {
     size: u64,
     alignment: u64,
     pub memory_flags: vk::MemoryPropertyFlags,
-    device_address: vk::DeviceAddress,
+    pub(super) device_address: vk::DeviceAddress,
     pub ptr: *mut c_void,
+    scenario: ScenarioKind,
+    allocation_size: u64,
+    // Kept so that the buffer can be recreated when its memory is moved.
+    pub(super) usage: vk::BufferUsageFlags,
+    pub(super) sharing_mode: vk::SharingMode,
+    pub(super) queue_families: Box<[u32]>,
 }
 unsafe impl Send for MemBuffer {}
 unsafe impl Sync for MemBuffer {}
@@ -370,6 +406,9 @@ impl HasBuffer for MemBuffer {
     fn raw_buffer(&self) -> vk::Buffer {
         self.buffer
     }
+    fn buffer_size(&self) -> Option<vk::DeviceSize> {
+        Some(self.size)
+    }
 }
 
 impl HasDevice for MemBuffer {
@@ -387,11 +426,18 @@ impl crate::debug::DebugObject for MemBuffer {
 
 impl Drop for MemBuffer {
     fn drop(&mut self) {
+        self.allocator
+            .device()
+            .resource_registry()
+            .unregister(vk::ObjectType::BUFFER, self.buffer);
         unsafe {
             let mut memory: Allocation = std::mem::zeroed();
             std::mem::swap(&mut memory, &mut self.memory);
             self.allocator.allocator.destroy_buffer(self.buffer, memory)
         }
+        self.allocator
+            .usage
+            .record_free(self.scenario, self.allocation_size);
     }
 }
 
@@ -0,0 +1,243 @@
+use ash::{prelude::VkResult, vk};
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    alloc::{Allocator, BufferRequest, MemBuffer, MemoryAllocScenario},
+    buffer::HasBuffer,
+};
+use crate::command::recorder::CommandBufferResource;
+
+/// Sub-allocates aligned ranges from a few large `SHADER_DEVICE_ADDRESS` buffers.
+///
+/// Use this for the many small buffers that are only referenced by device address, such as
+/// geometry, materials and AABBs for ray tracing, to avoid creating a `vk::Buffer` for each of them.
+pub struct BufferArena {
+    allocator: Arc<Allocator>,
+    usage: vk::BufferUsageFlags,
+    scenario: MemoryAllocScenario,
+    block_size: u64,
+    blocks: Mutex<Vec<ArenaBlock>>,
+}
+
+struct ArenaBlock {
+    buffer: Arc<MemBuffer>,
+    free_list: FreeList,
+}
+
+/// A range of a [`BufferArena`] block. The range is returned to the arena when dropped.
+pub struct ArenaBuffer {
+    arena: Arc<BufferArena>,
+    buffer: Arc<MemBuffer>,
+    offset: u64,
+    size: u64,
+}
+
+impl BufferArena {
+    /// Requests larger than `block_size` get a dedicated block.
+    pub fn new(
+        allocator: Arc<Allocator>,
+        usage: vk::BufferUsageFlags,
+        scenario: MemoryAllocScenario,
+        block_size: u64,
+    ) -> Self {
+        Self {
+            allocator,
+            usage: usage | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            scenario,
+            block_size,
+            blocks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Allocate `size` bytes whose device address is a multiple of `alignment`.
+    pub fn allocate(self: &Arc<Self>, size: u64, alignment: u64) -> VkResult<ArenaBuffer> {
+        assert!(alignment.is_power_of_two());
+        let size = size.max(1);
+        let mut blocks = self.blocks.lock().unwrap();
+        for block in blocks.iter_mut() {
+            let base = block.buffer.device_address;
+            if let Some(offset) = block.free_list.allocate(size, alignment, base) {
+                return Ok(ArenaBuffer {
+                    arena: self.clone(),
+                    buffer: block.buffer.clone(),
+                    offset,
+                    size,
+                });
+            }
+        }
+        let block_size = self.block_size.max(size);
+        let buffer = self.allocator.allocate_buffer(&BufferRequest {
+            size: block_size,
+            alignment,
+            usage: self.usage,
+            scenario: self.scenario.clone(),
+            allocation_flags: vk_mem::AllocationCreateFlags::MAPPED,
+            ..Default::default()
+        })?;
+        let buffer = Arc::new(buffer);
+        let mut free_list = FreeList::new(block_size);
+        let offset = free_list
+            .allocate(size, alignment, buffer.device_address)
+            .unwrap();
+        blocks.push(ArenaBlock {
+            buffer: buffer.clone(),
+            free_list,
+        });
+        Ok(ArenaBuffer {
+            arena: self.clone(),
+            buffer,
+            offset,
+            size,
+        })
+    }
+
+    /// Release the blocks that have no ranges allocated from them.
+    pub fn trim(&self) {
+        self.blocks
+            .lock()
+            .unwrap()
+            .retain(|block| !block.free_list.is_empty());
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.lock().unwrap().len()
+    }
+
+    fn free(&self, buffer: &Arc<MemBuffer>, offset: u64, size: u64) {
+        let mut blocks = self.blocks.lock().unwrap();
+        let block = blocks
+            .iter_mut()
+            .find(|block| Arc::ptr_eq(&block.buffer, buffer))
+            .expect("Range freed to the wrong arena");
+        block.free_list.free(offset, size);
+    }
+}
+
+impl ArenaBuffer {
+    /// The block this range was allocated from.
+    pub fn buffer(&self) -> &Arc<MemBuffer> {
+        &self.buffer
+    }
+    /// Offset of the range in [`ArenaBuffer::buffer`].
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+    pub fn device_address(&self) -> vk::DeviceAddress {
+        self.buffer.device_address + self.offset
+    }
+    pub fn descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo {
+            buffer: self.buffer.buffer,
+            offset: self.offset,
+            range: self.size,
+        }
+    }
+    /// The mapped range. Panics if the arena memory isn't host visible.
+    pub fn get_mut(&self) -> &mut [u8] {
+        &mut self.buffer.get_mut()[self.offset as usize..(self.offset + self.size) as usize]
+    }
+}
+
+impl HasBuffer for ArenaBuffer {
+    fn raw_buffer(&self) -> vk::Buffer {
+        self.buffer.buffer
+    }
+}
+
+impl CommandBufferResource for ArenaBuffer {}
+
+impl Drop for ArenaBuffer {
+    fn drop(&mut self) {
+        self.arena.free(&self.buffer, self.offset, self.size);
+    }
+}
+
+/// Sorted, coalesced list of free ranges in a block.
+struct FreeList {
+    size: u64,
+    ranges: Vec<Range<u64>>,
+}
+
+impl FreeList {
+    fn new(size: u64) -> Self {
+        Self {
+            size,
+            ranges: vec![0..size],
+        }
+    }
+    /// Whether nothing is allocated.
+    fn is_empty(&self) -> bool {
+        self.ranges.len() == 1 && self.ranges[0] == (0..self.size)
+    }
+    /// First fit. The returned offset plus `base` is a multiple of `alignment`.
+    fn allocate(&mut self, size: u64, alignment: u64, base: u64) -> Option<u64> {
+        let (index, start) = self.ranges.iter().enumerate().find_map(|(i, range)| {
+            let start = (base + range.start).next_multiple_of(alignment) - base;
+            (start + size <= range.end).then_some((i, start))
+        })?;
+        let range = self.ranges[index].clone();
+        let mut replacement = Vec::with_capacity(2);
+        if range.start < start {
+            replacement.push(range.start..start);
+        }
+        if start + size < range.end {
+            replacement.push(start + size..range.end);
+        }
+        self.ranges.splice(index..index + 1, replacement);
+        Some(start)
+    }
+    fn free(&mut self, offset: u64, size: u64) {
+        let end = offset + size;
+        let index = self.ranges.partition_point(|range| range.start < offset);
+        let merge_prev = index > 0 && self.ranges[index - 1].end == offset;
+        let merge_next = index < self.ranges.len() && self.ranges[index].start == end;
+        match (merge_prev, merge_next) {
+            (true, true) => {
+                self.ranges[index - 1].end = self.ranges[index].end;
+                self.ranges.remove(index);
+            }
+            (true, false) => self.ranges[index - 1].end = end,
+            (false, true) => self.ranges[index].start = offset,
+            (false, false) => self.ranges.insert(index, offset..end),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FreeList;
+
+    #[test]
+    fn allocate_aligned() {
+        let mut list = FreeList::new(1024);
+        assert_eq!(list.allocate(10, 1, 0), Some(0));
+        assert_eq!(list.allocate(16, 64, 0), Some(64));
+        assert_eq!(list.ranges, vec![10..64, 80..1024]);
+        // Alignment is relative to the base device address.
+        assert_eq!(list.allocate(8, 64, 32), Some(32));
+        assert_eq!(list.ranges, vec![10..32, 40..64, 80..1024]);
+        assert_eq!(list.allocate(2048, 1, 0), None);
+    }
+
+    #[test]
+    fn free_coalesces() {
+        let mut list = FreeList::new(300);
+        let a = list.allocate(100, 1, 0).unwrap();
+        let b = list.allocate(100, 1, 0).unwrap();
+        let c = list.allocate(100, 1, 0).unwrap();
+        assert!(list.ranges.is_empty());
+        list.free(a, 100);
+        list.free(c, 100);
+        assert_eq!(list.ranges, vec![0..100, 200..300]);
+        assert!(!list.is_empty());
+        list.free(b, 100);
+        assert_eq!(list.ranges, vec![0..300]);
+        assert!(list.is_empty());
+    }
+}
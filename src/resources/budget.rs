@@ -0,0 +1,290 @@
+use ash::{prelude::VkResult, vk};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+};
+
+use super::alloc::{Allocator, MemoryAllocScenario};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ScenarioKind {
+    StagingBuffer,
+    DeviceAccess,
+    AssetBuffer,
+    DynamicUniform,
+    DynamicStorage,
+    Custom,
+}
+
+impl ScenarioKind {
+    pub const ALL: [ScenarioKind; 6] = [
+        ScenarioKind::StagingBuffer,
+        ScenarioKind::DeviceAccess,
+        ScenarioKind::AssetBuffer,
+        ScenarioKind::DynamicUniform,
+        ScenarioKind::DynamicStorage,
+        ScenarioKind::Custom,
+    ];
+}
+
+impl MemoryAllocScenario {
+    pub fn kind(&self) -> ScenarioKind {
+        match self {
+            MemoryAllocScenario::StagingBuffer => ScenarioKind::StagingBuffer,
+            MemoryAllocScenario::DeviceAccess => ScenarioKind::DeviceAccess,
+            MemoryAllocScenario::AssetBuffer => ScenarioKind::AssetBuffer,
+            MemoryAllocScenario::DynamicUniform => ScenarioKind::DynamicUniform,
+            MemoryAllocScenario::DynamicStorage => ScenarioKind::DynamicStorage,
+            MemoryAllocScenario::Custom { .. } => ScenarioKind::Custom,
+        }
+    }
+}
+
+/// Budget and usage of a memory heap.
+#[derive(Clone, Copy, Debug)]
+pub struct HeapBudget {
+    pub heap_index: u32,
+    pub flags: vk::MemoryHeapFlags,
+    pub size: vk::DeviceSize,
+    /// Estimated amount of memory the process can use on this heap.
+    /// Reported by the driver when VK_EXT_memory_budget is enabled, and estimated by vk_mem otherwise.
+    pub budget: vk::DeviceSize,
+    /// Estimated memory usage of the process on this heap, including allocations not made by vk_mem.
+    pub usage: vk::DeviceSize,
+    /// Bytes allocated from Vulkan in memory blocks by vk_mem.
+    pub block_bytes: vk::DeviceSize,
+    /// Bytes occupied by allocations within the memory blocks.
+    pub allocation_bytes: vk::DeviceSize,
+    pub block_count: u32,
+    pub allocation_count: u32,
+}
+
+impl HeapBudget {
+    /// usage / budget
+    pub fn usage_ratio(&self) -> f32 {
+        if self.budget == 0 {
+            return 0.0;
+        }
+        self.usage as f32 / self.budget as f32
+    }
+}
+
+/// Statistics of a set of vk_mem memory blocks.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemoryStatistics {
+    pub block_count: u32,
+    pub allocation_count: u32,
+    pub block_bytes: vk::DeviceSize,
+    pub allocation_bytes: vk::DeviceSize,
+    pub unused_range_count: u32,
+    pub allocation_size_min: vk::DeviceSize,
+    pub allocation_size_max: vk::DeviceSize,
+    pub unused_range_size_min: vk::DeviceSize,
+    /// The largest free block.
+    pub unused_range_size_max: vk::DeviceSize,
+}
+
+impl MemoryStatistics {
+    fn from_vma(stats: &vk_mem::ffi::VmaDetailedStatistics) -> Self {
+        Self {
+            block_count: stats.statistics.blockCount,
+            allocation_count: stats.statistics.allocationCount,
+            block_bytes: stats.statistics.blockBytes,
+            allocation_bytes: stats.statistics.allocationBytes,
+            unused_range_count: stats.unusedRangeCount,
+            allocation_size_min: stats.allocationSizeMin,
+            allocation_size_max: stats.allocationSizeMax,
+            unused_range_size_min: stats.unusedRangeSizeMin,
+            unused_range_size_max: stats.unusedRangeSizeMax,
+        }
+    }
+    pub fn free_bytes(&self) -> vk::DeviceSize {
+        self.block_bytes - self.allocation_bytes
+    }
+    /// 0 when all free memory is in one contiguous range, approaching 1 as the free memory gets
+    /// split into many small ranges.
+    pub fn fragmentation(&self) -> f32 {
+        let free = self.free_bytes();
+        if free == 0 {
+            return 0.0;
+        }
+        1.0 - self.unused_range_size_max as f32 / free as f32
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScenarioStatistics {
+    pub allocation_count: u64,
+    pub allocation_bytes: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct AllocatorStatistics {
+    pub total: MemoryStatistics,
+    /// Indexed by memory heap index.
+    pub heaps: Vec<MemoryStatistics>,
+    /// Indexed by memory type index.
+    pub types: Vec<MemoryStatistics>,
+    /// Live buffers and images allocated through the allocator, by [`MemoryAllocScenario`].
+    pub scenarios: Vec<(ScenarioKind, ScenarioStatistics)>,
+}
+
+#[derive(Default)]
+struct ScenarioCounter {
+    allocation_count: AtomicU64,
+    allocation_bytes: AtomicU64,
+}
+
+/// Usage tracking state of an [`Allocator`].
+#[derive(Default)]
+pub(crate) struct AllocatorUsage {
+    scenarios: [ScenarioCounter; ScenarioKind::ALL.len()],
+    thresholds: Mutex<BudgetThresholds>,
+}
+
+#[derive(Default)]
+struct BudgetThresholds {
+    /// Sorted usage / budget ratios.
+    thresholds: Vec<f32>,
+    /// For each heap, the number of thresholds the usage exceeded when last checked.
+    levels: Vec<usize>,
+}
+
+impl AllocatorUsage {
+    pub(crate) fn record_allocation(&self, scenario: ScenarioKind, size: u64) {
+        let counter = &self.scenarios[scenario as usize];
+        counter.allocation_count.fetch_add(1, Ordering::Relaxed);
+        counter.allocation_bytes.fetch_add(size, Ordering::Relaxed);
+    }
+    pub(crate) fn record_free(&self, scenario: ScenarioKind, size: u64) {
+        let counter = &self.scenarios[scenario as usize];
+        counter.allocation_count.fetch_sub(1, Ordering::Relaxed);
+        counter.allocation_bytes.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+impl Allocator {
+    /// Budget and usage of each memory heap.
+    /// Checks the usage against the thresholds set by [`Allocator::set_budget_thresholds`].
+    pub fn heap_budgets(&self) -> VkResult<Vec<HeapBudget>> {
+        let budgets = self.allocator.get_heap_budgets()?;
+        let budgets: Vec<HeapBudget> = budgets
+            .iter()
+            .zip(self.heaps.iter())
+            .enumerate()
+            .map(|(heap_index, (budget, heap))| HeapBudget {
+                heap_index: heap_index as u32,
+                flags: heap.flags,
+                size: heap.size,
+                budget: budget.budget,
+                usage: budget.usage,
+                block_bytes: budget.statistics.blockBytes,
+                allocation_bytes: budget.statistics.allocationBytes,
+                block_count: budget.statistics.blockCount,
+                allocation_count: budget.statistics.allocationCount,
+            })
+            .collect();
+        self.check_budget_thresholds(&budgets);
+        Ok(budgets)
+    }
+
+    /// Detailed statistics of the memory allocated by the allocator.
+    /// This traverses all internal data structures, so it shouldn't be called every frame.
+    pub fn statistics(&self) -> VkResult<AllocatorStatistics> {
+        let stats = self.allocator.calculate_statistics()?;
+        let scenarios = ScenarioKind::ALL
+            .iter()
+            .map(|&kind| {
+                let counter = &self.usage.scenarios[kind as usize];
+                (
+                    kind,
+                    ScenarioStatistics {
+                        allocation_count: counter.allocation_count.load(Ordering::Relaxed),
+                        allocation_bytes: counter.allocation_bytes.load(Ordering::Relaxed),
+                    },
+                )
+            })
+            .collect();
+        Ok(AllocatorStatistics {
+            total: MemoryStatistics::from_vma(&stats.total),
+            heaps: stats.memoryHeap[..self.heaps.len()]
+                .iter()
+                .map(MemoryStatistics::from_vma)
+                .collect(),
+            types: stats.memoryType[..self.types.len()]
+                .iter()
+                .map(MemoryStatistics::from_vma)
+                .collect(),
+            scenarios,
+        })
+    }
+
+    /// Emit a tracing event whenever the usage / budget ratio of a heap crosses one of `thresholds`,
+    /// for example `&[0.8, 0.95]`. The usage is checked after each buffer or image allocation, and
+    /// whenever the heap budgets are queried. Non-finite thresholds are ignored.
+    pub fn set_budget_thresholds(&self, thresholds: &[f32]) {
+        let mut state = self.usage.thresholds.lock().unwrap();
+        state.thresholds = thresholds
+            .iter()
+            .copied()
+            .filter(|threshold| threshold.is_finite())
+            .collect();
+        if state.thresholds.len() < thresholds.len() {
+            tracing::warn!(?thresholds, "Ignoring non-finite budget thresholds");
+        }
+        state.thresholds.sort_by(f32::total_cmp);
+        state.levels.clear();
+    }
+
+    /// Tells the allocator the index of the current frame, which lets vk_mem refresh the heap budgets
+    /// at most once per frame. Also checks the budget thresholds.
+    pub fn set_current_frame_index(&self, frame_index: u32) -> VkResult<()> {
+        self.allocator.set_current_frame_index(frame_index);
+        if self.usage.thresholds.lock().unwrap().thresholds.is_empty() {
+            return Ok(());
+        }
+        self.heap_budgets()?;
+        Ok(())
+    }
+
+    /// Check the budget thresholds after an allocation, so that crossing one is reported right away
+    /// instead of on the next frame.
+    pub(crate) fn check_budget_after_allocation(&self) {
+        if self.usage.thresholds.lock().unwrap().thresholds.is_empty() {
+            return;
+        }
+        if let Err(err) = self.heap_budgets() {
+            tracing::warn!(%err, "Failed to query the heap budgets");
+        }
+    }
+
+    fn check_budget_thresholds(&self, budgets: &[HeapBudget]) {
+        let mut state = self.usage.thresholds.lock().unwrap();
+        if state.thresholds.is_empty() {
+            return;
+        }
+        state.levels.resize(budgets.len(), 0);
+        for budget in budgets {
+            let ratio = budget.usage_ratio();
+            let level = state.thresholds.iter().filter(|&&t| ratio >= t).count();
+            let previous = std::mem::replace(&mut state.levels[budget.heap_index as usize], level);
+            if level > previous {
+                tracing::warn!(
+                    heap = budget.heap_index,
+                    usage = budget.usage,
+                    budget = budget.budget,
+                    threshold = state.thresholds[level - 1],
+                    "Memory heap usage exceeded threshold"
+                );
+            } else if level < previous {
+                tracing::info!(
+                    heap = budget.heap_index,
+                    usage = budget.usage,
+                    budget = budget.budget,
+                    threshold = state.thresholds[level],
+                    "Memory heap usage dropped below threshold"
+                );
+            }
+        }
+    }
+}
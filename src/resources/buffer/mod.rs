@@ -1,8 +1,12 @@
 use ash::{prelude::VkResult, vk};
 use std::sync::Arc;
 
-use crate::Device;
-//pub mod vec_discrete;
+use super::format::FormatInfo;
+use crate::{command::recorder::CommandBufferResource, DebugObject, Device, HasDevice};
+pub mod typed;
+pub mod vec_discrete;
+
+pub use typed::{BufferSlice, TypedBuffer, TypedBufferCopy};
 
 pub struct Buffer {
     device: Arc<Device>,
@@ -12,6 +16,9 @@ pub struct Buffer {
 impl Buffer {
     pub fn new(device: Arc<Device>, create_info: &vk::BufferCreateInfo) -> VkResult<Self> {
         let buffer = unsafe { device.create_buffer(create_info, None)? };
+        device
+            .resource_registry()
+            .register(vk::ObjectType::BUFFER, buffer, None);
         Ok(Self {
             device,
             raw: buffer,
@@ -22,12 +29,21 @@ impl Buffer {
 impl Drop for Buffer {
     fn drop(&mut self) {
         tracing::debug!(buffer = ?self.raw, "drop buffer");
+        self.device
+            .resource_registry()
+            .unregister(vk::ObjectType::BUFFER, self.raw);
         unsafe { self.device.destroy_buffer(self.raw, None) }
     }
 }
 
 pub trait HasBuffer: Send + Sync + 'static {
     fn raw_buffer(&self) -> vk::Buffer;
+    /// The size of the whole buffer, if known. Handles to a range of a shared buffer, like
+    /// [`crate::resources::arena::ArenaBuffer`], return None so that `vk::WHOLE_SIZE` can't cover the
+    /// neighbouring ranges.
+    fn buffer_size(&self) -> Option<vk::DeviceSize> {
+        None
+    }
     fn boxed_type_erased(self: Box<Self>) -> Box<dyn Send + Sync> {
         Box::new(self)
     }
@@ -49,4 +65,274 @@ impl<T: HasBuffer> HasBuffer for Arc<T> {
         let r: &T = self.as_ref();
         r.raw_buffer()
     }
+    fn buffer_size(&self) -> Option<vk::DeviceSize> {
+        let r: &T = self.as_ref();
+        r.buffer_size()
+    }
+}
+
+impl<T: HasBufferView> HasBufferView for Arc<T> {
+    fn raw_buffer_view(&self) -> vk::BufferView {
+        let r: &T = self.as_ref();
+        r.raw_buffer_view()
+    }
+    fn subresource_range(&self) -> (vk::DeviceSize, vk::DeviceSize) {
+        let r: &T = self.as_ref();
+        r.subresource_range()
+    }
+}
+
+/// A typed view into a buffer, for use as an uniform or storage texel buffer.
+pub struct BufferView<T: HasBuffer> {
+    device: Arc<Device>,
+    buffer: T,
+    view: vk::BufferView,
+    format: vk::Format,
+    offset: vk::DeviceSize,
+    range: vk::DeviceSize,
+}
+
+impl<T: HasBuffer> BufferView<T> {
+    /// `usage` is `UNIFORM_TEXEL_BUFFER`, `STORAGE_TEXEL_BUFFER` or both, depending on how the view is
+    /// accessed. `range` may be `vk::WHOLE_SIZE` if the size of the buffer is known.
+    ///
+    /// Returns `ERROR_FORMAT_NOT_SUPPORTED` if the format doesn't support `usage`, and
+    /// `ERROR_VALIDATION_FAILED_EXT` if `offset` and `range` violate the texel buffer limits of the
+    /// physical device.
+    pub fn new(
+        device: Arc<Device>,
+        buffer: T,
+        usage: vk::BufferUsageFlags,
+        format: vk::Format,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> VkResult<Self> {
+        let physical_device = device.physical_device();
+        let features = physical_device.format_properties(format).buffer_features;
+        let required_features = texel_buffer_features(usage).ok_or_else(|| {
+            tracing::error!(?usage, "Buffer views are only used as texel buffers");
+            vk::Result::ERROR_VALIDATION_FAILED_EXT
+        })?;
+        if !features.contains(required_features) {
+            tracing::error!(
+                ?format,
+                ?usage,
+                "Format doesn't support the texel buffer usage"
+            );
+            return Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED);
+        }
+        let limits = &physical_device.properties().limits;
+        let texel_size = FormatInfo::of(format).map(|info| info.block_size() as vk::DeviceSize);
+        validate_texel_buffer_range(limits, texel_size, buffer.buffer_size(), offset, range)
+            .map_err(|message| {
+                tracing::error!(offset, range, "{}", message);
+                vk::Result::ERROR_VALIDATION_FAILED_EXT
+            })?;
+        let view = unsafe {
+            device.create_buffer_view(
+                &vk::BufferViewCreateInfo {
+                    buffer: buffer.raw_buffer(),
+                    format,
+                    offset,
+                    range,
+                    ..Default::default()
+                },
+                None,
+            )?
+        };
+        device
+            .resource_registry()
+            .register(vk::ObjectType::BUFFER_VIEW, view, None);
+        Ok(Self {
+            device,
+            buffer,
+            view,
+            format,
+            offset,
+            range,
+        })
+    }
+    pub fn buffer(&self) -> &T {
+        &self.buffer
+    }
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+    pub fn offset(&self) -> vk::DeviceSize {
+        self.offset
+    }
+    pub fn range(&self) -> vk::DeviceSize {
+        self.range
+    }
+}
+
+impl<T: HasBuffer> HasDevice for BufferView<T> {
+    fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+}
+
+impl<T: HasBuffer> DebugObject for BufferView<T> {
+    fn object_handle(&mut self) -> u64 {
+        unsafe { std::mem::transmute(self.view) }
+    }
+
+    const OBJECT_TYPE: vk::ObjectType = vk::ObjectType::BUFFER_VIEW;
+}
+
+impl<T: HasBuffer> HasBuffer for BufferView<T> {
+    fn raw_buffer(&self) -> vk::Buffer {
+        self.buffer.raw_buffer()
+    }
+    fn buffer_size(&self) -> Option<vk::DeviceSize> {
+        self.buffer.buffer_size()
+    }
+}
+
+impl<T: HasBuffer> HasBufferView for BufferView<T> {
+    fn raw_buffer_view(&self) -> vk::BufferView {
+        self.view
+    }
+    fn subresource_range(&self) -> (vk::DeviceSize, vk::DeviceSize) {
+        (self.offset, self.range)
+    }
+}
+
+impl<T: HasBuffer> CommandBufferResource for BufferView<T> {}
+
+impl<T: HasBuffer> Drop for BufferView<T> {
+    fn drop(&mut self) {
+        tracing::debug!(buffer_view = ?self.view, "drop buffer view");
+        self.device
+            .resource_registry()
+            .unregister(vk::ObjectType::BUFFER_VIEW, self.view);
+        unsafe { self.device.destroy_buffer_view(self.view, None) }
+    }
+}
+
+/// The format features required to access a texel buffer with `usage`.
+fn texel_buffer_features(usage: vk::BufferUsageFlags) -> Option<vk::FormatFeatureFlags> {
+    let texel_buffer_usage =
+        vk::BufferUsageFlags::UNIFORM_TEXEL_BUFFER | vk::BufferUsageFlags::STORAGE_TEXEL_BUFFER;
+    if usage.is_empty() || !texel_buffer_usage.contains(usage) {
+        return None;
+    }
+    let mut features = vk::FormatFeatureFlags::empty();
+    if usage.contains(vk::BufferUsageFlags::UNIFORM_TEXEL_BUFFER) {
+        features |= vk::FormatFeatureFlags::UNIFORM_TEXEL_BUFFER;
+    }
+    if usage.contains(vk::BufferUsageFlags::STORAGE_TEXEL_BUFFER) {
+        features |= vk::FormatFeatureFlags::STORAGE_TEXEL_BUFFER;
+    }
+    Some(features)
+}
+
+/// Checks `offset` and `range` of a texel buffer view against the limits of the physical device.
+/// `vk::WHOLE_SIZE` is resolved against `buffer_size`.
+fn validate_texel_buffer_range(
+    limits: &vk::PhysicalDeviceLimits,
+    texel_size: Option<vk::DeviceSize>,
+    buffer_size: Option<vk::DeviceSize>,
+    offset: vk::DeviceSize,
+    range: vk::DeviceSize,
+) -> Result<(), &'static str> {
+    if offset % limits.min_texel_buffer_offset_alignment != 0 {
+        return Err("Texel buffer offset must be a multiple of minTexelBufferOffsetAlignment");
+    }
+    let range = if range == vk::WHOLE_SIZE {
+        let buffer_size =
+            buffer_size.ok_or("The buffer size is unknown, so the range must be explicit")?;
+        if offset >= buffer_size {
+            return Err("Texel buffer offset is out of bounds");
+        }
+        // The remaining bytes are rounded down to a whole number of texels.
+        let remaining = buffer_size - offset;
+        texel_size.map_or(remaining, |texel_size| remaining - remaining % texel_size)
+    } else {
+        if let Some(buffer_size) = buffer_size
+            && offset
+                .checked_add(range)
+                .map_or(true, |end| end > buffer_size)
+        {
+            return Err("Texel buffer range is out of bounds");
+        }
+        if let Some(texel_size) = texel_size
+            && range % texel_size != 0
+        {
+            return Err("Texel buffer range must be a multiple of the texel size");
+        }
+        range
+    };
+    if let Some(texel_size) = texel_size
+        && range / texel_size > limits.max_texel_buffer_elements as vk::DeviceSize
+    {
+        return Err("Texel buffer range exceeds maxTexelBufferElements");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{texel_buffer_features, validate_texel_buffer_range};
+    use ash::vk;
+
+    fn limits() -> vk::PhysicalDeviceLimits {
+        vk::PhysicalDeviceLimits {
+            min_texel_buffer_offset_alignment: 16,
+            max_texel_buffer_elements: 1024,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn whole_size_is_checked_against_the_buffer() {
+        let limits = limits();
+        assert!(
+            validate_texel_buffer_range(&limits, Some(4), Some(4096), 0, vk::WHOLE_SIZE).is_ok()
+        );
+        // 4112 - 16 bytes is 1024 texels.
+        assert!(
+            validate_texel_buffer_range(&limits, Some(4), Some(4112), 16, vk::WHOLE_SIZE).is_ok()
+        );
+        assert!(
+            validate_texel_buffer_range(&limits, Some(4), Some(8192), 0, vk::WHOLE_SIZE).is_err()
+        );
+        assert!(validate_texel_buffer_range(&limits, Some(4), None, 0, vk::WHOLE_SIZE).is_err());
+        assert!(
+            validate_texel_buffer_range(&limits, Some(4), Some(64), 64, vk::WHOLE_SIZE).is_err()
+        );
+    }
+
+    #[test]
+    fn explicit_ranges() {
+        let limits = limits();
+        assert!(validate_texel_buffer_range(&limits, Some(4), None, 16, 4096).is_ok());
+        assert!(validate_texel_buffer_range(&limits, Some(4), None, 8, 64).is_err());
+        assert!(validate_texel_buffer_range(&limits, Some(4), None, 0, 6).is_err());
+        assert!(validate_texel_buffer_range(&limits, Some(4), None, 0, 4100).is_err());
+        assert!(validate_texel_buffer_range(&limits, Some(4), Some(64), 16, 64).is_err());
+    }
+
+    #[test]
+    fn features_follow_usage() {
+        assert_eq!(
+            texel_buffer_features(vk::BufferUsageFlags::STORAGE_TEXEL_BUFFER),
+            Some(vk::FormatFeatureFlags::STORAGE_TEXEL_BUFFER)
+        );
+        assert_eq!(
+            texel_buffer_features(
+                vk::BufferUsageFlags::UNIFORM_TEXEL_BUFFER
+                    | vk::BufferUsageFlags::STORAGE_TEXEL_BUFFER
+            ),
+            Some(
+                vk::FormatFeatureFlags::UNIFORM_TEXEL_BUFFER
+                    | vk::FormatFeatureFlags::STORAGE_TEXEL_BUFFER
+            )
+        );
+        assert_eq!(texel_buffer_features(vk::BufferUsageFlags::empty()), None);
+        assert_eq!(
+            texel_buffer_features(vk::BufferUsageFlags::UNIFORM_BUFFER),
+            None
+        );
+    }
 }
@@ -0,0 +1,294 @@
+use ash::{prelude::VkResult, vk};
+use bytemuck::Pod;
+use std::{
+    marker::PhantomData,
+    mem::{align_of, size_of},
+    ops::{Bound, Range, RangeBounds},
+    sync::Arc,
+};
+
+use super::HasBuffer;
+use crate::{
+    command::recorder::{CommandBufferResource, CommandRecorder, ReferencedResource},
+    resources::alloc::{Allocator, BufferRequest, MemBuffer},
+    sync::CommandsFuture,
+};
+
+/// A [`MemBuffer`] holding `len` elements of `T`.
+///
+/// Offsets and sizes are expressed in elements, and converted to bytes when talking to Vulkan.
+pub struct TypedBuffer<T: Pod> {
+    buffer: Arc<MemBuffer>,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> Clone for TypedBuffer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer.clone(),
+            len: self.len,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Pod> TypedBuffer<T> {
+    /// Interpret `buffer` as an array of `T`. Trailing bytes that don't fit a whole element are ignored.
+    pub fn from_raw(buffer: Arc<MemBuffer>) -> Self {
+        assert!(
+            size_of::<T>() > 0,
+            "Zero sized element types are not supported"
+        );
+        let len = buffer.size() as usize / size_of::<T>();
+        Self {
+            buffer,
+            len,
+            _marker: PhantomData,
+        }
+    }
+    pub fn raw(&self) -> &Arc<MemBuffer> {
+        &self.buffer
+    }
+    pub fn into_raw(self) -> Arc<MemBuffer> {
+        self.buffer
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn size_bytes(&self) -> vk::DeviceSize {
+        (self.len * size_of::<T>()) as vk::DeviceSize
+    }
+
+    /// Map the buffer for the duration of `f`. Panics if the buffer is shared.
+    pub fn map_scoped(&mut self, f: impl FnOnce(&mut [T])) {
+        let len = self.len;
+        Arc::get_mut(&mut self.buffer)
+            .expect("Can't map a TypedBuffer that is shared")
+            .map_scoped(|bytes| f(bytemuck::cast_slice_mut(&mut bytes[..len * size_of::<T>()])));
+    }
+    /// The persistently mapped elements. Panics if the buffer is shared, or if it wasn't allocated with
+    /// the `MAPPED` flag.
+    pub fn get_mut(&mut self) -> &mut [T] {
+        let len = self.len;
+        let buffer =
+            Arc::get_mut(&mut self.buffer).expect("Can't access a TypedBuffer that is shared");
+        bytemuck::cast_slice_mut(&mut buffer.get_mut()[..len * size_of::<T>()])
+    }
+
+    /// Panics if the buffer wasn't created with `SHADER_DEVICE_ADDRESS` usage.
+    pub fn device_address(&self) -> vk::DeviceAddress {
+        assert_ne!(
+            self.buffer.device_address, 0,
+            "Buffer wasn't created with SHADER_DEVICE_ADDRESS usage"
+        );
+        self.buffer.device_address
+    }
+    /// The device address of the element at `index`.
+    pub fn device_address_of(&self, index: usize) -> vk::DeviceAddress {
+        assert!(index < self.len, "Index out of bounds");
+        self.device_address() + (index * size_of::<T>()) as vk::DeviceAddress
+    }
+
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> BufferSlice<'_, T> {
+        let range = resolve_range(range, self.len);
+        BufferSlice {
+            buffer: self,
+            range,
+        }
+    }
+    /// Descriptor for the whole buffer.
+    pub fn descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        self.slice(..).descriptor_info()
+    }
+}
+
+impl<T: Pod> HasBuffer for TypedBuffer<T> {
+    fn raw_buffer(&self) -> vk::Buffer {
+        self.buffer.buffer
+    }
+    fn buffer_size(&self) -> Option<vk::DeviceSize> {
+        Some(self.buffer.size())
+    }
+}
+
+impl<T: Pod> CommandBufferResource for TypedBuffer<T> {
+    fn command_buffer_resource(self) -> ReferencedResource {
+        self.buffer.command_buffer_resource()
+    }
+}
+
+/// A range of elements of a [`TypedBuffer`].
+#[derive(Clone)]
+pub struct BufferSlice<'a, T: Pod> {
+    buffer: &'a TypedBuffer<T>,
+    range: Range<usize>,
+}
+
+impl<'a, T: Pod> BufferSlice<'a, T> {
+    pub fn buffer(&self) -> &'a TypedBuffer<T> {
+        self.buffer
+    }
+    /// The range of elements in the buffer.
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+    pub fn len(&self) -> usize {
+        self.range.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.range.is_empty()
+    }
+    pub fn offset_bytes(&self) -> vk::DeviceSize {
+        (self.range.start * size_of::<T>()) as vk::DeviceSize
+    }
+    pub fn size_bytes(&self) -> vk::DeviceSize {
+        (self.range.len() * size_of::<T>()) as vk::DeviceSize
+    }
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> BufferSlice<'a, T> {
+        let range = resolve_range(range, self.len());
+        BufferSlice {
+            buffer: self.buffer,
+            range: self.range.start + range.start..self.range.start + range.end,
+        }
+    }
+    pub fn device_address(&self) -> vk::DeviceAddress {
+        self.buffer.device_address() + self.offset_bytes()
+    }
+    pub fn descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo {
+            buffer: self.buffer.raw_buffer(),
+            offset: self.offset_bytes(),
+            range: self.size_bytes(),
+        }
+    }
+    /// A copy region from this slice to `dst`. Panics if the lengths differ.
+    pub fn copy_to(&self, dst: &BufferSlice<T>) -> vk::BufferCopy {
+        assert_eq!(
+            self.len(),
+            dst.len(),
+            "Copy between slices of different lengths"
+        );
+        vk::BufferCopy {
+            src_offset: self.offset_bytes(),
+            dst_offset: dst.offset_bytes(),
+            size: self.size_bytes(),
+        }
+    }
+    /// The persistently mapped elements of the slice.
+    ///
+    /// # Safety
+    /// Slices and clones of the buffer may overlap, so the caller must ensure that no other reference to
+    /// these elements is alive while the returned slice is in use, and that the device isn't accessing them.
+    pub unsafe fn get_mut(&self) -> &mut [T] {
+        let bytes = &mut self.buffer.buffer.get_mut()[..self.buffer.len * size_of::<T>()];
+        &mut bytemuck::cast_slice_mut(bytes)[self.range.clone()]
+    }
+}
+
+fn resolve_range(range: impl RangeBounds<usize>, len: usize) -> Range<usize> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start + 1,
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end + 1,
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+    assert!(start <= end && end <= len, "Range out of bounds");
+    start..end
+}
+
+/// Copy region between two [`TypedBuffer`]s, in elements.
+#[derive(Clone, Copy, Debug)]
+pub struct TypedBufferCopy {
+    pub src_index: usize,
+    pub dst_index: usize,
+    pub len: usize,
+}
+
+impl<'a> CommandRecorder<'a> {
+    /// [`CommandRecorder::copy_buffer`] with regions in elements. Panics if a region is out of bounds.
+    pub fn copy_typed_buffer<T: Pod>(
+        &mut self,
+        src_buffer: &TypedBuffer<T>,
+        dst_buffer: &TypedBuffer<T>,
+        regions: &[TypedBufferCopy],
+    ) -> &mut Self {
+        let regions: Vec<vk::BufferCopy> = regions
+            .iter()
+            .map(|region| {
+                src_buffer
+                    .slice(region.src_index..region.src_index + region.len)
+                    .copy_to(&dst_buffer.slice(region.dst_index..region.dst_index + region.len))
+            })
+            .collect();
+        self.copy_buffer(src_buffer.raw().clone(), dst_buffer.raw().clone(), &regions)
+    }
+}
+
+impl Allocator {
+    /// Allocate a buffer for `len` elements of `T`. `request.size` is ignored, and the alignment is at least
+    /// the alignment of `T`.
+    pub fn allocate_typed_buffer<T: Pod>(
+        self: &Arc<Self>,
+        len: usize,
+        request: &BufferRequest,
+    ) -> VkResult<TypedBuffer<T>> {
+        let buffer = self.allocate_buffer(&BufferRequest {
+            size: (len * size_of::<T>()).max(1) as u64,
+            alignment: request.alignment.max(align_of::<T>() as u64),
+            ..request.clone()
+        })?;
+        let mut buffer = TypedBuffer::from_raw(Arc::new(buffer));
+        buffer.len = len;
+        Ok(buffer)
+    }
+
+    /// [`Allocator::allocate_buffer_with_data`] with the content of `data`.
+    pub fn allocate_typed_buffer_with_data<T: Pod>(
+        self: &Arc<Self>,
+        request: BufferRequest,
+        data: &[T],
+        commands_future: &mut CommandsFuture,
+    ) -> VkResult<TypedBuffer<T>> {
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        let buffer = self.allocate_buffer_with_data(
+            BufferRequest {
+                size: bytes.len().max(1) as u64,
+                alignment: request.alignment.max(align_of::<T>() as u64),
+                ..request
+            },
+            |dst| dst[..bytes.len()].copy_from_slice(bytes),
+            commands_future,
+        )?;
+        let mut buffer = TypedBuffer::from_raw(buffer);
+        buffer.len = data.len();
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_range;
+
+    #[test]
+    fn resolve_ranges() {
+        assert_eq!(resolve_range(.., 10), 0..10);
+        assert_eq!(resolve_range(2..5, 10), 2..5);
+        assert_eq!(resolve_range(2..=5, 10), 2..6);
+        assert_eq!(resolve_range(..3, 10), 0..3);
+        assert_eq!(resolve_range(7.., 10), 7..10);
+    }
+
+    #[test]
+    #[should_panic]
+    fn resolve_out_of_bounds() {
+        resolve_range(5..11, 10);
+    }
+}
@@ -1,12 +1,19 @@
 use std::{ops::Range, sync::Arc};
 
-use ash::vk;
+use ash::{prelude::VkResult, vk};
 
-use crate::resources::alloc::{Allocator, BufferRequest, MemBuffer};
+use crate::resources::alloc::{
+    AllocationCreateFlags, Allocator, BufferRequest, MemBuffer, MemoryAllocScenario,
+};
 
-use crate::command::recorder::CommandRecorder;
+use crate::command::{
+    recorder::CommandRecorder,
+    sync::{AccessType, MemoryBarrier, PipelineBarrier},
+};
 
 // A vector with a DEVICE_LOCAL memory and write-combined updates through staging.
+// When the buffer lands in memory that is both DEVICE_LOCAL and HOST_VISIBLE (BAR / ReBAR),
+// updates within the current capacity are written directly into the mapped buffer instead.
 pub struct VecDiscrete<T> {
     allocator: Arc<Allocator>,
     create_info: VecCreateInfo,
@@ -19,26 +26,156 @@ pub struct VecDiscrete<T> {
     // src_index, dst_index, num_items
     staging_to_device_copies: Vec<vk::BufferCopy>,
     free_ranges: Vec<Range<u64>>,
+    direct_writes: DirectWrites<MemBuffer>,
+}
+
+/// Decides whether updates may be written into the mapped buffer instead of going through staging.
+struct DirectWrites<B> {
+    /// Whether the buffer is mapped, DEVICE_LOCAL memory.
+    supported: bool,
+    /// The buffer copied into the current buffer by the last growth. The command buffer recording the copy
+    /// holds on to it until it is dropped, so the copy is complete once this is the last reference.
+    /// Until then, a direct write could be overwritten by the copy.
+    growth_source: Option<Arc<B>>,
+}
+
+impl<B> DirectWrites<B> {
+    fn new(supported: bool) -> Self {
+        Self {
+            supported,
+            growth_source: None,
+        }
+    }
+    fn active(&self) -> bool {
+        self.supported
+            && self
+                .growth_source
+                .as_ref()
+                .map_or(true, |source| Arc::strong_count(source) == 1)
+    }
+    /// Whether an update may be written into the mapped buffer, given the staged copies not flushed yet.
+    /// Those copies are recorded by the next flush, and would overwrite a direct write to the same items.
+    fn allowed(&self, staged_copies: &[vk::BufferCopy]) -> bool {
+        self.active() && staged_copies.is_empty()
+    }
+    /// The buffer was replaced by a new one, and a copy from `source` to it was recorded.
+    fn grown(&mut self, supported: bool, source: Arc<B>) {
+        self.supported = supported;
+        self.growth_source = Some(source);
+    }
+    /// Release the old buffer once the growth copy completed.
+    fn poll(&mut self) {
+        if self
+            .growth_source
+            .as_ref()
+            .map_or(false, |source| Arc::strong_count(source) == 1)
+        {
+            self.growth_source = None;
+        }
+    }
+}
+
+/// The capacity after growing a vector of `len` items.
+fn grown_capacity(len: u64) -> u64 {
+    len + len / 2 // Inflate by 1.5x
 }
 
 pub struct VecCreateInfo {
-    pub flags: vk::BufferCreateFlags,
     pub usage: vk::BufferUsageFlags,
     pub sharing_mode: vk::SharingMode,
     pub queue_family_indices: Box<[u32]>,
     pub alignment: u64,
-    pub memory_usage: gpu_alloc::UsageFlags,
+    pub scenario: MemoryAllocScenario,
+    /// How the buffer is accessed on the device between flushes.
+    /// Used to synchronize the copies recorded by `flush` with those accesses.
+    pub accesses: Vec<AccessType>,
 }
 
-impl<T> VecDiscrete<T> {
+impl<T: Copy> VecDiscrete<T> {
+    pub fn new(
+        allocator: Arc<Allocator>,
+        create_info: VecCreateInfo,
+        capacity: u64,
+    ) -> VkResult<Self> {
+        let capacity = capacity.max(1);
+        let buffer = Self::allocate(&allocator, &create_info, capacity)?;
+        let direct_writes = DirectWrites::new(Self::supports_direct_writes(&buffer));
+        Ok(Self {
+            allocator,
+            create_info,
+            buffer: Arc::new(buffer),
+            len: 0,
+            capacity,
+            ops: Vec::new(),
+            staging_to_device_copies: Vec::new(),
+            free_ranges: Vec::new(),
+            direct_writes,
+        })
+    }
+    fn allocate(
+        allocator: &Arc<Allocator>,
+        create_info: &VecCreateInfo,
+        capacity: u64,
+    ) -> VkResult<MemBuffer> {
+        allocator.allocate_buffer(&BufferRequest {
+            size: capacity * std::mem::size_of::<T>() as u64,
+            alignment: create_info.alignment,
+            usage: create_info.usage
+                | vk::BufferUsageFlags::TRANSFER_SRC
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            scenario: create_info.scenario.clone(),
+            // This is ignored by VMA when the buffer isn't host visible.
+            allocation_flags: AllocationCreateFlags::MAPPED,
+            sharing_mode: create_info.sharing_mode,
+            queue_families: &create_info.queue_family_indices,
+        })
+    }
+
+    pub fn buffer(&self) -> &Arc<MemBuffer> {
+        &self.buffer
+    }
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+    fn supports_direct_writes(buffer: &MemBuffer) -> bool {
+        buffer.device_local() && buffer.host_visible() && !buffer.ptr.is_null()
+    }
+    /// Whether updates within the capacity are written directly into the device buffer.
+    /// In this case, the application must ensure that the device isn't accessing the updated items.
+    ///
+    /// After the buffer grows, updates go through staging again until the command buffer copying the old
+    /// buffer into the new one is dropped, along with any other reference to the old buffer, and the
+    /// staged updates were flushed.
+    pub fn writes_directly(&self) -> bool {
+        self.direct_writes.allowed(&self.staging_to_device_copies)
+    }
+
     fn push_staging_to_device_copy(&mut self, src_index: usize, dst_index: u64, num_items: usize) {
         let s = std::mem::size_of::<T>() as u64;
+        if self.writes_directly() && dst_index + num_items as u64 <= self.capacity {
+            let src = &self.ops[src_index..src_index + num_items];
+            let bytes = unsafe {
+                std::slice::from_raw_parts(src.as_ptr() as *const u8, num_items * s as usize)
+            };
+            let dst_offset = (s * dst_index) as usize;
+            self.buffer.get_mut()[dst_offset..dst_offset + bytes.len()].copy_from_slice(bytes);
+            return;
+        }
         self.staging_to_device_copies.push(vk::BufferCopy {
             src_offset: s * src_index as u64,
             dst_offset: s * dst_index,
             size: s * num_items as u64,
         })
     }
+    /// Items that were written directly don't need to stay in the staging buffer.
+    fn trim_staging(&mut self) {
+        if self.staging_to_device_copies.is_empty() {
+            self.ops.clear();
+        }
+    }
     /// Add the specified item to the buffer.
     /// If pushing multiple items, please use `extend` instead of calling `push` multiple times.
     pub fn push(&mut self, item: T) {
@@ -76,6 +213,10 @@ impl<T> VecDiscrete<T> {
                     range.start,
                     num_items_to_copy,
                 );
+                if num_items_to_copy < range_len as usize {
+                    self.free_ranges
+                        .push(range.start + num_items_to_copy as u64..range.end);
+                }
                 num_items_left -= num_items_to_copy;
                 num_items_inserted += num_items_to_copy;
             } else {
@@ -91,6 +232,7 @@ impl<T> VecDiscrete<T> {
             }
         }
         assert_eq!(num_items_inserted, num_items);
+        self.trim_staging();
     }
 
     /// Update the item at the specified index.
@@ -106,32 +248,48 @@ impl<T> VecDiscrete<T> {
         let original_staging_len = self.ops.len();
         self.ops.extend(items);
         let num_items = self.ops.len() - original_staging_len;
+        if num_items == 0 {
+            return;
+        }
         self.push_staging_to_device_copy(original_staging_len, start_index, num_items);
+        self.trim_staging();
     }
     /// Mark the item at the specified index as freed.
     pub fn free(&mut self, index: u64, length: u64) {
         self.free_ranges.push(index..(index + length));
     }
 
-    /// command_pool: Recommands to be a command pool created with the transient flag.
-    pub fn flush(&mut self, r: &mut CommandRecorder) {
-        if self.len > self.capacity {
+    /// Record the pending updates into `r`, growing the buffer first if needed.
+    /// After growing, `buffer()` returns the new buffer and the old buffer is kept alive by the command buffer.
+    pub fn flush(&mut self, r: &mut CommandRecorder) -> VkResult<()> {
+        let item_size = std::mem::size_of::<T>() as u64;
+        self.direct_writes.poll();
+        let grow = self.len > self.capacity;
+        if !grow && self.staging_to_device_copies.is_empty() {
+            self.ops.clear();
+            return Ok(());
+        }
+        if grow {
             // Needs to extend the buffer.
-            let new_len = self.len + self.len / 2; // Inflate by 1.5x
-
-            let new_buffer = self
-                .allocator
-                .allocate_buffer(BufferRequest {
-                    size: new_len * std::mem::size_of::<T>() as u64,
-                    alignment: self.create_info.alignment,
-                    usage: self.create_info.usage | vk::BufferUsageFlags::TRANSFER_DST,
-                    memory_usage: self.create_info.memory_usage,
-                    sharing_mode: self.create_info.sharing_mode,
-                    queue_families: &self.create_info.queue_family_indices,
-                })
-                .unwrap();
+            let new_capacity = grown_capacity(self.len);
+            let new_buffer = Self::allocate(&self.allocator, &self.create_info, new_capacity)?;
+            self.direct_writes.grown(
+                Self::supports_direct_writes(&new_buffer),
+                self.buffer.clone(),
+            );
             let new_buffer = Arc::new(new_buffer);
             let old_buffer = std::mem::replace(&mut self.buffer, new_buffer.clone());
+
+            // Wait for prior writes to the old buffer before reading it.
+            r.simple_pipeline_barrier(&PipelineBarrier::new(
+                Some(MemoryBarrier {
+                    prev_accesses: &self.create_info.accesses,
+                    next_accesses: &[AccessType::TransferRead],
+                }),
+                &[],
+                &[],
+                vk::DependencyFlags::empty(),
+            ));
             // Perform device-to-device transfer.
             r.copy_buffer(
                 old_buffer,
@@ -139,46 +297,156 @@ impl<T> VecDiscrete<T> {
                 &[vk::BufferCopy {
                     src_offset: 0,
                     dst_offset: 0,
-                    size: self.capacity,
+                    size: self.capacity * item_size,
                 }],
             );
-            self.capacity = new_len;
+            self.capacity = new_capacity;
+            if !self.staging_to_device_copies.is_empty() {
+                // Updates may overlap the copied range.
+                r.simple_pipeline_barrier(&PipelineBarrier::new(
+                    Some(MemoryBarrier {
+                        prev_accesses: &[AccessType::TransferWrite],
+                        next_accesses: &[AccessType::TransferWrite],
+                    }),
+                    &[],
+                    &[],
+                    vk::DependencyFlags::empty(),
+                ));
+            }
+        } else {
+            // Wait for prior accesses to the buffer before overwriting it.
+            r.simple_pipeline_barrier(&PipelineBarrier::new(
+                Some(MemoryBarrier {
+                    prev_accesses: &self.create_info.accesses,
+                    next_accesses: &[AccessType::TransferWrite],
+                }),
+                &[],
+                &[],
+                vk::DependencyFlags::empty(),
+            ));
         }
-        // now, drain pending changes.
-        let mut staging_buffer = self
-            .allocator
-            .allocate_buffer(BufferRequest {
-                size: std::mem::size_of::<T>() as u64 * self.ops.len() as u64,
-                alignment: 0,
+
+        if !self.staging_to_device_copies.is_empty() {
+            // now, drain pending changes.
+            let mut staging_buffer = self.allocator.allocate_buffer(&BufferRequest {
+                size: item_size * self.ops.len() as u64,
                 usage: vk::BufferUsageFlags::TRANSFER_SRC,
-                memory_usage: gpu_alloc::UsageFlags::UPLOAD,
-                sharing_mode: vk::SharingMode::EXCLUSIVE,
-                queue_families: &[],
-            })
-            .unwrap();
-
-        unsafe {
-            let bytes = std::slice::from_raw_parts(
-                self.ops.as_ptr() as *const u8,
-                self.ops.len() * std::mem::size_of::<T>(),
+                scenario: MemoryAllocScenario::StagingBuffer,
+                ..Default::default()
+            })?;
+            let bytes = unsafe {
+                std::slice::from_raw_parts(
+                    self.ops.as_ptr() as *const u8,
+                    self.ops.len() * std::mem::size_of::<T>(),
+                )
+            };
+            staging_buffer.map_scoped(|staging| staging.copy_from_slice(bytes));
+            r.copy_buffer(
+                staging_buffer,
+                self.buffer.clone(),
+                &self.staging_to_device_copies,
             );
-            staging_buffer.write_bytes(0, bytes);
-            self.ops.clear()
+            self.staging_to_device_copies.clear();
         }
-        r.copy_buffer(
-            staging_buffer,
-            self.buffer.clone(),
-            &self.staging_to_device_copies,
-        );
-        self.staging_to_device_copies.clear();
+        self.ops.clear();
+
+        // Make the copies visible to subsequent accesses.
+        r.simple_pipeline_barrier(&PipelineBarrier::new(
+            Some(MemoryBarrier {
+                prev_accesses: &[AccessType::TransferWrite],
+                next_accesses: &self.create_info.accesses,
+            }),
+            &[],
+            &[],
+            vk::DependencyFlags::empty(),
+        ));
+        Ok(())
     }
 }
 
-impl<T: Default> VecDiscrete<T> {
+impl<T: Copy + Default> VecDiscrete<T> {
     /// Mark the item at the specified index as freed, and set the corresponding entry in
     /// the device buffer to Default.
     pub fn remove(&mut self, index: u64, length: u64) {
         self.free(index, length);
-        self.update_range(std::iter::repeat_with(T::default), index);
+        self.update_range(
+            std::iter::repeat_with(T::default).take(length as usize),
+            index,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{grown_capacity, DirectWrites};
+    use ash::vk;
+    use std::sync::Arc;
+
+    #[test]
+    fn direct_writes_wait_for_growth_copy() {
+        let mut direct_writes = DirectWrites::new(true);
+        assert!(direct_writes.active());
+
+        // The command buffer recording the copy holds a reference to the old buffer.
+        let old_buffer = Arc::new(());
+        let recorded = old_buffer.clone();
+        direct_writes.grown(true, old_buffer);
+        assert!(!direct_writes.active());
+        direct_writes.poll();
+        assert!(direct_writes.growth_source.is_some());
+
+        drop(recorded);
+        assert!(direct_writes.active());
+        direct_writes.poll();
+        assert!(direct_writes.growth_source.is_none());
+    }
+
+    #[test]
+    fn direct_writes_wait_for_staged_copies() {
+        let mut direct_writes = DirectWrites::new(true);
+        let mut staged_copies = Vec::new();
+
+        // Grow, and update an item while the growth copy is pending.
+        let old_buffer = Arc::new(());
+        let recorded = old_buffer.clone();
+        direct_writes.grown(true, old_buffer);
+        assert!(!direct_writes.allowed(&staged_copies));
+        staged_copies.push(vk::BufferCopy {
+            src_offset: 0,
+            dst_offset: 4,
+            size: 4,
+        });
+
+        // The growth copy completes, but updating the same item directly would be overwritten by the
+        // staged copy on the next flush.
+        drop(recorded);
+        assert!(direct_writes.active());
+        assert!(!direct_writes.allowed(&staged_copies));
+
+        // The flush records the staged copies.
+        direct_writes.poll();
+        staged_copies.clear();
+        assert!(direct_writes.allowed(&staged_copies));
+    }
+
+    #[test]
+    fn direct_writes_follow_the_new_buffer() {
+        let mut direct_writes = DirectWrites::new(true);
+        direct_writes.grown(false, Arc::new(()));
+        assert!(!direct_writes.active());
+
+        let mut direct_writes = DirectWrites::new(false);
+        assert!(!direct_writes.active());
+        direct_writes.grown(true, Arc::new(()));
+        assert!(direct_writes.active());
+    }
+
+    #[test]
+    fn growth_covers_len() {
+        assert_eq!(grown_capacity(2), 3);
+        assert_eq!(grown_capacity(10), 15);
+        for len in 1..100 {
+            assert!(grown_capacity(len) >= len);
+        }
     }
 }
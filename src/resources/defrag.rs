@@ -0,0 +1,473 @@
+use ash::{prelude::VkResult, vk};
+use std::{collections::HashMap, sync::Arc};
+
+use super::{
+    alloc::{Allocation, Allocator, MemBuffer},
+    format::{format_aspects, FormatInfo},
+    image::{mip_extent, MemImage},
+};
+use crate::{
+    queue::{QueueIndex, Queues},
+    HasDevice,
+};
+use vk_mem::ffi;
+
+/// The resources that a defragmentation may move.
+///
+/// Allocations not listed here are never moved. Buffers and images are only moved if they were created
+/// with both `TRANSFER_SRC` and `TRANSFER_DST` usage, because their content is copied on the device.
+pub struct DefragmentationRequest<'a, 'b> {
+    pub buffers: &'a mut [&'b mut MemBuffer],
+    /// The images and their current layouts. Moved images are left in the same layout.
+    pub images: &'a mut [(&'b mut MemImage, vk::ImageLayout)],
+    /// Whether buffers with a device address may be moved. Moving those changes their device address,
+    /// so everything referencing the old address (for example an acceleration structure or a buffer of
+    /// pointers) must be updated by the application.
+    pub move_device_address_buffers: bool,
+    /// Maximum number of bytes moved per pass. 0 for no limit.
+    pub max_bytes_per_pass: vk::DeviceSize,
+    /// Maximum number of allocations moved per pass. 0 for no limit.
+    pub max_allocations_per_pass: u32,
+}
+
+impl<'a, 'b> DefragmentationRequest<'a, 'b> {
+    pub fn new(
+        buffers: &'a mut [&'b mut MemBuffer],
+        images: &'a mut [(&'b mut MemImage, vk::ImageLayout)],
+    ) -> Self {
+        Self {
+            buffers,
+            images,
+            move_device_address_buffers: false,
+            max_bytes_per_pass: 0,
+            max_allocations_per_pass: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DefragmentationReport {
+    pub bytes_moved: vk::DeviceSize,
+    pub bytes_freed: vk::DeviceSize,
+    pub allocations_moved: u32,
+    pub device_memory_blocks_freed: u32,
+    /// Indices into [`DefragmentationRequest::buffers`] of the buffers that were moved.
+    /// Their `buffer` handles changed, and so did their device addresses.
+    pub moved_buffers: Vec<usize>,
+    /// Indices into [`DefragmentationRequest::images`] of the images that were moved.
+    /// Their `image` handles changed, so image views of them need to be recreated.
+    pub moved_images: Vec<usize>,
+    /// Indices of the buffers that the allocator wanted to move but which were pinned, because
+    /// they have a device address or lack transfer usage.
+    pub pinned_buffers: Vec<usize>,
+    /// Indices of the images that the allocator wanted to move but which lack transfer usage.
+    pub pinned_images: Vec<usize>,
+    /// Number of moves skipped because the allocation wasn't listed in the request.
+    pub unlisted_allocations: u32,
+}
+
+#[derive(Clone, Copy)]
+enum Target {
+    Buffer(usize),
+    Image(usize),
+}
+
+/// A resource recreated on the new memory, waiting to replace the old one once the pass ends.
+enum Replacement {
+    Buffer(usize, vk::Buffer),
+    Image(usize, vk::Image),
+}
+
+/// Regions copying all subresources of an image. Multi-planar images get one region per plane, with the
+/// extent of that plane.
+fn image_copy_regions(
+    format: vk::Format,
+    extent: vk::Extent3D,
+    mip_levels: u32,
+    array_layers: u32,
+) -> Vec<vk::ImageCopy> {
+    let planes = FormatInfo::of(format).filter(FormatInfo::is_multi_planar);
+    let aspects: Vec<vk::ImageAspectFlags> = match planes {
+        Some(info) => [
+            vk::ImageAspectFlags::PLANE_0,
+            vk::ImageAspectFlags::PLANE_1,
+            vk::ImageAspectFlags::PLANE_2,
+        ]
+        .into_iter()
+        .filter(|&plane| info.copy_aspects().contains(plane))
+        .collect(),
+        None => vec![format_aspects(format)],
+    };
+    (0..mip_levels)
+        .flat_map(|mip_level| {
+            aspects.iter().map(move |&aspect_mask| {
+                let level_extent = mip_extent(extent, mip_level);
+                let subresource = vk::ImageSubresourceLayers {
+                    aspect_mask,
+                    mip_level,
+                    base_array_layer: 0,
+                    layer_count: array_layers,
+                };
+                vk::ImageCopy {
+                    src_subresource: subresource,
+                    src_offset: vk::Offset3D::default(),
+                    dst_subresource: subresource,
+                    dst_offset: vk::Offset3D::default(),
+                    extent: match planes {
+                        Some(info) => info.aspect_extent(aspect_mask, level_extent),
+                        None => level_extent,
+                    },
+                }
+            })
+        })
+        .collect()
+}
+
+fn raw_allocation(allocation: &Allocation) -> ffi::VmaAllocation {
+    // Safety: Allocation is a newtype around VmaAllocation.
+    unsafe { std::mem::transmute_copy(allocation) }
+}
+
+impl Allocator {
+    /// Compact the memory of the resources in `request`.
+    ///
+    /// Each defragmentation pass recreates the moved resources on their new memory, submits the copies of
+    /// their content directly on `queue` and blocks until the copies complete. Operations waiting in
+    /// `queues` for the next flush are left untouched. The device must not access the listed resources
+    /// during defragmentation, and all commands referencing them must have completed.
+    /// This is meant to be called at points where the application can afford to stall, such as loading screens.
+    pub fn defragment(
+        self: &Arc<Self>,
+        queues: &Queues,
+        queue: QueueIndex,
+        request: &mut DefragmentationRequest,
+    ) -> VkResult<DefragmentationReport> {
+        let mut report = DefragmentationReport::default();
+        let mut targets: HashMap<ffi::VmaAllocation, Target> = HashMap::new();
+        for (i, buffer) in request.buffers.iter().enumerate() {
+            targets.insert(raw_allocation(&buffer.memory), Target::Buffer(i));
+        }
+        for (i, (image, _)) in request.images.iter().enumerate() {
+            targets.insert(raw_allocation(&image.memory), Target::Image(i));
+        }
+
+        let info = ffi::VmaDefragmentationInfo {
+            flags: 0,
+            pool: std::ptr::null_mut(),
+            maxBytesPerPass: request.max_bytes_per_pass,
+            maxAllocationsPerPass: request.max_allocations_per_pass,
+            ..unsafe { std::mem::zeroed() }
+        };
+        let context = unsafe { self.allocator.begin_defragmentation(&info)? };
+        let mut error: Option<vk::Result> = None;
+        loop {
+            let mut replacements: Vec<Replacement> = Vec::new();
+            let more_passes = context.begin_pass(|moves| {
+                let result = self.defragmentation_pass(
+                    queues,
+                    queue,
+                    request,
+                    &targets,
+                    moves,
+                    &mut replacements,
+                    &mut report,
+                );
+                if let Err(err) = result {
+                    error = Some(err);
+                    // Don't move anything in this pass. The recreated resources are destroyed below.
+                    for m in moves.iter_mut() {
+                        m.operation = ffi::VmaDefragmentationMoveOperation::VMA_DEFRAGMENTATION_MOVE_OPERATION_IGNORE;
+                    }
+                }
+            });
+            // The pass ended, so the moved allocations now refer to the new memory.
+            for replacement in replacements {
+                unsafe {
+                    match replacement {
+                        Replacement::Buffer(i, new_buffer) if error.is_none() => {
+                            let buffer = &mut *request.buffers[i];
+                            let old_buffer = std::mem::replace(&mut buffer.buffer, new_buffer);
+                            self.device().resource_registry().rekey(
+                                vk::ObjectType::BUFFER,
+                                old_buffer,
+                                new_buffer,
+                            );
+                            self.device().destroy_buffer(old_buffer, None);
+                            let allocation_info =
+                                self.allocator.get_allocation_info(&buffer.memory).unwrap();
+                            buffer.ptr = allocation_info.mapped_data;
+                            if buffer.device_address != 0 {
+                                buffer.device_address = buffer.device_address();
+                            }
+                            report.moved_buffers.push(i);
+                        }
+                        Replacement::Image(i, new_image) if error.is_none() => {
+                            let image = &mut *request.images[i].0;
+                            let old_image = std::mem::replace(&mut image.image, new_image);
+                            self.device().resource_registry().rekey(
+                                vk::ObjectType::IMAGE,
+                                old_image,
+                                new_image,
+                            );
+                            self.device().destroy_image(old_image, None);
+                            report.moved_images.push(i);
+                        }
+                        Replacement::Buffer(_, new_buffer) => {
+                            self.device().destroy_buffer(new_buffer, None)
+                        }
+                        Replacement::Image(_, new_image) => {
+                            self.device().destroy_image(new_image, None)
+                        }
+                    }
+                }
+            }
+            if error.is_some() || !more_passes {
+                break;
+            }
+        }
+        let stats = context.end();
+        report.bytes_moved = stats.bytesMoved;
+        report.bytes_freed = stats.bytesFreed;
+        report.allocations_moved = stats.allocationsMoved;
+        report.device_memory_blocks_freed = stats.deviceMemoryBlocksFreed;
+        tracing::info!(
+            bytes_moved = report.bytes_moved,
+            bytes_freed = report.bytes_freed,
+            allocations_moved = report.allocations_moved,
+            pinned = report.pinned_buffers.len() + report.pinned_images.len(),
+            "Defragmentation finished"
+        );
+        match error {
+            Some(err) => Err(err),
+            None => Ok(report),
+        }
+    }
+
+    /// Recreate the resources for `moves` on their new memory and copy their content.
+    /// Blocks until the copies complete.
+    fn defragmentation_pass(
+        &self,
+        queues: &Queues,
+        queue: QueueIndex,
+        request: &mut DefragmentationRequest,
+        targets: &HashMap<ffi::VmaAllocation, Target>,
+        moves: &mut [ffi::VmaDefragmentationMove],
+        replacements: &mut Vec<Replacement>,
+        report: &mut DefragmentationReport,
+    ) -> VkResult<()> {
+        let transfer_buffer =
+            vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST;
+        let transfer_image = vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST;
+        let mut buffer_copies: Vec<(vk::Buffer, vk::Buffer, vk::DeviceSize)> = Vec::new();
+        let mut image_copies: Vec<(vk::Image, vk::Image, usize)> = Vec::new();
+
+        for m in moves.iter_mut() {
+            let ignore =
+                ffi::VmaDefragmentationMoveOperation::VMA_DEFRAGMENTATION_MOVE_OPERATION_IGNORE;
+            // Safety: dst_allocation is only used for binding, and VMA keeps ownership of it.
+            let dst_allocation: std::mem::ManuallyDrop<Allocation> =
+                unsafe { std::mem::transmute_copy(&m.dstTmpAllocation) };
+            match targets.get(&m.srcAllocation) {
+                None => {
+                    m.operation = ignore;
+                    report.unlisted_allocations += 1;
+                }
+                Some(&Target::Buffer(i)) => {
+                    let buffer = &request.buffers[i];
+                    if !buffer.usage.contains(transfer_buffer)
+                        || (buffer.device_address != 0 && !request.move_device_address_buffers)
+                    {
+                        m.operation = ignore;
+                        report.pinned_buffers.push(i);
+                        continue;
+                    }
+                    let create_info = vk::BufferCreateInfo::builder()
+                        .size(buffer.size())
+                        .usage(buffer.usage)
+                        .sharing_mode(buffer.sharing_mode)
+                        .queue_family_indices(&buffer.queue_families)
+                        .build();
+                    let new_buffer = unsafe { self.device().create_buffer(&create_info, None)? };
+                    replacements.push(Replacement::Buffer(i, new_buffer));
+                    unsafe {
+                        self.allocator
+                            .bind_buffer_memory(&dst_allocation, new_buffer)?;
+                    }
+                    buffer_copies.push((buffer.buffer, new_buffer, buffer.size()));
+                }
+                Some(&Target::Image(i)) => {
+                    let image = &request.images[i].0;
+                    if !image.create_params.metadata.usage.contains(transfer_image) {
+                        m.operation = ignore;
+                        report.pinned_images.push(i);
+                        continue;
+                    }
+                    let create_info = image.create_params.create_info(vk::ImageLayout::UNDEFINED);
+                    let new_image = unsafe { self.device().create_image(&create_info, None)? };
+                    replacements.push(Replacement::Image(i, new_image));
+                    unsafe {
+                        self.allocator
+                            .bind_image_memory(&dst_allocation, new_image)?;
+                    }
+                    image_copies.push((image.image, new_image, i));
+                }
+            }
+        }
+        if buffer_copies.is_empty() && image_copies.is_empty() {
+            return Ok(());
+        }
+
+        let dispatcher = queues.of_index(queue);
+        let mut builder = dispatcher
+            .shared_command_pool()
+            .allocate_one()?
+            .start(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+        builder.set_label("Defragmentation");
+        builder.record(|mut recorder| {
+            let mut before: Vec<vk::ImageMemoryBarrier2> = Vec::new();
+            let mut after: Vec<vk::ImageMemoryBarrier2> = Vec::new();
+            for &(old_image, new_image, i) in image_copies.iter() {
+                let (image, layout) = &request.images[i];
+                let params = &image.create_params.metadata;
+                let subresource_range = vk::ImageSubresourceRange {
+                    aspect_mask: format_aspects(params.format),
+                    base_mip_level: 0,
+                    level_count: params.mip_levels,
+                    base_array_layer: 0,
+                    layer_count: params.array_layers,
+                };
+                let barrier = vk::ImageMemoryBarrier2 {
+                    src_stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+                    src_access_mask: vk::AccessFlags2::MEMORY_WRITE,
+                    dst_stage_mask: vk::PipelineStageFlags2::COPY,
+                    dst_access_mask: vk::AccessFlags2::TRANSFER_READ,
+                    old_layout: *layout,
+                    new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    image: old_image,
+                    subresource_range,
+                    ..Default::default()
+                };
+                before.push(barrier);
+                before.push(vk::ImageMemoryBarrier2 {
+                    src_access_mask: vk::AccessFlags2::NONE,
+                    dst_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+                    old_layout: vk::ImageLayout::UNDEFINED,
+                    new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    image: new_image,
+                    ..barrier
+                });
+                after.push(vk::ImageMemoryBarrier2 {
+                    src_stage_mask: vk::PipelineStageFlags2::COPY,
+                    src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+                    dst_stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+                    dst_access_mask: vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
+                    old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    new_layout: *layout,
+                    image: new_image,
+                    ..barrier
+                });
+            }
+            let memory_barrier = vk::MemoryBarrier2 {
+                src_stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+                src_access_mask: vk::AccessFlags2::MEMORY_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags2::COPY,
+                dst_access_mask: vk::AccessFlags2::TRANSFER_READ,
+                ..Default::default()
+            };
+            unsafe {
+                recorder.pipeline_barrier2(&vk::DependencyInfo {
+                    memory_barrier_count: 1,
+                    p_memory_barriers: &memory_barrier,
+                    image_memory_barrier_count: before.len() as u32,
+                    p_image_memory_barriers: before.as_ptr(),
+                    ..Default::default()
+                });
+            }
+            for &(src, dst, size) in buffer_copies.iter() {
+                recorder.copy_buffer(
+                    src,
+                    dst,
+                    &[vk::BufferCopy {
+                        src_offset: 0,
+                        dst_offset: 0,
+                        size,
+                    }],
+                );
+            }
+            for &(src, dst, i) in image_copies.iter() {
+                let params = &request.images[i].0.create_params.metadata;
+                let regions = image_copy_regions(
+                    params.format,
+                    params.extent,
+                    params.mip_levels,
+                    params.array_layers,
+                );
+                recorder.copy_image(
+                    src,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    dst,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &regions,
+                );
+            }
+            unsafe {
+                recorder.pipeline_barrier2(&vk::DependencyInfo {
+                    image_memory_barrier_count: after.len() as u32,
+                    p_image_memory_barriers: after.as_ptr(),
+                    ..Default::default()
+                });
+            }
+        });
+        // Keep the command buffer alive until the copies complete.
+        let executable = Arc::new(builder.end()?);
+        let fence = dispatcher.submit_immediately(std::slice::from_ref(&executable))?;
+        fence.wait()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::image_copy_regions;
+    use ash::vk;
+
+    #[test]
+    fn copy_regions_per_plane() {
+        let extent = vk::Extent3D {
+            width: 64,
+            height: 32,
+            depth: 1,
+        };
+        let regions = image_copy_regions(vk::Format::R8G8B8A8_UNORM, extent, 2, 3);
+        assert_eq!(regions.len(), 2);
+        assert_eq!(
+            regions[1].src_subresource.aspect_mask,
+            vk::ImageAspectFlags::COLOR
+        );
+        assert_eq!(regions[1].src_subresource.layer_count, 3);
+        assert_eq!(
+            (regions[1].extent.width, regions[1].extent.height),
+            (32, 16)
+        );
+
+        let regions = image_copy_regions(vk::Format::G8_B8R8_2PLANE_420_UNORM, extent, 1, 1);
+        assert_eq!(regions.len(), 2);
+        assert_eq!(
+            regions[0].dst_subresource.aspect_mask,
+            vk::ImageAspectFlags::PLANE_0
+        );
+        assert_eq!(
+            (regions[0].extent.width, regions[0].extent.height),
+            (64, 32)
+        );
+        assert_eq!(
+            regions[1].dst_subresource.aspect_mask,
+            vk::ImageAspectFlags::PLANE_1
+        );
+        assert_eq!(
+            (regions[1].extent.width, regions[1].extent.height),
+            (32, 16)
+        );
+    }
+}
@@ -0,0 +1,602 @@
+use ash::{extensions::khr, prelude::VkResult, vk};
+use std::{
+    ffi::c_void,
+    os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd},
+    sync::Arc,
+};
+
+use super::{
+    alloc::{Allocator, BufferRequest, MemoryAllocScenario},
+    buffer::{Buffer, HasBuffer},
+    image::{HasImage, HasImageMetadata, Image, ImageMetadata, ImageRequest},
+};
+use crate::{command::recorder::CommandBufferResource, Device, HasDevice};
+
+/// Function pointers of VK_KHR_external_memory_fd, VK_KHR_external_semaphore_fd and
+/// VK_EXT_image_drm_format_modifier. The extensions must be enabled on the device.
+pub struct ExternalFdLoader {
+    device: Arc<Device>,
+    pub(crate) memory: khr::ExternalMemoryFd,
+    pub(crate) semaphore: khr::ExternalSemaphoreFd,
+    drm_format_modifier: vk::ExtImageDrmFormatModifierFn,
+}
+
+impl crate::HasDevice for ExternalFdLoader {
+    fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+}
+
+impl ExternalFdLoader {
+    pub fn new(device: Arc<Device>) -> Self {
+        let memory = khr::ExternalMemoryFd::new(device.instance(), &device);
+        let semaphore = khr::ExternalSemaphoreFd::new(device.instance(), &device);
+        let drm_format_modifier = vk::ExtImageDrmFormatModifierFn::load(|name| unsafe {
+            std::mem::transmute(
+                device
+                    .instance()
+                    .get_device_proc_addr(device.handle(), name.as_ptr()),
+            )
+        });
+        Self {
+            device,
+            memory,
+            semaphore,
+            drm_format_modifier,
+        }
+    }
+
+    /// The DRM format modifier the driver picked for an image created with a modifier list.
+    pub fn image_drm_format_modifier(&self, image: vk::Image) -> VkResult<u64> {
+        let mut properties = vk::ImageDrmFormatModifierPropertiesEXT::default();
+        unsafe {
+            (self
+                .drm_format_modifier
+                .get_image_drm_format_modifier_properties_ext)(
+                self.device.handle(),
+                image,
+                &mut properties,
+            )
+            .result()?;
+        }
+        Ok(properties.drm_format_modifier)
+    }
+}
+
+/// A dedicated `vk::DeviceMemory` allocation that can be shared with other processes and instances.
+///
+/// External memory is allocated outside of vk_mem, because vk_mem can't import memory and exported
+/// allocations need to own their `vk::DeviceMemory`.
+pub struct ExternalMemory {
+    device: Arc<Device>,
+    memory: vk::DeviceMemory,
+    size: vk::DeviceSize,
+    memory_type_index: u32,
+    handle_type: vk::ExternalMemoryHandleTypeFlags,
+}
+
+impl ExternalMemory {
+    pub fn raw(&self) -> vk::DeviceMemory {
+        self.memory
+    }
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+    pub fn memory_type_index(&self) -> u32 {
+        self.memory_type_index
+    }
+    pub fn handle_type(&self) -> vk::ExternalMemoryHandleTypeFlags {
+        self.handle_type
+    }
+    /// Export a new file descriptor referencing the memory.
+    pub fn export_fd(&self, loader: &ExternalFdLoader) -> VkResult<OwnedFd> {
+        let fd = unsafe {
+            loader.memory.get_memory_fd(&vk::MemoryGetFdInfoKHR {
+                memory: self.memory,
+                handle_type: self.handle_type,
+                ..Default::default()
+            })?
+        };
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+}
+
+impl HasDevice for ExternalMemory {
+    fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+}
+
+impl Drop for ExternalMemory {
+    fn drop(&mut self) {
+        tracing::debug!(memory = ?self.memory, "drop external memory");
+        self.device
+            .resource_registry()
+            .unregister(vk::ObjectType::DEVICE_MEMORY, self.memory);
+        unsafe { self.device.free_memory(self.memory, None) }
+    }
+}
+
+/// Buffer bound to [`ExternalMemory`].
+pub struct ExternalBuffer {
+    // Destroyed before the memory is freed.
+    buffer: Buffer,
+    memory: Arc<ExternalMemory>,
+    size: vk::DeviceSize,
+}
+
+impl ExternalBuffer {
+    pub fn memory(&self) -> &Arc<ExternalMemory> {
+        &self.memory
+    }
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+}
+
+impl HasBuffer for ExternalBuffer {
+    fn raw_buffer(&self) -> vk::Buffer {
+        self.buffer.raw
+    }
+    fn buffer_size(&self) -> Option<vk::DeviceSize> {
+        Some(self.size)
+    }
+}
+
+impl CommandBufferResource for ExternalBuffer {}
+
+/// Image bound to [`ExternalMemory`].
+pub struct ExternalImage {
+    // Destroyed before the memory is freed.
+    image: Image,
+    memory: Arc<ExternalMemory>,
+    drm_format_modifier: Option<u64>,
+}
+
+impl ExternalImage {
+    pub fn memory(&self) -> &Arc<ExternalMemory> {
+        &self.memory
+    }
+    /// The DRM format modifier of images with `DRM_FORMAT_MODIFIER_EXT` tiling.
+    pub fn drm_format_modifier(&self) -> Option<u64> {
+        self.drm_format_modifier
+    }
+}
+
+impl HasImage for ExternalImage {
+    fn raw_image(&self) -> vk::Image {
+        self.image.raw_image()
+    }
+}
+
+impl HasImageMetadata for ExternalImage {
+    fn metadata(&self) -> &ImageMetadata {
+        self.image.metadata()
+    }
+}
+
+impl HasDevice for ExternalImage {
+    fn device(&self) -> &Arc<Device> {
+        self.image.device()
+    }
+}
+
+impl CommandBufferResource for ExternalImage {}
+
+/// Layout of an imported image with an explicit DRM format modifier.
+pub struct DrmFormatModifierLayout<'a> {
+    pub modifier: u64,
+    /// One layout per memory plane. Only `offset` and `row_pitch` are used.
+    pub planes: &'a [vk::SubresourceLayout],
+}
+
+/// Memory exported by another device, instance or process, to be imported with
+/// [`Allocator::import_buffer`] or [`Allocator::import_image`].
+pub struct MemoryImport {
+    pub fd: OwnedFd,
+    /// The allocation size of the exported memory, from [`ExternalMemory::size`] on the exporting side.
+    pub size: vk::DeviceSize,
+    /// The memory type of the exported memory, from [`ExternalMemory::memory_type_index`] on the exporting
+    /// side. Required for `OPAQUE_FD`, which can only be imported into the memory type it was exported from.
+    /// Other handle types pick a memory type reported by vkGetMemoryFdPropertiesKHR when this is None.
+    pub memory_type_index: Option<u32>,
+}
+
+/// Where the memory of an external resource comes from.
+enum MemorySource {
+    Export,
+    Import(MemoryImport),
+}
+
+enum DedicatedResource {
+    Buffer(vk::Buffer),
+    Image(vk::Image),
+}
+
+impl Allocator {
+    /// Create a buffer with dedicated memory that can be exported with `handle_type`.
+    /// `allocation_flags` and `alignment` of the request are ignored.
+    pub fn allocate_exportable_buffer(
+        self: &Arc<Self>,
+        request: &BufferRequest,
+        handle_type: vk::ExternalMemoryHandleTypeFlags,
+    ) -> VkResult<ExternalBuffer> {
+        self.create_external_buffer(None, request, handle_type, MemorySource::Export)
+    }
+
+    /// Create a buffer bound to the memory in `import`. The fd is consumed, even on failure.
+    pub fn import_buffer(
+        self: &Arc<Self>,
+        loader: &ExternalFdLoader,
+        request: &BufferRequest,
+        handle_type: vk::ExternalMemoryHandleTypeFlags,
+        import: MemoryImport,
+    ) -> VkResult<ExternalBuffer> {
+        self.create_external_buffer(
+            Some(loader),
+            request,
+            handle_type,
+            MemorySource::Import(import),
+        )
+    }
+
+    /// Create an image with dedicated memory that can be exported with `handle_type`.
+    ///
+    /// If `drm_format_modifiers` isn't empty, the image is created with `DRM_FORMAT_MODIFIER_EXT` tiling
+    /// and the driver picks one of the modifiers, available from [`ExternalImage::drm_format_modifier`].
+    pub fn allocate_exportable_image(
+        self: &Arc<Self>,
+        loader: &ExternalFdLoader,
+        request: &ImageRequest,
+        handle_type: vk::ExternalMemoryHandleTypeFlags,
+        drm_format_modifiers: &[u64],
+    ) -> VkResult<ExternalImage> {
+        let modifier_list = vk::ImageDrmFormatModifierListCreateInfoEXT {
+            drm_format_modifier_count: drm_format_modifiers.len() as u32,
+            p_drm_format_modifiers: drm_format_modifiers.as_ptr(),
+            ..Default::default()
+        };
+        let modifier_info = if drm_format_modifiers.is_empty() {
+            None
+        } else {
+            Some(&modifier_list as *const _ as *const c_void)
+        };
+        self.create_external_image(
+            loader,
+            request,
+            handle_type,
+            modifier_info,
+            MemorySource::Export,
+        )
+    }
+
+    /// Create an image bound to the memory in `import`. The fd is consumed, even on failure.
+    /// Images shared through dma-buf usually need the `drm_format_modifier` of the exporter.
+    pub fn import_image(
+        self: &Arc<Self>,
+        loader: &ExternalFdLoader,
+        request: &ImageRequest,
+        handle_type: vk::ExternalMemoryHandleTypeFlags,
+        drm_format_modifier: Option<&DrmFormatModifierLayout>,
+        import: MemoryImport,
+    ) -> VkResult<ExternalImage> {
+        let explicit =
+            drm_format_modifier.map(|layout| vk::ImageDrmFormatModifierExplicitCreateInfoEXT {
+                drm_format_modifier: layout.modifier,
+                drm_format_modifier_plane_count: layout.planes.len() as u32,
+                p_plane_layouts: layout.planes.as_ptr(),
+                ..Default::default()
+            });
+        let modifier_info = explicit
+            .as_ref()
+            .map(|explicit| explicit as *const _ as *const c_void);
+        self.create_external_image(
+            loader,
+            request,
+            handle_type,
+            modifier_info,
+            MemorySource::Import(import),
+        )
+    }
+
+    fn create_external_buffer(
+        self: &Arc<Self>,
+        loader: Option<&ExternalFdLoader>,
+        request: &BufferRequest,
+        handle_type: vk::ExternalMemoryHandleTypeFlags,
+        source: MemorySource,
+    ) -> VkResult<ExternalBuffer> {
+        let external_info = vk::ExternalMemoryBufferCreateInfo {
+            handle_types: handle_type,
+            ..Default::default()
+        };
+        let buffer = Buffer::new(
+            self.device().clone(),
+            &vk::BufferCreateInfo {
+                p_next: &external_info as *const _ as *const c_void,
+                size: request.size,
+                usage: request.usage,
+                sharing_mode: request.sharing_mode,
+                queue_family_index_count: request.queue_families.len() as u32,
+                p_queue_family_indices: request.queue_families.as_ptr(),
+                ..Default::default()
+            },
+        )?;
+        let requirements = unsafe { self.device().get_buffer_memory_requirements(buffer.raw) };
+        let memory = self.allocate_external_memory(
+            loader,
+            requirements,
+            &request.scenario,
+            handle_type,
+            DedicatedResource::Buffer(buffer.raw),
+            source,
+        )?;
+        unsafe {
+            self.device()
+                .bind_buffer_memory(buffer.raw, memory.memory, 0)?;
+        }
+        Ok(ExternalBuffer {
+            buffer,
+            memory: Arc::new(memory),
+            size: request.size,
+        })
+    }
+
+    fn create_external_image(
+        self: &Arc<Self>,
+        loader: &ExternalFdLoader,
+        request: &ImageRequest,
+        handle_type: vk::ExternalMemoryHandleTypeFlags,
+        drm_format_modifier_info: Option<*const c_void>,
+        source: MemorySource,
+    ) -> VkResult<ExternalImage> {
+        let external_info = vk::ExternalMemoryImageCreateInfo {
+            p_next: drm_format_modifier_info.unwrap_or(std::ptr::null()),
+            handle_types: handle_type,
+            ..Default::default()
+        };
+        let tiling = if drm_format_modifier_info.is_some() {
+            vk::ImageTiling::DRM_FORMAT_MODIFIER_EXT
+        } else {
+            request.tiling
+        };
+        let image = Image::new(
+            self.device().clone(),
+            &vk::ImageCreateInfo {
+                p_next: &external_info as *const _ as *const c_void,
+                flags: request.flags,
+                image_type: request.image_type,
+                format: request.format,
+                extent: request.extent,
+                mip_levels: request.mip_levels,
+                array_layers: request.array_layers,
+                samples: request.samples,
+                tiling,
+                usage: request.usage,
+                sharing_mode: request.sharing_mode,
+                queue_family_index_count: request.queue_families.len() as u32,
+                p_queue_family_indices: request.queue_families.as_ptr(),
+                initial_layout: request.initial_layout,
+                ..Default::default()
+            },
+        )?;
+        let requirements = unsafe {
+            self.device()
+                .get_image_memory_requirements(image.raw_image())
+        };
+        let memory = self.allocate_external_memory(
+            Some(loader),
+            requirements,
+            &request.scenario,
+            handle_type,
+            DedicatedResource::Image(image.raw_image()),
+            source,
+        )?;
+        unsafe {
+            self.device()
+                .bind_image_memory(image.raw_image(), memory.memory, 0)?;
+        }
+        let drm_format_modifier = if drm_format_modifier_info.is_some() {
+            Some(loader.image_drm_format_modifier(image.raw_image())?)
+        } else {
+            None
+        };
+        Ok(ExternalImage {
+            image,
+            memory: Arc::new(memory),
+            drm_format_modifier,
+        })
+    }
+
+    fn allocate_external_memory(
+        &self,
+        loader: Option<&ExternalFdLoader>,
+        requirements: vk::MemoryRequirements,
+        scenario: &MemoryAllocScenario,
+        handle_type: vk::ExternalMemoryHandleTypeFlags,
+        dedicated: DedicatedResource,
+        source: MemorySource,
+    ) -> VkResult<ExternalMemory> {
+        let mut dedicated_info = vk::MemoryDedicatedAllocateInfo::default();
+        match dedicated {
+            DedicatedResource::Buffer(buffer) => dedicated_info.buffer = buffer,
+            DedicatedResource::Image(image) => dedicated_info.image = image,
+        }
+        let mut export_info = vk::ExportMemoryAllocateInfo::default();
+        let mut import_info = vk::ImportMemoryFdInfoKHR::default();
+        let mut import_fd: Option<RawFd> = None;
+        let (p_next, allocation_size, memory_type_index): (*const c_void, _, _) = match source {
+            MemorySource::Export => {
+                export_info.p_next = &dedicated_info as *const _ as *const c_void;
+                export_info.handle_types = handle_type;
+                let memory_type_index = self
+                    .external_memory_type(requirements.memory_type_bits, scenario)
+                    .ok_or(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY)?;
+                (
+                    &export_info as *const _ as *const c_void,
+                    requirements.size,
+                    memory_type_index,
+                )
+            }
+            MemorySource::Import(import) => {
+                // Opaque fds can only be imported into the memory type they were exported from,
+                // which isn't reported by vkGetMemoryFdPropertiesKHR.
+                let fd_type_bits = if handle_type == vk::ExternalMemoryHandleTypeFlags::OPAQUE_FD {
+                    None
+                } else {
+                    let properties = unsafe {
+                        loader
+                            .expect("Importing memory requires an ExternalFdLoader")
+                            .memory
+                            .get_memory_fd_properties(handle_type, import.fd.as_raw_fd())
+                    };
+                    Some(properties?.memory_type_bits)
+                };
+                if import.size < requirements.size {
+                    return Err(vk::Result::ERROR_INVALID_EXTERNAL_HANDLE);
+                }
+                let memory_type_index = match import_memory_type(
+                    requirements.memory_type_bits,
+                    fd_type_bits,
+                    import.memory_type_index,
+                ) {
+                    ImportMemoryType::Exact(index) => index,
+                    ImportMemoryType::Any(type_bits) => self
+                        .external_memory_type(type_bits, scenario)
+                        .ok_or(vk::Result::ERROR_INVALID_EXTERNAL_HANDLE)?,
+                    ImportMemoryType::Invalid => {
+                        return Err(vk::Result::ERROR_INVALID_EXTERNAL_HANDLE)
+                    }
+                };
+                import_info.p_next = &dedicated_info as *const _ as *const c_void;
+                import_info.handle_type = handle_type;
+                // Ownership of the fd is transferred to the implementation when the import succeeds.
+                import_info.fd = import.fd.into_raw_fd();
+                import_fd = Some(import_info.fd);
+                (
+                    &import_info as *const _ as *const c_void,
+                    import.size,
+                    memory_type_index,
+                )
+            }
+        };
+        let memory = unsafe {
+            self.device().allocate_memory(
+                &vk::MemoryAllocateInfo {
+                    p_next,
+                    allocation_size,
+                    memory_type_index,
+                    ..Default::default()
+                },
+                None,
+            )
+        };
+        let memory = match memory {
+            Ok(memory) => memory,
+            Err(err) => {
+                if let Some(fd) = import_fd {
+                    drop(unsafe { OwnedFd::from_raw_fd(fd) });
+                }
+                return Err(err);
+            }
+        };
+        self.device().resource_registry().register(
+            vk::ObjectType::DEVICE_MEMORY,
+            memory,
+            Some(allocation_size),
+        );
+        Ok(ExternalMemory {
+            device: self.device().clone(),
+            memory,
+            size: allocation_size,
+            memory_type_index,
+            handle_type,
+        })
+    }
+
+    /// The memory type in `type_bits` with the required flags of `scenario` and the most preferred flags.
+    fn external_memory_type(&self, type_bits: u32, scenario: &MemoryAllocScenario) -> Option<u32> {
+        let create_info =
+            self.create_info_by_scenario(vk_mem::AllocationCreateFlags::empty(), scenario);
+        self.types
+            .iter()
+            .enumerate()
+            .filter(|(i, ty)| {
+                type_bits & (1 << i) != 0 && ty.property_flags.contains(create_info.required_flags)
+            })
+            .max_by_key(|(_, ty)| {
+                (ty.property_flags & create_info.preferred_flags)
+                    .as_raw()
+                    .count_ones()
+            })
+            .map(|(i, _)| i as u32)
+    }
+}
+
+enum ImportMemoryType {
+    /// Import into this memory type.
+    Exact(u32),
+    /// Import into any of these memory types.
+    Any(u32),
+    /// The memory can't be imported for the resource.
+    Invalid,
+}
+
+/// The memory types imported memory may use. `fd_type_bits` are the types reported by
+/// vkGetMemoryFdPropertiesKHR, and `exported` is the memory type of the exporter, if known.
+fn import_memory_type(
+    requirement_bits: u32,
+    fd_type_bits: Option<u32>,
+    exported: Option<u32>,
+) -> ImportMemoryType {
+    let type_bits = requirement_bits & fd_type_bits.unwrap_or(u32::MAX);
+    match (fd_type_bits, exported) {
+        (_, Some(index)) if index < 32 && type_bits & (1 << index) != 0 => {
+            ImportMemoryType::Exact(index)
+        }
+        (Some(_), None) if type_bits != 0 => ImportMemoryType::Any(type_bits),
+        _ => ImportMemoryType::Invalid,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import_memory_type, ImportMemoryType};
+
+    #[test]
+    fn opaque_fd_uses_the_exported_memory_type() {
+        assert!(matches!(
+            import_memory_type(0b0110, None, Some(2)),
+            ImportMemoryType::Exact(2)
+        ));
+        // The exporter's memory type doesn't suit the resource.
+        assert!(matches!(
+            import_memory_type(0b0110, None, Some(0)),
+            ImportMemoryType::Invalid
+        ));
+        assert!(matches!(
+            import_memory_type(0b0110, None, None),
+            ImportMemoryType::Invalid
+        ));
+    }
+
+    #[test]
+    fn dma_buf_uses_the_fd_memory_types() {
+        assert!(matches!(
+            import_memory_type(0b0110, Some(0b1100), None),
+            ImportMemoryType::Any(0b0100)
+        ));
+        assert!(matches!(
+            import_memory_type(0b0110, Some(0b1100), Some(2)),
+            ImportMemoryType::Exact(2)
+        ));
+        assert!(matches!(
+            import_memory_type(0b0110, Some(0b1100), Some(1)),
+            ImportMemoryType::Invalid
+        ));
+        assert!(matches!(
+            import_memory_type(0b0110, Some(0b1000), None),
+            ImportMemoryType::Invalid
+        ));
+    }
+}
@@ -0,0 +1,880 @@
+//! Texel block layout, aspects and compatibility classes of Vulkan formats.
+//!
+//! Covers the core 1.0 - 1.3 formats, including the YCbCr formats, and the PVRTC formats of
+//! `VK_IMG_format_pvrtc`.
+
+use ash::vk;
+
+/// Format compatibility classes, as defined in the "Compatible Formats" table of the Vulkan specification.
+/// Formats of the same class have the same texel block layout and may be reinterpreted as each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FormatClass {
+    Bits8,
+    Bits16,
+    Bits24,
+    Bits32,
+    Bits48,
+    Bits64,
+    Bits96,
+    Bits128,
+    Bits192,
+    Bits256,
+    D16,
+    D24,
+    D32,
+    S8,
+    D16S8,
+    D24S8,
+    D32S8,
+    Bc1Rgb,
+    Bc1Rgba,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc5,
+    Bc6h,
+    Bc7,
+    Etc2Rgb,
+    Etc2Rgba,
+    Etc2EacRgba,
+    EacR,
+    EacRg,
+    Astc {
+        width: u32,
+        height: u32,
+    },
+    Pvrtc1Bpp2,
+    Pvrtc1Bpp4,
+    Pvrtc2Bpp2,
+    Pvrtc2Bpp4,
+    /// 4 component formats with 10 or 12 bits per component, padded to 16 bits.
+    Rgba16Padded {
+        bits: u32,
+    },
+    /// Horizontally subsampled formats storing two texels as G, B, G, R.
+    Gbgr422 {
+        bits: u32,
+    },
+    /// Horizontally subsampled formats storing two texels as B, G, R, G.
+    Bgrg422 {
+        bits: u32,
+    },
+    /// Formats with the G, B and R components stored in separate planes, or B and R sharing one plane.
+    MultiPlanar {
+        bits: u32,
+        planes: u32,
+        subsampling: ChromaSubsampling,
+    },
+}
+
+/// Resolution of the B and R planes of a multi-planar format relative to the G plane.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChromaSubsampling {
+    /// Half width and half height.
+    Chroma420,
+    /// Half width.
+    Chroma422,
+    /// Full resolution.
+    Chroma444,
+}
+
+/// How the components of a format are interpreted. For combined depth stencil formats, this describes the
+/// depth component.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NumericFormat {
+    Unorm,
+    Snorm,
+    Uscaled,
+    Sscaled,
+    Uint,
+    Sint,
+    Ufloat,
+    Sfloat,
+    Srgb,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FormatInfo {
+    pub format: vk::Format,
+    pub class: FormatClass,
+    pub numeric: NumericFormat,
+}
+
+impl FormatInfo {
+    /// Returns None for `vk::Format::UNDEFINED` and formats not covered by this module.
+    pub fn of(format: vk::Format) -> Option<Self> {
+        use vk::Format as F;
+        use FormatClass::*;
+        use NumericFormat::*;
+        let (class, numeric) = match format {
+            F::R4G4_UNORM_PACK8 | F::R8_UNORM => (Bits8, Unorm),
+            F::R8_SNORM => (Bits8, Snorm),
+            F::R8_USCALED => (Bits8, Uscaled),
+            F::R8_SSCALED => (Bits8, Sscaled),
+            F::R8_UINT => (Bits8, Uint),
+            F::R8_SINT => (Bits8, Sint),
+            F::R8_SRGB => (Bits8, Srgb),
+
+            F::R4G4B4A4_UNORM_PACK16
+            | F::B4G4R4A4_UNORM_PACK16
+            | F::R5G6B5_UNORM_PACK16
+            | F::B5G6R5_UNORM_PACK16
+            | F::R5G5B5A1_UNORM_PACK16
+            | F::B5G5R5A1_UNORM_PACK16
+            | F::A1R5G5B5_UNORM_PACK16
+            | F::A4R4G4B4_UNORM_PACK16
+            | F::A4B4G4R4_UNORM_PACK16
+            | F::R8G8_UNORM
+            | F::R16_UNORM
+            | F::R10X6_UNORM_PACK16
+            | F::R12X4_UNORM_PACK16 => (Bits16, Unorm),
+            F::R8G8_SNORM | F::R16_SNORM => (Bits16, Snorm),
+            F::R8G8_USCALED | F::R16_USCALED => (Bits16, Uscaled),
+            F::R8G8_SSCALED | F::R16_SSCALED => (Bits16, Sscaled),
+            F::R8G8_UINT | F::R16_UINT => (Bits16, Uint),
+            F::R8G8_SINT | F::R16_SINT => (Bits16, Sint),
+            F::R8G8_SRGB => (Bits16, Srgb),
+            F::R16_SFLOAT => (Bits16, Sfloat),
+
+            F::R8G8B8_UNORM | F::B8G8R8_UNORM => (Bits24, Unorm),
+            F::R8G8B8_SNORM | F::B8G8R8_SNORM => (Bits24, Snorm),
+            F::R8G8B8_USCALED | F::B8G8R8_USCALED => (Bits24, Uscaled),
+            F::R8G8B8_SSCALED | F::B8G8R8_SSCALED => (Bits24, Sscaled),
+            F::R8G8B8_UINT | F::B8G8R8_UINT => (Bits24, Uint),
+            F::R8G8B8_SINT | F::B8G8R8_SINT => (Bits24, Sint),
+            F::R8G8B8_SRGB | F::B8G8R8_SRGB => (Bits24, Srgb),
+
+            F::R8G8B8A8_UNORM
+            | F::B8G8R8A8_UNORM
+            | F::A8B8G8R8_UNORM_PACK32
+            | F::A2R10G10B10_UNORM_PACK32
+            | F::A2B10G10R10_UNORM_PACK32
+            | F::R16G16_UNORM
+            | F::R10X6G10X6_UNORM_2PACK16
+            | F::R12X4G12X4_UNORM_2PACK16 => (Bits32, Unorm),
+            F::R8G8B8A8_SNORM
+            | F::B8G8R8A8_SNORM
+            | F::A8B8G8R8_SNORM_PACK32
+            | F::A2R10G10B10_SNORM_PACK32
+            | F::A2B10G10R10_SNORM_PACK32
+            | F::R16G16_SNORM => (Bits32, Snorm),
+            F::R8G8B8A8_USCALED
+            | F::B8G8R8A8_USCALED
+            | F::A8B8G8R8_USCALED_PACK32
+            | F::A2R10G10B10_USCALED_PACK32
+            | F::A2B10G10R10_USCALED_PACK32
+            | F::R16G16_USCALED => (Bits32, Uscaled),
+            F::R8G8B8A8_SSCALED
+            | F::B8G8R8A8_SSCALED
+            | F::A8B8G8R8_SSCALED_PACK32
+            | F::A2R10G10B10_SSCALED_PACK32
+            | F::A2B10G10R10_SSCALED_PACK32
+            | F::R16G16_SSCALED => (Bits32, Sscaled),
+            F::R8G8B8A8_UINT
+            | F::B8G8R8A8_UINT
+            | F::A8B8G8R8_UINT_PACK32
+            | F::A2R10G10B10_UINT_PACK32
+            | F::A2B10G10R10_UINT_PACK32
+            | F::R16G16_UINT
+            | F::R32_UINT => (Bits32, Uint),
+            F::R8G8B8A8_SINT
+            | F::B8G8R8A8_SINT
+            | F::A8B8G8R8_SINT_PACK32
+            | F::A2R10G10B10_SINT_PACK32
+            | F::A2B10G10R10_SINT_PACK32
+            | F::R16G16_SINT
+            | F::R32_SINT => (Bits32, Sint),
+            F::R8G8B8A8_SRGB | F::B8G8R8A8_SRGB | F::A8B8G8R8_SRGB_PACK32 => (Bits32, Srgb),
+            F::R16G16_SFLOAT | F::R32_SFLOAT => (Bits32, Sfloat),
+            F::B10G11R11_UFLOAT_PACK32 | F::E5B9G9R9_UFLOAT_PACK32 => (Bits32, Ufloat),
+
+            F::R16G16B16_UNORM => (Bits48, Unorm),
+            F::R16G16B16_SNORM => (Bits48, Snorm),
+            F::R16G16B16_USCALED => (Bits48, Uscaled),
+            F::R16G16B16_SSCALED => (Bits48, Sscaled),
+            F::R16G16B16_UINT => (Bits48, Uint),
+            F::R16G16B16_SINT => (Bits48, Sint),
+            F::R16G16B16_SFLOAT => (Bits48, Sfloat),
+
+            F::R16G16B16A16_UNORM => (Bits64, Unorm),
+            F::R16G16B16A16_SNORM => (Bits64, Snorm),
+            F::R16G16B16A16_USCALED => (Bits64, Uscaled),
+            F::R16G16B16A16_SSCALED => (Bits64, Sscaled),
+            F::R16G16B16A16_UINT | F::R32G32_UINT | F::R64_UINT => (Bits64, Uint),
+            F::R16G16B16A16_SINT | F::R32G32_SINT | F::R64_SINT => (Bits64, Sint),
+            F::R16G16B16A16_SFLOAT | F::R32G32_SFLOAT | F::R64_SFLOAT => (Bits64, Sfloat),
+
+            F::R32G32B32_UINT => (Bits96, Uint),
+            F::R32G32B32_SINT => (Bits96, Sint),
+            F::R32G32B32_SFLOAT => (Bits96, Sfloat),
+
+            F::R32G32B32A32_UINT | F::R64G64_UINT => (Bits128, Uint),
+            F::R32G32B32A32_SINT | F::R64G64_SINT => (Bits128, Sint),
+            F::R32G32B32A32_SFLOAT | F::R64G64_SFLOAT => (Bits128, Sfloat),
+
+            F::R64G64B64_UINT => (Bits192, Uint),
+            F::R64G64B64_SINT => (Bits192, Sint),
+            F::R64G64B64_SFLOAT => (Bits192, Sfloat),
+
+            F::R64G64B64A64_UINT => (Bits256, Uint),
+            F::R64G64B64A64_SINT => (Bits256, Sint),
+            F::R64G64B64A64_SFLOAT => (Bits256, Sfloat),
+
+            F::D16_UNORM => (D16, Unorm),
+            F::X8_D24_UNORM_PACK32 => (D24, Unorm),
+            F::D32_SFLOAT => (D32, Sfloat),
+            F::S8_UINT => (S8, Uint),
+            F::D16_UNORM_S8_UINT => (D16S8, Unorm),
+            F::D24_UNORM_S8_UINT => (D24S8, Unorm),
+            F::D32_SFLOAT_S8_UINT => (D32S8, Sfloat),
+
+            F::BC1_RGB_UNORM_BLOCK => (Bc1Rgb, Unorm),
+            F::BC1_RGB_SRGB_BLOCK => (Bc1Rgb, Srgb),
+            F::BC1_RGBA_UNORM_BLOCK => (Bc1Rgba, Unorm),
+            F::BC1_RGBA_SRGB_BLOCK => (Bc1Rgba, Srgb),
+            F::BC2_UNORM_BLOCK => (Bc2, Unorm),
+            F::BC2_SRGB_BLOCK => (Bc2, Srgb),
+            F::BC3_UNORM_BLOCK => (Bc3, Unorm),
+            F::BC3_SRGB_BLOCK => (Bc3, Srgb),
+            F::BC4_UNORM_BLOCK => (Bc4, Unorm),
+            F::BC4_SNORM_BLOCK => (Bc4, Snorm),
+            F::BC5_UNORM_BLOCK => (Bc5, Unorm),
+            F::BC5_SNORM_BLOCK => (Bc5, Snorm),
+            F::BC6H_UFLOAT_BLOCK => (Bc6h, Ufloat),
+            F::BC6H_SFLOAT_BLOCK => (Bc6h, Sfloat),
+            F::BC7_UNORM_BLOCK => (Bc7, Unorm),
+            F::BC7_SRGB_BLOCK => (Bc7, Srgb),
+
+            F::ETC2_R8G8B8_UNORM_BLOCK => (Etc2Rgb, Unorm),
+            F::ETC2_R8G8B8_SRGB_BLOCK => (Etc2Rgb, Srgb),
+            F::ETC2_R8G8B8A1_UNORM_BLOCK => (Etc2Rgba, Unorm),
+            F::ETC2_R8G8B8A1_SRGB_BLOCK => (Etc2Rgba, Srgb),
+            F::ETC2_R8G8B8A8_UNORM_BLOCK => (Etc2EacRgba, Unorm),
+            F::ETC2_R8G8B8A8_SRGB_BLOCK => (Etc2EacRgba, Srgb),
+            F::EAC_R11_UNORM_BLOCK => (EacR, Unorm),
+            F::EAC_R11_SNORM_BLOCK => (EacR, Snorm),
+            F::EAC_R11G11_UNORM_BLOCK => (EacRg, Unorm),
+            F::EAC_R11G11_SNORM_BLOCK => (EacRg, Snorm),
+
+            F::ASTC_4X4_UNORM_BLOCK => (astc(4, 4), Unorm),
+            F::ASTC_4X4_SRGB_BLOCK => (astc(4, 4), Srgb),
+            F::ASTC_4X4_SFLOAT_BLOCK => (astc(4, 4), Sfloat),
+            F::ASTC_5X4_UNORM_BLOCK => (astc(5, 4), Unorm),
+            F::ASTC_5X4_SRGB_BLOCK => (astc(5, 4), Srgb),
+            F::ASTC_5X4_SFLOAT_BLOCK => (astc(5, 4), Sfloat),
+            F::ASTC_5X5_UNORM_BLOCK => (astc(5, 5), Unorm),
+            F::ASTC_5X5_SRGB_BLOCK => (astc(5, 5), Srgb),
+            F::ASTC_5X5_SFLOAT_BLOCK => (astc(5, 5), Sfloat),
+            F::ASTC_6X5_UNORM_BLOCK => (astc(6, 5), Unorm),
+            F::ASTC_6X5_SRGB_BLOCK => (astc(6, 5), Srgb),
+            F::ASTC_6X5_SFLOAT_BLOCK => (astc(6, 5), Sfloat),
+            F::ASTC_6X6_UNORM_BLOCK => (astc(6, 6), Unorm),
+            F::ASTC_6X6_SRGB_BLOCK => (astc(6, 6), Srgb),
+            F::ASTC_6X6_SFLOAT_BLOCK => (astc(6, 6), Sfloat),
+            F::ASTC_8X5_UNORM_BLOCK => (astc(8, 5), Unorm),
+            F::ASTC_8X5_SRGB_BLOCK => (astc(8, 5), Srgb),
+            F::ASTC_8X5_SFLOAT_BLOCK => (astc(8, 5), Sfloat),
+            F::ASTC_8X6_UNORM_BLOCK => (astc(8, 6), Unorm),
+            F::ASTC_8X6_SRGB_BLOCK => (astc(8, 6), Srgb),
+            F::ASTC_8X6_SFLOAT_BLOCK => (astc(8, 6), Sfloat),
+            F::ASTC_8X8_UNORM_BLOCK => (astc(8, 8), Unorm),
+            F::ASTC_8X8_SRGB_BLOCK => (astc(8, 8), Srgb),
+            F::ASTC_8X8_SFLOAT_BLOCK => (astc(8, 8), Sfloat),
+            F::ASTC_10X5_UNORM_BLOCK => (astc(10, 5), Unorm),
+            F::ASTC_10X5_SRGB_BLOCK => (astc(10, 5), Srgb),
+            F::ASTC_10X5_SFLOAT_BLOCK => (astc(10, 5), Sfloat),
+            F::ASTC_10X6_UNORM_BLOCK => (astc(10, 6), Unorm),
+            F::ASTC_10X6_SRGB_BLOCK => (astc(10, 6), Srgb),
+            F::ASTC_10X6_SFLOAT_BLOCK => (astc(10, 6), Sfloat),
+            F::ASTC_10X8_UNORM_BLOCK => (astc(10, 8), Unorm),
+            F::ASTC_10X8_SRGB_BLOCK => (astc(10, 8), Srgb),
+            F::ASTC_10X8_SFLOAT_BLOCK => (astc(10, 8), Sfloat),
+            F::ASTC_10X10_UNORM_BLOCK => (astc(10, 10), Unorm),
+            F::ASTC_10X10_SRGB_BLOCK => (astc(10, 10), Srgb),
+            F::ASTC_10X10_SFLOAT_BLOCK => (astc(10, 10), Sfloat),
+            F::ASTC_12X10_UNORM_BLOCK => (astc(12, 10), Unorm),
+            F::ASTC_12X10_SRGB_BLOCK => (astc(12, 10), Srgb),
+            F::ASTC_12X10_SFLOAT_BLOCK => (astc(12, 10), Sfloat),
+            F::ASTC_12X12_UNORM_BLOCK => (astc(12, 12), Unorm),
+            F::ASTC_12X12_SRGB_BLOCK => (astc(12, 12), Srgb),
+            F::ASTC_12X12_SFLOAT_BLOCK => (astc(12, 12), Sfloat),
+
+            F::PVRTC1_2BPP_UNORM_BLOCK_IMG => (Pvrtc1Bpp2, Unorm),
+            F::PVRTC1_2BPP_SRGB_BLOCK_IMG => (Pvrtc1Bpp2, Srgb),
+            F::PVRTC1_4BPP_UNORM_BLOCK_IMG => (Pvrtc1Bpp4, Unorm),
+            F::PVRTC1_4BPP_SRGB_BLOCK_IMG => (Pvrtc1Bpp4, Srgb),
+            F::PVRTC2_2BPP_UNORM_BLOCK_IMG => (Pvrtc2Bpp2, Unorm),
+            F::PVRTC2_2BPP_SRGB_BLOCK_IMG => (Pvrtc2Bpp2, Srgb),
+            F::PVRTC2_4BPP_UNORM_BLOCK_IMG => (Pvrtc2Bpp4, Unorm),
+            F::PVRTC2_4BPP_SRGB_BLOCK_IMG => (Pvrtc2Bpp4, Srgb),
+
+            F::R10X6G10X6B10X6A10X6_UNORM_4PACK16 => (Rgba16Padded { bits: 10 }, Unorm),
+            F::R12X4G12X4B12X4A12X4_UNORM_4PACK16 => (Rgba16Padded { bits: 12 }, Unorm),
+
+            F::G8B8G8R8_422_UNORM => (Gbgr422 { bits: 8 }, Unorm),
+            F::G10X6B10X6G10X6R10X6_422_UNORM_4PACK16 => (Gbgr422 { bits: 10 }, Unorm),
+            F::G12X4B12X4G12X4R12X4_422_UNORM_4PACK16 => (Gbgr422 { bits: 12 }, Unorm),
+            F::G16B16G16R16_422_UNORM => (Gbgr422 { bits: 16 }, Unorm),
+            F::B8G8R8G8_422_UNORM => (Bgrg422 { bits: 8 }, Unorm),
+            F::B10X6G10X6R10X6G10X6_422_UNORM_4PACK16 => (Bgrg422 { bits: 10 }, Unorm),
+            F::B12X4G12X4R12X4G12X4_422_UNORM_4PACK16 => (Bgrg422 { bits: 12 }, Unorm),
+            F::B16G16R16G16_422_UNORM => (Bgrg422 { bits: 16 }, Unorm),
+
+            F::G8_B8_R8_3PLANE_420_UNORM => (planar(8, 3, ChromaSubsampling::Chroma420), Unorm),
+            F::G8_B8_R8_3PLANE_422_UNORM => (planar(8, 3, ChromaSubsampling::Chroma422), Unorm),
+            F::G8_B8_R8_3PLANE_444_UNORM => (planar(8, 3, ChromaSubsampling::Chroma444), Unorm),
+            F::G8_B8R8_2PLANE_420_UNORM => (planar(8, 2, ChromaSubsampling::Chroma420), Unorm),
+            F::G8_B8R8_2PLANE_422_UNORM => (planar(8, 2, ChromaSubsampling::Chroma422), Unorm),
+            F::G8_B8R8_2PLANE_444_UNORM => (planar(8, 2, ChromaSubsampling::Chroma444), Unorm),
+            F::G10X6_B10X6_R10X6_3PLANE_420_UNORM_3PACK16 => {
+                (planar(10, 3, ChromaSubsampling::Chroma420), Unorm)
+            }
+            F::G10X6_B10X6_R10X6_3PLANE_422_UNORM_3PACK16 => {
+                (planar(10, 3, ChromaSubsampling::Chroma422), Unorm)
+            }
+            F::G10X6_B10X6_R10X6_3PLANE_444_UNORM_3PACK16 => {
+                (planar(10, 3, ChromaSubsampling::Chroma444), Unorm)
+            }
+            F::G10X6_B10X6R10X6_2PLANE_420_UNORM_3PACK16 => {
+                (planar(10, 2, ChromaSubsampling::Chroma420), Unorm)
+            }
+            F::G10X6_B10X6R10X6_2PLANE_422_UNORM_3PACK16 => {
+                (planar(10, 2, ChromaSubsampling::Chroma422), Unorm)
+            }
+            F::G10X6_B10X6R10X6_2PLANE_444_UNORM_3PACK16 => {
+                (planar(10, 2, ChromaSubsampling::Chroma444), Unorm)
+            }
+            F::G12X4_B12X4_R12X4_3PLANE_420_UNORM_3PACK16 => {
+                (planar(12, 3, ChromaSubsampling::Chroma420), Unorm)
+            }
+            F::G12X4_B12X4_R12X4_3PLANE_422_UNORM_3PACK16 => {
+                (planar(12, 3, ChromaSubsampling::Chroma422), Unorm)
+            }
+            F::G12X4_B12X4_R12X4_3PLANE_444_UNORM_3PACK16 => {
+                (planar(12, 3, ChromaSubsampling::Chroma444), Unorm)
+            }
+            F::G12X4_B12X4R12X4_2PLANE_420_UNORM_3PACK16 => {
+                (planar(12, 2, ChromaSubsampling::Chroma420), Unorm)
+            }
+            F::G12X4_B12X4R12X4_2PLANE_422_UNORM_3PACK16 => {
+                (planar(12, 2, ChromaSubsampling::Chroma422), Unorm)
+            }
+            F::G12X4_B12X4R12X4_2PLANE_444_UNORM_3PACK16 => {
+                (planar(12, 2, ChromaSubsampling::Chroma444), Unorm)
+            }
+            F::G16_B16_R16_3PLANE_420_UNORM => (planar(16, 3, ChromaSubsampling::Chroma420), Unorm),
+            F::G16_B16_R16_3PLANE_422_UNORM => (planar(16, 3, ChromaSubsampling::Chroma422), Unorm),
+            F::G16_B16_R16_3PLANE_444_UNORM => (planar(16, 3, ChromaSubsampling::Chroma444), Unorm),
+            F::G16_B16R16_2PLANE_420_UNORM => (planar(16, 2, ChromaSubsampling::Chroma420), Unorm),
+            F::G16_B16R16_2PLANE_422_UNORM => (planar(16, 2, ChromaSubsampling::Chroma422), Unorm),
+            F::G16_B16R16_2PLANE_444_UNORM => (planar(16, 2, ChromaSubsampling::Chroma444), Unorm),
+            _ => return None,
+        };
+        Some(Self {
+            format,
+            class,
+            numeric,
+        })
+    }
+
+    /// Size in bytes of a texel block. For multi-planar formats, this is the size of one texel across all planes.
+    pub fn block_size(&self) -> u32 {
+        use FormatClass::*;
+        match self.class {
+            Bits8 | S8 => 1,
+            Bits16 | D16 => 2,
+            Bits24 | D16S8 => 3,
+            Bits32 | D24 | D32 | D24S8 => 4,
+            D32S8 => 5,
+            Bits48 => 6,
+            Bits64 | Rgba16Padded { .. } => 8,
+            Bits96 => 12,
+            Bits128 => 16,
+            Bits192 => 24,
+            Bits256 => 32,
+            Bc1Rgb | Bc1Rgba | Bc4 | Etc2Rgb | Etc2Rgba | EacR => 8,
+            Bc2 | Bc3 | Bc5 | Bc6h | Bc7 | Etc2EacRgba | EacRg | Astc { .. } => 16,
+            Pvrtc1Bpp2 | Pvrtc1Bpp4 | Pvrtc2Bpp2 | Pvrtc2Bpp4 => 8,
+            Gbgr422 { bits: 8 } | Bgrg422 { bits: 8 } => 4,
+            Gbgr422 { .. } | Bgrg422 { .. } => 8,
+            MultiPlanar { bits: 8, .. } => 3,
+            MultiPlanar { .. } => 6,
+        }
+    }
+
+    /// Width, height and depth in texels of a texel block.
+    pub fn block_extent(&self) -> vk::Extent3D {
+        use FormatClass::*;
+        let (width, height) = match self.class {
+            Bc1Rgb | Bc1Rgba | Bc2 | Bc3 | Bc4 | Bc5 | Bc6h | Bc7 => (4, 4),
+            Etc2Rgb | Etc2Rgba | Etc2EacRgba | EacR | EacRg => (4, 4),
+            Astc { width, height } => (width, height),
+            Pvrtc1Bpp2 | Pvrtc2Bpp2 => (8, 4),
+            Pvrtc1Bpp4 | Pvrtc2Bpp4 => (4, 4),
+            Gbgr422 { .. } | Bgrg422 { .. } => (2, 1),
+            _ => (1, 1),
+        };
+        vk::Extent3D {
+            width,
+            height,
+            depth: 1,
+        }
+    }
+
+    /// The aspects to use for layout transitions and views of the whole image.
+    ///
+    /// Multi-planar formats return `COLOR`; their planes are listed by [`FormatInfo::copy_aspects`].
+    pub fn aspects(&self) -> vk::ImageAspectFlags {
+        use FormatClass::*;
+        match self.class {
+            D16 | D24 | D32 => vk::ImageAspectFlags::DEPTH,
+            S8 => vk::ImageAspectFlags::STENCIL,
+            D16S8 | D24S8 | D32S8 => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+            _ => vk::ImageAspectFlags::COLOR,
+        }
+    }
+
+    /// The aspects that buffer image copies address separately. Each copy region covers exactly one of them.
+    pub fn copy_aspects(&self) -> vk::ImageAspectFlags {
+        match self.class {
+            FormatClass::MultiPlanar { planes: 2, .. } => {
+                vk::ImageAspectFlags::PLANE_0 | vk::ImageAspectFlags::PLANE_1
+            }
+            FormatClass::MultiPlanar { .. } => {
+                vk::ImageAspectFlags::PLANE_0
+                    | vk::ImageAspectFlags::PLANE_1
+                    | vk::ImageAspectFlags::PLANE_2
+            }
+            _ => self.aspects(),
+        }
+    }
+
+    pub fn is_color(&self) -> bool {
+        self.aspects() == vk::ImageAspectFlags::COLOR
+    }
+    pub fn has_depth(&self) -> bool {
+        self.aspects().contains(vk::ImageAspectFlags::DEPTH)
+    }
+    pub fn has_stencil(&self) -> bool {
+        self.aspects().contains(vk::ImageAspectFlags::STENCIL)
+    }
+    pub fn is_depth_stencil(&self) -> bool {
+        self.has_depth() || self.has_stencil()
+    }
+    /// Block compressed formats. The packed 4:2:2 formats have 2x1 texel blocks, but aren't compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.block_extent().height > 1
+    }
+    pub fn is_srgb(&self) -> bool {
+        self.numeric == NumericFormat::Srgb
+    }
+    pub fn is_multi_planar(&self) -> bool {
+        matches!(self.class, FormatClass::MultiPlanar { .. })
+    }
+
+    /// The format of the texel data of `aspect` in buffer image copies, and the factors by which the width and
+    /// height of `aspect` are reduced relative to the image.
+    ///
+    /// `aspect` must be a single aspect from [`FormatInfo::copy_aspects`].
+    pub fn aspect_format(&self, aspect: vk::ImageAspectFlags) -> (vk::Format, (u32, u32)) {
+        use vk::Format as F;
+        assert!(
+            aspect.as_raw().is_power_of_two() && self.copy_aspects().contains(aspect),
+            "Aspect {:?} can't be copied separately for format {:?}",
+            aspect,
+            self.format
+        );
+        match self.class {
+            FormatClass::D16S8 | FormatClass::D24S8 | FormatClass::D32S8
+                if aspect == vk::ImageAspectFlags::STENCIL =>
+            {
+                (F::S8_UINT, (1, 1))
+            }
+            FormatClass::D16S8 => (F::D16_UNORM, (1, 1)),
+            FormatClass::D24S8 => (F::X8_D24_UNORM_PACK32, (1, 1)),
+            FormatClass::D32S8 => (F::D32_SFLOAT, (1, 1)),
+            FormatClass::MultiPlanar {
+                bits,
+                planes,
+                subsampling,
+            } => {
+                let two_components = planes == 2 && aspect == vk::ImageAspectFlags::PLANE_1;
+                let format = match (bits, two_components) {
+                    (8, false) => F::R8_UNORM,
+                    (8, true) => F::R8G8_UNORM,
+                    (10, false) => F::R10X6_UNORM_PACK16,
+                    (10, true) => F::R10X6G10X6_UNORM_2PACK16,
+                    (12, false) => F::R12X4_UNORM_PACK16,
+                    (12, true) => F::R12X4G12X4_UNORM_2PACK16,
+                    (_, false) => F::R16_UNORM,
+                    (_, true) => F::R16G16_UNORM,
+                };
+                let divisor = match subsampling {
+                    _ if aspect == vk::ImageAspectFlags::PLANE_0 => (1, 1),
+                    ChromaSubsampling::Chroma420 => (2, 2),
+                    ChromaSubsampling::Chroma422 => (2, 1),
+                    ChromaSubsampling::Chroma444 => (1, 1),
+                };
+                (format, divisor)
+            }
+            _ => (self.format, (1, 1)),
+        }
+    }
+
+    /// The extent of `aspect` for an image of this format with the given extent.
+    pub fn aspect_extent(
+        &self,
+        aspect: vk::ImageAspectFlags,
+        extent: vk::Extent3D,
+    ) -> vk::Extent3D {
+        let (_, (x, y)) = self.aspect_format(aspect);
+        vk::Extent3D {
+            width: extent.width.div_ceil(x),
+            height: extent.height.div_ceil(y),
+            depth: extent.depth,
+        }
+    }
+
+    /// Size in bytes of a tightly packed row of texel blocks of `aspect`, `width` texels wide.
+    pub fn copy_row_size(&self, aspect: vk::ImageAspectFlags, width: u32) -> u64 {
+        let info = self.copy_format_info(aspect);
+        width.div_ceil(info.block_extent().width) as u64 * info.block_size() as u64
+    }
+
+    /// Number of rows of texel blocks of `aspect` in a region of the given extent and layer count.
+    pub fn copy_row_count(
+        &self,
+        aspect: vk::ImageAspectFlags,
+        extent: vk::Extent3D,
+        layer_count: u32,
+    ) -> u64 {
+        let info = self.copy_format_info(aspect);
+        extent.height.div_ceil(info.block_extent().height) as u64
+            * extent.depth as u64
+            * layer_count as u64
+    }
+
+    /// Size in bytes of tightly packed texel data of `aspect` covering a region of the given extent and
+    /// layer count, as used by `vk::BufferImageCopy` with a `buffer_row_length` and `buffer_image_height` of 0.
+    ///
+    /// `extent` is in texels of `aspect`, see [`FormatInfo::aspect_extent`].
+    pub fn copy_size(
+        &self,
+        aspect: vk::ImageAspectFlags,
+        extent: vk::Extent3D,
+        layer_count: u32,
+    ) -> u64 {
+        self.copy_row_size(aspect, extent.width) * self.copy_row_count(aspect, extent, layer_count)
+    }
+
+    fn copy_format_info(&self, aspect: vk::ImageAspectFlags) -> FormatInfo {
+        let (format, _) = self.aspect_format(aspect);
+        if format == self.format {
+            *self
+        } else {
+            FormatInfo::of(format).unwrap()
+        }
+    }
+}
+
+const fn astc(width: u32, height: u32) -> FormatClass {
+    FormatClass::Astc { width, height }
+}
+
+const fn planar(bits: u32, planes: u32, subsampling: ChromaSubsampling) -> FormatClass {
+    FormatClass::MultiPlanar {
+        bits,
+        planes,
+        subsampling,
+    }
+}
+
+/// The aspects to use for layout transitions and views of an image of `format`. Unknown formats are assumed to be
+/// color formats.
+pub fn format_aspects(format: vk::Format) -> vk::ImageAspectFlags {
+    FormatInfo::of(format).map_or(vk::ImageAspectFlags::COLOR, |info| info.aspects())
+}
+
+/// Whether a view with `view_format` and `aspect_mask` may be created on an image of `image_format` created with
+/// `image_flags`.
+///
+/// Unknown formats are only compatible with themselves.
+pub fn is_view_format_compatible(
+    image_format: vk::Format,
+    image_flags: vk::ImageCreateFlags,
+    view_format: vk::Format,
+    aspect_mask: vk::ImageAspectFlags,
+) -> bool {
+    let (image, view) = match (FormatInfo::of(image_format), FormatInfo::of(view_format)) {
+        (Some(image), Some(view)) => (image, view),
+        _ => return image_format == view_format,
+    };
+    if image.is_multi_planar()
+        && aspect_mask.as_raw().is_power_of_two()
+        && image.copy_aspects().contains(aspect_mask)
+    {
+        // Views of a single plane use a format compatible with the plane, and require MUTABLE_FORMAT.
+        let (plane_format, _) = image.aspect_format(aspect_mask);
+        return image_flags.contains(vk::ImageCreateFlags::MUTABLE_FORMAT)
+            && (plane_format == view_format
+                || FormatInfo::of(plane_format).unwrap().class == view.class);
+    }
+    if image_format == view_format {
+        return true;
+    }
+    if !image_flags.contains(vk::ImageCreateFlags::MUTABLE_FORMAT) {
+        return false;
+    }
+    if image_flags.contains(vk::ImageCreateFlags::BLOCK_TEXEL_VIEW_COMPATIBLE)
+        && image.is_compressed()
+        && !view.is_compressed()
+        && !view.is_depth_stencil()
+    {
+        return image.block_size() == view.block_size();
+    }
+    image.class == view.class && !image.is_depth_stencil() && !image.is_multi_planar()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Raw values of every format covered by the table.
+    fn all_formats() -> impl Iterator<Item = vk::Format> {
+        let ranges = [
+            // Core 1.0
+            1..=184,
+            // VK_IMG_format_pvrtc
+            1000054000..=1000054007,
+            // Core 1.3, ASTC HDR
+            1000066000..=1000066013,
+            // Core 1.1, YCbCr
+            1000156000..=1000156033,
+            // Core 1.3, 4:4:4 two plane formats
+            1000330000..=1000330003,
+            // Core 1.3, 4444 formats
+            1000340000..=1000340001,
+        ];
+        ranges.into_iter().flatten().map(vk::Format::from_raw)
+    }
+
+    #[test]
+    fn table_is_complete() {
+        assert_eq!(FormatInfo::of(vk::Format::UNDEFINED), None);
+        for format in all_formats() {
+            let info = FormatInfo::of(format).unwrap_or_else(|| panic!("Missing {:?}", format));
+            assert_eq!(info.format, format);
+        }
+        assert_eq!(all_formats().count(), 184 + 8 + 14 + 34 + 4 + 2);
+    }
+
+    #[test]
+    fn table_is_consistent() {
+        for format in all_formats() {
+            let info = FormatInfo::of(format).unwrap();
+            let name = format!("{:?}", format);
+            assert!(info.block_size() > 0);
+            assert_eq!(info.is_srgb(), name.contains("SRGB"), "{}", name);
+            assert_eq!(info.is_compressed(), name.contains("BLOCK"), "{}", name);
+            assert_eq!(info.is_multi_planar(), name.contains("PLANE"), "{}", name);
+            assert_eq!(
+                info.has_depth(),
+                name.starts_with("D") || name.starts_with("X8_D24"),
+                "{}",
+                name
+            );
+            assert_eq!(info.has_stencil(), name.contains("S8_UINT"), "{}", name);
+            if info.is_compressed() {
+                assert!(info.block_size() == 8 || info.block_size() == 16);
+            }
+            // Every copy aspect resolves to a known single-plane format.
+            for bit in 0..32 {
+                let aspect = vk::ImageAspectFlags::from_raw(1 << bit);
+                if info.copy_aspects().contains(aspect) {
+                    let (aspect_format, _) = info.aspect_format(aspect);
+                    let aspect_info = FormatInfo::of(aspect_format).unwrap();
+                    assert!(!aspect_info.is_multi_planar());
+                    assert_eq!(aspect_info.copy_aspects().as_raw().count_ones(), 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn copy_sizes() {
+        let extent = vk::Extent3D {
+            width: 30,
+            height: 30,
+            depth: 1,
+        };
+        let rgba8 = FormatInfo::of(vk::Format::R8G8B8A8_UNORM).unwrap();
+        assert_eq!(rgba8.copy_row_size(vk::ImageAspectFlags::COLOR, 30), 120);
+        assert_eq!(
+            rgba8.copy_size(vk::ImageAspectFlags::COLOR, extent, 2),
+            120 * 30 * 2
+        );
+
+        // 30x30 texels round up to 8x8 blocks.
+        let bc1 = FormatInfo::of(vk::Format::BC1_RGB_SRGB_BLOCK).unwrap();
+        assert_eq!(bc1.copy_row_size(vk::ImageAspectFlags::COLOR, 30), 8 * 8);
+        assert_eq!(
+            bc1.copy_size(vk::ImageAspectFlags::COLOR, extent, 1),
+            8 * 8 * 8
+        );
+
+        let astc = FormatInfo::of(vk::Format::ASTC_10X6_UNORM_BLOCK).unwrap();
+        assert_eq!(
+            astc.copy_size(vk::ImageAspectFlags::COLOR, extent, 1),
+            3 * 5 * 16
+        );
+
+        let d24s8 = FormatInfo::of(vk::Format::D24_UNORM_S8_UINT).unwrap();
+        assert_eq!(
+            d24s8.copy_size(vk::ImageAspectFlags::DEPTH, extent, 1),
+            30 * 30 * 4
+        );
+        assert_eq!(
+            d24s8.copy_size(vk::ImageAspectFlags::STENCIL, extent, 1),
+            30 * 30
+        );
+
+        let d32s8 = FormatInfo::of(vk::Format::D32_SFLOAT_S8_UINT).unwrap();
+        assert_eq!(
+            d32s8.copy_size(vk::ImageAspectFlags::DEPTH, extent, 1),
+            30 * 30 * 4
+        );
+
+        let yuy2 = FormatInfo::of(vk::Format::G8B8G8R8_422_UNORM).unwrap();
+        assert_eq!(yuy2.copy_row_size(vk::ImageAspectFlags::COLOR, 31), 16 * 4);
+    }
+
+    #[test]
+    fn multi_planar_aspects() {
+        let nv12 = FormatInfo::of(vk::Format::G8_B8R8_2PLANE_420_UNORM).unwrap();
+        assert_eq!(nv12.aspects(), vk::ImageAspectFlags::COLOR);
+        assert_eq!(
+            nv12.copy_aspects(),
+            vk::ImageAspectFlags::PLANE_0 | vk::ImageAspectFlags::PLANE_1
+        );
+        let extent = vk::Extent3D {
+            width: 63,
+            height: 32,
+            depth: 1,
+        };
+        let chroma = nv12.aspect_extent(vk::ImageAspectFlags::PLANE_1, extent);
+        assert_eq!((chroma.width, chroma.height), (32, 16));
+        assert_eq!(
+            nv12.aspect_format(vk::ImageAspectFlags::PLANE_1).0,
+            vk::Format::R8G8_UNORM
+        );
+        assert_eq!(
+            nv12.copy_size(vk::ImageAspectFlags::PLANE_1, chroma, 1),
+            32 * 16 * 2
+        );
+
+        let p010 = FormatInfo::of(vk::Format::G10X6_B10X6_R10X6_3PLANE_422_UNORM_3PACK16).unwrap();
+        assert_eq!(
+            p010.aspect_format(vk::ImageAspectFlags::PLANE_2),
+            (vk::Format::R10X6_UNORM_PACK16, (2, 1))
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn aspect_format_rejects_combined_aspects() {
+        FormatInfo::of(vk::Format::D24_UNORM_S8_UINT)
+            .unwrap()
+            .aspect_format(vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL);
+    }
+
+    #[test]
+    fn view_compatibility() {
+        let mutable = vk::ImageCreateFlags::MUTABLE_FORMAT;
+        let color = vk::ImageAspectFlags::COLOR;
+        use vk::Format as F;
+        assert!(is_view_format_compatible(
+            F::R8G8B8A8_UNORM,
+            vk::ImageCreateFlags::empty(),
+            F::R8G8B8A8_UNORM,
+            color
+        ));
+        assert!(!is_view_format_compatible(
+            F::R8G8B8A8_UNORM,
+            vk::ImageCreateFlags::empty(),
+            F::R8G8B8A8_SRGB,
+            color
+        ));
+        assert!(is_view_format_compatible(
+            F::R8G8B8A8_UNORM,
+            mutable,
+            F::R8G8B8A8_SRGB,
+            color
+        ));
+        assert!(is_view_format_compatible(
+            F::R8G8B8A8_UNORM,
+            mutable,
+            F::R32_UINT,
+            color
+        ));
+        assert!(!is_view_format_compatible(
+            F::R8G8B8A8_UNORM,
+            mutable,
+            F::R16_UINT,
+            color
+        ));
+        assert!(is_view_format_compatible(
+            F::BC7_UNORM_BLOCK,
+            mutable,
+            F::BC7_SRGB_BLOCK,
+            color
+        ));
+        assert!(!is_view_format_compatible(
+            F::BC7_UNORM_BLOCK,
+            mutable,
+            F::BC3_UNORM_BLOCK,
+            color
+        ));
+        assert!(!is_view_format_compatible(
+            F::BC7_UNORM_BLOCK,
+            mutable,
+            F::R32G32B32A32_UINT,
+            color
+        ));
+        assert!(is_view_format_compatible(
+            F::BC7_UNORM_BLOCK,
+            mutable | vk::ImageCreateFlags::BLOCK_TEXEL_VIEW_COMPATIBLE,
+            F::R32G32B32A32_UINT,
+            color
+        ));
+        assert!(!is_view_format_compatible(
+            F::D32_SFLOAT,
+            mutable,
+            F::R32_SFLOAT,
+            color
+        ));
+        assert!(!is_view_format_compatible(
+            F::G8_B8R8_2PLANE_420_UNORM,
+            vk::ImageCreateFlags::empty(),
+            F::R8G8_UNORM,
+            vk::ImageAspectFlags::PLANE_1
+        ));
+        assert!(is_view_format_compatible(
+            F::G8_B8R8_2PLANE_420_UNORM,
+            mutable,
+            F::R8G8_UNORM,
+            vk::ImageAspectFlags::PLANE_1
+        ));
+        assert!(is_view_format_compatible(
+            F::G8_B8R8_2PLANE_420_UNORM,
+            mutable,
+            F::R16_UINT,
+            vk::ImageAspectFlags::PLANE_1
+        ));
+        assert!(!is_view_format_compatible(
+            F::G8_B8R8_2PLANE_420_UNORM,
+            mutable,
+            F::R8_UNORM,
+            vk::ImageAspectFlags::PLANE_1
+        ));
+    }
+}
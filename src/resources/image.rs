@@ -1,8 +1,18 @@
 use ash::{prelude::VkResult, vk};
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
-use super::alloc::{Allocation, AllocationCreateFlags, Allocator, MemoryAllocScenario};
-use crate::{DebugObject, Device, HasDevice};
+use crate::command::recorder::CommandBufferResource;
+
+use super::alloc::{
+    Allocation, AllocationCreateFlags, Allocator, BufferRequest, DeviceMemoryModel,
+    MemoryAllocScenario,
+};
+use super::budget::ScenarioKind;
+use super::format::{format_aspects, is_view_format_compatible, FormatInfo};
+use crate::{sync::CommandsFuture, DebugObject, Device, HasDevice};
 
 pub trait HasImage: Send + Sync + 'static {
     fn raw_image(&self) -> vk::Image;
@@ -16,6 +26,58 @@ pub trait HasImageView: HasImage {
     fn subresource_range(&self) -> vk::ImageSubresourceRange;
 }
 
+/// Images that remember the parameters they were created with.
+pub trait HasImageMetadata: HasImage {
+    fn metadata(&self) -> &ImageMetadata;
+}
+
+/// The parameters an image was created with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageMetadata {
+    pub flags: vk::ImageCreateFlags,
+    pub image_type: vk::ImageType,
+    pub format: vk::Format,
+    pub extent: vk::Extent3D,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub samples: vk::SampleCountFlags,
+    pub tiling: vk::ImageTiling,
+    pub usage: vk::ImageUsageFlags,
+}
+
+impl ImageMetadata {
+    pub fn from_create_info(info: &vk::ImageCreateInfo) -> Self {
+        Self {
+            flags: info.flags,
+            image_type: info.image_type,
+            format: info.format,
+            extent: info.extent,
+            mip_levels: info.mip_levels,
+            array_layers: info.array_layers,
+            samples: info.samples,
+            tiling: info.tiling,
+            usage: info.usage,
+        }
+    }
+    /// All aspects of the image format.
+    pub fn aspects(&self) -> vk::ImageAspectFlags {
+        format_aspects(self.format)
+    }
+    /// The range covering all aspects, mip levels and array layers of the image.
+    pub fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask: self.aspects(),
+            base_mip_level: 0,
+            level_count: self.mip_levels,
+            base_array_layer: 0,
+            layer_count: self.array_layers,
+        }
+    }
+    pub fn mip_extent(&self, mip_level: u32) -> vk::Extent3D {
+        mip_extent(self.extent, mip_level)
+    }
+}
+
 impl HasImage for vk::Image {
     fn raw_image(&self) -> vk::Image {
         *self
@@ -25,6 +87,7 @@ impl HasImage for vk::Image {
 pub struct Image {
     device: Arc<Device>,
     pub(crate) image: vk::Image,
+    metadata: ImageMetadata,
 }
 
 impl HasDevice for Image {
@@ -44,7 +107,20 @@ impl DebugObject for Image {
 impl Image {
     pub fn new(device: Arc<Device>, info: &vk::ImageCreateInfo) -> VkResult<Self> {
         let image = unsafe { device.create_image(info, None)? };
-        Ok(Self { device, image })
+        device
+            .resource_registry()
+            .register(vk::ObjectType::IMAGE, image, None);
+        Ok(Self {
+            device,
+            image,
+            metadata: ImageMetadata::from_create_info(info),
+        })
+    }
+}
+
+impl HasImageMetadata for Image {
+    fn metadata(&self) -> &ImageMetadata {
+        &self.metadata
     }
 }
 
@@ -61,9 +137,30 @@ impl<T: HasImage> HasImage for Arc<T> {
     }
 }
 
+impl<T: HasImageMetadata> HasImageMetadata for Arc<T> {
+    fn metadata(&self) -> &ImageMetadata {
+        let r: &T = self.as_ref();
+        r.metadata()
+    }
+}
+
+impl<T: HasImageView> HasImageView for Arc<T> {
+    fn raw_image_view(&self) -> vk::ImageView {
+        let r: &T = self.as_ref();
+        r.raw_image_view()
+    }
+    fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        let r: &T = self.as_ref();
+        r.subresource_range()
+    }
+}
+
 impl Drop for Image {
     fn drop(&mut self) {
         tracing::debug!(image = ?self.image, "drop image");
+        self.device
+            .resource_registry()
+            .unregister(vk::ObjectType::IMAGE, self.image);
         unsafe { self.device.destroy_image(self.image, None) }
     }
 }
@@ -74,6 +171,41 @@ pub struct MemImage {
     pub image: vk::Image,
     pub memory: Allocation,
     pub memory_flags: vk::MemoryPropertyFlags,
+    scenario: ScenarioKind,
+    allocation_size: u64,
+    // Kept so that the image can be recreated when its memory is moved.
+    pub(super) create_params: ImageCreateParams,
+}
+
+/// The parameters of an [`ImageRequest`] that describe the image itself.
+#[derive(Clone)]
+pub(super) struct ImageCreateParams {
+    pub metadata: ImageMetadata,
+    pub sharing_mode: vk::SharingMode,
+    pub queue_families: Box<[u32]>,
+}
+
+impl ImageCreateParams {
+    /// The returned struct borrows `self.queue_families`.
+    pub fn create_info(&self, initial_layout: vk::ImageLayout) -> vk::ImageCreateInfo {
+        let metadata = &self.metadata;
+        vk::ImageCreateInfo {
+            flags: metadata.flags,
+            image_type: metadata.image_type,
+            format: metadata.format,
+            extent: metadata.extent,
+            mip_levels: metadata.mip_levels,
+            array_layers: metadata.array_layers,
+            samples: metadata.samples,
+            tiling: metadata.tiling,
+            usage: metadata.usage,
+            sharing_mode: self.sharing_mode,
+            queue_family_index_count: self.queue_families.len() as u32,
+            p_queue_family_indices: self.queue_families.as_ptr(),
+            initial_layout,
+            ..Default::default()
+        }
+    }
 }
 
 impl HasImage for MemImage {
@@ -82,14 +214,32 @@ impl HasImage for MemImage {
     }
 }
 
+impl HasImageMetadata for MemImage {
+    fn metadata(&self) -> &ImageMetadata {
+        &self.create_params.metadata
+    }
+}
+
+impl HasDevice for MemImage {
+    fn device(&self) -> &Arc<Device> {
+        self.allocator.device()
+    }
+}
+
 impl Drop for MemImage {
     fn drop(&mut self) {
         tracing::debug!(image = ?self.image, "drop mem image");
+        self.device()
+            .resource_registry()
+            .unregister(vk::ObjectType::IMAGE, self.image);
         unsafe {
             let mut memory: Allocation = std::mem::zeroed();
             std::mem::swap(&mut memory, &mut self.memory);
             self.allocator.allocator.destroy_image(self.image, memory);
         }
+        self.allocator
+            .usage
+            .record_free(self.scenario, self.allocation_size);
     }
 }
 
@@ -98,6 +248,8 @@ pub struct ImageRequest<'a> {
     pub scenario: MemoryAllocScenario,
     pub allocation_flags: AllocationCreateFlags,
 
+    /// Set `MUTABLE_FORMAT` to create views with a different format, and `CUBE_COMPATIBLE` for cube views.
+    pub flags: vk::ImageCreateFlags,
     pub image_type: vk::ImageType,
     pub format: vk::Format,
     pub extent: vk::Extent3D,
@@ -116,6 +268,7 @@ impl<'a> Default for ImageRequest<'a> {
             scenario: MemoryAllocScenario::DeviceAccess,
             allocation_flags: AllocationCreateFlags::empty(),
 
+            flags: vk::ImageCreateFlags::empty(),
             image_type: vk::ImageType::TYPE_2D,
             format: vk::Format::R8G8B8A8_UNORM,
             extent: vk::Extent3D::default(),
@@ -134,44 +287,478 @@ impl<'a> Default for ImageRequest<'a> {
 impl Allocator {
     pub fn allocate_image(self: &Arc<Self>, image_request: &ImageRequest) -> VkResult<MemImage> {
         use vk_mem::Alloc;
-        let build_info = vk::ImageCreateInfo {
-            flags: vk::ImageCreateFlags::empty(),
-            image_type: image_request.image_type,
-            format: image_request.format,
-            extent: image_request.extent,
-            mip_levels: image_request.mip_levels,
-            array_layers: image_request.array_layers,
-            samples: image_request.samples,
-            tiling: image_request.tiling,
-            usage: image_request.usage,
+        let create_params = ImageCreateParams {
+            metadata: ImageMetadata {
+                flags: image_request.flags,
+                image_type: image_request.image_type,
+                format: image_request.format,
+                extent: image_request.extent,
+                mip_levels: image_request.mip_levels,
+                array_layers: image_request.array_layers,
+                samples: image_request.samples,
+                tiling: image_request.tiling,
+                usage: image_request.usage,
+            },
             sharing_mode: image_request.sharing_mode,
-            queue_family_index_count: image_request.queue_families.len() as u32,
-            p_queue_family_indices: image_request.queue_families.as_ptr(),
-            initial_layout: image_request.initial_layout,
-            ..Default::default()
+            queue_families: image_request.queue_families.into(),
         };
+        let build_info = create_params.create_info(image_request.initial_layout);
         let create_info =
             self.create_info_by_scenario(image_request.allocation_flags, &image_request.scenario);
         let (image, allocation) =
             unsafe { self.allocator.create_image(&build_info, &create_info) }?;
-        let memory_flags = unsafe {
+        let (memory_flags, allocation_size) = unsafe {
             let allocation_info = self.allocator.get_allocation_info(&allocation).unwrap();
             let memory_flags = self.types[allocation_info.memory_type as usize].property_flags;
-            memory_flags
+            (memory_flags, allocation_info.size)
         };
+        let scenario = image_request.scenario.kind();
+        self.usage.record_allocation(scenario, allocation_size);
+        self.check_budget_after_allocation();
+        self.device()
+            .resource_registry()
+            .register(vk::ObjectType::IMAGE, image, Some(allocation_size));
         Ok(MemImage {
             allocator: self.clone(),
             image,
             memory: allocation,
             memory_flags,
+            scenario,
+            allocation_size,
+            create_params,
         })
     }
 }
 
+/// Texel data for one mip level of one array layer, with rows and slices tightly packed.
+#[derive(Clone, Copy)]
+pub struct ImageSubresourceData<'a> {
+    pub mip_level: u32,
+    pub array_layer: u32,
+    pub data: &'a [u8],
+}
+
+/// Offsets of the subresources in the staging buffer are aligned to this value, which is a multiple of
+/// every texel block size and of 4, as required for vkCmdCopyBufferToImage. The offsets are relative to
+/// the start of the buffer, so this isn't a valid alignment for the buffer allocation itself, which
+/// must be a power of two.
+pub(super) const STAGING_OFFSET_ALIGNMENT: u64 = 96;
+
+impl Allocator {
+    /// Create an image and fill it with `subresources`, leaving it in `final_layout`.
+    ///
+    /// On integrated GPUs, single-level single-layer images are written directly through a host-visible
+    /// linear image if the format supports it. Otherwise, the data is copied into a staging buffer and
+    /// transferred with `vkCmdCopyBufferToImage` on `commands_future`. The layout transitions are always
+    /// recorded on `commands_future`, so the image may only be used after it was submitted.
+    pub fn allocate_image_with_data(
+        self: &Arc<Self>,
+        request: &ImageRequest,
+        subresources: &[ImageSubresourceData],
+        final_layout: vk::ImageLayout,
+        commands_future: &mut CommandsFuture,
+    ) -> VkResult<Arc<MemImage>> {
+        let format_info = FormatInfo::of(request.format).expect("Unsupported image format");
+        assert!(
+            !format_info.is_multi_planar(),
+            "Multi-planar images can't be created with data"
+        );
+        // For combined depth stencil formats, only the depth aspect is written.
+        let aspect_mask = if format_info.has_depth() {
+            vk::ImageAspectFlags::DEPTH
+        } else {
+            format_info.aspects()
+        };
+        for subresource in subresources.iter() {
+            let extent = mip_extent(request.extent, subresource.mip_level);
+            assert_eq!(
+                subresource.data.len() as u64,
+                format_info.copy_size(aspect_mask, extent, 1),
+                "Subresource data doesn't match the size of mip level {}",
+                subresource.mip_level
+            );
+        }
+        if self.memory_model() == DeviceMemoryModel::Integrated
+            && request.mip_levels == 1
+            && request.array_layers == 1
+            && subresources.len() == 1
+            && self.supports_linear(request)?
+        {
+            if let Some(image) = self.allocate_linear_image_with_data(
+                request,
+                subresources[0].data,
+                &format_info,
+                aspect_mask,
+            )? {
+                let image = Arc::new(image);
+                commands_future.then_commands(|mut recorder| {
+                    let barrier = vk::ImageMemoryBarrier2 {
+                        // Host writes are made visible by the queue submission.
+                        src_stage_mask: vk::PipelineStageFlags2::NONE,
+                        src_access_mask: vk::AccessFlags2::NONE,
+                        dst_stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+                        dst_access_mask: vk::AccessFlags2::MEMORY_READ
+                            | vk::AccessFlags2::MEMORY_WRITE,
+                        old_layout: vk::ImageLayout::PREINITIALIZED,
+                        new_layout: final_layout,
+                        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                        image: image.image,
+                        subresource_range: full_subresource_range(request),
+                        ..Default::default()
+                    };
+                    unsafe {
+                        recorder.pipeline_barrier2(&vk::DependencyInfo {
+                            image_memory_barrier_count: 1,
+                            p_image_memory_barriers: &barrier,
+                            ..Default::default()
+                        });
+                    }
+                    recorder.track_resource(image.clone().command_buffer_resource());
+                });
+                return Ok(image);
+            }
+        }
+
+        let image = self.allocate_image(&ImageRequest {
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage: request.usage | vk::ImageUsageFlags::TRANSFER_DST,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            ..request.clone()
+        })?;
+        let image = Arc::new(image);
+
+        let mut regions: Vec<vk::BufferImageCopy> = Vec::with_capacity(subresources.len());
+        let mut staging_size: u64 = 0;
+        for subresource in subresources.iter() {
+            debug_assert!(subresource.mip_level < request.mip_levels);
+            debug_assert!(subresource.array_layer < request.array_layers);
+            regions.push(vk::BufferImageCopy {
+                buffer_offset: staging_size,
+                // Tightly packed
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask,
+                    mip_level: subresource.mip_level,
+                    base_array_layer: subresource.array_layer,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D::default(),
+                image_extent: mip_extent(request.extent, subresource.mip_level),
+            });
+            staging_size = (staging_size + subresource.data.len() as u64)
+                .next_multiple_of(STAGING_OFFSET_ALIGNMENT);
+        }
+        let mut staging_buffer = self.allocate_buffer(&BufferRequest {
+            size: staging_size.max(1),
+            usage: vk::BufferUsageFlags::TRANSFER_SRC,
+            scenario: MemoryAllocScenario::StagingBuffer,
+            ..Default::default()
+        })?;
+        staging_buffer.map_scoped(|staging| {
+            for (subresource, region) in subresources.iter().zip(regions.iter()) {
+                let offset = region.buffer_offset as usize;
+                staging[offset..offset + subresource.data.len()].copy_from_slice(subresource.data);
+            }
+        });
+
+        let subresource_range = full_subresource_range(request);
+        commands_future.then_commands(|mut recorder| {
+            let to_transfer_dst = vk::ImageMemoryBarrier2 {
+                src_stage_mask: vk::PipelineStageFlags2::NONE,
+                src_access_mask: vk::AccessFlags2::NONE,
+                dst_stage_mask: vk::PipelineStageFlags2::COPY,
+                dst_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+                old_layout: vk::ImageLayout::UNDEFINED,
+                new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                image: image.image,
+                subresource_range,
+                ..Default::default()
+            };
+            unsafe {
+                recorder.pipeline_barrier2(&vk::DependencyInfo {
+                    image_memory_barrier_count: 1,
+                    p_image_memory_barriers: &to_transfer_dst,
+                    ..Default::default()
+                });
+            }
+            recorder.copy_buffer_to_image(
+                staging_buffer,
+                image.clone(),
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &regions,
+            );
+            if final_layout != vk::ImageLayout::TRANSFER_DST_OPTIMAL {
+                let to_final_layout = vk::ImageMemoryBarrier2 {
+                    src_stage_mask: vk::PipelineStageFlags2::COPY,
+                    src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+                    dst_stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+                    dst_access_mask: vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
+                    old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    new_layout: final_layout,
+                    ..to_transfer_dst
+                };
+                unsafe {
+                    recorder.pipeline_barrier2(&vk::DependencyInfo {
+                        image_memory_barrier_count: 1,
+                        p_image_memory_barriers: &to_final_layout,
+                        ..Default::default()
+                    });
+                }
+            }
+        });
+        Ok(image)
+    }
+
+    fn supports_linear(&self, request: &ImageRequest) -> VkResult<bool> {
+        let properties = self.device().physical_device().image_format_properties(
+            &vk::PhysicalDeviceImageFormatInfo2 {
+                format: request.format,
+                ty: request.image_type,
+                tiling: vk::ImageTiling::LINEAR,
+                usage: request.usage,
+                flags: request.flags,
+                ..Default::default()
+            },
+        )?;
+        Ok(properties.map_or(false, |properties| {
+            let max_extent = properties.image_format_properties.max_extent;
+            request.extent.width <= max_extent.width
+                && request.extent.height <= max_extent.height
+                && request.extent.depth <= max_extent.depth
+                && properties
+                    .image_format_properties
+                    .sample_counts
+                    .contains(request.samples)
+        }))
+    }
+
+    /// Returns None if the linear image didn't end up in host-visible memory.
+    fn allocate_linear_image_with_data(
+        self: &Arc<Self>,
+        request: &ImageRequest,
+        data: &[u8],
+        format_info: &FormatInfo,
+        aspect_mask: vk::ImageAspectFlags,
+    ) -> VkResult<Option<MemImage>> {
+        let mut image = self.allocate_image(&ImageRequest {
+            tiling: vk::ImageTiling::LINEAR,
+            initial_layout: vk::ImageLayout::PREINITIALIZED,
+            scenario: MemoryAllocScenario::AssetBuffer,
+            ..request.clone()
+        })?;
+        if !image
+            .memory_flags
+            .contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+        {
+            return Ok(None);
+        }
+        let layout = unsafe {
+            self.device().get_image_subresource_layout(
+                image.image,
+                vk::ImageSubresource {
+                    aspect_mask,
+                    mip_level: 0,
+                    array_layer: 0,
+                },
+            )
+        };
+        let row_size = format_info.copy_row_size(aspect_mask, request.extent.width) as usize;
+        let num_rows = format_info.copy_row_count(aspect_mask, request.extent, 1) as usize
+            / request.extent.depth as usize;
+        unsafe {
+            let ptr = self.allocator.map_memory(&mut image.memory)?;
+            let dst = std::slice::from_raw_parts_mut(
+                ptr.add(layout.offset as usize),
+                layout.size as usize,
+            );
+            for slice in 0..request.extent.depth as usize {
+                for row in 0..num_rows {
+                    let src_offset = (slice * num_rows + row) * row_size;
+                    let dst_offset =
+                        slice * layout.depth_pitch as usize + row * layout.row_pitch as usize;
+                    dst[dst_offset..dst_offset + row_size]
+                        .copy_from_slice(&data[src_offset..src_offset + row_size]);
+                }
+            }
+            self.allocator.unmap_memory(&mut image.memory);
+        }
+        Ok(Some(image))
+    }
+}
+
+pub(super) fn mip_extent(extent: vk::Extent3D, mip_level: u32) -> vk::Extent3D {
+    vk::Extent3D {
+        width: (extent.width >> mip_level).max(1),
+        height: (extent.height >> mip_level).max(1),
+        depth: (extent.depth >> mip_level).max(1),
+    }
+}
+
+/// All aspects of the image, as required for layout transitions of combined depth stencil images.
+fn full_subresource_range(request: &ImageRequest) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange {
+        aspect_mask: format_aspects(request.format),
+        base_mip_level: 0,
+        level_count: request.mip_levels,
+        base_array_layer: 0,
+        layer_count: request.array_layers,
+    }
+}
+
+/// Describes an [`ImageView`] relative to the image it is created from.
+/// Unset fields are derived from the image metadata.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ImageViewDesc {
+    pub view_type: Option<vk::ImageViewType>,
+    /// Reinterpret the texels with another compatible format. Requires `MUTABLE_FORMAT`.
+    pub format: Option<vk::Format>,
+    pub aspect_mask: Option<vk::ImageAspectFlags>,
+    pub base_mip_level: u32,
+    /// `vk::REMAINING_MIP_LEVELS` for all levels after `base_mip_level`.
+    pub level_count: u32,
+    pub base_array_layer: u32,
+    /// `vk::REMAINING_ARRAY_LAYERS` for all layers after `base_array_layer`.
+    pub layer_count: u32,
+    /// R, G, B, A
+    pub swizzle: [vk::ComponentSwizzle; 4],
+}
+
+impl Default for ImageViewDesc {
+    fn default() -> Self {
+        Self {
+            view_type: None,
+            format: None,
+            aspect_mask: None,
+            base_mip_level: 0,
+            level_count: vk::REMAINING_MIP_LEVELS,
+            base_array_layer: 0,
+            layer_count: vk::REMAINING_ARRAY_LAYERS,
+            swizzle: [vk::ComponentSwizzle::IDENTITY; 4],
+        }
+    }
+}
+
+impl ImageViewDesc {
+    pub fn view_type(mut self, view_type: vk::ImageViewType) -> Self {
+        self.view_type = Some(view_type);
+        self
+    }
+    pub fn format(mut self, format: vk::Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+    pub fn aspect(mut self, aspect_mask: vk::ImageAspectFlags) -> Self {
+        self.aspect_mask = Some(aspect_mask);
+        self
+    }
+    pub fn mip_levels(mut self, levels: std::ops::Range<u32>) -> Self {
+        assert!(levels.start <= levels.end, "Reversed range {:?}", levels);
+        self.base_mip_level = levels.start;
+        self.level_count = levels.end - levels.start;
+        self
+    }
+    pub fn array_layers(mut self, layers: std::ops::Range<u32>) -> Self {
+        assert!(layers.start <= layers.end, "Reversed range {:?}", layers);
+        self.base_array_layer = layers.start;
+        self.layer_count = layers.end - layers.start;
+        self
+    }
+    pub fn swizzle(mut self, components: vk::ComponentMapping) -> Self {
+        self.swizzle = [components.r, components.g, components.b, components.a];
+        self
+    }
+
+    /// Resolve the unset fields against `metadata`. Returns a description of the problem if the view is
+    /// invalid for the image.
+    fn resolve(
+        &self,
+        metadata: &ImageMetadata,
+    ) -> Result<(vk::ImageViewType, vk::Format, vk::ImageSubresourceRange), String> {
+        if self.base_mip_level >= metadata.mip_levels {
+            return Err("Base mip level out of range".to_string());
+        }
+        if self.base_array_layer >= metadata.array_layers {
+            return Err("Base array layer out of range".to_string());
+        }
+        let level_count = if self.level_count == vk::REMAINING_MIP_LEVELS {
+            metadata.mip_levels - self.base_mip_level
+        } else {
+            if self.level_count == 0 || self.base_mip_level + self.level_count > metadata.mip_levels
+            {
+                return Err("Mip level range out of range".to_string());
+            }
+            self.level_count
+        };
+        let layer_count = if self.layer_count == vk::REMAINING_ARRAY_LAYERS {
+            metadata.array_layers - self.base_array_layer
+        } else {
+            if self.layer_count == 0
+                || self.base_array_layer + self.layer_count > metadata.array_layers
+            {
+                return Err("Array layer range out of range".to_string());
+            }
+            self.layer_count
+        };
+        let aspect_mask = self.aspect_mask.unwrap_or_else(|| metadata.aspects());
+        let format_aspects = FormatInfo::of(metadata.format).map_or(metadata.aspects(), |info| {
+            info.aspects() | info.copy_aspects()
+        });
+        if !format_aspects.contains(aspect_mask) {
+            return Err(format!(
+                "Aspect {:?} not present in image format {:?}",
+                aspect_mask, metadata.format
+            ));
+        }
+        let format = self.format.unwrap_or(metadata.format);
+        if !is_view_format_compatible(metadata.format, metadata.flags, format, aspect_mask) {
+            return Err(format!(
+                "View format {:?} isn't compatible with image format {:?}",
+                format, metadata.format
+            ));
+        }
+        let view_type = self.view_type.unwrap_or(match metadata.image_type {
+            vk::ImageType::TYPE_1D if layer_count > 1 => vk::ImageViewType::TYPE_1D_ARRAY,
+            vk::ImageType::TYPE_1D => vk::ImageViewType::TYPE_1D,
+            vk::ImageType::TYPE_3D => vk::ImageViewType::TYPE_3D,
+            _ if layer_count > 1 => vk::ImageViewType::TYPE_2D_ARRAY,
+            _ => vk::ImageViewType::TYPE_2D,
+        });
+        if view_type == vk::ImageViewType::CUBE || view_type == vk::ImageViewType::CUBE_ARRAY {
+            if !metadata
+                .flags
+                .contains(vk::ImageCreateFlags::CUBE_COMPATIBLE)
+            {
+                return Err("Cube views require an image created with CUBE_COMPATIBLE".to_string());
+            }
+            if view_type == vk::ImageViewType::CUBE && layer_count != 6 {
+                return Err("Cube views require exactly 6 layers".to_string());
+            }
+            if layer_count % 6 != 0 {
+                return Err("Cube array views require a multiple of 6 layers".to_string());
+            }
+        }
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask,
+            base_mip_level: self.base_mip_level,
+            level_count,
+            base_array_layer: self.base_array_layer,
+            layer_count,
+        };
+        Ok((view_type, format, subresource_range))
+    }
+}
+
 pub struct ImageView<T: HasImage> {
     device: Arc<Device>,
     image: T,
     view: vk::ImageView,
+    view_type: vk::ImageViewType,
+    format: vk::Format,
+    subresource_range: vk::ImageSubresourceRange,
 }
 impl<T: HasImage> ImageView<T> {
     pub fn new(
@@ -199,12 +786,70 @@ impl<T: HasImage> ImageView<T> {
             device,
             image,
             view,
+            view_type,
+            format,
+            subresource_range,
         })
     }
     pub fn raw_image_view(&self) -> vk::ImageView {
         self.view
     }
+    pub fn image(&self) -> &T {
+        &self.image
+    }
+    pub fn view_type(&self) -> vk::ImageViewType {
+        self.view_type
+    }
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+}
+impl<T: HasImageMetadata + HasDevice> ImageView<T> {
+    /// Create a view of `image` described by `desc`. Returns `ERROR_VALIDATION_FAILED_EXT` if the view is
+    /// invalid for the image.
+    pub fn with_desc(image: T, desc: &ImageViewDesc) -> VkResult<Self> {
+        let (view_type, format, subresource_range) =
+            desc.resolve(image.metadata()).map_err(|message| {
+                tracing::error!(?desc, "{}", message);
+                vk::Result::ERROR_VALIDATION_FAILED_EXT
+            })?;
+        let [r, g, b, a] = desc.swizzle;
+        Self::new(
+            image.device().clone(),
+            image,
+            view_type,
+            format,
+            vk::ComponentMapping { r, g, b, a },
+            subresource_range,
+        )
+    }
+}
+impl<T: HasImage> HasImage for ImageView<T> {
+    fn raw_image(&self) -> vk::Image {
+        self.image.raw_image()
+    }
+}
+impl<T: HasImage> HasImageView for ImageView<T> {
+    fn raw_image_view(&self) -> vk::ImageView {
+        self.view
+    }
+    fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        self.subresource_range
+    }
 }
+impl<T: HasImage> HasDevice for ImageView<T> {
+    fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+}
+impl<T: HasImage> DebugObject for ImageView<T> {
+    fn object_handle(&mut self) -> u64 {
+        unsafe { std::mem::transmute(self.view) }
+    }
+
+    const OBJECT_TYPE: vk::ObjectType = vk::ObjectType::IMAGE_VIEW;
+}
+impl<T: HasImage> CommandBufferResource for ImageView<T> {}
 impl<T: HasImage> Drop for ImageView<T> {
     fn drop(&mut self) {
         unsafe {
@@ -212,3 +857,132 @@ impl<T: HasImage> Drop for ImageView<T> {
         }
     }
 }
+
+/// Owns an image and the views created from it, so that repeated requests for the same view
+/// return the same handle.
+pub struct ImageViewCache<T: HasImageMetadata + HasDevice> {
+    image: Arc<T>,
+    views: Mutex<HashMap<ImageViewDesc, Arc<ImageView<Arc<T>>>>>,
+}
+
+impl<T: HasImageMetadata + HasDevice> ImageViewCache<T> {
+    pub fn new(image: Arc<T>) -> Self {
+        Self {
+            image,
+            views: Mutex::new(HashMap::new()),
+        }
+    }
+    pub fn image(&self) -> &Arc<T> {
+        &self.image
+    }
+    pub fn get(&self, desc: &ImageViewDesc) -> VkResult<Arc<ImageView<Arc<T>>>> {
+        let mut views = self.views.lock().unwrap();
+        if let Some(view) = views.get(desc) {
+            return Ok(view.clone());
+        }
+        let view = Arc::new(ImageView::with_desc(self.image.clone(), desc)?);
+        views.insert(*desc, view.clone());
+        Ok(view)
+    }
+    /// Drop the views that aren't referenced outside of the cache.
+    pub fn trim(&self) {
+        self.views
+            .lock()
+            .unwrap()
+            .retain(|_, view| Arc::strong_count(view) > 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImageMetadata, ImageViewDesc};
+    use ash::vk;
+
+    fn metadata() -> ImageMetadata {
+        ImageMetadata {
+            flags: vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            image_type: vk::ImageType::TYPE_2D,
+            format: vk::Format::D24_UNORM_S8_UINT,
+            extent: vk::Extent3D {
+                width: 64,
+                height: 64,
+                depth: 1,
+            },
+            mip_levels: 4,
+            array_layers: 6,
+            samples: vk::SampleCountFlags::TYPE_1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage: vk::ImageUsageFlags::SAMPLED,
+        }
+    }
+
+    #[test]
+    fn resolve_defaults() {
+        let (view_type, format, range) = ImageViewDesc::default().resolve(&metadata()).unwrap();
+        assert_eq!(view_type, vk::ImageViewType::TYPE_2D_ARRAY);
+        assert_eq!(format, vk::Format::D24_UNORM_S8_UINT);
+        assert_eq!(
+            range.aspect_mask,
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        );
+        assert_eq!((range.base_mip_level, range.level_count), (0, 4));
+        assert_eq!((range.base_array_layer, range.layer_count), (0, 6));
+    }
+
+    #[test]
+    fn resolve_ranges() {
+        let desc = ImageViewDesc::default()
+            .mip_levels(1..3)
+            .array_layers(2..3)
+            .aspect(vk::ImageAspectFlags::DEPTH);
+        let (view_type, _, range) = desc.resolve(&metadata()).unwrap();
+        assert_eq!(view_type, vk::ImageViewType::TYPE_2D);
+        assert_eq!(range.aspect_mask, vk::ImageAspectFlags::DEPTH);
+        assert_eq!((range.base_mip_level, range.level_count), (1, 2));
+        assert_eq!((range.base_array_layer, range.layer_count), (2, 1));
+
+        let cube = ImageViewDesc::default().view_type(vk::ImageViewType::CUBE);
+        assert_eq!(
+            cube.resolve(&metadata()).unwrap().0,
+            vk::ImageViewType::CUBE
+        );
+    }
+
+    #[test]
+    fn resolve_rejects_out_of_range_mips() {
+        assert!(ImageViewDesc::default()
+            .mip_levels(2..5)
+            .resolve(&metadata())
+            .is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Reversed range")]
+    fn reversed_mip_range() {
+        #[allow(clippy::reversed_empty_ranges)]
+        let _ = ImageViewDesc::default().mip_levels(3..1);
+    }
+
+    #[test]
+    fn resolve_rejects_format_reinterpretation() {
+        assert!(ImageViewDesc::default()
+            .format(vk::Format::D32_SFLOAT)
+            .resolve(&metadata())
+            .is_err());
+    }
+
+    #[test]
+    fn resolve_cube_layer_counts() {
+        let metadata = ImageMetadata {
+            array_layers: 12,
+            ..metadata()
+        };
+        let cube = ImageViewDesc::default().view_type(vk::ImageViewType::CUBE);
+        assert!(cube.array_layers(6..12).resolve(&metadata).is_ok());
+        assert!(cube.resolve(&metadata).is_err());
+
+        let cube_array = ImageViewDesc::default().view_type(vk::ImageViewType::CUBE_ARRAY);
+        assert!(cube_array.resolve(&metadata).is_ok());
+        assert!(cube_array.array_layers(0..8).resolve(&metadata).is_err());
+    }
+}
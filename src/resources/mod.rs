@@ -1,7 +1,22 @@
 pub mod alloc;
+pub mod arena;
+pub mod budget;
 pub mod buffer;
+pub mod defrag;
+#[cfg(unix)]
+pub mod external;
+pub mod format;
 pub mod image;
+pub mod readback;
+pub mod sampler;
+pub mod sparse;
+pub mod staging;
+pub mod texture;
 
-pub use image::{HasImage, Image};
+pub use image::{
+    HasImage, HasImageMetadata, Image, ImageMetadata, ImageSubresourceData, ImageView,
+    ImageViewCache, ImageViewDesc,
+};
 
-pub use buffer::{Buffer, HasBuffer};
+pub use buffer::{Buffer, BufferView, HasBuffer, TypedBuffer};
+pub use staging::StagingBelt;
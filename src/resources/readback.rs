@@ -0,0 +1,293 @@
+use ash::{prelude::VkResult, vk};
+use std::{future::Future, sync::Arc};
+
+use super::{
+    alloc::{
+        AllocationCreateFlags, Allocator, BufferRequest, MemBuffer, MemoryAllocScenario,
+        MemoryUsage,
+    },
+    buffer::HasBuffer,
+    format::FormatInfo,
+    image::HasImage,
+};
+use crate::{
+    command::recorder::{CommandBufferResource, CommandRecorder},
+    queue::semaphore::TimelineSemaphoreOp,
+    sync::{CommandsFuture, GPUFuture},
+};
+
+/// The region of an image to read back. `offset` and `extent` are in texels.
+#[derive(Clone, Copy)]
+pub struct ImageReadbackRegion {
+    pub subresource: vk::ImageSubresourceLayers,
+    pub offset: vk::Offset3D,
+    pub extent: vk::Extent3D,
+}
+
+/// A pending copy from device memory into a host-visible buffer.
+/// The data becomes available once the [`CommandsFuture`] it was recorded on was submitted and executed.
+pub struct Readback {
+    buffer: Arc<MemBuffer>,
+    completion: TimelineSemaphoreOp,
+    row_size: usize,
+    row_pitch: usize,
+}
+
+/// Data read back from the device, mapped on the host.
+pub struct ReadbackData {
+    buffer: Arc<MemBuffer>,
+    row_size: usize,
+    row_pitch: usize,
+}
+
+impl Readback {
+    /// The timeline semaphore value signaled when the copy completes.
+    pub fn completion(&self) -> &TimelineSemaphoreOp {
+        &self.completion
+    }
+    pub fn is_ready(&self) -> VkResult<bool> {
+        self.completion.finished()
+    }
+    /// Blocks until the copy completes.
+    pub fn block(self) -> VkResult<ReadbackData> {
+        self.completion.clone().block()?;
+        Ok(self.into_data())
+    }
+    pub fn wait(self) -> impl Future<Output = VkResult<ReadbackData>> {
+        let completion = self.completion.clone().wait();
+        async move {
+            completion.await?;
+            Ok(self.into_data())
+        }
+    }
+    fn into_data(self) -> ReadbackData {
+        ReadbackData {
+            buffer: self.buffer,
+            row_size: self.row_size,
+            row_pitch: self.row_pitch,
+        }
+    }
+}
+
+impl ReadbackData {
+    /// The mapped staging buffer, including any padding between rows.
+    pub fn bytes(&self) -> &[u8] {
+        self.buffer.get_mut()
+    }
+    /// Size of a row of texel blocks in bytes, excluding padding. Equal to the size of the buffer for buffer readbacks.
+    pub fn row_size(&self) -> usize {
+        self.row_size
+    }
+    /// Distance between the start of two consecutive rows of texel blocks in bytes.
+    pub fn row_pitch(&self) -> usize {
+        self.row_pitch
+    }
+    /// Rows of texel blocks, in the order of array layers, depth slices and rows. Empty for zero-size readbacks.
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        let bytes = if self.row_pitch == 0 {
+            &[][..]
+        } else {
+            self.bytes()
+        };
+        bytes
+            .chunks(self.row_pitch.max(1))
+            .map(|row| &row[..self.row_size])
+    }
+    /// Copies the data into a tightly packed vec.
+    pub fn to_vec(&self) -> Vec<u8> {
+        if self.row_size == self.row_pitch {
+            return self.bytes().to_vec();
+        }
+        let mut data = Vec::with_capacity(self.bytes().len() / self.row_pitch * self.row_size);
+        for row in self.rows() {
+            data.extend_from_slice(row);
+        }
+        data
+    }
+}
+
+impl Allocator {
+    /// Host-visible, host-coherent memory, preferably host-cached so that reads from the host are fast.
+    fn allocate_readback_buffer(self: &Arc<Self>, size: u64) -> VkResult<MemBuffer> {
+        self.allocate_buffer(&BufferRequest {
+            size,
+            usage: vk::BufferUsageFlags::TRANSFER_DST,
+            scenario: MemoryAllocScenario::Custom {
+                memory_usage: MemoryUsage::Unknown,
+                require_flags: vk::MemoryPropertyFlags::HOST_VISIBLE
+                    | vk::MemoryPropertyFlags::HOST_COHERENT,
+                preferred_flags: vk::MemoryPropertyFlags::HOST_CACHED,
+                non_preferred_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            },
+            allocation_flags: AllocationCreateFlags::MAPPED,
+            ..Default::default()
+        })
+    }
+
+    /// Copy `size` bytes starting at `offset` in `buffer` to the host.
+    /// All prior writes to `buffer` on the queue of `commands_future` are made visible to the copy.
+    pub fn read_buffer<T: HasBuffer + CommandBufferResource>(
+        self: &Arc<Self>,
+        buffer: T,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+        commands_future: &mut CommandsFuture,
+    ) -> VkResult<Readback> {
+        // The command buffer keeps a reference to the staging buffer so that it outlives the copy
+        // even if the readback is dropped early.
+        let staging = Arc::new(self.allocate_readback_buffer(size)?);
+        commands_future.then_commands(|mut recorder| {
+            let before = vk::MemoryBarrier2 {
+                src_stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+                src_access_mask: vk::AccessFlags2::MEMORY_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags2::COPY,
+                dst_access_mask: vk::AccessFlags2::TRANSFER_READ,
+                ..Default::default()
+            };
+            unsafe {
+                recorder.pipeline_barrier2(&vk::DependencyInfo {
+                    memory_barrier_count: 1,
+                    p_memory_barriers: &before,
+                    ..Default::default()
+                });
+            }
+            recorder.copy_buffer(
+                buffer,
+                staging.clone(),
+                &[vk::BufferCopy {
+                    src_offset: offset,
+                    dst_offset: 0,
+                    size,
+                }],
+            );
+            host_read_barrier(&mut recorder);
+        });
+        let completion = commands_future
+            .stage(vk::PipelineStageFlags2::COPY)
+            .then_signal();
+        Ok(Readback {
+            buffer: staging,
+            completion,
+            row_size: size as usize,
+            row_pitch: size as usize,
+        })
+    }
+
+    /// Copy a region of `image` to the host.
+    ///
+    /// `layout` is the current layout of the image. If it isn't `TRANSFER_SRC_OPTIMAL` or `GENERAL`,
+    /// the image is transitioned to `TRANSFER_SRC_OPTIMAL` for the copy and back to `layout` afterwards.
+    /// The rows in the result are tightly packed, with each row containing one row of texel blocks.
+    pub fn read_image<T: HasImage + CommandBufferResource>(
+        self: &Arc<Self>,
+        image: T,
+        format: vk::Format,
+        layout: vk::ImageLayout,
+        region: &ImageReadbackRegion,
+        commands_future: &mut CommandsFuture,
+    ) -> VkResult<Readback> {
+        let format_info = FormatInfo::of(format).expect("Unsupported format for readback");
+        let row_size =
+            format_info.copy_row_size(region.subresource.aspect_mask, region.extent.width);
+        let size = format_info.copy_size(
+            region.subresource.aspect_mask,
+            region.extent,
+            region.subresource.layer_count,
+        );
+        let staging = Arc::new(self.allocate_readback_buffer(size)?);
+
+        let copy_layout = match layout {
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL | vk::ImageLayout::GENERAL => layout,
+            _ => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        };
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: region.subresource.aspect_mask,
+            base_mip_level: region.subresource.mip_level,
+            level_count: 1,
+            base_array_layer: region.subresource.base_array_layer,
+            layer_count: region.subresource.layer_count,
+        };
+        let raw_image = image.raw_image();
+        commands_future.then_commands(|mut recorder| {
+            let before = vk::ImageMemoryBarrier2 {
+                src_stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+                src_access_mask: vk::AccessFlags2::MEMORY_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags2::COPY,
+                dst_access_mask: vk::AccessFlags2::TRANSFER_READ,
+                old_layout: layout,
+                new_layout: copy_layout,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                image: raw_image,
+                subresource_range,
+                ..Default::default()
+            };
+            unsafe {
+                recorder.pipeline_barrier2(&vk::DependencyInfo {
+                    image_memory_barrier_count: 1,
+                    p_image_memory_barriers: &before,
+                    ..Default::default()
+                });
+            }
+            recorder.copy_image_to_buffer(
+                image,
+                copy_layout,
+                staging.clone(),
+                &[vk::BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_row_length: 0,
+                    buffer_image_height: 0,
+                    image_subresource: region.subresource,
+                    image_offset: region.offset,
+                    image_extent: region.extent,
+                }],
+            );
+            if copy_layout != layout {
+                let after = vk::ImageMemoryBarrier2 {
+                    src_stage_mask: vk::PipelineStageFlags2::COPY,
+                    src_access_mask: vk::AccessFlags2::NONE,
+                    dst_stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+                    dst_access_mask: vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
+                    old_layout: copy_layout,
+                    new_layout: layout,
+                    ..before
+                };
+                unsafe {
+                    recorder.pipeline_barrier2(&vk::DependencyInfo {
+                        image_memory_barrier_count: 1,
+                        p_image_memory_barriers: &after,
+                        ..Default::default()
+                    });
+                }
+            }
+            host_read_barrier(&mut recorder);
+        });
+        let completion = commands_future
+            .stage(vk::PipelineStageFlags2::COPY)
+            .then_signal();
+        Ok(Readback {
+            buffer: staging,
+            completion,
+            row_size: row_size as usize,
+            row_pitch: row_size as usize,
+        })
+    }
+}
+
+/// Make the transfer writes available to the host.
+fn host_read_barrier(recorder: &mut CommandRecorder) {
+    let barrier = vk::MemoryBarrier2 {
+        src_stage_mask: vk::PipelineStageFlags2::COPY,
+        src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+        dst_stage_mask: vk::PipelineStageFlags2::HOST,
+        dst_access_mask: vk::AccessFlags2::HOST_READ,
+        ..Default::default()
+    };
+    unsafe {
+        recorder.pipeline_barrier2(&vk::DependencyInfo {
+            memory_barrier_count: 1,
+            p_memory_barriers: &barrier,
+            ..Default::default()
+        });
+    }
+}
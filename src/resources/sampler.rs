@@ -0,0 +1,350 @@
+use ash::{prelude::VkResult, vk};
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+};
+
+use crate::{DebugObject, Device, HasDevice};
+
+/// Parameters of a [`SamplerYcbcrConversion`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct YcbcrConversionCreateInfo {
+    pub format: vk::Format,
+    pub ycbcr_model: vk::SamplerYcbcrModelConversion,
+    pub ycbcr_range: vk::SamplerYcbcrRange,
+    pub components: [vk::ComponentSwizzle; 4],
+    pub x_chroma_offset: vk::ChromaLocation,
+    pub y_chroma_offset: vk::ChromaLocation,
+    pub chroma_filter: vk::Filter,
+    pub force_explicit_reconstruction: bool,
+}
+
+impl Default for YcbcrConversionCreateInfo {
+    fn default() -> Self {
+        Self {
+            format: vk::Format::G8_B8R8_2PLANE_420_UNORM,
+            ycbcr_model: vk::SamplerYcbcrModelConversion::YCBCR_709,
+            ycbcr_range: vk::SamplerYcbcrRange::ITU_NARROW,
+            components: [vk::ComponentSwizzle::IDENTITY; 4],
+            x_chroma_offset: vk::ChromaLocation::MIDPOINT,
+            y_chroma_offset: vk::ChromaLocation::MIDPOINT,
+            chroma_filter: vk::Filter::LINEAR,
+            force_explicit_reconstruction: false,
+        }
+    }
+}
+
+/// Parameters of a [`Sampler`]. Floats are compared and hashed by their bit patterns.
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerCreateInfo {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+    pub address_mode_w: vk::SamplerAddressMode,
+    pub mip_lod_bias: f32,
+    /// Anisotropic filtering is enabled when this is Some.
+    pub max_anisotropy: Option<f32>,
+    /// Depth comparison is enabled when this is Some.
+    pub compare_op: Option<vk::CompareOp>,
+    pub min_lod: f32,
+    pub max_lod: f32,
+    pub border_color: vk::BorderColor,
+    pub unnormalized_coordinates: bool,
+    /// Requires VK_EXT_sampler_filter_minmax or Vulkan 1.2 when not `WEIGHTED_AVERAGE`.
+    pub reduction_mode: vk::SamplerReductionMode,
+    pub ycbcr_conversion: Option<YcbcrConversionCreateInfo>,
+}
+
+impl Default for SamplerCreateInfo {
+    fn default() -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            address_mode_w: vk::SamplerAddressMode::REPEAT,
+            mip_lod_bias: 0.0,
+            max_anisotropy: None,
+            compare_op: None,
+            min_lod: 0.0,
+            max_lod: vk::LOD_CLAMP_NONE,
+            border_color: vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+            unnormalized_coordinates: false,
+            reduction_mode: vk::SamplerReductionMode::WEIGHTED_AVERAGE,
+            ycbcr_conversion: None,
+        }
+    }
+}
+
+impl SamplerCreateInfo {
+    fn key(
+        &self,
+    ) -> (
+        [i32; 6],
+        [u32; 4],
+        Option<vk::CompareOp>,
+        vk::BorderColor,
+        bool,
+        vk::SamplerReductionMode,
+        Option<YcbcrConversionCreateInfo>,
+    ) {
+        (
+            [
+                self.mag_filter.as_raw(),
+                self.min_filter.as_raw(),
+                self.mipmap_mode.as_raw(),
+                self.address_mode_u.as_raw(),
+                self.address_mode_v.as_raw(),
+                self.address_mode_w.as_raw(),
+            ],
+            [
+                self.mip_lod_bias.to_bits(),
+                self.max_anisotropy.map_or(u32::MAX, f32::to_bits),
+                self.min_lod.to_bits(),
+                self.max_lod.to_bits(),
+            ],
+            self.compare_op,
+            self.border_color,
+            self.unnormalized_coordinates,
+            self.reduction_mode,
+            self.ycbcr_conversion,
+        )
+    }
+}
+
+impl PartialEq for SamplerCreateInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+impl Eq for SamplerCreateInfo {}
+impl Hash for SamplerCreateInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+pub struct SamplerYcbcrConversion {
+    device: Arc<Device>,
+    raw: vk::SamplerYcbcrConversion,
+}
+
+impl SamplerYcbcrConversion {
+    pub fn new(device: Arc<Device>, info: &YcbcrConversionCreateInfo) -> VkResult<Self> {
+        let create_info = vk::SamplerYcbcrConversionCreateInfo {
+            format: info.format,
+            ycbcr_model: info.ycbcr_model,
+            ycbcr_range: info.ycbcr_range,
+            components: vk::ComponentMapping {
+                r: info.components[0],
+                g: info.components[1],
+                b: info.components[2],
+                a: info.components[3],
+            },
+            x_chroma_offset: info.x_chroma_offset,
+            y_chroma_offset: info.y_chroma_offset,
+            chroma_filter: info.chroma_filter,
+            force_explicit_reconstruction: info.force_explicit_reconstruction.into(),
+            ..Default::default()
+        };
+        let raw = unsafe { device.create_sampler_ycbcr_conversion(&create_info, None)? };
+        Ok(Self { device, raw })
+    }
+    pub fn raw(&self) -> vk::SamplerYcbcrConversion {
+        self.raw
+    }
+}
+
+impl HasDevice for SamplerYcbcrConversion {
+    fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+}
+
+impl DebugObject for SamplerYcbcrConversion {
+    const OBJECT_TYPE: vk::ObjectType = vk::ObjectType::SAMPLER_YCBCR_CONVERSION;
+    fn object_handle(&mut self) -> u64 {
+        unsafe { std::mem::transmute(self.raw) }
+    }
+}
+
+impl Drop for SamplerYcbcrConversion {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_sampler_ycbcr_conversion(self.raw, None);
+        }
+    }
+}
+
+pub struct Sampler {
+    device: Arc<Device>,
+    raw: vk::Sampler,
+    // The conversion must outlive the sampler.
+    ycbcr_conversion: Option<Arc<SamplerYcbcrConversion>>,
+}
+
+impl Sampler {
+    /// `ycbcr_conversion` must have been created with `info.ycbcr_conversion`.
+    pub fn new(
+        device: Arc<Device>,
+        info: &SamplerCreateInfo,
+        ycbcr_conversion: Option<Arc<SamplerYcbcrConversion>>,
+    ) -> VkResult<Self> {
+        assert_eq!(
+            info.ycbcr_conversion.is_some(),
+            ycbcr_conversion.is_some(),
+            "YCbCr conversion object mismatch"
+        );
+        let mut create_info = vk::SamplerCreateInfo {
+            mag_filter: info.mag_filter,
+            min_filter: info.min_filter,
+            mipmap_mode: info.mipmap_mode,
+            address_mode_u: info.address_mode_u,
+            address_mode_v: info.address_mode_v,
+            address_mode_w: info.address_mode_w,
+            mip_lod_bias: info.mip_lod_bias,
+            anisotropy_enable: info.max_anisotropy.is_some().into(),
+            max_anisotropy: info.max_anisotropy.unwrap_or(1.0),
+            compare_enable: info.compare_op.is_some().into(),
+            compare_op: info.compare_op.unwrap_or(vk::CompareOp::NEVER),
+            min_lod: info.min_lod,
+            max_lod: info.max_lod,
+            border_color: info.border_color,
+            unnormalized_coordinates: info.unnormalized_coordinates.into(),
+            ..Default::default()
+        };
+        let mut reduction_mode_info = vk::SamplerReductionModeCreateInfo {
+            reduction_mode: info.reduction_mode,
+            ..Default::default()
+        };
+        let mut conversion_info = vk::SamplerYcbcrConversionInfo {
+            conversion: ycbcr_conversion
+                .as_ref()
+                .map_or(vk::SamplerYcbcrConversion::null(), |c| c.raw()),
+            ..Default::default()
+        };
+        if info.reduction_mode != vk::SamplerReductionMode::WEIGHTED_AVERAGE {
+            reduction_mode_info.p_next = create_info.p_next;
+            create_info.p_next = &reduction_mode_info as *const _ as *const std::ffi::c_void;
+        }
+        if ycbcr_conversion.is_some() {
+            conversion_info.p_next = create_info.p_next;
+            create_info.p_next = &conversion_info as *const _ as *const std::ffi::c_void;
+        }
+        let raw = unsafe { device.create_sampler(&create_info, None)? };
+        Ok(Self {
+            device,
+            raw,
+            ycbcr_conversion,
+        })
+    }
+    pub fn raw(&self) -> vk::Sampler {
+        self.raw
+    }
+    pub fn ycbcr_conversion(&self) -> Option<&Arc<SamplerYcbcrConversion>> {
+        self.ycbcr_conversion.as_ref()
+    }
+}
+
+// Samplers are compared by handle, so that bindings referencing them can be used as cache keys.
+impl PartialEq for Sampler {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw == other.raw
+    }
+}
+impl Eq for Sampler {}
+impl Hash for Sampler {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.raw.hash(state)
+    }
+}
+
+impl HasDevice for Sampler {
+    fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+}
+
+impl DebugObject for Sampler {
+    const OBJECT_TYPE: vk::ObjectType = vk::ObjectType::SAMPLER;
+    fn object_handle(&mut self) -> u64 {
+        unsafe { std::mem::transmute(self.raw) }
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        tracing::debug!(sampler = ?self.raw, "drop sampler");
+        unsafe {
+            self.device.destroy_sampler(self.raw, None);
+        }
+    }
+}
+
+/// Device-wide cache of samplers and YCbCr conversions, deduplicated by their create parameters.
+/// Entries are kept alive until the cache is dropped or [`SamplerCache::trim`] is called.
+pub struct SamplerCache {
+    device: Arc<Device>,
+    samplers: Mutex<HashMap<SamplerCreateInfo, Arc<Sampler>>>,
+    ycbcr_conversions: Mutex<HashMap<YcbcrConversionCreateInfo, Arc<SamplerYcbcrConversion>>>,
+}
+
+impl SamplerCache {
+    pub fn new(device: Arc<Device>) -> Self {
+        Self {
+            device,
+            samplers: Mutex::new(HashMap::new()),
+            ycbcr_conversions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, info: &SamplerCreateInfo) -> VkResult<Arc<Sampler>> {
+        let mut samplers = self.samplers.lock().unwrap();
+        if let Some(sampler) = samplers.get(info) {
+            return Ok(sampler.clone());
+        }
+        let ycbcr_conversion = info
+            .ycbcr_conversion
+            .as_ref()
+            .map(|conversion| self.get_ycbcr_conversion(conversion))
+            .transpose()?;
+        let sampler = Arc::new(Sampler::new(self.device.clone(), info, ycbcr_conversion)?);
+        samplers.insert(*info, sampler.clone());
+        Ok(sampler)
+    }
+
+    pub fn get_ycbcr_conversion(
+        &self,
+        info: &YcbcrConversionCreateInfo,
+    ) -> VkResult<Arc<SamplerYcbcrConversion>> {
+        let mut conversions = self.ycbcr_conversions.lock().unwrap();
+        if let Some(conversion) = conversions.get(info) {
+            return Ok(conversion.clone());
+        }
+        let conversion = Arc::new(SamplerYcbcrConversion::new(self.device.clone(), info)?);
+        conversions.insert(*info, conversion.clone());
+        Ok(conversion)
+    }
+
+    /// Release the cached objects that are not referenced outside of the cache.
+    pub fn trim(&self) {
+        self.samplers
+            .lock()
+            .unwrap()
+            .retain(|_, sampler| Arc::strong_count(sampler) > 1);
+        self.ycbcr_conversions
+            .lock()
+            .unwrap()
+            .retain(|_, conversion| Arc::strong_count(conversion) > 1);
+    }
+}
+
+impl HasDevice for SamplerCache {
+    fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+}
@@ -0,0 +1,743 @@
+use ash::{prelude::VkResult, vk};
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::Range,
+    sync::Arc,
+};
+
+use super::{
+    alloc::{Allocation, Allocator, MemoryAllocScenario},
+    buffer::{Buffer, HasBuffer},
+    format::format_aspects,
+    image::{HasImage, HasImageMetadata, Image, ImageMetadata},
+};
+use crate::{
+    command::recorder::CommandBufferResource,
+    queue::semaphore::TimelineSemaphoreOp,
+    sync::{GPUFuture, SparseBindingFuture},
+    Device, HasDevice,
+};
+
+/// Page memory shared by the sparse resources.
+///
+/// Pages unbound by an eviction are only freed once the sparse binding operation that unbound them
+/// has completed on the device. When the sparse resource is dropped, the resource and all of its pages are
+/// handed to the [`crate::queue::DeferredDestructionQueue`] until its last binding operation completed.
+struct PageAllocator {
+    allocator: Arc<Allocator>,
+    /// `size` and `alignment` are the page size.
+    page_requirements: vk::MemoryRequirements,
+    retired: Vec<Allocation>,
+    in_flight: VecDeque<(TimelineSemaphoreOp, Vec<Allocation>)>,
+    /// Signaled once the binds of the last flush with binds have completed.
+    last_bind: Option<TimelineSemaphoreOp>,
+}
+
+/// Pages of a dropped sparse resource, freed on drop.
+struct RetiredPages {
+    allocator: Arc<Allocator>,
+    allocations: Vec<Allocation>,
+}
+
+unsafe impl Send for RetiredPages {}
+unsafe impl Sync for RetiredPages {}
+
+impl Drop for RetiredPages {
+    fn drop(&mut self) {
+        for allocation in self.allocations.drain(..) {
+            unsafe { self.allocator.free_memory(allocation) };
+        }
+    }
+}
+
+impl PageAllocator {
+    fn new(allocator: Arc<Allocator>, requirements: vk::MemoryRequirements) -> Self {
+        Self {
+            allocator,
+            page_requirements: vk::MemoryRequirements {
+                size: requirements.alignment,
+                alignment: requirements.alignment,
+                memory_type_bits: requirements.memory_type_bits,
+            },
+            retired: Vec::new(),
+            in_flight: VecDeque::new(),
+            last_bind: None,
+        }
+    }
+    fn page_size(&self) -> vk::DeviceSize {
+        self.page_requirements.alignment
+    }
+    /// Allocate `size` bytes aligned to the page size.
+    /// Returns the allocation and the memory range to bind.
+    fn allocate(
+        &self,
+        size: vk::DeviceSize,
+    ) -> VkResult<(Allocation, vk::DeviceMemory, vk::DeviceSize)> {
+        let create_info = self.allocator.create_info_by_scenario(
+            vk_mem::AllocationCreateFlags::empty(),
+            &MemoryAllocScenario::DeviceAccess,
+        );
+        let allocation = self.allocator.allocate_memory(
+            &vk::MemoryRequirements {
+                size,
+                ..self.page_requirements
+            },
+            &create_info,
+        )?;
+        let info = self
+            .allocator
+            .allocator
+            .get_allocation_info(&allocation)
+            .unwrap();
+        Ok((allocation, info.device_memory, info.offset))
+    }
+    fn retire(&mut self, allocation: Allocation) {
+        self.retired.push(allocation);
+    }
+    /// Free the retired pages whose unbinding has completed.
+    fn reclaim(&mut self) -> VkResult<()> {
+        while let Some((completion, _)) = self.in_flight.front() {
+            if !completion.finished()? {
+                break;
+            }
+            let (_, allocations) = self.in_flight.pop_front().unwrap();
+            for allocation in allocations {
+                unsafe { self.allocator.free_memory(allocation) };
+            }
+        }
+        Ok(())
+    }
+    /// Free the retired pages after `future` completes. `bound` is true if binds were added to `future`.
+    fn retire_after(&mut self, future: &mut SparseBindingFuture, bound: bool) {
+        if self.retired.is_empty() && !bound {
+            return;
+        }
+        let completion = future.then_signal();
+        self.last_bind = Some(completion.clone());
+        if !self.retired.is_empty() {
+            self.in_flight
+                .push_back((completion, std::mem::take(&mut self.retired)));
+        }
+    }
+    /// Release `resource` and all pages once the last binding operation has completed. Blocks if the
+    /// device has no deferred destruction queue.
+    fn release<R: CommandBufferResource>(&mut self, resource: R) {
+        self.retired.extend(
+            std::mem::take(&mut self.in_flight)
+                .into_iter()
+                .flat_map(|(_, a)| a),
+        );
+        let Some(completion) = self.last_bind.take() else {
+            return;
+        };
+        if completion.finished().unwrap_or(false) {
+            return;
+        }
+        match self.allocator.device().deferred_destruction_queue() {
+            Some(queue) => {
+                let pages = RetiredPages {
+                    allocator: self.allocator.clone(),
+                    allocations: std::mem::take(&mut self.retired),
+                };
+                // The resource is dropped before its pages.
+                queue.retire(Box::new((resource, pages)), completion);
+            }
+            // The remaining pages are freed on drop, after the resource.
+            None => {
+                if let Err(err) = completion.block() {
+                    tracing::error!(%err, "failed to wait for sparse binds");
+                }
+            }
+        }
+    }
+}
+
+impl Drop for PageAllocator {
+    fn drop(&mut self) {
+        let retired = std::mem::take(&mut self.retired);
+        let in_flight = std::mem::take(&mut self.in_flight);
+        for allocation in retired
+            .into_iter()
+            .chain(in_flight.into_iter().flat_map(|(_, a)| a))
+        {
+            unsafe { self.allocator.free_memory(allocation) };
+        }
+    }
+}
+
+/// Location of a tile of a [`SparseImage`], in units of the sparse image granularity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TileIndex {
+    pub aspect_mask: vk::ImageAspectFlags,
+    pub mip_level: u32,
+    pub array_layer: u32,
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
+}
+
+/// Residency manager for an image created with `SPARSE_BINDING | SPARSE_RESIDENCY`.
+///
+/// Tiles are committed and evicted on the host, and the resulting binds are batched until
+/// [`SparseImage::flush`] pushes them into a [`SparseBindingFuture`].
+/// Mip levels at or after the mip tail are committed and evicted as a whole for each array layer.
+pub struct SparseImage {
+    // Released along with the pages.
+    image: Arc<Image>,
+    pages: PageAllocator,
+    /// One entry per aspect with separate sparse memory requirements, excluding the metadata aspect.
+    aspects: Vec<vk::SparseImageMemoryRequirements>,
+    tiles: HashMap<TileIndex, Allocation>,
+    /// Keyed by aspect and array layer. The layer is always 0 for single mip tail images.
+    mip_tails: HashMap<(vk::ImageAspectFlags, u32), Allocation>,
+    /// The metadata aspect is always resident.
+    metadata: Vec<Allocation>,
+    pending_binds: Vec<vk::SparseImageMemoryBind>,
+    pending_opaque_binds: Vec<vk::SparseMemoryBind>,
+}
+
+impl SparseImage {
+    /// Returns `ERROR_FORMAT_NOT_SUPPORTED` if the device doesn't support sparse residency for the image.
+    /// The metadata aspect, if required by the format, is bound on the next flush and stays resident.
+    pub fn new(allocator: Arc<Allocator>, info: &vk::ImageCreateInfo) -> VkResult<Self> {
+        let info = vk::ImageCreateInfo {
+            flags: info.flags
+                | vk::ImageCreateFlags::SPARSE_BINDING
+                | vk::ImageCreateFlags::SPARSE_RESIDENCY,
+            ..*info
+        };
+        let device = allocator.device().clone();
+        if device
+            .physical_device()
+            .sparse_image_format_properties(
+                info.format,
+                info.image_type,
+                info.samples,
+                info.usage,
+                info.tiling,
+            )
+            .is_empty()
+        {
+            return Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED);
+        }
+        let image = Image::new(device.clone(), &info)?;
+        let (requirements, sparse_requirements) = unsafe {
+            (
+                device.get_image_memory_requirements(image.raw_image()),
+                device.get_image_sparse_memory_requirements(image.raw_image()),
+            )
+        };
+        let mut this = Self {
+            image: Arc::new(image),
+            pages: PageAllocator::new(allocator, requirements),
+            aspects: Vec::new(),
+            tiles: HashMap::new(),
+            mip_tails: HashMap::new(),
+            metadata: Vec::new(),
+            pending_binds: Vec::new(),
+            pending_opaque_binds: Vec::new(),
+        };
+        for requirements in sparse_requirements {
+            if requirements
+                .format_properties
+                .aspect_mask
+                .contains(vk::ImageAspectFlags::METADATA)
+            {
+                for layer in 0..this.mip_tail_count(&requirements) {
+                    let allocation = this.bind_mip_tail(&requirements, layer)?;
+                    this.metadata.push(allocation);
+                }
+            } else {
+                this.aspects.push(requirements);
+            }
+        }
+        Ok(this)
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// The size in texels of a tile of `aspect_mask`.
+    pub fn granularity(&self, aspect_mask: vk::ImageAspectFlags) -> vk::Extent3D {
+        self.requirements(aspect_mask)
+            .format_properties
+            .image_granularity
+    }
+
+    /// The first mip level of the mip tail of `aspect_mask`.
+    pub fn mip_tail_first_lod(&self, aspect_mask: vk::ImageAspectFlags) -> u32 {
+        self.requirements(aspect_mask).image_mip_tail_first_lod
+    }
+
+    /// Number of tiles in each dimension of a mip level before the mip tail.
+    pub fn tile_count(&self, aspect_mask: vk::ImageAspectFlags, mip_level: u32) -> vk::Extent3D {
+        let granularity = self.granularity(aspect_mask);
+        let extent = self.image.metadata().mip_extent(mip_level);
+        vk::Extent3D {
+            width: (extent.width + granularity.width - 1) / granularity.width,
+            height: (extent.height + granularity.height - 1) / granularity.height,
+            depth: (extent.depth + granularity.depth - 1) / granularity.depth,
+        }
+    }
+
+    /// Bytes of memory bound to each tile.
+    pub fn page_size(&self) -> vk::DeviceSize {
+        self.pages.page_size()
+    }
+
+    pub fn is_resident(&self, tile: &TileIndex) -> bool {
+        if tile.mip_level >= self.mip_tail_first_lod(tile.aspect_mask) {
+            let key = self.mip_tail_key(tile.aspect_mask, tile.array_layer);
+            return self.mip_tails.contains_key(&key);
+        }
+        self.tiles.contains_key(tile)
+    }
+
+    /// Number of tiles and mip tails with memory bound.
+    pub fn resident_count(&self) -> usize {
+        self.tiles.len() + self.mip_tails.len()
+    }
+
+    /// Bind memory to the tiles overlapping the region of the subresource, given in texels.
+    pub fn commit(
+        &mut self,
+        aspect_mask: vk::ImageAspectFlags,
+        mip_level: u32,
+        array_layer: u32,
+        offset: vk::Offset3D,
+        extent: vk::Extent3D,
+    ) -> VkResult<()> {
+        let requirements = *self.requirements(aspect_mask);
+        if mip_level >= requirements.image_mip_tail_first_lod {
+            let key = self.mip_tail_key(aspect_mask, array_layer);
+            if !self.mip_tails.contains_key(&key) {
+                let allocation = self.bind_mip_tail(&requirements, key.1)?;
+                self.mip_tails.insert(key, allocation);
+            }
+            return Ok(());
+        }
+        let granularity = requirements.format_properties.image_granularity;
+        let mip_extent = self.image.metadata().mip_extent(mip_level);
+        for tile in self.tiles_in_region(aspect_mask, mip_level, array_layer, offset, extent) {
+            if self.tiles.contains_key(&tile) {
+                continue;
+            }
+            let (allocation, memory, memory_offset) = self.pages.allocate(self.page_size())?;
+            self.pending_binds.push(tile_bind(
+                &tile,
+                granularity,
+                mip_extent,
+                memory,
+                memory_offset,
+            ));
+            self.tiles.insert(tile, allocation);
+        }
+        Ok(())
+    }
+
+    /// Unbind the tiles overlapping the region of the subresource, given in texels.
+    /// Their memory is freed once the unbinding has completed on the device.
+    pub fn evict(
+        &mut self,
+        aspect_mask: vk::ImageAspectFlags,
+        mip_level: u32,
+        array_layer: u32,
+        offset: vk::Offset3D,
+        extent: vk::Extent3D,
+    ) {
+        let requirements = *self.requirements(aspect_mask);
+        if mip_level >= requirements.image_mip_tail_first_lod {
+            let key = self.mip_tail_key(aspect_mask, array_layer);
+            if let Some(allocation) = self.mip_tails.remove(&key) {
+                self.pending_opaque_binds.push(vk::SparseMemoryBind {
+                    resource_offset: mip_tail_offset(&requirements, key.1),
+                    size: requirements.image_mip_tail_size,
+                    memory: vk::DeviceMemory::null(),
+                    memory_offset: 0,
+                    flags: vk::SparseMemoryBindFlags::empty(),
+                });
+                self.pages.retire(allocation);
+            }
+            return;
+        }
+        let granularity = requirements.format_properties.image_granularity;
+        let mip_extent = self.image.metadata().mip_extent(mip_level);
+        for tile in self.tiles_in_region(aspect_mask, mip_level, array_layer, offset, extent) {
+            if let Some(allocation) = self.tiles.remove(&tile) {
+                self.pending_binds.push(tile_bind(
+                    &tile,
+                    granularity,
+                    mip_extent,
+                    vk::DeviceMemory::null(),
+                    0,
+                ));
+                self.pages.retire(allocation);
+            }
+        }
+    }
+
+    /// Add the binds since the last flush to `future`.
+    ///
+    /// Memory of evicted tiles is freed after `future` completes, so `future` should wait for
+    /// all device work that reads the evicted tiles.
+    pub fn flush<'q>(
+        &mut self,
+        mut future: SparseBindingFuture<'q>,
+    ) -> VkResult<SparseBindingFuture<'q>> {
+        self.pages.reclaim()?;
+        let image = self.image.raw_image();
+        let bound = !self.pending_binds.is_empty() || !self.pending_opaque_binds.is_empty();
+        if !self.pending_binds.is_empty() {
+            let binds = std::mem::take(&mut self.pending_binds).into_boxed_slice();
+            future = future.bind_image(image, binds);
+        }
+        if !self.pending_opaque_binds.is_empty() {
+            let binds = std::mem::take(&mut self.pending_opaque_binds).into_boxed_slice();
+            future = future.bind_image_opaque(image, binds);
+        }
+        self.pages.retire_after(&mut future, bound);
+        Ok(future)
+    }
+
+    fn requirements(
+        &self,
+        aspect_mask: vk::ImageAspectFlags,
+    ) -> &vk::SparseImageMemoryRequirements {
+        self.aspects
+            .iter()
+            .find(|r| r.format_properties.aspect_mask.contains(aspect_mask))
+            .expect("Aspect not present in the sparse image")
+    }
+
+    fn single_mip_tail(requirements: &vk::SparseImageMemoryRequirements) -> bool {
+        requirements
+            .format_properties
+            .flags
+            .contains(vk::SparseImageFormatFlags::SINGLE_MIPTAIL)
+    }
+
+    fn mip_tail_count(&self, requirements: &vk::SparseImageMemoryRequirements) -> u32 {
+        if requirements.image_mip_tail_first_lod >= self.image.metadata().mip_levels {
+            0
+        } else if Self::single_mip_tail(requirements) {
+            1
+        } else {
+            self.image.metadata().array_layers
+        }
+    }
+
+    fn mip_tail_key(
+        &self,
+        aspect_mask: vk::ImageAspectFlags,
+        array_layer: u32,
+    ) -> (vk::ImageAspectFlags, u32) {
+        let requirements = self.requirements(aspect_mask);
+        let layer = if Self::single_mip_tail(requirements) {
+            0
+        } else {
+            array_layer
+        };
+        (requirements.format_properties.aspect_mask, layer)
+    }
+
+    fn bind_mip_tail(
+        &mut self,
+        requirements: &vk::SparseImageMemoryRequirements,
+        layer: u32,
+    ) -> VkResult<Allocation> {
+        let (allocation, memory, memory_offset) =
+            self.pages.allocate(requirements.image_mip_tail_size)?;
+        let flags = if requirements
+            .format_properties
+            .aspect_mask
+            .contains(vk::ImageAspectFlags::METADATA)
+        {
+            vk::SparseMemoryBindFlags::METADATA
+        } else {
+            vk::SparseMemoryBindFlags::empty()
+        };
+        self.pending_opaque_binds.push(vk::SparseMemoryBind {
+            resource_offset: mip_tail_offset(requirements, layer),
+            size: requirements.image_mip_tail_size,
+            memory,
+            memory_offset,
+            flags,
+        });
+        Ok(allocation)
+    }
+
+    fn tiles_in_region(
+        &self,
+        aspect_mask: vk::ImageAspectFlags,
+        mip_level: u32,
+        array_layer: u32,
+        offset: vk::Offset3D,
+        extent: vk::Extent3D,
+    ) -> Vec<TileIndex> {
+        let metadata = self.image.metadata();
+        assert!(mip_level < metadata.mip_levels && array_layer < metadata.array_layers);
+        assert!(format_aspects(metadata.format).contains(aspect_mask));
+        let granularity = self.granularity(aspect_mask);
+        let count = self.tile_count(aspect_mask, mip_level);
+        let range = |offset: i32, extent: u32, granularity: u32, count: u32| {
+            let start = offset.max(0) as u32 / granularity;
+            let end = ((offset.max(0) as u32 + extent + granularity - 1) / granularity).min(count);
+            start..end
+        };
+        let xs = range(offset.x, extent.width, granularity.width, count.width);
+        let ys = range(offset.y, extent.height, granularity.height, count.height);
+        let zs = range(offset.z, extent.depth, granularity.depth, count.depth);
+        zs.flat_map(move |z| {
+            let xs = xs.clone();
+            ys.clone()
+                .flat_map(move |y| xs.clone().map(move |x| (x, y, z)))
+        })
+        .map(move |(x, y, z)| TileIndex {
+            aspect_mask,
+            mip_level,
+            array_layer,
+            x,
+            y,
+            z,
+        })
+        .collect()
+    }
+}
+
+fn mip_tail_offset(requirements: &vk::SparseImageMemoryRequirements, layer: u32) -> vk::DeviceSize {
+    requirements.image_mip_tail_offset
+        + layer as vk::DeviceSize * requirements.image_mip_tail_stride
+}
+
+/// Tiles on the edge of a mip level are clamped to the extent of the mip level.
+fn tile_bind(
+    tile: &TileIndex,
+    granularity: vk::Extent3D,
+    mip_extent: vk::Extent3D,
+    memory: vk::DeviceMemory,
+    memory_offset: vk::DeviceSize,
+) -> vk::SparseImageMemoryBind {
+    let x = tile.x * granularity.width;
+    let y = tile.y * granularity.height;
+    let z = tile.z * granularity.depth;
+    vk::SparseImageMemoryBind {
+        subresource: vk::ImageSubresource {
+            aspect_mask: tile.aspect_mask,
+            mip_level: tile.mip_level,
+            array_layer: tile.array_layer,
+        },
+        offset: vk::Offset3D {
+            x: x as i32,
+            y: y as i32,
+            z: z as i32,
+        },
+        extent: vk::Extent3D {
+            width: granularity.width.min(mip_extent.width - x),
+            height: granularity.height.min(mip_extent.height - y),
+            depth: granularity.depth.min(mip_extent.depth - z),
+        },
+        memory,
+        memory_offset,
+        flags: vk::SparseMemoryBindFlags::empty(),
+    }
+}
+
+impl Drop for SparseImage {
+    fn drop(&mut self) {
+        let pages = self
+            .tiles
+            .drain()
+            .map(|(_, a)| a)
+            .chain(self.mip_tails.drain().map(|(_, a)| a))
+            .chain(self.metadata.drain(..));
+        for allocation in pages.collect::<Vec<_>>() {
+            self.pages.retire(allocation);
+        }
+        self.pages.release(self.image.clone());
+    }
+}
+
+impl HasImage for SparseImage {
+    fn raw_image(&self) -> vk::Image {
+        self.image.raw_image()
+    }
+}
+
+impl HasImageMetadata for SparseImage {
+    fn metadata(&self) -> &ImageMetadata {
+        self.image.metadata()
+    }
+}
+
+impl HasDevice for SparseImage {
+    fn device(&self) -> &Arc<Device> {
+        self.image.device()
+    }
+}
+
+/// Residency manager for a buffer created with `SPARSE_BINDING | SPARSE_RESIDENCY`.
+/// Memory is committed and evicted in pages of [`SparseBuffer::page_size`] bytes.
+pub struct SparseBuffer {
+    // Released along with the pages.
+    buffer: Arc<Buffer>,
+    size: vk::DeviceSize,
+    pages: PageAllocator,
+    /// Keyed by page index.
+    resident: HashMap<u64, Allocation>,
+    pending_binds: Vec<vk::SparseMemoryBind>,
+}
+
+impl SparseBuffer {
+    pub fn new(
+        allocator: Arc<Allocator>,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+    ) -> VkResult<Self> {
+        let device = allocator.device().clone();
+        let buffer = Buffer::new(
+            device.clone(),
+            &vk::BufferCreateInfo {
+                flags: vk::BufferCreateFlags::SPARSE_BINDING
+                    | vk::BufferCreateFlags::SPARSE_RESIDENCY,
+                size,
+                usage,
+                sharing_mode: vk::SharingMode::EXCLUSIVE,
+                ..Default::default()
+            },
+        )?;
+        let requirements = unsafe { device.get_buffer_memory_requirements(buffer.raw) };
+        Ok(Self {
+            buffer: Arc::new(buffer),
+            size,
+            pages: PageAllocator::new(allocator, requirements),
+            resident: HashMap::new(),
+            pending_binds: Vec::new(),
+        })
+    }
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+    pub fn page_size(&self) -> vk::DeviceSize {
+        self.pages.page_size()
+    }
+    pub fn is_resident(&self, offset: vk::DeviceSize) -> bool {
+        self.resident.contains_key(&(offset / self.page_size()))
+    }
+    /// Bind memory to the pages overlapping `range`.
+    pub fn commit(&mut self, range: Range<vk::DeviceSize>) -> VkResult<()> {
+        for page in self.pages_in_range(range) {
+            if self.resident.contains_key(&page) {
+                continue;
+            }
+            let (allocation, memory, memory_offset) = self.pages.allocate(self.page_size())?;
+            let bind = self.page_bind(page, memory, memory_offset);
+            self.pending_binds.push(bind);
+            self.resident.insert(page, allocation);
+        }
+        Ok(())
+    }
+    /// Unbind the pages overlapping `range`.
+    /// Their memory is freed once the unbinding has completed on the device.
+    pub fn evict(&mut self, range: Range<vk::DeviceSize>) {
+        for page in self.pages_in_range(range) {
+            if let Some(allocation) = self.resident.remove(&page) {
+                let bind = self.page_bind(page, vk::DeviceMemory::null(), 0);
+                self.pending_binds.push(bind);
+                self.pages.retire(allocation);
+            }
+        }
+    }
+    /// Add the binds since the last flush to `future`. See [`SparseImage::flush`].
+    pub fn flush<'q>(
+        &mut self,
+        mut future: SparseBindingFuture<'q>,
+    ) -> VkResult<SparseBindingFuture<'q>> {
+        self.pages.reclaim()?;
+        let bound = !self.pending_binds.is_empty();
+        if !self.pending_binds.is_empty() {
+            let binds = std::mem::take(&mut self.pending_binds).into_boxed_slice();
+            future = future.bind_buffer(self.buffer.raw, binds);
+        }
+        self.pages.retire_after(&mut future, bound);
+        Ok(future)
+    }
+
+    fn pages_in_range(&self, range: Range<vk::DeviceSize>) -> Range<u64> {
+        let page_size = self.page_size();
+        let end = range.end.min(self.size);
+        range.start / page_size..(end + page_size - 1) / page_size
+    }
+    fn page_bind(
+        &self,
+        page: u64,
+        memory: vk::DeviceMemory,
+        memory_offset: vk::DeviceSize,
+    ) -> vk::SparseMemoryBind {
+        let resource_offset = page * self.page_size();
+        vk::SparseMemoryBind {
+            resource_offset,
+            // The last page may be partial if the size isn't a multiple of the page size.
+            size: self.page_size().min(self.size - resource_offset),
+            memory,
+            memory_offset,
+            flags: vk::SparseMemoryBindFlags::empty(),
+        }
+    }
+}
+
+impl Drop for SparseBuffer {
+    fn drop(&mut self) {
+        for (_, allocation) in self.resident.drain().collect::<Vec<_>>() {
+            self.pages.retire(allocation);
+        }
+        self.pages.release(self.buffer.clone());
+    }
+}
+
+impl HasBuffer for SparseBuffer {
+    fn raw_buffer(&self) -> vk::Buffer {
+        self.buffer.raw
+    }
+    fn buffer_size(&self) -> Option<vk::DeviceSize> {
+        Some(self.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tile_bind, TileIndex};
+    use ash::vk;
+
+    #[test]
+    fn edge_tiles_are_clamped() {
+        let granularity = vk::Extent3D {
+            width: 32,
+            height: 32,
+            depth: 32,
+        };
+        let mip_extent = vk::Extent3D {
+            width: 80,
+            height: 64,
+            depth: 1,
+        };
+        let tile = TileIndex {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 1,
+            array_layer: 0,
+            x: 2,
+            y: 1,
+            z: 0,
+        };
+        let bind = tile_bind(&tile, granularity, mip_extent, vk::DeviceMemory::null(), 0);
+        assert_eq!((bind.offset.x, bind.offset.y, bind.offset.z), (64, 32, 0));
+        assert_eq!(
+            (bind.extent.width, bind.extent.height, bind.extent.depth),
+            (16, 32, 1)
+        );
+        assert_eq!(bind.subresource.mip_level, 1);
+    }
+}
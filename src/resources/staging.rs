@@ -0,0 +1,271 @@
+use ash::{prelude::VkResult, vk};
+use std::{collections::VecDeque, sync::Arc};
+
+use super::{
+    alloc::{AllocationCreateFlags, Allocator, BufferRequest, MemBuffer, MemoryAllocScenario},
+    buffer::HasBuffer,
+    image::{HasImage, STAGING_OFFSET_ALIGNMENT},
+};
+use crate::{
+    command::recorder::CommandBufferResource,
+    queue::{
+        semaphore::{TimelineSemaphore, TimelineSemaphoreOp},
+        QueueIndex, Queues,
+    },
+    sync::{CommandsFuture, GPUFuture},
+    HasDevice,
+};
+
+/// Offsets of buffer uploads in the staging chunks are aligned to this value.
+const BUFFER_OFFSET_ALIGNMENT: u64 = 16;
+
+/// Uploads data to the device through a set of persistently mapped staging buffers.
+///
+/// Uploads are sub-allocated from fixed-size staging chunks and recorded into a single
+/// [`CommandsFuture`] until [`StagingBelt::flush`] is called. Chunks used by a flush are recycled
+/// once the timeline semaphore value signaled by that flush is reached. Uploads larger than the
+/// chunk size get a dedicated staging buffer which is freed after use.
+pub struct StagingBelt {
+    allocator: Arc<Allocator>,
+    queues: Arc<Queues>,
+    queue: QueueIndex,
+    chunk_size: u64,
+    /// The chunk currently being written to, and the offset of its free space.
+    active: Option<(Arc<MemBuffer>, u64)>,
+    /// Chunks written to since the last flush.
+    used: Vec<Arc<MemBuffer>>,
+    /// Chunks in use by the device, and the value signaled once the device is done with them.
+    in_flight: VecDeque<(TimelineSemaphoreOp, Vec<Arc<MemBuffer>>)>,
+    free: Vec<Arc<MemBuffer>>,
+    commands_future: Option<CommandsFuture>,
+    /// The semaphore op to be signaled by the next flush.
+    next_signal: TimelineSemaphoreOp,
+}
+
+impl StagingBelt {
+    pub fn new(
+        allocator: Arc<Allocator>,
+        queues: Arc<Queues>,
+        queue: QueueIndex,
+        chunk_size: u64,
+    ) -> VkResult<Self> {
+        let semaphore = TimelineSemaphore::new(allocator.device().clone(), 0)?;
+        Ok(Self {
+            allocator,
+            queues,
+            queue,
+            chunk_size: chunk_size.next_multiple_of(STAGING_OFFSET_ALIGNMENT),
+            active: None,
+            used: Vec::new(),
+            in_flight: VecDeque::new(),
+            free: Vec::new(),
+            commands_future: None,
+            next_signal: TimelineSemaphoreOp {
+                semaphore: Arc::new(semaphore),
+                value: 1,
+            },
+        })
+    }
+
+    pub fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+
+    /// The future that uploads are recorded into until the next flush.
+    /// Use this to add dependencies to the uploads, for example to wait for the previous readers of
+    /// the destination resources.
+    pub fn commands_future(&mut self) -> &mut CommandsFuture {
+        let queues = &self.queues;
+        let queue = self.queue;
+        let next_signal = &self.next_signal;
+        self.commands_future.get_or_insert_with(|| {
+            let mut future = CommandsFuture::new(queues.clone(), queue);
+            future.set_label("Staging belt");
+            // Have the flush signal our own semaphore instead of creating a new one each time.
+            future.push_semaphore_pool(next_signal.clone());
+            future
+        })
+    }
+
+    /// Move chunks that the device has finished reading from back into the free list.
+    pub fn recall(&mut self) -> VkResult<()> {
+        while let Some((completion, _)) = self.in_flight.front() {
+            if !completion.finished()? {
+                break;
+            }
+            let (_, chunks) = self.in_flight.pop_front().unwrap();
+            self.free.extend(
+                chunks
+                    .into_iter()
+                    .filter(|chunk| chunk.size() == self.chunk_size),
+            );
+        }
+        Ok(())
+    }
+
+    /// Reserve `size` bytes of staging memory aligned to `alignment`.
+    /// Returns the staging buffer and the offset of the reserved range.
+    fn reserve(&mut self, size: u64, alignment: u64) -> VkResult<(Arc<MemBuffer>, u64)> {
+        if size > self.chunk_size {
+            let buffer = Arc::new(self.allocate_chunk(size)?);
+            self.used.push(buffer.clone());
+            return Ok((buffer, 0));
+        }
+        if let Some((chunk, offset)) = self.active.as_mut() {
+            let start = offset.next_multiple_of(alignment);
+            if start + size <= self.chunk_size {
+                *offset = start + size;
+                return Ok((chunk.clone(), start));
+            }
+        }
+        if self.free.is_empty() {
+            self.recall()?;
+        }
+        let chunk = match self.free.pop() {
+            Some(chunk) => chunk,
+            None => Arc::new(self.allocate_chunk(self.chunk_size)?),
+        };
+        self.used.push(chunk.clone());
+        self.active = Some((chunk.clone(), size));
+        Ok((chunk, 0))
+    }
+
+    fn allocate_chunk(&self, size: u64) -> VkResult<MemBuffer> {
+        self.allocator.allocate_buffer(&BufferRequest {
+            size,
+            usage: vk::BufferUsageFlags::TRANSFER_SRC,
+            scenario: MemoryAllocScenario::StagingBuffer,
+            allocation_flags: AllocationCreateFlags::MAPPED,
+            ..Default::default()
+        })
+    }
+
+    /// Copy `data` into `dst_buffer` at `dst_offset`.
+    pub fn upload_buffer<T: HasBuffer + CommandBufferResource>(
+        &mut self,
+        dst_buffer: T,
+        dst_offset: vk::DeviceSize,
+        data: &[u8],
+    ) -> VkResult<()> {
+        let size = data.len() as u64;
+        let (staging, offset) = self.reserve(size, BUFFER_OFFSET_ALIGNMENT)?;
+        staging.get_mut()[offset as usize..(offset + size) as usize].copy_from_slice(data);
+        self.commands_future().then_commands(|mut recorder| {
+            recorder.copy_buffer(
+                staging,
+                dst_buffer,
+                &[vk::BufferCopy {
+                    src_offset: offset,
+                    dst_offset,
+                    size,
+                }],
+            );
+        });
+        Ok(())
+    }
+
+    /// Copy tightly packed texel data into a region of `dst_image`.
+    ///
+    /// The image is transitioned from `old_layout` to `TRANSFER_DST_OPTIMAL` for the copy, and then to `final_layout`.
+    /// Pass `vk::ImageLayout::UNDEFINED` as `old_layout` if the previous content of the image can be discarded.
+    pub fn upload_image<T: HasImage + CommandBufferResource>(
+        &mut self,
+        dst_image: T,
+        old_layout: vk::ImageLayout,
+        final_layout: vk::ImageLayout,
+        subresource: vk::ImageSubresourceLayers,
+        offset: vk::Offset3D,
+        extent: vk::Extent3D,
+        data: &[u8],
+    ) -> VkResult<()> {
+        let size = data.len() as u64;
+        let (staging, staging_offset) = self.reserve(size, STAGING_OFFSET_ALIGNMENT)?;
+        staging.get_mut()[staging_offset as usize..(staging_offset + size) as usize]
+            .copy_from_slice(data);
+
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: subresource.aspect_mask,
+            base_mip_level: subresource.mip_level,
+            level_count: 1,
+            base_array_layer: subresource.base_array_layer,
+            layer_count: subresource.layer_count,
+        };
+        let raw_image = dst_image.raw_image();
+        self.commands_future().then_commands(|mut recorder| {
+            let before = vk::ImageMemoryBarrier2 {
+                src_stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+                src_access_mask: vk::AccessFlags2::MEMORY_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags2::COPY,
+                dst_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+                old_layout,
+                new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                image: raw_image,
+                subresource_range,
+                ..Default::default()
+            };
+            let after = vk::ImageMemoryBarrier2 {
+                src_stage_mask: vk::PipelineStageFlags2::COPY,
+                src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+                dst_access_mask: vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
+                old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                new_layout: final_layout,
+                ..before
+            };
+            unsafe {
+                recorder.pipeline_barrier2(&vk::DependencyInfo {
+                    image_memory_barrier_count: 1,
+                    p_image_memory_barriers: &before,
+                    ..Default::default()
+                });
+            }
+            recorder.copy_buffer_to_image(
+                staging,
+                dst_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[vk::BufferImageCopy {
+                    buffer_offset: staging_offset,
+                    buffer_row_length: 0,
+                    buffer_image_height: 0,
+                    image_subresource: subresource,
+                    image_offset: offset,
+                    image_extent: extent,
+                }],
+            );
+            if final_layout != vk::ImageLayout::TRANSFER_DST_OPTIMAL {
+                unsafe {
+                    recorder.pipeline_barrier2(&vk::DependencyInfo {
+                        image_memory_barrier_count: 1,
+                        p_image_memory_barriers: &after,
+                        ..Default::default()
+                    });
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Submit all uploads recorded since the last flush to the queue.
+    ///
+    /// Returns the semaphore op signaled when the uploads complete, or None if there was nothing to upload.
+    /// The commands are handed to the queue dispatcher and will be submitted on the next [`Queues::flush`].
+    pub fn flush(&mut self) -> Option<TimelineSemaphoreOp> {
+        let mut commands_future = self.commands_future.take()?;
+        // The value of `next_signal` may have been handed out through `commands_future`, by this flush or
+        // by the application. Either way it can't be signaled again.
+        self.next_signal = self.next_signal.clone().increment();
+        if commands_future.is_empty() {
+            return None;
+        }
+        let completion = commands_future
+            .stage(vk::PipelineStageFlags2::COPY)
+            .then_signal();
+        drop(commands_future);
+        self.active = None;
+        self.in_flight
+            .push_back((completion.clone(), std::mem::take(&mut self.used)));
+        Some(completion)
+    }
+}
@@ -0,0 +1,819 @@
+//! Loading of KTX2 and DDS texture containers.
+//!
+//! [`Texture`] borrows the texel data from the container bytes when possible. Levels of KTX2 files
+//! supercompressed with zstd are decompressed on parsing, which requires the `ktx2_zstd` feature.
+
+use ash::vk;
+use std::{borrow::Cow, fmt::Display, ops::Range, sync::Arc};
+
+use super::{
+    alloc::Allocator,
+    format::FormatInfo,
+    image::{mip_extent, ImageRequest, ImageSubresourceData, MemImage},
+};
+use crate::{sync::CommandsFuture, HasDevice};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextureError {
+    Vk(vk::Result),
+    /// The container is truncated or inconsistent.
+    Malformed(&'static str),
+    /// The container is valid, but uses a feature that can't be loaded.
+    Unsupported(&'static str),
+    /// The device can't create sampled images of the format with the texture's dimensions.
+    FormatNotSupported(vk::Format),
+}
+
+impl From<vk::Result> for TextureError {
+    fn from(result: vk::Result) -> Self {
+        TextureError::Vk(result)
+    }
+}
+
+impl Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::Vk(result) => Display::fmt(result, f),
+            TextureError::Malformed(message) => write!(f, "malformed texture: {}", message),
+            TextureError::Unsupported(message) => write!(f, "unsupported texture: {}", message),
+            TextureError::FormatNotSupported(format) => {
+                write!(f, "{:?} textures are not supported by the device", format)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+struct Subresource {
+    mip_level: u32,
+    array_layer: u32,
+    chunk: usize,
+    range: Range<usize>,
+}
+
+/// A texture parsed from a KTX2 or DDS container.
+pub struct Texture<'a> {
+    pub format: vk::Format,
+    pub image_type: vk::ImageType,
+    pub extent: vk::Extent3D,
+    pub mip_levels: u32,
+    /// Number of array layers, counting each face of a cube map as one layer.
+    pub array_layers: u32,
+    pub cube: bool,
+    chunks: Vec<Cow<'a, [u8]>>,
+    subresources: Vec<Subresource>,
+}
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const KTX2_HEADER_SIZE: usize = 80;
+const KTX2_SUPERCOMPRESSION_NONE: u32 = 0;
+const KTX2_SUPERCOMPRESSION_ZSTD: u32 = 2;
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const DDS_HEADER_SIZE: usize = 128;
+const DDS_DX10_HEADER_SIZE: usize = 20;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDSD_DEPTH: u32 = 0x800000;
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x20000;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALL_FACES: u32 = 0xFC00;
+const DDSCAPS2_VOLUME: u32 = 0x200000;
+const D3D10_RESOURCE_DIMENSION_TEXTURE1D: u32 = 2;
+const D3D10_RESOURCE_DIMENSION_TEXTURE3D: u32 = 4;
+const D3D10_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, TextureError> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(TextureError::Malformed("unexpected end of file"))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, TextureError> {
+    bytes
+        .get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(TextureError::Malformed("unexpected end of file"))
+}
+
+/// Capacity to reserve for `count` subresources declared by the header of a `file_size` bytes long
+/// container. The header isn't trusted to match the data, so the capacity is capped by the file size.
+fn subresource_capacity(count: u32, file_size: usize) -> usize {
+    (count as usize).min(file_size)
+}
+
+impl<'a> Texture<'a> {
+    /// Parse a KTX2 or DDS container, detected from its magic number.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, TextureError> {
+        if bytes.starts_with(&KTX2_IDENTIFIER) {
+            Self::from_ktx2(bytes)
+        } else if bytes.starts_with(DDS_MAGIC) {
+            Self::from_dds(bytes)
+        } else {
+            Err(TextureError::Unsupported("unknown container format"))
+        }
+    }
+
+    pub fn from_ktx2(bytes: &'a [u8]) -> Result<Self, TextureError> {
+        if !bytes.starts_with(&KTX2_IDENTIFIER) {
+            return Err(TextureError::Malformed("missing KTX2 identifier"));
+        }
+        let format = vk::Format::from_raw(read_u32(bytes, 12)? as i32);
+        let width = read_u32(bytes, 20)?;
+        let height = read_u32(bytes, 24)?;
+        let depth = read_u32(bytes, 28)?;
+        let layer_count = read_u32(bytes, 32)?.max(1);
+        let face_count = read_u32(bytes, 36)?;
+        // A level count of 0 asks the loader to generate mipmaps. Only the base level is loaded.
+        let mip_levels = read_u32(bytes, 40)?.max(1);
+        let supercompression = read_u32(bytes, 44)?;
+
+        if format == vk::Format::UNDEFINED {
+            return Err(TextureError::Unsupported(
+                "KTX2 files without a Vulkan format need transcoding",
+            ));
+        }
+        if face_count != 1 && face_count != 6 {
+            return Err(TextureError::Malformed("face count must be 1 or 6"));
+        }
+        if width == 0 {
+            return Err(TextureError::Malformed("width must not be 0"));
+        }
+        let array_layers = layer_count
+            .checked_mul(face_count)
+            .ok_or(TextureError::Malformed("too many array layers"))?;
+        let subresource_count = array_layers
+            .checked_mul(mip_levels)
+            .ok_or(TextureError::Malformed("too many subresources"))?;
+        let image_type = if depth > 0 {
+            vk::ImageType::TYPE_3D
+        } else if height > 0 {
+            vk::ImageType::TYPE_2D
+        } else {
+            vk::ImageType::TYPE_1D
+        };
+        let mut texture = Texture {
+            format,
+            image_type,
+            extent: vk::Extent3D {
+                width,
+                height: height.max(1),
+                depth: depth.max(1),
+            },
+            mip_levels,
+            array_layers,
+            cube: face_count == 6,
+            chunks: Vec::new(),
+            subresources: Vec::new(),
+        };
+        texture.validate()?;
+        texture.chunks.reserve(mip_levels as usize);
+        texture
+            .subresources
+            .reserve(subresource_capacity(subresource_count, bytes.len()));
+
+        for level in 0..mip_levels {
+            let index_offset = KTX2_HEADER_SIZE + level as usize * 24;
+            let offset = read_u64(bytes, index_offset)? as usize;
+            let length = read_u64(bytes, index_offset + 8)? as usize;
+            let uncompressed_length = read_u64(bytes, index_offset + 16)?;
+            let data = offset
+                .checked_add(length)
+                .and_then(|end| bytes.get(offset..end))
+                .ok_or(TextureError::Malformed("level data out of bounds"))?;
+            // Doesn't overflow, the base level size was checked by `validate`.
+            let layer_size = texture.layer_size(level)?;
+            let level_size = layer_size * texture.array_layers as usize;
+            let data = match supercompression {
+                KTX2_SUPERCOMPRESSION_NONE => Cow::Borrowed(data),
+                KTX2_SUPERCOMPRESSION_ZSTD => {
+                    // The uncompressed length is only trusted once it matches the texture dimensions.
+                    if uncompressed_length != level_size as u64 {
+                        return Err(TextureError::Malformed(
+                            "level size doesn't match the texture dimensions",
+                        ));
+                    }
+                    Cow::Owned(zstd_decompress(data, level_size)?)
+                }
+                _ => {
+                    return Err(TextureError::Unsupported(
+                        "only zstd supercompression is supported",
+                    ))
+                }
+            };
+            if data.len() != level_size {
+                return Err(TextureError::Malformed(
+                    "level size doesn't match the texture dimensions",
+                ));
+            }
+            for array_layer in 0..texture.array_layers {
+                let start = array_layer as usize * layer_size;
+                texture.subresources.push(Subresource {
+                    mip_level: level,
+                    array_layer,
+                    chunk: texture.chunks.len(),
+                    range: start..start + layer_size,
+                });
+            }
+            texture.chunks.push(data);
+        }
+        Ok(texture)
+    }
+
+    pub fn from_dds(bytes: &'a [u8]) -> Result<Self, TextureError> {
+        if !bytes.starts_with(DDS_MAGIC) {
+            return Err(TextureError::Malformed("missing DDS magic number"));
+        }
+        if read_u32(bytes, 4)? != 124 {
+            return Err(TextureError::Malformed("invalid DDS header size"));
+        }
+        let flags = read_u32(bytes, 8)?;
+        let height = read_u32(bytes, 12)?;
+        let width = read_u32(bytes, 16)?;
+        let depth = if flags & DDSD_DEPTH != 0 {
+            read_u32(bytes, 24)?.max(1)
+        } else {
+            1
+        };
+        let mip_levels = if flags & DDSD_MIPMAPCOUNT != 0 {
+            read_u32(bytes, 28)?.max(1)
+        } else {
+            1
+        };
+        let pixel_format_flags = read_u32(bytes, 80)?;
+        let four_cc = read_u32(bytes, 84)?;
+        let caps2 = read_u32(bytes, 112)?;
+
+        let (format, image_type, array_size, cube, data_offset) =
+            if pixel_format_flags & DDPF_FOURCC != 0 && four_cc == u32::from_le_bytes(*b"DX10") {
+                let dxgi_format = read_u32(bytes, DDS_HEADER_SIZE)?;
+                let dimension = read_u32(bytes, DDS_HEADER_SIZE + 4)?;
+                let misc_flags = read_u32(bytes, DDS_HEADER_SIZE + 8)?;
+                let array_size = read_u32(bytes, DDS_HEADER_SIZE + 12)?.max(1);
+                let image_type = match dimension {
+                    D3D10_RESOURCE_DIMENSION_TEXTURE1D => vk::ImageType::TYPE_1D,
+                    D3D10_RESOURCE_DIMENSION_TEXTURE3D => vk::ImageType::TYPE_3D,
+                    _ => vk::ImageType::TYPE_2D,
+                };
+                let format = dxgi_format_to_vk(dxgi_format).ok_or(TextureError::Unsupported(
+                    "DXGI format has no Vulkan equivalent",
+                ))?;
+                let cube = misc_flags & D3D10_RESOURCE_MISC_TEXTURECUBE != 0;
+                (
+                    format,
+                    image_type,
+                    array_size,
+                    cube,
+                    DDS_HEADER_SIZE + DDS_DX10_HEADER_SIZE,
+                )
+            } else {
+                let format = dds_pixel_format_to_vk(bytes, pixel_format_flags, four_cc)?;
+                let image_type = if caps2 & DDSCAPS2_VOLUME != 0 {
+                    vk::ImageType::TYPE_3D
+                } else {
+                    vk::ImageType::TYPE_2D
+                };
+                let cube = caps2 & DDSCAPS2_CUBEMAP != 0;
+                if cube && caps2 & DDSCAPS2_CUBEMAP_ALL_FACES != DDSCAPS2_CUBEMAP_ALL_FACES {
+                    return Err(TextureError::Unsupported(
+                        "cube maps must have all six faces",
+                    ));
+                }
+                (format, image_type, 1, cube, DDS_HEADER_SIZE)
+            };
+
+        let faces = if cube { 6 } else { 1 };
+        let array_layers = array_size
+            .checked_mul(faces)
+            .ok_or(TextureError::Malformed("too many array layers"))?;
+        let subresource_count = array_layers
+            .checked_mul(mip_levels)
+            .ok_or(TextureError::Malformed("too many subresources"))?;
+        let mut texture = Texture {
+            format,
+            image_type,
+            extent: vk::Extent3D {
+                width,
+                height: height.max(1),
+                depth: if image_type == vk::ImageType::TYPE_3D {
+                    depth
+                } else {
+                    1
+                },
+            },
+            mip_levels,
+            array_layers,
+            cube,
+            chunks: vec![Cow::Borrowed(bytes)],
+            subresources: Vec::new(),
+        };
+        texture.validate()?;
+        texture
+            .subresources
+            .reserve(subresource_capacity(subresource_count, bytes.len()));
+
+        // DDS stores the full mip chain of each array layer one after another.
+        let mut offset = data_offset;
+        for array_layer in 0..texture.array_layers {
+            for mip_level in 0..mip_levels {
+                let size = texture.layer_size(mip_level)?;
+                let end = offset
+                    .checked_add(size)
+                    .ok_or(TextureError::Malformed("unexpected end of file"))?;
+                if end > bytes.len() {
+                    return Err(TextureError::Malformed("unexpected end of file"));
+                }
+                texture.subresources.push(Subresource {
+                    mip_level,
+                    array_layer,
+                    chunk: 0,
+                    range: offset..offset + size,
+                });
+                offset += size;
+            }
+        }
+        Ok(texture)
+    }
+
+    /// Check the parameters that the subresource sizes are derived from.
+    fn validate(&self) -> Result<(), TextureError> {
+        let format_info =
+            FormatInfo::of(self.format).ok_or(TextureError::Unsupported("unknown format"))?;
+        if format_info.is_multi_planar() || format_info.copy_aspects() != format_info.aspects() {
+            return Err(TextureError::Unsupported(
+                "multi-planar and combined depth stencil formats can't be loaded",
+            ));
+        }
+        if self.extent.width == 0 {
+            return Err(TextureError::Malformed("width must not be 0"));
+        }
+        let max_dimension = self
+            .extent
+            .width
+            .max(self.extent.height)
+            .max(self.extent.depth);
+        if self.mip_levels > 32 - max_dimension.leading_zeros() {
+            return Err(TextureError::Malformed("too many mip levels"));
+        }
+        if self.cube
+            && (self.image_type != vk::ImageType::TYPE_2D
+                || self.extent.width != self.extent.height)
+        {
+            return Err(TextureError::Malformed(
+                "cube maps must be square 2D images",
+            ));
+        }
+        if self.image_type == vk::ImageType::TYPE_3D && self.array_layers > 1 {
+            return Err(TextureError::Unsupported("arrays of 3D textures"));
+        }
+        // Every other subresource size is bounded by the base level.
+        self.layer_size(0)?
+            .checked_mul(self.array_layers as usize)
+            .ok_or(TextureError::Malformed("texture too large"))?;
+        Ok(())
+    }
+
+    /// Size in bytes of one array layer of `mip_level`.
+    fn layer_size(&self, mip_level: u32) -> Result<usize, TextureError> {
+        let format_info = FormatInfo::of(self.format).unwrap();
+        let aspect = format_info.aspects();
+        let extent = mip_extent(self.extent, mip_level);
+        // Neither factor overflows a u64 for a single layer, but their product might.
+        format_info
+            .copy_row_size(aspect, extent.width)
+            .checked_mul(format_info.copy_row_count(aspect, extent, 1))
+            .and_then(|size| usize::try_from(size).ok())
+            .ok_or(TextureError::Malformed("texture too large"))
+    }
+
+    /// The texel data of each mip level of each array layer.
+    pub fn subresources(&self) -> Vec<ImageSubresourceData<'_>> {
+        self.subresources
+            .iter()
+            .map(|subresource| ImageSubresourceData {
+                mip_level: subresource.mip_level,
+                array_layer: subresource.array_layer,
+                data: &self.chunks[subresource.chunk][subresource.range.clone()],
+            })
+            .collect()
+    }
+
+    /// An optimal tiling image request for this texture.
+    pub fn image_request(&self, usage: vk::ImageUsageFlags) -> ImageRequest<'static> {
+        ImageRequest {
+            flags: if self.cube {
+                vk::ImageCreateFlags::CUBE_COMPATIBLE
+            } else {
+                vk::ImageCreateFlags::empty()
+            },
+            image_type: self.image_type,
+            format: self.format,
+            extent: self.extent,
+            mip_levels: self.mip_levels,
+            array_layers: self.array_layers,
+            usage,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(feature = "ktx2_zstd")]
+fn zstd_decompress(data: &[u8], uncompressed_length: usize) -> Result<Vec<u8>, TextureError> {
+    use std::io::Read;
+    let mut decoder = ruzstd::StreamingDecoder::new(data)
+        .map_err(|_| TextureError::Malformed("invalid zstd frame"))?;
+    let mut out = Vec::with_capacity(uncompressed_length);
+    decoder
+        .read_to_end(&mut out)
+        .map_err(|_| TextureError::Malformed("invalid zstd frame"))?;
+    if out.len() != uncompressed_length {
+        return Err(TextureError::Malformed(
+            "decompressed level size doesn't match the level index",
+        ));
+    }
+    Ok(out)
+}
+
+#[cfg(not(feature = "ktx2_zstd"))]
+fn zstd_decompress(_data: &[u8], _uncompressed_length: usize) -> Result<Vec<u8>, TextureError> {
+    Err(TextureError::Unsupported(
+        "zstd supercompression requires the ktx2_zstd feature",
+    ))
+}
+
+/// Formats of DDS files without the DX10 header.
+fn dds_pixel_format_to_vk(
+    bytes: &[u8],
+    pixel_format_flags: u32,
+    four_cc: u32,
+) -> Result<vk::Format, TextureError> {
+    use vk::Format as F;
+    if pixel_format_flags & DDPF_FOURCC != 0 {
+        let format = match &four_cc.to_le_bytes() {
+            b"DXT1" => F::BC1_RGBA_UNORM_BLOCK,
+            b"DXT2" | b"DXT3" => F::BC2_UNORM_BLOCK,
+            b"DXT4" | b"DXT5" => F::BC3_UNORM_BLOCK,
+            b"ATI1" | b"BC4U" => F::BC4_UNORM_BLOCK,
+            b"BC4S" => F::BC4_SNORM_BLOCK,
+            b"ATI2" | b"BC5U" => F::BC5_UNORM_BLOCK,
+            b"BC5S" => F::BC5_SNORM_BLOCK,
+            // D3DFORMAT values
+            _ => match four_cc {
+                36 => F::R16G16B16A16_UNORM,
+                110 => F::R16G16B16A16_SNORM,
+                111 => F::R16_SFLOAT,
+                112 => F::R16G16_SFLOAT,
+                113 => F::R16G16B16A16_SFLOAT,
+                114 => F::R32_SFLOAT,
+                115 => F::R32G32_SFLOAT,
+                116 => F::R32G32B32A32_SFLOAT,
+                _ => return Err(TextureError::Unsupported("unknown DDS FourCC")),
+            },
+        };
+        return Ok(format);
+    }
+    let bit_count = read_u32(bytes, 88)?;
+    let masks = (
+        read_u32(bytes, 92)?,
+        read_u32(bytes, 96)?,
+        read_u32(bytes, 100)?,
+        if pixel_format_flags & DDPF_ALPHAPIXELS != 0 {
+            read_u32(bytes, 104)?
+        } else {
+            0
+        },
+    );
+    let format = if pixel_format_flags & DDPF_RGB != 0 {
+        match (bit_count, masks) {
+            (32, (0xff, 0xff00, 0xff0000, 0xff000000 | 0)) => F::R8G8B8A8_UNORM,
+            (32, (0xff0000, 0xff00, 0xff, 0xff000000 | 0)) => F::B8G8R8A8_UNORM,
+            (32, (0x3ff, 0xffc00, 0x3ff00000, 0xc0000000)) => F::A2B10G10R10_UNORM_PACK32,
+            (32, (0xffff, 0xffff0000, 0, 0)) => F::R16G16_UNORM,
+            (16, (0xf800, 0x7e0, 0x1f, 0)) => F::R5G6B5_UNORM_PACK16,
+            (16, (0x7c00, 0x3e0, 0x1f, 0x8000)) => F::A1R5G5B5_UNORM_PACK16,
+            (16, (0xf00, 0xf0, 0xf, 0xf000)) => F::A4R4G4B4_UNORM_PACK16,
+            _ => return Err(TextureError::Unsupported("unknown DDS RGB pixel format")),
+        }
+    } else if pixel_format_flags & DDPF_LUMINANCE != 0 {
+        match (bit_count, masks) {
+            (8, (0xff, 0, 0, 0)) => F::R8_UNORM,
+            (16, (0xffff, 0, 0, 0)) => F::R16_UNORM,
+            (16, (0xff, 0, 0, 0xff00)) => F::R8G8_UNORM,
+            _ => {
+                return Err(TextureError::Unsupported(
+                    "unknown DDS luminance pixel format",
+                ))
+            }
+        }
+    } else {
+        return Err(TextureError::Unsupported("unknown DDS pixel format"));
+    };
+    Ok(format)
+}
+
+fn dxgi_format_to_vk(dxgi_format: u32) -> Option<vk::Format> {
+    use vk::Format as F;
+    let format = match dxgi_format {
+        2 => F::R32G32B32A32_SFLOAT,
+        3 => F::R32G32B32A32_UINT,
+        4 => F::R32G32B32A32_SINT,
+        6 => F::R32G32B32_SFLOAT,
+        7 => F::R32G32B32_UINT,
+        8 => F::R32G32B32_SINT,
+        10 => F::R16G16B16A16_SFLOAT,
+        11 => F::R16G16B16A16_UNORM,
+        12 => F::R16G16B16A16_UINT,
+        13 => F::R16G16B16A16_SNORM,
+        14 => F::R16G16B16A16_SINT,
+        16 => F::R32G32_SFLOAT,
+        17 => F::R32G32_UINT,
+        18 => F::R32G32_SINT,
+        24 => F::A2B10G10R10_UNORM_PACK32,
+        25 => F::A2B10G10R10_UINT_PACK32,
+        26 => F::B10G11R11_UFLOAT_PACK32,
+        28 => F::R8G8B8A8_UNORM,
+        29 => F::R8G8B8A8_SRGB,
+        30 => F::R8G8B8A8_UINT,
+        31 => F::R8G8B8A8_SNORM,
+        32 => F::R8G8B8A8_SINT,
+        34 => F::R16G16_SFLOAT,
+        35 => F::R16G16_UNORM,
+        36 => F::R16G16_UINT,
+        37 => F::R16G16_SNORM,
+        38 => F::R16G16_SINT,
+        40 => F::D32_SFLOAT,
+        41 => F::R32_SFLOAT,
+        42 => F::R32_UINT,
+        43 => F::R32_SINT,
+        49 => F::R8G8_UNORM,
+        50 => F::R8G8_UINT,
+        51 => F::R8G8_SNORM,
+        52 => F::R8G8_SINT,
+        54 => F::R16_SFLOAT,
+        55 => F::D16_UNORM,
+        56 => F::R16_UNORM,
+        57 => F::R16_UINT,
+        58 => F::R16_SNORM,
+        59 => F::R16_SINT,
+        61 => F::R8_UNORM,
+        62 => F::R8_UINT,
+        63 => F::R8_SNORM,
+        64 => F::R8_SINT,
+        67 => F::E5B9G9R9_UFLOAT_PACK32,
+        71 => F::BC1_RGBA_UNORM_BLOCK,
+        72 => F::BC1_RGBA_SRGB_BLOCK,
+        74 => F::BC2_UNORM_BLOCK,
+        75 => F::BC2_SRGB_BLOCK,
+        77 => F::BC3_UNORM_BLOCK,
+        78 => F::BC3_SRGB_BLOCK,
+        80 => F::BC4_UNORM_BLOCK,
+        81 => F::BC4_SNORM_BLOCK,
+        83 => F::BC5_UNORM_BLOCK,
+        84 => F::BC5_SNORM_BLOCK,
+        85 => F::R5G6B5_UNORM_PACK16,
+        86 => F::A1R5G5B5_UNORM_PACK16,
+        87 => F::B8G8R8A8_UNORM,
+        88 => F::B8G8R8A8_UNORM,
+        91 => F::B8G8R8A8_SRGB,
+        93 => F::B8G8R8A8_SRGB,
+        95 => F::BC6H_UFLOAT_BLOCK,
+        96 => F::BC6H_SFLOAT_BLOCK,
+        98 => F::BC7_UNORM_BLOCK,
+        99 => F::BC7_SRGB_BLOCK,
+        115 => F::A4R4G4B4_UNORM_PACK16,
+        _ => return None,
+    };
+    Some(format)
+}
+
+impl Allocator {
+    /// Create a sampled image from `texture` and upload all of its mip levels and array layers on
+    /// `commands_future`, leaving the image in `final_layout`.
+    ///
+    /// Returns [`TextureError::FormatNotSupported`] if the device can't create the image.
+    pub fn allocate_texture(
+        self: &Arc<Self>,
+        texture: &Texture,
+        usage: vk::ImageUsageFlags,
+        final_layout: vk::ImageLayout,
+        commands_future: &mut CommandsFuture,
+    ) -> Result<Arc<MemImage>, TextureError> {
+        let request = texture.image_request(
+            usage | vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+        );
+        let properties = self
+            .device()
+            .physical_device()
+            .image_format_properties(&vk::PhysicalDeviceImageFormatInfo2 {
+                format: request.format,
+                ty: request.image_type,
+                tiling: request.tiling,
+                usage: request.usage,
+                flags: request.flags,
+                ..Default::default()
+            })?
+            .ok_or(TextureError::FormatNotSupported(texture.format))?
+            .image_format_properties;
+        if texture.extent.width > properties.max_extent.width
+            || texture.extent.height > properties.max_extent.height
+            || texture.extent.depth > properties.max_extent.depth
+            || texture.mip_levels > properties.max_mip_levels
+            || texture.array_layers > properties.max_array_layers
+        {
+            return Err(TextureError::FormatNotSupported(texture.format));
+        }
+        let image = self.allocate_image_with_data(
+            &request,
+            &texture.subresources(),
+            final_layout,
+            commands_future,
+        )?;
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ktx2(
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        faces: u32,
+        levels: &[Vec<u8>],
+    ) -> Vec<u8> {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        for value in [
+            format.as_raw() as u32,
+            1,
+            width,
+            height,
+            0,
+            0,
+            faces,
+            levels.len() as u32,
+            KTX2_SUPERCOMPRESSION_NONE,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        // Empty DFD, KVD and SGD
+        bytes.extend_from_slice(&[0; 32]);
+        let mut offset = (KTX2_HEADER_SIZE + levels.len() * 24) as u64;
+        for level in levels {
+            for value in [offset, level.len() as u64, level.len() as u64] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            offset += level.len() as u64;
+        }
+        for level in levels {
+            bytes.extend_from_slice(level);
+        }
+        bytes
+    }
+
+    fn dds_dx10(dxgi_format: u32, width: u32, height: u32, mip_levels: u32, cube: bool) -> Vec<u8> {
+        let mut bytes = vec![0; DDS_HEADER_SIZE + DDS_DX10_HEADER_SIZE];
+        let mut write = |offset: usize, value: u32| {
+            bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        };
+        write(0, u32::from_le_bytes(*DDS_MAGIC));
+        write(4, 124);
+        write(8, DDSD_MIPMAPCOUNT);
+        write(12, height);
+        write(16, width);
+        write(28, mip_levels);
+        write(76, 32);
+        write(80, DDPF_FOURCC);
+        write(84, u32::from_le_bytes(*b"DX10"));
+        write(DDS_HEADER_SIZE, dxgi_format);
+        write(DDS_HEADER_SIZE + 4, 3);
+        write(
+            DDS_HEADER_SIZE + 8,
+            if cube {
+                D3D10_RESOURCE_MISC_TEXTURECUBE
+            } else {
+                0
+            },
+        );
+        write(DDS_HEADER_SIZE + 12, 1);
+        bytes
+    }
+
+    #[test]
+    fn parse_ktx2_mip_chain() {
+        // 8x4 BC1 texture: 2x1 blocks, then 1x1 block for 4x2 and 2x1.
+        let levels = vec![vec![1; 16], vec![2; 8], vec![3; 8], vec![4; 8]];
+        let bytes = ktx2(vk::Format::BC1_RGB_UNORM_BLOCK, 8, 4, 1, &levels);
+        let texture = Texture::parse(&bytes).unwrap();
+        assert_eq!(texture.format, vk::Format::BC1_RGB_UNORM_BLOCK);
+        assert_eq!(texture.image_type, vk::ImageType::TYPE_2D);
+        assert_eq!((texture.mip_levels, texture.array_layers), (4, 1));
+        let subresources = texture.subresources();
+        assert_eq!(subresources.len(), 4);
+        assert_eq!(subresources[1].mip_level, 1);
+        assert_eq!(subresources[3].data, &[4; 8]);
+    }
+
+    #[test]
+    fn parse_ktx2_cube() {
+        let faces: Vec<u8> = (0..6).flat_map(|face| [face; 4 * 4 * 4]).collect();
+        let bytes = ktx2(vk::Format::R8G8B8A8_SRGB, 4, 4, 6, &[faces]);
+        let texture = Texture::from_ktx2(&bytes).unwrap();
+        assert!(texture.cube);
+        assert_eq!(texture.array_layers, 6);
+        let request = texture.image_request(vk::ImageUsageFlags::SAMPLED);
+        assert!(request
+            .flags
+            .contains(vk::ImageCreateFlags::CUBE_COMPATIBLE));
+        for (face, subresource) in texture.subresources().iter().enumerate() {
+            assert_eq!(subresource.array_layer, face as u32);
+            assert!(subresource.data.iter().all(|&b| b == face as u8));
+        }
+    }
+
+    #[test]
+    fn reject_ktx2_level_size_mismatch() {
+        let bytes = ktx2(vk::Format::R8G8B8A8_UNORM, 4, 4, 1, &[vec![0; 60]]);
+        assert!(matches!(
+            Texture::from_ktx2(&bytes),
+            Err(TextureError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn parse_dds_layer_major() {
+        // 4x4 BC7 with 3 mip levels: one block per level.
+        let mut bytes = dds_dx10(98, 4, 4, 3, false);
+        for level in 0..3 {
+            bytes.extend_from_slice(&[level; 16]);
+        }
+        let texture = Texture::parse(&bytes).unwrap();
+        assert_eq!(texture.format, vk::Format::BC7_UNORM_BLOCK);
+        let subresources = texture.subresources();
+        assert_eq!(subresources.len(), 3);
+        assert_eq!(subresources[2].mip_level, 2);
+        assert_eq!(subresources[2].data, &[2; 16]);
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(matches!(
+            Texture::from_dds(&bytes),
+            Err(TextureError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn reject_overflowing_layer_counts() {
+        let mut bytes = dds_dx10(28, 2, 2, 1, true);
+        bytes[DDS_HEADER_SIZE + 12..DDS_HEADER_SIZE + 16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            Texture::from_dds(&bytes),
+            Err(TextureError::Malformed(_))
+        ));
+
+        let mut bytes = ktx2(vk::Format::R8G8B8A8_UNORM, 4, 4, 6, &[vec![0; 6 * 64]]);
+        bytes[32..36].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            Texture::from_ktx2(&bytes),
+            Err(TextureError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn reject_oversized_textures() {
+        let mut bytes = ktx2(vk::Format::R8G8B8A8_UNORM, 4, 4, 1, &[vec![0; 64]]);
+        bytes[20..28].fill(0xff);
+        assert!(matches!(
+            Texture::from_ktx2(&bytes),
+            Err(TextureError::Malformed(_))
+        ));
+
+        // The uncompressed length of zstd levels is checked before decompressing.
+        let mut bytes = ktx2(vk::Format::R8G8B8A8_UNORM, 4, 4, 1, &[vec![0; 64]]);
+        bytes[44..48].copy_from_slice(&KTX2_SUPERCOMPRESSION_ZSTD.to_le_bytes());
+        bytes[KTX2_HEADER_SIZE + 16..KTX2_HEADER_SIZE + 24]
+            .copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            Texture::from_ktx2(&bytes),
+            Err(TextureError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn parse_dds_cube() {
+        let mut bytes = dds_dx10(28, 2, 2, 1, true);
+        for face in 0..6 {
+            bytes.extend_from_slice(&[face; 16]);
+        }
+        let texture = Texture::from_dds(&bytes).unwrap();
+        assert!(texture.cube);
+        assert_eq!(texture.array_layers, 6);
+        assert_eq!(texture.subresources()[5].data, &[5; 16]);
+    }
+}
@@ -32,6 +32,9 @@ impl std::fmt::Debug for Shader {
 
 impl Drop for Shader {
     fn drop(&mut self) {
+        self.device
+            .resource_registry()
+            .unregister(vk::ObjectType::SHADER_MODULE, self.module);
         unsafe { self.device.destroy_shader_module(self.module, None) }
     }
 }
@@ -50,6 +53,11 @@ impl Shader {
                 )
                 .unwrap()
         };
+        device.resource_registry().register(
+            vk::ObjectType::SHADER_MODULE,
+            module,
+            Some(spirv.len() as u64),
+        );
         let reflection = Reflection::new_from_spirv(spirv).unwrap();
         println!("{:?}", reflection.0.entry_points);
         Self {
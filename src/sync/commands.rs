@@ -7,7 +7,7 @@ use crate::{
     },
     queue::{
         semaphore::{TimelineSemaphore, TimelineSemaphoreOp},
-        QueueIndex, Queues, SemaphoreOp, StagedSemaphoreOp,
+        QueueIndex, QueueType, Queues, SemaphoreOp, StagedSemaphoreOp,
     },
 };
 use ash::vk;
@@ -25,6 +25,7 @@ pub struct CommandsFuture {
     pub(crate) semaphore_signals: Vec<StagedSemaphoreOp>,
 
     recording_cmd_buf: Option<CommandBufferBuilder>,
+    label: Option<String>,
 }
 impl Drop for CommandsFuture {
     fn drop(&mut self) {
@@ -58,6 +59,9 @@ impl Drop for CommandsFuture {
     }
 }
 impl CommandsFuture {
+    /// Create a future on exactly `queue`, bypassing [`Queues::route`]. Use this when the work must
+    /// run on a specific queue, for example to stay on the queue that owns a resource. Otherwise,
+    /// prefer [`CommandsFuture::on_queue_type`] so that work is spread over all the queues of a type.
     pub fn new(queues: Arc<Queues>, queue: QueueIndex) -> Self {
         Self {
             queues,
@@ -67,8 +71,22 @@ impl CommandsFuture {
             cmd_execs: Vec::new(),
             available_semaphore_pool: Vec::new(),
             recording_cmd_buf: None,
+            label: None,
         }
     }
+    /// Create a future on one of the queues of `ty`, picked by [`Queues::route`].
+    pub fn on_queue_type(queues: Arc<Queues>, ty: QueueType) -> Self {
+        let queue = queues.route(ty);
+        Self::new(queues, queue)
+    }
+    /// Label the command buffers recorded by this future from now on.
+    pub fn set_label(&mut self, label: &str) -> &mut Self {
+        if let Some(recording_cmd_buf) = self.recording_cmd_buf.as_mut() {
+            recording_cmd_buf.set_label(label);
+        }
+        self.label = Some(label.to_string());
+        self
+    }
     pub fn is_empty(&self) -> bool {
         self.cmd_execs.len() == 0 && self.recording_cmd_buf.is_none()
     }
@@ -101,8 +119,13 @@ impl CommandsFuture {
                 .shared_command_pool()
                 .allocate_one()
                 .unwrap();
-            buf.start(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
-                .unwrap()
+            let mut builder = buf
+                .start(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+                .unwrap();
+            if let Some(label) = self.label.as_ref() {
+                builder.set_label(label);
+            }
+            builder
         });
         let ret = recording_buffer.record(f);
         self.recording_cmd_buf = Some(recording_buffer);
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use crate::queue::{
     semaphore::{TimelineSemaphore, TimelineSemaphoreOp},
-    QueueIndex, Queues, SemaphoreOp,
+    QueueIndex, QueueType, Queues, SemaphoreOp,
 };
 use ash::vk;
 
@@ -53,6 +53,22 @@ impl<'q> GPUFuture for SparseBindingFuture<'q> {
 }
 
 impl<'q> SparseBindingFuture<'q> {
+    pub fn new(queues: &'q Queues, queue: QueueIndex) -> Self {
+        Self {
+            queues,
+            queue,
+            available_semaphore_pool: Vec::new(),
+            semaphore_waits: Vec::new(),
+            semaphore_signals: Vec::new(),
+            buffer_binds: Vec::new(),
+            image_opaque_binds: Vec::new(),
+            image_binds: Vec::new(),
+        }
+    }
+    /// Create a future on the queue picked by [`Queues::route`] for sparse binding.
+    pub fn on_sparse_binding_queue(queues: &'q Queues) -> Self {
+        Self::new(queues, queues.route(QueueType::SparseBinding))
+    }
     pub fn bind_buffer(mut self, buffer: vk::Buffer, binds: Box<[vk::SparseMemoryBind]>) -> Self {
         self.buffer_binds.push((buffer, binds));
         self
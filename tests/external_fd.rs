@@ -0,0 +1,147 @@
+//! Shares memory and semaphores between two Vulkan instances through Linux file descriptors.
+//!
+//! Runs on software drivers like lavapipe, for example with
+//! `VK_ICD_FILENAMES=/usr/share/vulkan/icd.d/lvp_icd.x86_64.json cargo test --test external_fd`.
+//! The test is skipped when no Vulkan device supports the external fd extensions.
+#![cfg(unix)]
+
+use std::{ffi::CStr, sync::Arc};
+
+use ash::{extensions::khr, vk};
+use dustash::{
+    queue::semaphore::TimelineSemaphore,
+    resources::{
+        alloc::{Allocator, BufferRequest, MemoryAllocScenario},
+        external::{ExternalFdLoader, MemoryImport},
+    },
+    Device, Instance, PhysicalDevice,
+};
+
+fn device_extensions() -> [&'static CStr; 2] {
+    [
+        khr::ExternalMemoryFd::name(),
+        khr::ExternalSemaphoreFd::name(),
+    ]
+}
+
+/// A device supporting the external fd extensions on a new instance, if any.
+fn create_device(entry: &Arc<ash::Entry>) -> Option<Arc<Device>> {
+    let application_info = vk::ApplicationInfo {
+        api_version: vk::make_api_version(0, 1, 3, 0),
+        ..Default::default()
+    };
+    let instance_extensions = [ash::extensions::ext::DebugUtils::name().as_ptr()];
+    let instance = Instance::create(
+        entry.clone(),
+        &vk::InstanceCreateInfo {
+            p_application_info: &application_info,
+            enabled_extension_count: instance_extensions.len() as u32,
+            pp_enabled_extension_names: instance_extensions.as_ptr(),
+            ..Default::default()
+        },
+    )
+    .ok()?;
+    let physical_device = PhysicalDevice::enumerate(&Arc::new(instance))
+        .ok()?
+        .into_iter()
+        .find(|physical_device| {
+            device_extensions()
+                .iter()
+                .all(|&name| physical_device.supports_extension(name))
+        })?;
+    let mut features12 = vk::PhysicalDeviceVulkan12Features {
+        timeline_semaphore: vk::TRUE,
+        ..Default::default()
+    };
+    let features = vk::PhysicalDeviceFeatures2 {
+        p_next: &mut features12 as *mut _ as *mut std::ffi::c_void,
+        ..Default::default()
+    };
+    let device_extensions = device_extensions().map(CStr::as_ptr);
+    let (device, _queues) = physical_device
+        .create_device(&[], &device_extensions, &features)
+        .ok()?;
+    Some(device)
+}
+
+#[test]
+fn share_memory_and_semaphores_between_instances() {
+    let Ok(entry) = (unsafe { ash::Entry::load() }) else {
+        eprintln!("No Vulkan loader, skipping");
+        return;
+    };
+    let entry = Arc::new(entry);
+    let (Some(exporter), Some(importer)) = (create_device(&entry), create_device(&entry)) else {
+        eprintln!("No device supports external fds, skipping");
+        return;
+    };
+    let exporter_loader = ExternalFdLoader::new(exporter.clone());
+    let importer_loader = ExternalFdLoader::new(importer.clone());
+    let exporter_allocator = Arc::new(Allocator::new(exporter.clone()));
+    let importer_allocator = Arc::new(Allocator::new(importer.clone()));
+
+    let request = BufferRequest {
+        size: 4096,
+        usage: vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST,
+        // Host visible, so the contents can be compared through mappings on both sides.
+        scenario: MemoryAllocScenario::StagingBuffer,
+        ..Default::default()
+    };
+    let handle_type = vk::ExternalMemoryHandleTypeFlags::OPAQUE_FD;
+    let exported = exporter_allocator
+        .allocate_exportable_buffer(&request, handle_type)
+        .unwrap();
+    let import = MemoryImport {
+        fd: exported.memory().export_fd(&exporter_loader).unwrap(),
+        size: exported.memory().size(),
+        memory_type_index: Some(exported.memory().memory_type_index()),
+    };
+    let imported = importer_allocator
+        .import_buffer(&importer_loader, &request, handle_type, import)
+        .unwrap();
+    assert_eq!(imported.size(), exported.size());
+    assert_eq!(imported.memory().size(), exported.memory().size());
+
+    let pattern: Vec<u8> = (0..request.size).map(|i| (i * 7 % 251) as u8).collect();
+    unsafe {
+        let memory = exported.memory().raw();
+        let ptr = exporter
+            .map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+            .unwrap() as *mut u8;
+        std::ptr::copy_nonoverlapping(pattern.as_ptr(), ptr, pattern.len());
+        exporter
+            .flush_mapped_memory_ranges(&[vk::MappedMemoryRange {
+                memory,
+                offset: 0,
+                size: vk::WHOLE_SIZE,
+                ..Default::default()
+            }])
+            .unwrap();
+        exporter.unmap_memory(memory);
+    }
+    let contents = unsafe {
+        let memory = imported.memory().raw();
+        let ptr = importer
+            .map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+            .unwrap() as *const u8;
+        importer
+            .invalidate_mapped_memory_ranges(&[vk::MappedMemoryRange {
+                memory,
+                offset: 0,
+                size: vk::WHOLE_SIZE,
+                ..Default::default()
+            }])
+            .unwrap();
+        let contents = std::slice::from_raw_parts(ptr, pattern.len()).to_vec();
+        importer.unmap_memory(memory);
+        contents
+    };
+    assert_eq!(contents, pattern);
+
+    let semaphore = TimelineSemaphore::new_exportable(exporter.clone(), 0).unwrap();
+    let fd = semaphore.export_fd(&exporter_loader).unwrap();
+    let imported_semaphore =
+        TimelineSemaphore::from_fd(importer.clone(), &importer_loader, fd).unwrap();
+    semaphore.signal(3).unwrap();
+    assert_eq!(imported_semaphore.value().unwrap(), 3);
+}